@@ -0,0 +1,160 @@
+//! Summaries of where in a tree of files the matches are.
+
+use std::collections::HashMap;
+use std::path::{Component, Path};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use crate::{get_regex_for_atoms, normalize, read_contents, FileMatchResult, QueryGroup, SearchOptions};
+
+/// The matching files below a directory.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DirectoryGroup {
+    /// The directory, or `.` for files given without one.
+    #[pyo3(get)]
+    pub directory: String,
+
+    /// Number of matching files in it.
+    #[pyo3(get)]
+    pub files: usize,
+
+    /// The first few of those results, e.g. to show their contexts.
+    #[pyo3(get)]
+    pub examples: Vec<FileMatchResult>,
+}
+
+#[pymethods]
+impl DirectoryGroup {
+    fn __repr__(&self) -> String {
+        format!("DirectoryGroup(directory={:?}, files={})", self.directory, self.files)
+    }
+}
+
+/// The matching files where an atom of an OR group matches.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlternativeGroup {
+    /// The atom.
+    #[pyo3(get)]
+    pub alternative: String,
+
+    /// Paths of the files where it matches.
+    #[pyo3(get)]
+    pub files: Vec<String>,
+}
+
+#[pymethods]
+impl AlternativeGroup {
+    fn __repr__(&self) -> String {
+        format!("AlternativeGroup(alternative={:?}, files={})", self.alternative, self.files.len())
+    }
+}
+
+/// The directory `path` is grouped into: its parent, or with `depth`, at most the first `depth`
+/// components of its parent.
+fn group_of(path: &str, depth: Option<usize>) -> String {
+    let parent = Path::new(path).parent().unwrap_or(Path::new(""));
+    let components: Vec<_> = parent
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect();
+    let kept = depth.map_or(components.len(), |depth| depth.min(components.len()));
+    if kept == 0 {
+        return ".".to_string();
+    }
+    components[..kept]
+        .iter()
+        .collect::<std::path::PathBuf>()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Group results by directory, keeping up to `examples` results of each group. The groups are
+/// sorted by decreasing number of files, then by directory.
+pub fn aggregate_by_directory(results: &[FileMatchResult], depth: Option<usize>, examples: usize) -> Vec<DirectoryGroup> {
+    let mut groups: HashMap<String, DirectoryGroup> = HashMap::new();
+    for result in results.iter() {
+        let directory = group_of(result.path(), depth);
+        let group = groups.entry(directory.clone()).or_insert_with(|| DirectoryGroup {
+            directory,
+            files: 0,
+            examples: Vec::new(),
+        });
+        group.files += 1;
+        if group.examples.len() < examples {
+            group.examples.push(result.clone());
+        }
+    }
+    let mut groups: Vec<_> = groups.into_values().collect();
+    groups.sort_by(|a, b| b.files.cmp(&a.files).then_with(|| a.directory.cmp(&b.directory)));
+    groups
+}
+
+/// Bucket the files of `results` by which atoms of the `group`-th OR group of the query match in
+/// them, a file going into the bucket of each atom that matches. The files are read again, with
+/// the encodings and normalizers of `options`; those that can't be are left out. The buckets,
+/// one per atom, are sorted by decreasing number of files, then in the order of the atoms.
+pub fn group_by_alternative(query_group: &QueryGroup, group: usize, results: &[FileMatchResult], options: &SearchOptions) -> PyResult<Vec<AlternativeGroup>> {
+    let atoms = query_group.atoms.get(group).ok_or_else(|| {
+        PyValueError::new_err(format!("group {} out of range for a query of {} groups", group, query_group.atoms.len()))
+    })?;
+    let patterns = atoms
+        .iter()
+        .map(|atom| get_regex_for_atoms(std::slice::from_ref(atom), &query_group.query_options))
+        .collect::<Result<Vec<_>, _>>()?;
+    let matched: Vec<Vec<bool>> = results
+        .par_iter()
+        .map(|result| match read_contents(result.path(), options) {
+            Ok(contents) => {
+                let contents = normalize::normalize(&options.normalizers, &contents);
+                patterns.iter().map(|pat| pat.is_match(&contents)).collect()
+            }
+            Err(_) => vec![false; patterns.len()],
+        })
+        .collect();
+    let mut groups: Vec<_> = atoms
+        .iter()
+        .enumerate()
+        .map(|(i, atom)| AlternativeGroup {
+            alternative: atom.clone(),
+            files: results
+                .iter()
+                .zip(matched.iter())
+                .filter(|(_, matched)| matched[i])
+                .map(|(result, _)| result.path().to_string())
+                .collect(),
+        })
+        .collect();
+    groups.sort_by_key(|group| std::cmp::Reverse(group.files.len()));
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{search_text, QueryGroup, SearchOptions};
+    use super::{group_by_alternative, group_of};
+
+    #[test]
+    fn test_group_of() {
+        assert_eq!(group_of("a/b/c.txt", None), "a/b");
+        assert_eq!(group_of("a/b/c.txt", Some(1)), "a");
+        assert_eq!(group_of("a/b/c.txt", Some(5)), "a/b");
+        assert_eq!(group_of("./a/c.txt", None), "a");
+        assert_eq!(group_of("c.txt", None), ".");
+        assert_eq!(group_of("/data/c.txt", Some(1)), "/");
+        assert_eq!(group_of("/data/x/c.txt", Some(2)), "/data");
+    }
+
+    #[test]
+    fn test_group_by_alternative() {
+        let query_group = QueryGroup::new(vec![vec!["qux".to_string(), "bar".to_string(), "o".to_string()]]).unwrap();
+        let paths = vec![String::from("sample_texts/hello.txt"), String::from("sample_texts/world.txt")];
+        let results = search_text(&query_group, &paths, false);
+        let groups = group_by_alternative(&query_group, 0, &results, &SearchOptions::default()).unwrap();
+        let summary: Vec<_> = groups.iter().map(|g| (g.alternative.as_str(), g.files.len())).collect();
+        assert_eq!(summary, vec![("o", 2), ("bar", 1), ("qux", 0)]);
+        assert_eq!(groups[1].files, vec!["sample_texts/hello.txt"]);
+        assert!(group_by_alternative(&query_group, 1, &results, &SearchOptions::default()).is_err());
+    }
+}
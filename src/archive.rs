@@ -0,0 +1,243 @@
+//! Searches inside zip and tar archives, each member file matched like a file of its own and
+//! reported as `archive.zip!inner/path.txt`, without extracting them to a directory first.
+//!
+//! Tar archives, compressed or not, are read here, while the members of zip archives are
+//! listed here and extracted by piping them through the system's `unzip`, like compressed files
+//! are decompressed. The members of an archive are read into memory together.
+
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::process::{Command, Stdio};
+use crate::streams::search_contents;
+use crate::{compress, encoding, score, search_text_with, FileMatchResult, QueryGroup, SearchOptions};
+
+/// Separates the path of an archive from the name of a member in the paths of the results.
+pub const MEMBER_SEPARATOR: char = '!';
+
+/// The local header and central directory entry signatures, and that of the end of the central
+/// directory.
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const ZIP_ENTRY: &[u8] = b"PK\x01\x02";
+const ZIP_END: &[u8] = b"PK\x05\x06";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    /// A tar archive, possibly compressed as one of the formats `compress` knows.
+    Tar,
+}
+
+impl ArchiveFormat {
+    /// The format of the archive at `path`, told from its extension: `.zip`, or `.tar`, `.tgz`
+    /// and `.tar` followed by `.gz`, `.bz2`, `.xz` or `.zst`.
+    pub fn of(path: &str) -> Option<Self> {
+        let path = path.to_ascii_lowercase();
+        if path.ends_with(".zip") {
+            return Some(ArchiveFormat::Zip);
+        }
+        let tar = [".tar", ".tgz", ".tar.gz", ".tar.bz2", ".tar.xz", ".tar.zst"];
+        tar.iter().any(|extension| path.ends_with(extension)).then_some(ArchiveFormat::Tar)
+    }
+}
+
+fn invalid(path: &str, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, message))
+}
+
+/// The member files of the archive at `path`, their names and contents, in the order they're
+/// archived in.
+pub fn read_members(path: &str, format: ArchiveFormat) -> io::Result<Vec<(String, Vec<u8>)>> {
+    match format {
+        ArchiveFormat::Tar => {
+            let bytes = match compress::read_decompressed(path, None)? {
+                Some(bytes) => bytes,
+                None => fs::read(path)?,
+            };
+            tar_members(path, &bytes)
+        }
+        ArchiveFormat::Zip => zip_members(path),
+    }
+}
+
+/// The regular files of the tar archive `bytes`, named with their GNU or pax long names if any.
+fn tar_members(path: &str, bytes: &[u8]) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let until_nul = |field: &[u8]| String::from_utf8_lossy(&field[..field.iter().position(|&b| b == 0).unwrap_or(field.len())]).into_owned();
+    let (mut members, mut offset, mut long_name) = (Vec::new(), 0, None);
+    while offset + 512 <= bytes.len() {
+        let header = &bytes[offset..offset + 512];
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let field = |range: Range<usize>| until_nul(&header[range]);
+        let size = usize::from_str_radix(field(124..136).trim(), 8).map_err(|_| invalid(path, "invalid tar header"))?;
+        let start = offset + 512;
+        let data = start
+            .checked_add(size)
+            .and_then(|end| bytes.get(start..end))
+            .ok_or_else(|| invalid(path, "truncated tar archive"))?;
+        match header[156] {
+            b'L' => long_name = Some(until_nul(data)),
+            b'x' => long_name = pax_path(data).or(long_name),
+            b'0' | 0 => {
+                let name = long_name.take().unwrap_or_else(|| match (&header[257..262] == b"ustar", field(345..500)) {
+                    (true, prefix) if !prefix.is_empty() => format!("{}/{}", prefix, field(0..100)),
+                    _ => field(0..100),
+                });
+                members.push((name, data.to_vec()));
+            }
+            _ => long_name = None,
+        }
+        offset = start + size.div_ceil(512) * 512;
+    }
+    Ok(members)
+}
+
+/// The `path` record of a pax extended header, records of the form `<length> <key>=<value>\n`.
+fn pax_path(mut records: &[u8]) -> Option<String> {
+    let mut path = None;
+    while let Some(space) = records.iter().position(|&b| b == b' ') {
+        let length: usize = std::str::from_utf8(&records[..space]).ok()?.parse().ok()?;
+        let record = records.get(space + 1..length)?.strip_suffix(b"\n")?;
+        if let Some(value) = record.strip_prefix(b"path=") {
+            path = Some(String::from_utf8_lossy(value).into_owned());
+        }
+        records = &records[length..];
+    }
+    path
+}
+
+/// The files of the zip archive at `path`, listed from its central directory and extracted,
+/// in its order, by `unzip -p`, whose output is split by their sizes.
+fn zip_members(path: &str) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let mut file = fs::File::open(path)?;
+    // the end of the central directory, at most a 64 KiB comment away from the end
+    let start = file.metadata()?.len().saturating_sub(22 + 0xffff);
+    file.seek(SeekFrom::Start(start))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    let end = tail.windows(4).rposition(|window| window == ZIP_END).ok_or_else(|| invalid(path, "not a zip archive"))?;
+    let u16_at = |bytes: &[u8], i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]) as usize;
+    let u32_at = |bytes: &[u8], i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]) as u64;
+    let end = tail.get(end..end + 22).ok_or_else(|| invalid(path, "truncated zip archive"))?;
+    let (entries, directory_size, directory_offset) = (u16_at(end, 10), u32_at(end, 12), u32_at(end, 16));
+    if entries == 0xffff || directory_offset == 0xffff_ffff {
+        return Err(invalid(path, "zip64 archives aren't supported"));
+    }
+    let mut directory = vec![0; directory_size as usize];
+    file.seek(SeekFrom::Start(directory_offset))?;
+    file.read_exact(&mut directory)?;
+    let mut listed = Vec::new();
+    let mut offset = 0;
+    for _ in 0..entries {
+        let entry = directory.get(offset..offset + 46).filter(|entry| entry.starts_with(ZIP_ENTRY)).ok_or_else(|| invalid(path, "invalid zip central directory"))?;
+        if u16_at(entry, 8) & 1 != 0 {
+            return Err(invalid(path, "encrypted zip archives aren't supported"));
+        }
+        let size = u32_at(entry, 24) as usize;
+        let name_end = offset + 46 + u16_at(entry, 28);
+        let name = directory.get(offset + 46..name_end).ok_or_else(|| invalid(path, "invalid zip central directory"))?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        offset = name_end + u16_at(entry, 30) + u16_at(entry, 32);
+        if !name.ends_with('/') {
+            listed.push((name, size));
+        }
+    }
+    let output = Command::new("unzip")
+        .args(["-p", "-qq", "--", path])
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("unzip is needed to read {}: {}", path, e)))?;
+    let total: usize = listed.iter().map(|(_, size)| size).sum();
+    if !output.status.success() || output.stdout.len() != total {
+        return Err(invalid(path, &format!("unzip failed: {}", String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    let mut start = 0;
+    Ok(listed
+        .into_iter()
+        .map(|(name, size)| {
+            start += size;
+            (name, output.stdout[start - size..start].to_vec())
+        })
+        .collect())
+}
+
+/// Whether the file at `path` is a zip archive, whatever its extension.
+fn is_zip(path: &str) -> bool {
+    let mut magic = [0; 4];
+    fs::File::open(path).and_then(|mut file| file.read_exact(&mut magic)).is_ok() && magic == ZIP_MAGIC
+}
+
+/// The member files of the archive at `path` matching `query_group`, named
+/// `<path>!<member name>`. Members that can't be decoded, e.g. binary files, are left out. They
+/// are matched whole, `head_bytes`, `tail_bytes` and `ranges` not applying to them.
+pub fn search_archive(query_group: &QueryGroup, path: &str, format: ArchiveFormat, options: &SearchOptions) -> io::Result<Vec<FileMatchResult>> {
+    let documents: Vec<(String, String)> = read_members(path, format)?
+        .into_iter()
+        .filter_map(|(name, bytes)| {
+            let name = format!("{}{}{}", path, MEMBER_SEPARATOR, name);
+            let contents = encoding::decode(&name, bytes, &options.encoding_hints).ok()?;
+            Some((name, contents))
+        })
+        .collect();
+    Ok(search_contents(query_group, &documents, options))
+}
+
+/// Search the files at `paths` like `search_text_with`, except for the archives among them,
+/// told from their extension, whose members are searched instead. Archives that can't be read
+/// are skipped like files that can't be. The results are in the order of the paths, and of
+/// the members in each archive, unless ranked.
+pub fn search_archives(query_group: &QueryGroup, paths: &[String], options: &SearchOptions) -> Vec<FileMatchResult> {
+    let format = |path: &str| ArchiveFormat::of(path).filter(|&format| format == ArchiveFormat::Tar || is_zip(path));
+    let files: Vec<_> = paths.iter().filter(|path| format(path).is_none()).cloned().collect();
+    let mut file_results = search_text_with(query_group, &files, options).into_iter().peekable();
+    let mut results = Vec::new();
+    for path in paths.iter() {
+        match format(path) {
+            Some(format) => results.extend(search_archive(query_group, path, format, options).unwrap_or_default()),
+            None if options.scoring.is_none() => results.extend(file_results.next_if(|result| &result.path == path)),
+            None => {}
+        }
+    }
+    if let Some(scoring) = &options.scoring {
+        results.extend(file_results);
+        score::rank(&mut results, scoring.top_k);
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::process::Command;
+    use crate::{QueryGroup, SearchOptions};
+    use super::{pax_path, search_archives, ArchiveFormat};
+
+    #[test]
+    fn test_search_archives() {
+        assert_eq!(ArchiveFormat::of("logs.TAR.GZ"), Some(ArchiveFormat::Tar));
+        assert_eq!(ArchiveFormat::of("notes.txt"), None);
+        assert_eq!(pax_path(b"20 path=a/long/name\n12 size=100\n"), Some("a/long/name".to_string()));
+        let root = std::env::temp_dir().join(format!("textsearcher-archive-{}", std::process::id()));
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("docs/a.txt"), "foo bar").unwrap();
+        fs::write(root.join("docs/b.txt"), "baz").unwrap();
+        fs::write(root.join("docs/c.bin"), b"foo \xff\xfe").unwrap();
+        let archive = |program: &str, args: &[&str]| {
+            assert!(Command::new(program).args(args).current_dir(&root).status().unwrap().success());
+        };
+        archive("tar", &["-czf", "docs.tar.gz", "docs"]);
+        archive("zip", &["-qr", "docs.zip", "docs"]);
+        let paths: Vec<_> = ["docs.tar.gz", "docs/b.txt", "docs.zip"].iter().map(|path| root.join(path).to_str().unwrap().to_string()).collect();
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string(), "baz".to_string()]]).unwrap();
+        let results = search_archives(&query_group, &paths, &SearchOptions::default());
+        let mut names: Vec<_> = results.iter().map(|result| &result.path()[root.to_str().unwrap().len() + 1..]).collect();
+        // in the order of the paths, the members of each archive in the order they were archived in
+        assert_eq!(names[2], "docs/b.txt");
+        names[..2].sort();
+        names[3..].sort();
+        assert_eq!(names, vec!["docs.tar.gz!docs/a.txt", "docs.tar.gz!docs/b.txt", "docs/b.txt", "docs.zip!docs/a.txt", "docs.zip!docs/b.txt"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
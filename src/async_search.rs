@@ -0,0 +1,113 @@
+//! Searches for async Rust services: the files are read with tokio, a bounded number at once,
+//! and matched on tokio's blocking threads, so that awaiting a search doesn't starve the
+//! runtime's workers.
+
+use std::io;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use crate::report::{ScanStats, SearchReport};
+use crate::tags::Tags;
+use crate::{encoding, finish_match, match_contents, score, FileMatchResult, QueryGroup, SearchOptions};
+
+/// Read the file at `path`, or its first `head_bytes` or last `tail_bytes` bytes, unless it's
+/// larger than `max_file_size` bytes, and decode it like `read_contents` does.
+async fn read(path: &str, options: &SearchOptions) -> io::Result<String> {
+    if let Some(max) = options.max_file_size {
+        if tokio::fs::metadata(path).await?.len() > max {
+            return Err(io::Error::new(io::ErrorKind::FileTooLarge, format!("{} is larger than {} bytes", path, max)));
+        }
+    }
+    let mut bytes = Vec::new();
+    match (options.head_bytes, options.tail_bytes) {
+        (Some(max_bytes), _) => {
+            tokio::fs::File::open(path).await?.take(max_bytes).read_to_end(&mut bytes).await?;
+            encoding::decode_head(path, bytes, &options.encoding_hints, max_bytes)
+        }
+        (None, Some(max_bytes)) => {
+            let mut file = tokio::fs::File::open(path).await?;
+            let start = file.metadata().await?.len().saturating_sub(max_bytes);
+            file.seek(io::SeekFrom::Start(start)).await?;
+            file.read_to_end(&mut bytes).await?;
+            encoding::decode_tail(path, bytes, &options.encoding_hints, start)
+        }
+        (None, None) => encoding::decode(path, tokio::fs::read(path).await?, &options.encoding_hints),
+    }
+}
+
+/// Like `search_text_report`, reading at most `max_concurrent_reads` files at once. The
+/// results are in the order of `textfile_paths`, or ranked if scoring. The result cache, read
+/// limit and chunked reading of `options` aren't used, and neither is `parallel`: the files are
+/// matched concurrently on tokio's blocking threads.
+pub async fn search_text_async(query_group: Arc<QueryGroup>, textfile_paths: Vec<String>, options: Arc<SearchOptions>, max_concurrent_reads: usize) -> (Vec<FileMatchResult>, SearchReport) {
+    let started = Instant::now();
+    let stats = Arc::new(ScanStats::default());
+    let reads = Arc::new(Semaphore::new(max_concurrent_reads.max(1)));
+    let files_total = textfile_paths.len();
+    let mut tasks = JoinSet::new();
+    for (i, path) in textfile_paths.into_iter().enumerate() {
+        let (query_group, options, stats, reads) = (query_group.clone(), options.clone(), stats.clone(), reads.clone());
+        tasks.spawn(async move {
+            let decoded = {
+                let _permit = reads.acquire().await.expect("the semaphore is never closed");
+                read(&path, &options).await
+            };
+            let contents = match decoded {
+                Ok(contents) => match options.code {
+                    Some(mode) => crate::code::filter(&path, mode, contents),
+                    None => contents,
+                },
+                Err(e) => {
+                    stats.record_skip(&path, &e);
+                    return None;
+                }
+            };
+            stats.record_read(contents.len());
+            tokio::task::spawn_blocking(move || {
+                let file_started = Instant::now();
+                let matched = match_contents(&query_group, &path, &contents, &options);
+                stats.record_busy(file_started.elapsed());
+                let (result, match_stats) = matched?;
+                Some((i, finish_match(result, match_stats, &Tags::new(), &options)))
+            })
+            .await
+            .expect("matching doesn't panic")
+        });
+    }
+    let mut results = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        results.extend(joined.expect("reading doesn't panic"));
+    }
+    results.sort_by_key(|&(i, _)| i);
+    let mut results: Vec<_> = results.into_iter().map(|(_, result)| result).collect();
+    if let Some(scoring) = &options.scoring {
+        score::rank(&mut results, scoring.top_k);
+    }
+    let stats = Arc::try_unwrap(stats).unwrap_or_default();
+    let report = stats.finish(files_total, results.len(), started.elapsed());
+    (results, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::{QueryGroup, SearchOptions};
+    use super::search_text_async;
+
+    #[test]
+    fn test_search_text_async() {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let query_group = Arc::new(QueryGroup::new(vec![vec!["o".to_string()]]).unwrap());
+        let paths = vec![
+            "sample_texts/world.txt".to_string(),
+            "sample_texts/missing.txt".to_string(),
+            "sample_texts/hello.txt".to_string(),
+        ];
+        let (results, report) = runtime.block_on(search_text_async(query_group, paths, Arc::new(SearchOptions::default()), 1));
+        let paths: Vec<_> = results.iter().map(|r| r.path()).collect();
+        assert_eq!(paths, vec!["sample_texts/world.txt", "sample_texts/hello.txt"]);
+        assert_eq!((report.files_total, report.files_scanned, report.files_matched), (3, 2, 2));
+    }
+}
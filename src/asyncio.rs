@@ -0,0 +1,264 @@
+//! Searches awaited from Python's asyncio, e.g. by the handlers of an async web service, which
+//! mustn't block the event loop. The files are searched on background threads, and the results
+//! handed to the event loop with `call_soon_threadsafe`, so that no Python async runtime crate
+//! is needed.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use pyo3::exceptions::{PyRuntimeError, PyStopAsyncIteration};
+use pyo3::prelude::*;
+use pyo3::types::{PyCFunction, PyDict, PyTuple};
+use crate::lazy::{self, SearchStream};
+use crate::progress::{CancelToken, SIGNAL_INTERVAL};
+use crate::report::SearchReport;
+use crate::{search_text_with, QueryGroup, SearchOptions};
+
+/// Set the result of `future`, unless it's done already, e.g. cancelled.
+#[pyfunction]
+fn set_result(future: &PyAny, result: PyObject) -> PyResult<()> {
+    if !future.call_method0("done")?.is_true()? {
+        future.call_method1("set_result", (result,))?;
+    }
+    Ok(())
+}
+
+/// End the iteration awaiting `future`, unless it's done already.
+#[pyfunction]
+fn set_stopped(py: Python<'_>, future: &PyAny) -> PyResult<()> {
+    if !future.call_method0("done")?.is_true()? {
+        future.call_method1("set_exception", (PyStopAsyncIteration::new_err(()).into_value(py),))?;
+    }
+    Ok(())
+}
+
+/// Call `callback` with `args` on the thread of `event_loop`; a loop closed meanwhile is no
+/// longer waiting for anything.
+fn call_soon(py: Python<'_>, event_loop: &PyObject, callback: &PyCFunction, args: impl IntoPy<Py<PyTuple>>) {
+    let mut call = vec![callback.into_py(py)];
+    call.extend(args.into_py(py).as_ref(py).iter().map(|arg| arg.into_py(py)));
+    let _ = event_loop.call_method1(py, "call_soon_threadsafe", PyTuple::new(py, call));
+}
+
+/// Call `cancelled` with `future` once it's cancelled, e.g. by a timeout of the awaiting task.
+fn on_cancelled<F>(py: Python<'_>, future: &PyAny, cancelled: F) -> PyResult<()>
+where
+    F: Fn(&PyAny) + Send + 'static,
+{
+    let on_done = PyCFunction::new_closure(py, None, None, move |args: &PyTuple, _: Option<&PyDict>| -> PyResult<()> {
+        let future = args.get_item(0)?;
+        if future.call_method0("cancelled")?.is_true()? {
+            cancelled(future);
+        }
+        Ok(())
+    })?;
+    future.call_method1("add_done_callback", (on_done,))?;
+    Ok(())
+}
+
+/// An asyncio future of the matches of `query_group` in the files at `paths`, like
+/// `search_text_with`, created on the running event loop. Cancelling the future cancels the
+/// search.
+pub fn search_text_future(py: Python<'_>, query_group: &QueryGroup, paths: &[String], mut options: SearchOptions) -> PyResult<PyObject> {
+    let event_loop: PyObject = py.import("asyncio")?.call_method0("get_running_loop")?.into();
+    let future: PyObject = event_loop.call_method0(py, "create_future")?;
+    let cancel = options.cancel.get_or_insert_with(CancelToken::new).clone();
+    let cancelling = cancel.clone();
+    on_cancelled(py, future.as_ref(py), move |_| cancelling.cancel())?;
+    let (query_group, paths) = (query_group.clone(), paths.to_vec());
+    let pending = future.clone_ref(py);
+    thread::spawn(move || {
+        let results = search_text_with(&query_group, &paths, &options);
+        // nothing awaits a cancelled search, and the interpreter may be finalizing meanwhile
+        if cancel.is_cancelled() {
+            return;
+        }
+        Python::with_gil(|py| {
+            if let Ok(callback) = wrap_pyfunction!(set_result, py) {
+                call_soon(py, &event_loop, callback, (pending, results));
+            }
+        });
+    });
+    Ok(future)
+}
+
+/// What the consumer of an `AsyncSearchStream` awaits, shared with the thread feeding it.
+#[derive(Default)]
+struct Feed {
+    /// The future of the next match, while it's awaited.
+    waiter: Option<PyObject>,
+    done: bool,
+    report: Option<SearchReport>,
+}
+
+struct Shared {
+    feed: Mutex<Feed>,
+    awaited: Condvar,
+    cancel: CancelToken,
+    event_loop: PyObject,
+}
+
+/// Hand the matches of `stream` to the futures awaiting them, one match per future, until all
+/// of them are taken or the search is cancelled.
+fn feed(mut stream: SearchStream, shared: &Shared) {
+    loop {
+        {
+            let mut feed = shared.feed.lock().unwrap();
+            // the matches are searched ahead only as far as the stream buffers them
+            while feed.waiter.is_none() && !shared.cancel.is_cancelled() {
+                feed = shared.awaited.wait_timeout(feed, SIGNAL_INTERVAL).unwrap().0;
+            }
+        }
+        let next = match shared.cancel.is_cancelled() {
+            true => {
+                // the search stops once the matches found meanwhile are taken
+                for _ in stream.by_ref() {}
+                None
+            }
+            false => stream.next(),
+        };
+        // the GIL is never taken while holding the feed, which the event loop takes with the GIL
+        let waiter = {
+            let mut feed = shared.feed.lock().unwrap();
+            if next.is_none() {
+                feed.done = true;
+                feed.report = stream.report().cloned();
+            }
+            feed.waiter.take()
+        };
+        let Some(waiter) = waiter else {
+            return;
+        };
+        let finished = next.is_none();
+        Python::with_gil(|py| match next {
+            Some(result) => {
+                if let Ok(callback) = wrap_pyfunction!(set_result, py) {
+                    call_soon(py, &shared.event_loop, callback, (waiter, result));
+                }
+            }
+            None => {
+                if let Ok(callback) = wrap_pyfunction!(set_stopped, py) {
+                    call_soon(py, &shared.event_loop, callback, (waiter,));
+                }
+            }
+        });
+        if finished {
+            return;
+        }
+    }
+}
+
+/// A stream of the files matching, iterated with `async for` as they're found by the threads
+/// searching them in the background. The stream is cancelled with its `cancel` method, by
+/// cancelling an `await` of its next match, or once garbage collected.
+#[pyclass(name = "AsyncSearchStream")]
+pub struct PyAsyncSearchStream {
+    shared: Arc<Shared>,
+}
+
+impl PyAsyncSearchStream {
+    /// Start searching the files at `paths` in the background, for the running event loop.
+    pub fn new(py: Python<'_>, query_group: &QueryGroup, paths: &[String], mut options: SearchOptions) -> PyResult<Self> {
+        let event_loop: PyObject = py.import("asyncio")?.call_method0("get_running_loop")?.into();
+        let cancel = options.cancel.get_or_insert_with(CancelToken::new).clone();
+        let shared = Arc::new(Shared {
+            feed: Mutex::new(Feed::default()),
+            awaited: Condvar::new(),
+            cancel,
+            event_loop,
+        });
+        let stream = lazy::search_stream(query_group, paths, &options);
+        let feeding = shared.clone();
+        thread::spawn(move || feed(stream, &feeding));
+        Ok(PyAsyncSearchStream {
+            shared,
+        })
+    }
+}
+
+#[pymethods]
+impl PyAsyncSearchStream {
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// A future of the next match, ending the iteration once all the files are searched.
+    fn __anext__(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let future = self.shared.event_loop.call_method0(py, "create_future")?;
+        {
+            let mut feed = self.shared.feed.lock().unwrap();
+            if feed.done {
+                return Ok(None);
+            }
+            if feed.waiter.is_some() {
+                return Err(PyRuntimeError::new_err("the next match of the stream is already awaited"));
+            }
+            feed.waiter = Some(future.clone_ref(py));
+        }
+        self.shared.awaited.notify_all();
+        let shared = self.shared.clone();
+        on_cancelled(py, future.as_ref(py), move |future| {
+            shared.cancel.cancel();
+            // handing it its match would need the GIL, e.g. while the interpreter is finalizing
+            let mut feed = shared.feed.lock().unwrap();
+            if feed.waiter.as_ref().is_some_and(|waiter| waiter.is(future)) {
+                feed.waiter = None;
+            }
+        })?;
+        Ok(Some(future))
+    }
+
+    #[pyo3(name = "cancel")]
+    fn py_cancel(&self) {
+        self.shared.cancel.cancel()
+    }
+
+    /// The report of the search, once all the matches are taken.
+    #[getter]
+    fn report(&self) -> Option<SearchReport> {
+        self.shared.feed.lock().unwrap().report.clone()
+    }
+}
+
+impl Drop for PyAsyncSearchStream {
+    fn drop(&mut self) {
+        self.shared.cancel.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn test_search_async() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "textsearcher").unwrap();
+            crate::py_module(py, module).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("__builtins__", py.import("builtins").unwrap()).unwrap();
+            globals.set_item("t", module).unwrap();
+            py.run(
+                r#"
+import asyncio
+
+async def search():
+    q = t.QueryGroup([['o']])
+    files = t.FilePaths(['sample_texts/hello.txt', 'sample_texts/gbk.txt', 'sample_texts/world.txt'])
+    results = await t.search_text_async(q, files)
+    streamed = [r.path async for r in t.search_stream_async(q, files)]
+    return sorted(r.path for r in results), sorted(streamed)
+
+found = asyncio.run(search())
+"#,
+                Some(globals),
+                None,
+            )
+            .unwrap();
+            let found: (Vec<String>, Vec<String>) = globals.get_item("found").unwrap().extract().unwrap();
+            let expected = vec!["sample_texts/hello.txt".to_string(), "sample_texts/world.txt".to_string()];
+            assert_eq!(found, (expected.clone(), expected));
+        });
+    }
+}
@@ -0,0 +1,300 @@
+//! The searcher as a command-line tool, e.g.
+//! `textsearcher -B 1 -A 1 --parallel '(disk OR drive) AND full' logs/`.
+
+#[cfg(unix)]
+mod tui;
+
+use std::io::{self, Write};
+use std::process::ExitCode;
+use std::sync::Arc;
+use textsearcher::corpus::Corpus;
+use textsearcher::exclude::Excludes;
+use textsearcher::query::Query;
+use textsearcher::streams::search_reader;
+use textsearcher::typeahead::TypeAhead;
+use textsearcher::walk::{walk_files, WalkOptions};
+use textsearcher::{search_text_report, ContextOptions, ContextUnit, FileMatchResult, QueryGroup, QueryOptions, SearchOptions};
+
+const USAGE: &str = "\
+Usage: textsearcher [OPTIONS] QUERY [PATH]...
+       textsearcher -i [OPTIONS] [PATH]...
+
+Search the files, and the files below the directories, given (by default the current directory)
+for QUERY: atoms combined with AND, OR and NOT, OR binding loosest, in parentheses or quoted as
+needed, e.g. '(disk OR drive) AND full AND NOT \"read only\"'; consecutive words are a phrase. A
+PATH of - searches the standard input, e.g. piped from another command.
+
+With -i, the files are searched as the query is typed instead, its words all of which must
+match, the matching files listed above the context of the one selected; Up and Down select a
+file, Enter prints its path:line:column and Esc leaves.
+
+Options:
+  -A, --after-context NUM   print NUM lines of context after the first match
+  -B, --before-context NUM  print NUM lines of context before the first match
+  -C, --context NUM         print NUM lines of context before and after the first match
+      --not ATOM            leave out the files where ATOM matches; may be repeated
+  -v, --invert-match        print the files that don't match instead
+  -w, --word-regexp         match the words of the atoms only as whole words
+      --exclude GLOB        leave out the files and directories matching GLOB; may be repeated
+      --gitignore           leave out what .gitignore files ignore
+      --parallel            search the files in parallel
+  -z, --decompress          search files compressed with gzip, bzip2, xz or zstd like plain text
+      --json                print each match as a line of JSON
+  -i, --interactive         search as the query is typed, with the context of the first match
+                            previewed, 3 lines before and after unless given
+  -h, --help                print this help
+";
+
+/// What the matches of the standard input are named after, like grep names them.
+const STDIN_LABEL: &str = "(standard input)";
+
+#[derive(Default)]
+struct Args {
+    query: Option<String>,
+    paths: Vec<String>,
+    before: Option<usize>,
+    after: Option<usize>,
+    excluded_atoms: Vec<String>,
+    invert: bool,
+    whole_words: bool,
+    exclude: Vec<String>,
+    gitignore: bool,
+    parallel: bool,
+    decompress: bool,
+    json: bool,
+    interactive: bool,
+    help: bool,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} requires a value", name));
+        let number = |name: &str, value: String| value.parse::<usize>().map_err(|_| format!("{} requires a number, not {:?}", name, value));
+        match arg.as_str() {
+            "-A" | "--after-context" => parsed.after = Some(number(&arg, value(&arg)?)?),
+            "-B" | "--before-context" => parsed.before = Some(number(&arg, value(&arg)?)?),
+            "-C" | "--context" => {
+                let n = number(&arg, value(&arg)?)?;
+                (parsed.before, parsed.after) = (Some(n), Some(n));
+            }
+            "--not" => parsed.excluded_atoms.push(value(&arg)?),
+            "-v" | "--invert-match" => parsed.invert = true,
+            "-w" | "--word-regexp" => parsed.whole_words = true,
+            "--exclude" => parsed.exclude.push(value(&arg)?),
+            "--gitignore" => parsed.gitignore = true,
+            "--parallel" => parsed.parallel = true,
+            "-z" | "--decompress" => parsed.decompress = true,
+            "--json" => parsed.json = true,
+            "-i" | "--interactive" => parsed.interactive = true,
+            "-h" | "--help" => parsed.help = true,
+            "--" => {
+                parsed.paths.extend(args.by_ref());
+            }
+            option if option.starts_with('-') && option.len() > 1 => return Err(format!("unknown option {}", option)),
+            _ if parsed.query.is_none() => parsed.query = Some(arg),
+            _ => parsed.paths.push(arg),
+        }
+    }
+    // the query is typed in rather than given
+    if parsed.interactive {
+        parsed.paths.splice(0..0, parsed.query.take());
+    } else if parsed.query.is_none() && !parsed.help {
+        return Err("a query is required".to_string());
+    }
+    Ok(parsed)
+}
+
+/// The query group of the query string `query`, parsed like `Query::parse` does, without the
+/// files where any of `excluded_atoms` matches.
+fn parse_query(query: &str, excluded_atoms: &[String], options: QueryOptions) -> Result<QueryGroup, String> {
+    let query = excluded_atoms
+        .iter()
+        .fold(Query::parse(query).map_err(|e| e.to_string())?, |query, atom| query.and(Query::atom(atom.as_str()).negate()));
+    query.compile(options).map_err(|e| e.to_string())
+}
+
+/// What a search found, telling its exit status.
+struct Outcome {
+    matched: bool,
+    /// Whether any file couldn't be searched.
+    errors: bool,
+}
+
+impl Outcome {
+    /// Like grep: 2 if a file couldn't be searched, else 0 if anything matched and 1 if not.
+    fn exit_code(&self) -> u8 {
+        match (self.errors, self.matched) {
+            (true, _) => 2,
+            (false, true) => 0,
+            (false, false) => 1,
+        }
+    }
+}
+
+fn print_result(out: &mut impl Write, result: &FileMatchResult, json: bool) -> io::Result<()> {
+    if json {
+        return writeln!(out, "{}", result.to_json());
+    }
+    match result.position() {
+        Some(position) => writeln!(out, "{}:{}:{}", result.path(), position.line, position.column)?,
+        None => writeln!(out, "{}", result.path())?,
+    }
+    if let Some(context) = result.context() {
+        writeln!(out, "{}", context.trim_end_matches('\n'))?;
+        writeln!(out, "--")?;
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> Result<Outcome, String> {
+    let query_options = QueryOptions {
+        whole_words: args.whole_words,
+        ..Default::default()
+    };
+    let walk = WalkOptions {
+        exclude: Excludes::new(&args.exclude).map_err(|e| e.to_string())?,
+        gitignore: args.gitignore,
+        ..Default::default()
+    };
+    let roots = if args.paths.is_empty() { vec![".".to_string()] } else { args.paths };
+    let stdin = roots.iter().any(|root| root == "-");
+    let mut paths = Vec::new();
+    for root in roots.into_iter().filter(|root| root != "-") {
+        match std::fs::metadata(&root) {
+            Ok(metadata) if metadata.is_dir() => paths.extend(walk_files(&root, &walk).map_err(|e| format!("{}: {}", root, e))?),
+            _ => paths.push(root),
+        }
+    }
+    let context = (args.before.is_some() || args.after.is_some()).then(|| ContextOptions {
+        before: args.before.unwrap_or(0),
+        after: args.after.unwrap_or(0),
+        unit: ContextUnit::Lines,
+        ..Default::default()
+    });
+    let options = SearchOptions {
+        parallel: args.parallel,
+        context,
+        invert: args.invert,
+        decompress: args.decompress,
+        ..Default::default()
+    };
+    if args.interactive {
+        let matched = interactive(paths, stdin, query_options, options, &args.excluded_atoms)?;
+        return Ok(Outcome {
+            matched,
+            errors: false,
+        });
+    }
+    let query_group = parse_query(&args.query.unwrap_or_default(), &args.excluded_atoms, query_options)?;
+    let (results, errors) = search(&query_group, &paths, stdin.then(|| io::stdin().lock()), &options);
+    let mut out = io::stdout().lock();
+    for result in results.iter() {
+        print_result(&mut out, result, args.json).map_err(|e| e.to_string())?;
+    }
+    for (path, message) in errors.iter() {
+        eprintln!("textsearcher: {}: {}", path, message);
+    }
+    Ok(Outcome {
+        matched: !results.is_empty(),
+        errors: !errors.is_empty(),
+    })
+}
+
+/// The matches in the files at `paths`, then in `stdin` if given, and the path and error of
+/// each file that couldn't be searched.
+fn search(query_group: &QueryGroup, paths: &[String], stdin: Option<impl io::Read>, options: &SearchOptions) -> (Vec<FileMatchResult>, Vec<(String, String)>) {
+    let (mut results, report) = search_text_report(query_group, paths, options, |_| ());
+    let mut errors: Vec<_> = report.errors.into_iter().map(|(path, _, message)| (path, message)).collect();
+    if let Some(stdin) = stdin {
+        match search_reader(query_group, stdin, STDIN_LABEL, options) {
+            Ok(result) => results.extend(result),
+            Err(e) => errors.push((STDIN_LABEL.to_string(), e.to_string())),
+        }
+    }
+    (results, errors)
+}
+
+/// Search the files at `paths` as the query is typed, printing the match chosen if any.
+#[cfg(unix)]
+fn interactive(paths: Vec<String>, stdin: bool, query_options: QueryOptions, mut options: SearchOptions, excluded_atoms: &[String]) -> Result<bool, String> {
+    if stdin || options.invert || !excluded_atoms.is_empty() {
+        return Err("--interactive doesn't search the standard input, nor take --not or -v".to_string());
+    }
+    options.context.get_or_insert(ContextOptions {
+        before: 3,
+        after: 3,
+        unit: ContextUnit::Lines,
+        ..Default::default()
+    });
+    let files = paths.len();
+    // the contents kept in memory for the searches of each keystroke
+    let session = TypeAhead::new(Arc::new(Corpus::new(paths, true)), query_options, options);
+    match tui::run(session, files).map_err(|e| format!("the terminal: {}", e))? {
+        Some(result) => {
+            // the path of the match alone, without its context
+            match result.position() {
+                Some(position) => println!("{}:{}:{}", result.path(), position.line, position.column),
+                None => println!("{}", result.path()),
+            }
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+#[cfg(not(unix))]
+fn interactive(_paths: Vec<String>, _stdin: bool, _query_options: QueryOptions, _options: SearchOptions, _excluded_atoms: &[String]) -> Result<bool, String> {
+    Err("--interactive is only supported on Unix".to_string())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("textsearcher: {}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    if args.help {
+        print!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    match run(args) {
+        Ok(outcome) => ExitCode::from(outcome.exit_code()),
+        Err(message) => {
+            eprintln!("textsearcher: {}", message);
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use textsearcher::{QueryOptions, SearchOptions};
+    use super::{parse_args, parse_query, search, Outcome};
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse_args(["-C", "2", "--not", "draft", "(disk OR drive) AND full", "logs", "notes"].into_iter().map(String::from)).unwrap();
+        assert_eq!((args.before, args.after, args.excluded_atoms.clone()), (Some(2), Some(2), vec!["draft".to_string()]));
+        let query_group = parse_query(&args.query.unwrap(), &args.excluded_atoms, QueryOptions::default()).unwrap();
+        assert_eq!(query_group.atoms, vec![vec!["disk", "drive"], vec!["full"]]);
+        assert_eq!(query_group.excluded_atoms, vec![vec!["draft"]]);
+        assert_eq!(args.paths, vec!["logs", "notes"]);
+        assert!(parse_args(["-A", "many", "disk"].into_iter().map(String::from)).is_err());
+        assert_eq!(parse_query("disk AND OR full", &[], QueryOptions::default()).unwrap_err(), "expected an atom, a quoted phrase, NOT or '(' at position 9 of \"disk AND OR full\"");
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let query_group = parse_query("o", &[], QueryOptions::default()).unwrap();
+        let paths = vec![String::from("sample_texts/hello.txt"), String::from("sample_texts/missing.txt")];
+        let (results, errors) = search(&query_group, &paths, Some(io::Cursor::new("xyz")), &SearchOptions::default());
+        assert_eq!(results.len(), 1);
+        assert_eq!(errors.iter().map(|(path, _)| path.as_str()).collect::<Vec<_>>(), vec!["sample_texts/missing.txt"]);
+        let outcome = |matched, errors| Outcome { matched, errors }.exit_code();
+        assert_eq!((outcome(true, !errors.is_empty()), outcome(true, false), outcome(false, false)), (2, 0, 1));
+    }
+}
@@ -0,0 +1,261 @@
+//! The interactive mode, `textsearcher -i logs/`: the files are read into memory once, and the
+//! query typed in at the top is searched for in them as it's typed, listing the matching files
+//! above a preview of the context of the one selected. The query is the AND of its words, as
+//! `typeahead::TypeAhead` takes it, so that each keystroke only searches the files that matched
+//! before. The screen is drawn on the terminal itself, leaving the standard output to the path
+//! chosen with Enter, e.g. for `vim $(textsearcher -i .)`.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use textsearcher::typeahead::TypeAhead;
+use textsearcher::FileMatchResult;
+
+/// A key read from the terminal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Key {
+    Char(char),
+    Backspace,
+    /// Ctrl-U, clearing the query.
+    Clear,
+    Up,
+    Down,
+    Enter,
+    /// Esc or Ctrl-C.
+    Quit,
+}
+
+/// The keys of `bytes`, read at once from the terminal; escape sequences other than the arrows
+/// and other control characters are ignored.
+fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut chars = text.chars().peekable();
+    let mut keys = Vec::new();
+    while let Some(c) = chars.next() {
+        let key = match c {
+            '\x1b' => match chars.peek() {
+                // a lone escape, not starting a sequence
+                None | Some('\x1b') => Some(Key::Quit),
+                Some('[' | 'O') => {
+                    chars.next();
+                    // the parameters of the sequence, up to its final character
+                    let last = chars.by_ref().find(|c| !c.is_ascii_digit() && *c != ';');
+                    match last {
+                        Some('A') => Some(Key::Up),
+                        Some('B') => Some(Key::Down),
+                        _ => None,
+                    }
+                }
+                Some(_) => None,
+            },
+            '\x03' => Some(Key::Quit),
+            '\r' | '\n' => Some(Key::Enter),
+            '\x7f' | '\x08' => Some(Key::Backspace),
+            '\x15' => Some(Key::Clear),
+            '\x10' => Some(Key::Up),
+            '\x0e' => Some(Key::Down),
+            c if c.is_control() || c == char::REPLACEMENT_CHARACTER => None,
+            c => Some(Key::Char(c)),
+        };
+        keys.extend(key);
+    }
+    keys
+}
+
+/// The query typed in so far, its matches and the one selected.
+#[derive(Default)]
+struct State {
+    query: String,
+    results: Vec<FileMatchResult>,
+    error: Option<String>,
+    selected: usize,
+}
+
+impl State {
+    /// Apply `key`, returning whether the query changed.
+    fn press(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char(c) => self.query.push(c),
+            Key::Backspace => return self.query.pop().is_some(),
+            Key::Clear => return !std::mem::take(&mut self.query).is_empty(),
+            Key::Up => self.selected = self.selected.saturating_sub(1),
+            Key::Down => self.selected = (self.selected + 1).min(self.results.len().saturating_sub(1)),
+            Key::Enter | Key::Quit => return false,
+        }
+        matches!(key, Key::Char(_))
+    }
+
+    fn search(&mut self, session: &mut TypeAhead) {
+        match session.update(&self.query) {
+            Ok(results) => {
+                self.results = results;
+                self.error = None;
+            }
+            Err(e) => {
+                self.results.clear();
+                self.error = Some(e.to_string());
+            }
+        }
+        self.selected = 0;
+    }
+
+    /// The screen, `width` columns by `height` rows: the query, a status line, the matches and
+    /// the context of the one selected.
+    fn render(&self, files: usize, width: usize, height: usize) -> String {
+        let fit = |line: &str| line.chars().take(width).collect::<String>();
+        let mut screen = String::from("\x1b[H\x1b[2J");
+        let mut lines = vec![fit(&format!("> {}", self.query))];
+        lines.push(fit(&match &self.error {
+            Some(error) => error.clone(),
+            None => format!("{} of {} files match", self.results.len(), files),
+        }));
+        // half of the rows left for the matches, scrolled to keep the one selected in view
+        let rows = height.saturating_sub(3).div_ceil(2).max(1);
+        let first = (self.selected + 1).saturating_sub(rows);
+        for (i, result) in self.results.iter().enumerate().skip(first).take(rows) {
+            let line = match result.position() {
+                Some(position) => fit(&format!("{}:{}:{}", result.path(), position.line, position.column)),
+                None => fit(result.path()),
+            };
+            // the selected match in reverse video
+            lines.push(if i == self.selected { format!("\x1b[7m{}\x1b[0m", line) } else { line });
+        }
+        lines.resize(rows + 2, String::new());
+        lines.push("─".repeat(width));
+        if let Some(context) = self.results.get(self.selected).and_then(|result| result.context()) {
+            lines.extend(context.lines().take(height.saturating_sub(lines.len())).map(fit));
+        }
+        screen.push_str(&lines.join("\r\n"));
+        // the cursor at the end of the query
+        screen.push_str(&format!("\x1b[1;{}H", (self.query.chars().count() + 3).min(width)));
+        screen
+    }
+}
+
+/// The terminal in raw mode and on its alternate screen, until dropped.
+struct Terminal {
+    tty: File,
+    original: libc::termios,
+}
+
+impl Terminal {
+    fn open() -> io::Result<Self> {
+        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        let mut original = std::mem::MaybeUninit::uninit();
+        // SAFETY: the attributes of a terminal opened above, checked for failure
+        if unsafe { libc::tcgetattr(tty.as_raw_fd(), original.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: initialized by `tcgetattr`
+        let original = unsafe { original.assume_init() };
+        let mut raw = original;
+        // SAFETY: updating attributes gotten above
+        unsafe { libc::cfmakeraw(&mut raw) };
+        // SAFETY: as for `tcgetattr`
+        if unsafe { libc::tcsetattr(tty.as_raw_fd(), libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut terminal = Terminal {
+            tty,
+            original,
+        };
+        terminal.tty.write_all(b"\x1b[?1049h")?;
+        Ok(terminal)
+    }
+
+    /// Columns and rows of the terminal, read again each time it's drawn in case it's resized.
+    fn size(&self) -> (usize, usize) {
+        let mut size = libc::winsize {
+            ws_row: 0,
+            ws_col: 0,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        // SAFETY: TIOCGWINSZ fills in the `winsize` passed
+        match unsafe { libc::ioctl(self.tty.as_raw_fd(), libc::TIOCGWINSZ, &mut size) } {
+            0 if size.ws_col > 0 && size.ws_row > 0 => (size.ws_col as usize, size.ws_row as usize),
+            _ => (80, 24),
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.tty.write_all(b"\x1b[?1049l");
+        // SAFETY: restoring the attributes gotten by `open`
+        unsafe {
+            libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSAFLUSH, &self.original);
+        }
+    }
+}
+
+/// Search the `files` of the corpus of `session` interactively until a match is chosen with
+/// Enter, returning it, or the search is left with Esc or Ctrl-C.
+pub fn run(mut session: TypeAhead, files: usize) -> io::Result<Option<FileMatchResult>> {
+    let mut terminal = Terminal::open()?;
+    let mut state = State::default();
+    let mut buffer = [0; 64];
+    loop {
+        let (width, height) = terminal.size();
+        terminal.tty.write_all(state.render(files, width, height).as_bytes())?;
+        terminal.tty.flush()?;
+        let read = terminal.tty.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        let mut changed = false;
+        for key in parse_keys(&buffer[..read]) {
+            match key {
+                Key::Quit => return Ok(None),
+                Key::Enter => return Ok(state.results.get(state.selected).cloned()),
+                key => changed |= state.press(key),
+            }
+        }
+        // the keys read at once, e.g. pasted, searched for together
+        if changed {
+            state.search(&mut session);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use textsearcher::corpus::Corpus;
+    use textsearcher::typeahead::TypeAhead;
+    use textsearcher::{QueryOptions, SearchOptions};
+    use super::{parse_keys, Key, State};
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(parse_keys(b"h\xc3\xa9\x7f\x1b[A\x1b[1;5B\x1b[C\r"), vec![Key::Char('h'), Key::Char('é'), Key::Backspace, Key::Up, Key::Down, Key::Enter]);
+        assert_eq!(parse_keys(b"\x1b"), vec![Key::Quit]);
+        assert_eq!(parse_keys(b"\x15\x03"), vec![Key::Clear, Key::Quit]);
+    }
+
+    #[test]
+    fn test_state() {
+        let paths = vec![String::from("sample_texts/hello.txt"), String::from("sample_texts/world.txt")];
+        let mut session = TypeAhead::new(Arc::new(Corpus::new(paths, true)), QueryOptions::default(), SearchOptions::default());
+        let mut state = State::default();
+        for key in parse_keys(b"o") {
+            state.press(key);
+        }
+        state.search(&mut session);
+        assert_eq!(state.results.len(), 2);
+        assert!(!state.press(Key::Down));
+        assert_eq!(state.selected, 1);
+        assert!(!state.press(Key::Down));
+        assert_eq!(state.selected, 1);
+        let screen = state.render(2, 40, 10);
+        assert!(screen.contains("> o\r\n2 of 2 files match\r\nsample_texts/hello.txt:1:"));
+        assert!(screen.contains("\x1b[7msample_texts/world.txt:"));
+        assert!(state.press(Key::Clear) && !state.press(Key::Clear));
+        for key in parse_keys(b"wo") {
+            state.press(key);
+        }
+        state.search(&mut session);
+        assert_eq!((state.results.len(), state.selected), (1, 0));
+        assert_eq!(state.results[0].path(), "sample_texts/world.txt");
+    }
+}
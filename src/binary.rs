@@ -0,0 +1,111 @@
+//! Binary files, e.g. images or executables among documents, told from text like ripgrep does:
+//! by a NUL byte near their start, which text doesn't contain.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+/// Bytes at the start of a file where a NUL byte makes it binary.
+const SNIFF_BYTES: usize = 8 << 10;
+
+/// What is done with binary files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BinaryPolicy {
+    /// Skip them, reported as skipped for being `binary`.
+    #[default]
+    Skip,
+    /// Search them as text, the bytes that aren't valid UTF-8 replaced.
+    SearchAsLossyText,
+    /// Skip them like `Skip`, then fail searches from Python, naming them.
+    Error,
+}
+
+impl BinaryPolicy {
+    pub fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "skip" => Ok(BinaryPolicy::Skip),
+            "text" => Ok(BinaryPolicy::SearchAsLossyText),
+            "error" => Ok(BinaryPolicy::Error),
+            _ => Err(PyValueError::new_err(format!("unknown binary policy {:?}, expected \"skip\", \"text\" or \"error\"", name))),
+        }
+    }
+}
+
+/// The error binary files are skipped with.
+#[derive(Debug)]
+struct BinaryFile;
+
+impl fmt::Display for BinaryFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "binary file")
+    }
+}
+
+impl std::error::Error for BinaryFile {}
+
+/// Whether a file starting with `bytes` is binary.
+pub(crate) fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(SNIFF_BYTES)].contains(&0)
+}
+
+/// Whether a file was skipped with `error` for being binary.
+pub(crate) fn is_binary_error(error: &io::Error) -> bool {
+    error.get_ref().is_some_and(|e| e.is::<BinaryFile>())
+}
+
+/// Fail if the `contents` of a file, valid UTF-8 already, are binary and skipped by `policy`.
+pub(crate) fn check_text(contents: &str, policy: BinaryPolicy) -> io::Result<()> {
+    match is_binary(contents.as_bytes()) && policy != BinaryPolicy::SearchAsLossyText {
+        true => Err(io::Error::new(io::ErrorKind::InvalidData, BinaryFile)),
+        false => Ok(()),
+    }
+}
+
+/// The contents of the file at `path`, `read` as text, unless found binary and skipped by
+/// `policy`. Files that couldn't be decoded are looked at again for a NUL byte.
+pub(crate) fn apply(path: &str, policy: BinaryPolicy, read: io::Result<String>) -> io::Result<String> {
+    let binary = match &read {
+        Ok(contents) => is_binary(contents.as_bytes()),
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            let mut head = Vec::new();
+            fs::File::open(path)?.take(SNIFF_BYTES as u64).read_to_end(&mut head)?;
+            is_binary(&head)
+        }
+        Err(_) => false,
+    };
+    match (binary, policy, read) {
+        (false, _, read) | (true, BinaryPolicy::SearchAsLossyText, read @ Ok(_)) => read,
+        (true, BinaryPolicy::SearchAsLossyText, Err(_)) => Ok(String::from_utf8_lossy(&fs::read(path)?).into_owned()),
+        (true, BinaryPolicy::Skip | BinaryPolicy::Error, _) => Err(io::Error::new(io::ErrorKind::InvalidData, BinaryFile)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::{search_text_report, QueryGroup, SearchOptions};
+    use super::{is_binary, BinaryPolicy};
+
+    #[test]
+    fn test_binary() {
+        assert!(is_binary(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        assert!(!is_binary("foo bar".as_bytes()));
+        let path = std::env::temp_dir().join(format!("textsearcher-binary-{}.bin", std::process::id()));
+        fs::write(&path, b"\x7fELF\x02\x01\x01\0\0\xff\xfe foo").unwrap();
+        let paths = vec![path.to_str().unwrap().to_string(), "sample_texts/hello.txt".to_string()];
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string()]]).unwrap();
+        let search = |binary: BinaryPolicy| {
+            let options = SearchOptions {
+                binary,
+                ..Default::default()
+            };
+            let (results, report) = search_text_report(&query_group, &paths, &options, |_| ());
+            (results.len(), report.files_skipped.get("binary").copied())
+        };
+        assert_eq!(search(BinaryPolicy::Skip), (1, Some(1)));
+        assert_eq!(search(BinaryPolicy::SearchAsLossyText), (2, None));
+        fs::remove_file(&path).unwrap();
+    }
+}
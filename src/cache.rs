@@ -0,0 +1,315 @@
+//! Persistent cache of per-file match outcomes, so that re-running a query only evaluates new
+//! and changed files.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+use pyo3::exceptions::PyIOError;
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::binary::BinaryPolicy;
+use crate::score::MatchStats;
+use crate::{metrics, MatchPosition, QueryGroup, SearchOptions};
+
+/// Size and modification time of a file; a file whose fingerprint changed is re-evaluated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub size: u64,
+    pub mtime_ns: u128,
+}
+
+impl Fingerprint {
+    pub fn of(path: &str) -> io::Result<Self> {
+        Self::of_metadata(&fs::metadata(path)?)
+    }
+
+    pub(crate) fn of_metadata(metadata: &fs::Metadata) -> io::Result<Self> {
+        let mtime_ns = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        Ok(Fingerprint {
+            size: metadata.len(),
+            mtime_ns,
+        })
+    }
+}
+
+/// What a search concluded about a file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Outcome {
+    pub matched: bool,
+    pub context: Option<String>,
+    /// Recorded when ranking the results.
+    #[serde(default)]
+    pub stats: Option<MatchStats>,
+    /// Recorded when detecting the language of the files.
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Whether the context stops short of the start and of the end of the file.
+    #[serde(default)]
+    pub truncated: (bool, bool),
+    /// Where the file first matches.
+    #[serde(default)]
+    pub position: Option<MatchPosition>,
+    /// The contexts of all the matches, when extracted with `max_contexts`.
+    #[serde(default)]
+    pub contexts: Vec<String>,
+    /// The contexts of the first match of each AND group, when extracted with `clause_contexts`.
+    #[serde(default)]
+    pub clause_contexts: Vec<(usize, String)>,
+    /// Where the context highlights the matches.
+    #[serde(default)]
+    pub highlights: Vec<(usize, usize)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    query: u64,
+    path: String,
+    fingerprint: Fingerprint,
+    outcome: Outcome,
+}
+
+#[derive(Debug, Default)]
+struct Store {
+    entries: HashMap<(u64, String), (Fingerprint, Outcome)>,
+    dirty: bool,
+}
+
+/// (query hash, file fingerprint) -> match outcome, persisted as JSON lines at `path`.
+/// Clones share the same store.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct ResultCache {
+    path: PathBuf,
+    store: Arc<Mutex<Store>>,
+}
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` is stable across builds.
+pub(crate) fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Hash of everything that determines the outcome of matching a file's contents.
+pub fn query_key(query_group: &QueryGroup, options: &SearchOptions) -> u64 {
+    let mut hash = 0xcbf29ce484222325;
+    for pat in query_group.patterns.iter() {
+        hash = fnv1a(hash, pat.as_str().as_bytes());
+        hash = fnv1a(hash, &[0]);
+    }
+    for pat in query_group.excluded.iter() {
+        hash = fnv1a(hash, &[4]);
+        hash = fnv1a(hash, pat.as_str().as_bytes());
+    }
+    if let Some(within) = query_group.within {
+        hash = fnv1a(hash, &[5]);
+        hash = fnv1a(hash, &within.to_le_bytes());
+    }
+    if let Some(lines) = query_group.within_lines {
+        hash = fnv1a(hash, &[15]);
+        hash = fnv1a(hash, &lines.to_le_bytes());
+    }
+    if let Some(apart) = query_group.apart {
+        hash = fnv1a(hash, &[6]);
+        hash = fnv1a(hash, &apart.to_le_bytes());
+    }
+    if query_group.minimum_should_match.iter().any(|&n| n > 1) {
+        hash = fnv1a(hash, &[8]);
+        for n in query_group.minimum_should_match.iter() {
+            hash = fnv1a(hash, &n.to_le_bytes());
+        }
+    }
+    if query_group.is_weighted() {
+        hash = fnv1a(hash, &[9]);
+        for weight in query_group.atom_weights.iter().flatten().chain(query_group.group_boosts.iter()) {
+            hash = fnv1a(hash, &weight.to_le_bytes());
+        }
+    }
+    if let Some(context) = &options.context {
+        hash = fnv1a(hash, format!("{:?}", context).as_bytes());
+    }
+    if options.scoring.is_some() {
+        hash = fnv1a(hash, &[2]);
+    }
+    if options.invert {
+        hash = fnv1a(hash, &[3]);
+    }
+    if options.detect_language {
+        hash = fnv1a(hash, &[7]);
+    }
+    if let Some(head_bytes) = options.head_bytes {
+        hash = fnv1a(hash, &[10]);
+        hash = fnv1a(hash, &head_bytes.to_le_bytes());
+    }
+    if let Some(tail_bytes) = options.tail_bytes {
+        hash = fnv1a(hash, &[13]);
+        hash = fnv1a(hash, &tail_bytes.to_le_bytes());
+    }
+    if let Some(ranges) = &options.ranges {
+        hash = fnv1a(hash, &[11]);
+        hash = fnv1a(hash, format!("{:?}{:?}", ranges.unit, ranges.sorted()).as_bytes());
+    }
+    if let Some(code) = options.code {
+        hash = fnv1a(hash, &[12]);
+        hash = fnv1a(hash, format!("{:?}", code).as_bytes());
+    }
+    if let Some(chunks) = &options.chunked {
+        hash = fnv1a(hash, &[14]);
+        hash = fnv1a(hash, format!("{:?}", chunks).as_bytes());
+    }
+    if options.decompress {
+        hash = fnv1a(hash, &[16]);
+    }
+    if options.binary == BinaryPolicy::SearchAsLossyText {
+        hash = fnv1a(hash, &[18]);
+    }
+    if options.binary == BinaryPolicy::Error {
+        hash = fnv1a(hash, &[20]);
+    }
+    if let Some(hints) = options.encoding_hints.key() {
+        hash = fnv1a(hash, &[21]);
+        hash = fnv1a(hash, hints.as_bytes());
+    }
+    if let Some(max_file_size) = options.max_file_size {
+        hash = fnv1a(hash, &[19]);
+        hash = fnv1a(hash, &max_file_size.to_le_bytes());
+    }
+    for normalizer in options.normalizers.iter() {
+        hash = fnv1a(hash, &[1]);
+        hash = fnv1a(hash, normalizer.name().as_bytes());
+    }
+    hash
+}
+
+impl ResultCache {
+    /// Open the cache at `path`, loading its records if the file exists.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut store = Store::default();
+        match fs::File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    // skip corrupted records rather than failing; they're simply re-evaluated
+                    if let Ok(record) = serde_json::from_str::<Record>(&line?) {
+                        store.entries.insert((record.query, record.path), (record.fingerprint, record.outcome));
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e),
+        }
+        Ok(ResultCache {
+            path,
+            store: Arc::new(Mutex::new(store)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The cached outcome, if the file hasn't changed since, counted as a hit or a miss in the
+    /// metrics.
+    pub fn get(&self, query: u64, path: &str, fingerprint: Fingerprint) -> Option<Outcome> {
+        let store = self.store.lock().unwrap();
+        let outcome = match store.entries.get(&(query, path.to_string())) {
+            Some((cached, outcome)) if *cached == fingerprint => Some(outcome.clone()),
+            _ => None,
+        };
+        metrics::record_cache_lookup(outcome.is_some());
+        outcome
+    }
+
+    pub fn insert(&self, query: u64, path: &str, fingerprint: Fingerprint, outcome: Outcome) {
+        let mut store = self.store.lock().unwrap();
+        store.entries.insert((query, path.to_string()), (fingerprint, outcome));
+        store.dirty = true;
+    }
+
+    /// Forget every outcome of `path`.
+    pub fn invalidate(&self, path: &str) {
+        let mut store = self.store.lock().unwrap();
+        let before = store.entries.len();
+        store.entries.retain(|(_, p), _| p != path);
+        store.dirty |= store.entries.len() != before;
+    }
+
+    pub fn clear(&self) {
+        let mut store = self.store.lock().unwrap();
+        store.dirty |= !store.entries.is_empty();
+        store.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the records to disk if anything changed, replacing the file atomically.
+    pub fn save(&self) -> io::Result<()> {
+        let mut store = self.store.lock().unwrap();
+        if !store.dirty {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+            for ((query, path), (fingerprint, outcome)) in store.entries.iter() {
+                let record = Record {
+                    query: *query,
+                    path: path.clone(),
+                    fingerprint: *fingerprint,
+                    outcome: outcome.clone(),
+                };
+                serde_json::to_writer(&mut writer, &record)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        store.dirty = false;
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl ResultCache {
+    #[new]
+    fn py_new(path: &str) -> PyResult<Self> {
+        ResultCache::open(path).map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    #[getter(path)]
+    fn py_path(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
+
+    #[pyo3(name = "invalidate")]
+    fn py_invalidate(&self, path: &str) {
+        self.invalidate(path)
+    }
+
+    #[pyo3(name = "clear")]
+    fn py_clear(&self) {
+        self.clear()
+    }
+
+    #[pyo3(name = "save")]
+    fn py_save(&self) -> PyResult<()> {
+        self.save().map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    fn __len__(&self) -> usize {
+        self.len()
+    }
+}
@@ -0,0 +1,193 @@
+//! Searches that record their progress in a file, so that an interrupted scan of a large corpus
+//! resumes where it stopped instead of starting over.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::cache::{self, fnv1a};
+use crate::progress::CancelToken;
+use crate::report::SearchReport;
+use crate::tags::TagFilter;
+use crate::{file_entries, paths, scan_entries, score, Entry, FileMatchResult, FilePaths, QueryGroup, SearchOptions};
+
+/// A line of a checkpoint file, which is made of a header, then for each chunk of files
+/// searched, their matches followed by the number of files searched so far.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Line {
+    Header { search: u64 },
+    Result(Box<FileMatchResult>),
+    Done(usize),
+}
+
+/// Identifies a search: the query, the options that change its results and the files searched.
+fn search_key(query_group: &QueryGroup, entries: &[Entry<'_>], options: &SearchOptions) -> u64 {
+    let mut hash = cache::query_key(query_group, options);
+    for (path, _) in entries.iter() {
+        hash = fnv1a(hash, path.as_bytes());
+        hash = fnv1a(hash, &[0]);
+    }
+    hash
+}
+
+/// The matches and number of files, counted from the first, that the checkpoint at `path`
+/// records as searched, or nothing if there's no checkpoint yet. Matches of a chunk whose end
+/// wasn't recorded are dropped, since the chunk is searched again.
+fn load(path: &Path, search: u64) -> io::Result<(Vec<FileMatchResult>, usize)> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+        Err(e) => return Err(e),
+    };
+    let mut lines = BufReader::new(file).lines();
+    match lines.next().transpose()?.map(|line| serde_json::from_str(&line)).transpose()? {
+        None => return Ok((Vec::new(), 0)),
+        Some(Line::Header { search: recorded }) if recorded == search => (),
+        Some(_) => {
+            let message = format!("{} is the checkpoint of another search", path.display());
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+    }
+    let (mut results, mut pending, mut done) = (Vec::new(), Vec::new(), 0);
+    for line in lines {
+        // a line cut short by the interruption ends the checkpoint
+        match serde_json::from_str(&line?) {
+            Ok(Line::Result(result)) => pending.push(*result),
+            Ok(Line::Done(n)) => {
+                results.append(&mut pending);
+                done = n;
+            }
+            Ok(Line::Header { .. }) | Err(_) => break,
+        }
+    }
+    Ok((results, done))
+}
+
+fn write_line<W: Write>(writer: &mut W, line: &Line) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, line)?;
+    writer.write_all(b"\n")
+}
+
+/// Like `search_files_report`, but recording the matches to the checkpoint file at `path` every
+/// `every` files, and resuming from it if it exists. The checkpoint is removed once the search
+/// completes; a search cancelled meanwhile fails, keeping the checkpoint to resume from. The
+/// report only counts the files searched in this run, but `files_total` and
+/// `files_matched`, and `on_result` is only called with the matches found in this run.
+pub fn search_files_checkpointed<F>(query_group: &QueryGroup, files: &FilePaths, filter: &TagFilter, options: &SearchOptions, path: &Path, every: usize, on_result: F) -> io::Result<(Vec<FileMatchResult>, SearchReport)>
+where
+    F: Fn(&FileMatchResult) + Sync,
+{
+    if options.deadline.is_some() || options.max_results.is_some() {
+        // the files skipped once it passes, or once enough matched, would be recorded as searched
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "a deadline or max_results can't be combined with a checkpoint"));
+    }
+    let entries = file_entries(files, filter);
+    // deduplicated here, so that the files done count those searched
+    let (entries, collapsed) = match options.dedup_paths {
+        Some(policy) => paths::dedup_entries(&entries, policy, options.parallel),
+        None => (entries, 0),
+    };
+    let search = search_key(query_group, &entries, options);
+    let (mut results, done) = load(path, search)?;
+    // rewrite the checkpoint without the matches of an unfinished chunk, replacing it atomically
+    // so that an interruption meanwhile keeps it, then append to it
+    let tmp_path = path.with_extension("tmp");
+    let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+    write_line(&mut writer, &Line::Header { search })?;
+    for result in results.iter() {
+        write_line(&mut writer, &Line::Result(Box::new(result.clone())))?;
+    }
+    write_line(&mut writer, &Line::Done(done))?;
+    writer.flush()?;
+    fs::rename(&tmp_path, path)?;
+    let every = every.max(1);
+    let remaining = &entries[done.min(entries.len())..];
+    let (mut searched, mut error, mut stopped) = (done, None, false);
+    let mut report = scan_entries(query_group, remaining, options, every, on_result, |chunk| {
+        searched = (searched + every).min(entries.len());
+        // the files of a chunk may have been skipped once cancelled, so it's searched again
+        stopped |= options.cancel.as_ref().is_some_and(CancelToken::is_cancelled);
+        if error.is_none() && !stopped {
+            let written = chunk
+                .iter()
+                .try_for_each(|result| write_line(&mut writer, &Line::Result(Box::new(result.clone()))))
+                .and_then(|_| write_line(&mut writer, &Line::Done(searched)))
+                .and_then(|_| writer.flush());
+            error = written.err();
+        }
+        results.extend(chunk);
+    });
+    if let Some(e) = error {
+        return Err(e);
+    }
+    if report.cancelled || report.timed_out {
+        let message = format!("the search was stopped before all the files were searched, its progress kept in {}", path.display());
+        return Err(io::Error::new(io::ErrorKind::Interrupted, message));
+    }
+    drop(writer);
+    fs::remove_file(path)?;
+    report.files_total = entries.len();
+    report.paths_collapsed = collapsed;
+    report.files_matched = results.len();
+    if let Some(scoring) = &options.scoring {
+        score::rank(&mut results, scoring.top_k);
+    }
+    Ok((results, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::progress::CancelToken;
+    use crate::{FilePaths, QueryGroup, SearchOptions};
+    use crate::tags::TagFilter;
+    use super::{search_files_checkpointed, search_key};
+
+    #[test]
+    fn test_resume() {
+        let path = std::env::temp_dir().join(format!("textsearcher-checkpoint-{}.jsonl", std::process::id()));
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()]]).unwrap();
+        let files = FilePaths::new(vec![
+            "sample_texts/hello.txt".to_string(),
+            "sample_texts/world.txt".to_string(),
+            "sample_texts/hello.txt".to_string(),
+        ]);
+        let options = SearchOptions::default();
+        let entries = crate::file_entries(&files, &TagFilter::default());
+        // an interrupted run: the first file searched, and part of the next chunk written
+        let search = search_key(&query_group, &entries, &options);
+        fs::write(&path, format!(
+            "{{\"header\":{{\"search\":{}}}}}\n{{\"result\":{{\"path\":\"sample_texts/hello.txt\",\"context\":null}}}}\n{{\"done\":1}}\n{{\"result\":{{\"path\":\"sample_texts/wor",
+            search,
+        )).unwrap();
+        let (results, report) = search_files_checkpointed(&query_group, &files, &TagFilter::default(), &options, &path, 1, |_| ()).unwrap();
+        let paths: Vec<_> = results.iter().map(|r| r.path()).collect();
+        assert_eq!(paths, vec!["sample_texts/hello.txt", "sample_texts/world.txt", "sample_texts/hello.txt"]);
+        assert_eq!((report.files_total, report.files_scanned, report.files_matched), (3, 2, 3));
+        assert!(!path.exists());
+
+        fs::write(&path, "{\"header\":{\"search\":0}}\n").unwrap();
+        assert!(search_files_checkpointed(&query_group, &files, &TagFilter::default(), &options, &path, 1, |_| ()).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cancel() {
+        let path = std::env::temp_dir().join(format!("textsearcher-checkpoint-cancel-{}.jsonl", std::process::id()));
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()]]).unwrap();
+        let files = FilePaths::new(vec!["sample_texts/hello.txt".to_string(), "sample_texts/world.txt".to_string()]);
+        let cancel = CancelToken::new();
+        let options = SearchOptions {
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        };
+        // cancelled once the first file matched: nothing recorded as searched, the checkpoint kept
+        let searched = search_files_checkpointed(&query_group, &files, &TagFilter::default(), &options, &path, 1, |_| cancel.cancel());
+        assert_eq!(searched.unwrap_err().kind(), std::io::ErrorKind::Interrupted);
+        assert!(fs::read_to_string(&path).unwrap().ends_with("{\"done\":0}\n"));
+        let (results, report) = search_files_checkpointed(&query_group, &files, &TagFilter::default(), &SearchOptions::default(), &path, 1, |_| ()).unwrap();
+        assert_eq!((results.len(), report.files_scanned), (2, 2));
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,212 @@
+//! Searches of files too large to hold in memory, e.g. logs of several gigabytes, read and
+//! matched a chunk at a time. Each chunk is matched along with the end of the previous one, so
+//! that matches no longer than that overlap are found across chunk boundaries.
+
+use std::fs;
+use std::io::{self, Read};
+use std::time::Instant;
+use encoding_rs::{Decoder, DecoderResult};
+use crate::report::ScanStats;
+use crate::tags::{TagValue, Tags};
+use crate::{check_file_size, check_file_time, context_range, context_result, shift_highlights, encoding, language, normalize, FileMatchResult, Matched, MatchPosition, QueryGroup, SearchOptions};
+
+/// How the files are read in chunks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkOptions {
+    /// Bytes read from the file at a time.
+    pub chunk_bytes: usize,
+    /// Bytes of the decoded contents of a chunk matched again with the next one, at least as
+    /// many as the longest match, and the widest context, expected.
+    pub overlap: usize,
+}
+
+/// Decode the next `bytes` of a file, failing on invalid UTF-8 if `utf8` is set, like
+/// `encoding::read_to_string` does.
+fn decode_chunk(decoder: &mut Decoder, bytes: &[u8], last: bool, utf8: bool) -> io::Result<String> {
+    let mut text = String::with_capacity(decoder.max_utf8_buffer_length(bytes.len()).unwrap_or(bytes.len() * 3 + 4));
+    if utf8 {
+        if let (DecoderResult::Malformed(..), _) = decoder.decode_to_string_without_replacement(bytes, &mut text, last) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8"));
+        }
+    } else {
+        let _ = decoder.decode_to_string(bytes, &mut text, last);
+    }
+    Ok(text)
+}
+
+/// Match the file at `path` a chunk at a time, normalizing each chunk on its own. Every AND
+/// group must match in some chunk and no excluded one in any, while the `within`, `within_lines`,
+/// `apart` and `minimum_should_match` constraints must hold within a chunk and its overlap. The context and
+/// position are those of the first match of the first AND group, and the other contexts those
+/// in its chunk; the position counts from the start of the file. The results aren't scored, and
+/// scoped contexts are extracted like others.
+pub(crate) fn match_chunked(query_group: &QueryGroup, path: &str, options: &SearchOptions, chunks: &ChunkOptions, stats: &ScanStats, started: Instant) -> io::Result<Option<Matched>> {
+    check_file_size(path, options)?;
+    let _permit = options.read_limit.as_ref().map(|limit| limit.acquire());
+    let mut file = fs::File::open(path)?;
+    let mut decoder = None;
+    let (chunk_bytes, mut bytes, mut read) = (chunks.chunk_bytes.max(1), Vec::new(), 0);
+    let mut window = String::new();
+    // where the window starts in the contents: bytes, lines and characters of its first line
+    let (mut offset, mut lines, mut line_chars) = (0, 0, 0);
+    let mut language = None;
+    let mut query_group = query_group;
+    let mut found = vec![false; query_group.patterns.len()];
+    let (mut placed, mut excluded, mut first) = (false, false, None);
+    loop {
+        bytes.clear();
+        (&mut file).take(chunk_bytes as u64).read_to_end(&mut bytes)?;
+        let last = bytes.len() < chunk_bytes;
+        read += bytes.len();
+        if decoder.is_none() {
+            decoder = Some(encoding::stream_decoder(path, &bytes, &options.encoding_hints)?);
+        }
+        let (decoder, utf8) = decoder.as_mut().unwrap();
+        let chunk = decode_chunk(decoder, &bytes, last, *utf8)?;
+        window.push_str(&normalize::normalize(&options.normalizers, &chunk));
+        if options.detect_language && language.is_none() {
+            let detected = language::detect(&window);
+            query_group = query_group.for_language(&detected);
+            language = Some(detected);
+        }
+        for (found, pat) in found.iter_mut().zip(query_group.patterns.iter()) {
+            *found = *found || pat.is_match(&window);
+        }
+        excluded = excluded || query_group.is_excluded(&window);
+        placed = placed || (query_group.is_placed(&window) && query_group.has_enough_alternatives(&window));
+        if first.is_none() && found[0] {
+            first = query_group.patterns[0].find(&window).map(|m| {
+                let mut result = match &options.context {
+                    None => FileMatchResult {
+                        path: String::from(path),
+                        context: None,
+                        tags: Tags::new(),
+                        score: None,
+                        truncated_before: false,
+                        truncated_after: false,
+                        position: Some(MatchPosition::of(&window, m.start(), m.end())),
+                        contexts: Vec::new(),
+                        clause_contexts: Vec::new(),
+                        highlights: Vec::new(),
+                    },
+                    Some(context) => {
+                        let (start, end) = context_range(&window, m.start(), m.end(), context);
+                        let mut result = context_result(query_group, path, &window, (m.start(), m.end()), (start, end), context);
+                        if let Some(text) = result.context.as_mut().filter(|_| context.ellipsis || context.snippet) {
+                            if offset > 0 && !result.truncated_before {
+                                text.insert(0, '…');
+                                shift_highlights(&mut result.highlights, 1);
+                            }
+                            if !last && !result.truncated_after {
+                                text.push('…');
+                            }
+                        }
+                        result.truncated_before |= offset > 0;
+                        result.truncated_after |= !last;
+                        result
+                    }
+                };
+                if let Some(position) = result.position.as_mut() {
+                    if position.line == 1 {
+                        position.column += line_chars;
+                    }
+                    position.line += lines;
+                    position.span = (position.span.0 + offset, position.span.1 + offset);
+                }
+                result
+            });
+        }
+        check_file_time(path, started, options)?;
+        // nothing left to learn, unless another chunk could rule the file out
+        let matched = found.iter().all(|&found| found) && placed;
+        if last || excluded || (matched && query_group.excluded.is_empty()) {
+            break;
+        }
+        if options.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            stats.record_timeout();
+            return Ok(None);
+        }
+        let mut cut = window.len().saturating_sub(chunks.overlap);
+        while !window.is_char_boundary(cut) {
+            cut += 1;
+        }
+        let dropped = &window[..cut];
+        let newlines = dropped.matches('\n').count();
+        line_chars = match dropped.rfind('\n') {
+            Some(i) => dropped[i + 1..].chars().count(),
+            None => line_chars + dropped.chars().count(),
+        };
+        lines += newlines;
+        offset += cut;
+        window.drain(..cut);
+    }
+    stats.record_read(read);
+    let matched = found.iter().all(|&found| found) && placed && !excluded;
+    let result = if options.invert {
+        (!matched).then(|| FileMatchResult {
+            path: String::from(path),
+            context: None,
+            tags: Tags::new(),
+            score: None,
+            truncated_before: false,
+            truncated_after: false,
+            position: None,
+            contexts: Vec::new(),
+            clause_contexts: Vec::new(),
+            highlights: Vec::new(),
+        })
+    } else {
+        first.filter(|_| matched)
+    };
+    Ok(result.map(|mut result| {
+        if let Some(language) = language {
+            result.tags.insert("language".to_string(), TagValue::Str(language));
+        }
+        (result, None)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Instant;
+    use crate::report::ScanStats;
+    use crate::{ContextOptions, MatchPosition, QueryGroup, SearchOptions};
+    use super::{match_chunked, ChunkOptions};
+
+    #[test]
+    fn test_match_chunked() {
+        let path = std::env::temp_dir().join(format!("textsearcher-chunked-{}.txt", std::process::id()));
+        fs::write(&path, "first line\nsecond line, the needle é\nthird haystack line\n").unwrap();
+        let path = path.to_str().unwrap();
+        let chunks = ChunkOptions {
+            chunk_bytes: 8,
+            overlap: 16,
+        };
+        let stats = ScanStats::default();
+        let matched = |atoms: &[&str], options: &SearchOptions| {
+            let query_group = QueryGroup::new(atoms.iter().map(|atom| vec![atom.to_string()]).collect()).unwrap();
+            match_chunked(&query_group, path, options, &chunks, &stats, Instant::now()).unwrap().map(|(result, _)| result)
+        };
+        let result = matched(&["needle", "haystack"], &SearchOptions::default()).unwrap();
+        let expected = MatchPosition {
+            line: 2,
+            column: 18,
+            span: (28, 34),
+        };
+        assert_eq!(result.position(), Some(expected));
+        assert!(matched(&["needle", "missing"], &SearchOptions::default()).is_none());
+        let context = SearchOptions {
+            context: Some(ContextOptions {
+                before: 4,
+                after: 2,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let result = matched(&["line, the"], &context).unwrap();
+        assert_eq!(result.context(), Some("ond line, the n"));
+        assert_eq!(result.truncated(), (true, true));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,213 @@
+//! Searches of source code restricted to its comments and string literals, or to the code
+//! without them, so that natural-language queries don't match identifiers, found with
+//! lightweight lexers picked by the extension of each file.
+
+use std::ops::Range;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+/// Which parts of source files are matched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodeMode {
+    /// Comments and string literals.
+    Prose,
+    Comments,
+    Strings,
+    /// Everything but comments and string literals.
+    Code,
+}
+
+impl CodeMode {
+    pub fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "prose" => Ok(CodeMode::Prose),
+            "comments" => Ok(CodeMode::Comments),
+            "strings" => Ok(CodeMode::Strings),
+            "code" => Ok(CodeMode::Code),
+            _ => Err(PyValueError::new_err(format!("unknown code mode {:?}, expected \"prose\", \"comments\", \"strings\" or \"code\"", name))),
+        }
+    }
+
+    fn keeps(self, part: Part) -> bool {
+        matches!(
+            (self, part),
+            (CodeMode::Prose, Part::Comment | Part::String)
+                | (CodeMode::Comments, Part::Comment)
+                | (CodeMode::Strings, Part::String)
+                | (CodeMode::Code, Part::Code)
+        )
+    }
+}
+
+/// How comments and string literals are written in a language. Strings end at their closing
+/// quote, unescaped by a backslash, or at the end of the line unless their quote is longer than a
+/// character, like Python's `"""`.
+struct Syntax {
+    line_comments: &'static [&'static str],
+    block_comments: &'static [(&'static str, &'static str)],
+    /// Longest first, so that `"""` is tried before `"`.
+    quotes: &'static [&'static str],
+}
+
+const C_LIKE: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    quotes: &["\"", "'", "`"],
+};
+
+// single quotes are lifetimes as much as characters
+const RUST: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    quotes: &["\""],
+};
+
+const CSS: Syntax = Syntax {
+    line_comments: &[],
+    block_comments: &[("/*", "*/")],
+    quotes: &["\"", "'"],
+};
+
+const PYTHON: Syntax = Syntax {
+    line_comments: &["#"],
+    block_comments: &[],
+    quotes: &["\"\"\"", "'''", "\"", "'"],
+};
+
+const HASH: Syntax = Syntax {
+    line_comments: &["#"],
+    block_comments: &[],
+    quotes: &["\"", "'"],
+};
+
+const SQL: Syntax = Syntax {
+    line_comments: &["--"],
+    block_comments: &[("/*", "*/")],
+    quotes: &["'", "\""],
+};
+
+const LUA: Syntax = Syntax {
+    line_comments: &["--"],
+    block_comments: &[("--[[", "]]")],
+    quotes: &["\"", "'"],
+};
+
+const MARKUP: Syntax = Syntax {
+    line_comments: &[],
+    block_comments: &[("<!--", "-->")],
+    quotes: &[],
+};
+
+/// The syntax of the language of `path`, by its extension.
+fn syntax_for(path: &str) -> Option<&'static Syntax> {
+    let extension = path.rsplit_once('.')?.1.to_ascii_lowercase();
+    let syntax = match extension.as_str() {
+        "rs" => &RUST,
+        "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" | "java" | "js" | "jsx" | "mjs" | "ts" | "tsx" | "go" | "cs" | "swift" | "kt" | "scala" | "dart" | "php" => &C_LIKE,
+        "css" | "scss" | "less" => &CSS,
+        "py" | "pyi" => &PYTHON,
+        "rb" | "sh" | "bash" | "zsh" | "pl" | "r" | "yaml" | "yml" | "toml" => &HASH,
+        "sql" => &SQL,
+        "lua" => &LUA,
+        "html" | "htm" | "xml" | "svg" => &MARKUP,
+        _ => return None,
+    };
+    Some(syntax)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Part {
+    Code,
+    Comment,
+    String,
+}
+
+/// The byte ranges of the parts of `contents`, in order. The delimiters of comments and strings
+/// belong to no part.
+fn lex(syntax: &Syntax, contents: &str) -> Vec<(Part, Range<usize>)> {
+    let mut parts = Vec::new();
+    let (mut code_start, mut i) = (0, 0);
+    while i < contents.len() {
+        let rest = &contents[i..];
+        let (part, open, close, escapes) = if let Some(&(open, close)) = syntax.block_comments.iter().find(|(open, _)| rest.starts_with(open)) {
+            (Part::Comment, open, close, false)
+        } else if let Some(open) = syntax.line_comments.iter().find(|open| rest.starts_with(*open)) {
+            (Part::Comment, *open, "\n", false)
+        } else if let Some(quote) = syntax.quotes.iter().find(|quote| rest.starts_with(*quote)) {
+            (Part::String, *quote, *quote, true)
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+            continue;
+        };
+        parts.push((Part::Code, code_start..i));
+        let start = i + open.len();
+        let mut end = start;
+        let mut chars = contents[start..].char_indices();
+        let closed = loop {
+            let Some((offset, c)) = chars.next() else {
+                break None;
+            };
+            end = start + offset;
+            if contents[end..].starts_with(close) {
+                break Some(close.len());
+            } else if escapes && c == '\\' {
+                chars.next();
+            } else if part == Part::String && close.len() == 1 && c == '\n' {
+                break Some(0);
+            }
+        };
+        let (end, close_len) = match closed {
+            Some(close_len) => (end, close_len),
+            None => (contents.len(), 0),
+        };
+        parts.push((part, start..end));
+        // the newline ending a line comment is code again
+        i = if close == "\n" { end } else { end + close_len };
+        code_start = i;
+    }
+    parts.push((Part::Code, code_start..contents.len()));
+    parts.retain(|(_, range)| !range.is_empty());
+    parts
+}
+
+/// The parts of `contents` that `mode` keeps, if `path` is in a language it knows, and else all
+/// of it. What's left out is replaced by its line breaks, or else by a space, so that the lines
+/// are kept and no match spans two parts.
+pub fn filter(path: &str, mode: CodeMode, contents: String) -> String {
+    let Some(syntax) = syntax_for(path) else {
+        return contents;
+    };
+    let mut filtered = String::with_capacity(contents.len());
+    let mut kept_end = 0;
+    for (part, range) in lex(syntax, &contents) {
+        if !mode.keeps(part) {
+            continue;
+        }
+        let (gap, kept) = (&contents[kept_end..range.start], &contents[range.clone()]);
+        let newlines = gap.matches('\n').count();
+        if newlines > 0 {
+            filtered.extend(std::iter::repeat_n('\n', newlines));
+        } else if !gap.is_empty() && !filtered.is_empty() && !filtered.ends_with(char::is_whitespace) && !kept.starts_with(char::is_whitespace) {
+            filtered.push(' ');
+        }
+        filtered.push_str(kept);
+        kept_end = range.end;
+    }
+    filtered.extend(std::iter::repeat_n('\n', contents[kept_end..].matches('\n').count()));
+    filtered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{filter, CodeMode};
+
+    #[test]
+    fn test_filter() {
+        let source = "// parse the header\nlet header = parse(\"a \\\"quoted\\\" header\"); /* done */\nx";
+        assert_eq!(filter("a.rs", CodeMode::Comments, source.to_string()), " parse the header\n done \n");
+        assert_eq!(filter("a.rs", CodeMode::Strings, source.to_string()), "\na \\\"quoted\\\" header\n");
+        assert_eq!(filter("a.rs", CodeMode::Code, source.to_string()), "\nlet header = parse( ); \nx");
+        assert_eq!(filter("a.py", CodeMode::Prose, "s = '''doc\nstring''' # it's\n".to_string()), "doc\nstring it's\n");
+        assert_eq!(filter("a.md", CodeMode::Comments, "// kept".to_string()), "// kept");
+    }
+}
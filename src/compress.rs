@@ -0,0 +1,128 @@
+//! Searches of compressed files, e.g. rotated `.log.gz` logs, decompressed on the fly like plain
+//! text. Like `rg --search-zip`, the files are piped through the system's `gzip`, `bzip2`, `xz`
+//! or `zstd`, so that no compression library is linked in.
+
+use std::fs;
+use std::io::{self, Read};
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// A compression format, told from the magic bytes a file starts with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    /// The format of a file starting with `head`, if compressed. Only formats whose magic bytes
+    /// text can't start with are detected, so extensions aren't needed.
+    pub fn detect(head: &[u8]) -> Option<Self> {
+        let bzip2 = head.len() >= 10 && head.starts_with(b"BZh") && (b'1'..=b'9').contains(&head[3]) && &head[4..10] == b"1AY&SY";
+        match head {
+            [0x1f, 0x8b, 0x08, ..] => Some(Compression::Gzip),
+            [0xfd, b'7', b'z', b'X', b'Z', 0x00, ..] => Some(Compression::Xz),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Compression::Zstd),
+            _ if bzip2 => Some(Compression::Bzip2),
+            _ => None,
+        }
+    }
+
+    /// The decompressor writing the contents of a file given after its arguments to stdout.
+    fn command(self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            Compression::Gzip => ("gzip", &["-dc"]),
+            Compression::Bzip2 => ("bzip2", &["-dc"]),
+            Compression::Xz => ("xz", &["-dc"]),
+            Compression::Zstd => ("zstd", &["-dcq"]),
+        }
+    }
+}
+
+/// The format of the file at `path`, if compressed.
+pub(crate) fn compression_of(path: &str) -> io::Result<Option<Compression>> {
+    let mut head = Vec::new();
+    fs::File::open(path)?.take(10).read_to_end(&mut head)?;
+    Ok(Compression::detect(&head))
+}
+
+/// The decompressed bytes of the file at `path`, only the first `max_bytes` if given, or `None`
+/// if it isn't compressed.
+pub(crate) fn read_decompressed(path: &str, max_bytes: Option<u64>) -> io::Result<Option<Vec<u8>>> {
+    let Some(compression) = compression_of(path)? else {
+        return Ok(None);
+    };
+    let (program, args) = compression.command();
+    let mut child = Command::new(program)
+        .args(args)
+        .arg("--")
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("{} is needed to decompress {}: {}", program, path, e)))?;
+    // drained meanwhile, so that a decompressor writing many warnings doesn't block on them
+    let mut stderr = child.stderr.take().unwrap();
+    let warnings = thread::spawn(move || {
+        let mut warnings = Vec::new();
+        let _ = stderr.read_to_end(&mut warnings);
+        warnings
+    });
+    let mut bytes = Vec::new();
+    let read = child.stdout.take().unwrap().take(max_bytes.unwrap_or(u64::MAX)).read_to_end(&mut bytes);
+    let truncated = max_bytes.is_some_and(|max| bytes.len() as u64 >= max);
+    if read.is_err() || truncated {
+        // the rest isn't needed
+        let _ = child.kill();
+    }
+    let status = child.wait()?;
+    let warnings = warnings.join().unwrap_or_default();
+    read?;
+    if !truncated && !status.success() {
+        let message = format!("{} failed to decompress {}: {}", program, path, String::from_utf8_lossy(&warnings).trim());
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    Ok(Some(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::process::Command;
+    use crate::{search_text_with, QueryGroup, SearchOptions};
+    use super::{compression_of, read_decompressed, Compression};
+
+    #[test]
+    fn test_decompress() {
+        assert_eq!(Compression::detect(b"\x1f\x8b\x08\x00"), Some(Compression::Gzip));
+        assert_eq!(Compression::detect(b"BZh91AY&SY"), Some(Compression::Bzip2));
+        assert_eq!(Compression::detect(b"BZh9 is text"), None);
+        assert_eq!(Compression::detect(b"foo bar\n"), None);
+        let path = std::env::temp_dir().join(format!("textsearcher-compress-{}.txt", std::process::id()));
+        fs::write(&path, "foo bar\nbaz\n").unwrap();
+        let status = Command::new("gzip").arg("-f").arg(&path).status().unwrap();
+        assert!(status.success());
+        let path = format!("{}.gz", path.to_str().unwrap());
+        assert_eq!(read_decompressed(&path, None).unwrap().unwrap(), b"foo bar\nbaz\n");
+        assert_eq!(read_decompressed(&path, Some(3)).unwrap().unwrap(), b"foo");
+        assert_eq!(compression_of(&path).unwrap(), Some(Compression::Gzip));
+        assert!(read_decompressed("sample_texts/hello.txt", None).unwrap().is_none());
+        let query_group = QueryGroup::new(vec![vec!["baz".to_string()]]).unwrap();
+        let paths = vec![path.clone()];
+        assert!(search_text_with(&query_group, &paths, &SearchOptions::default()).is_empty());
+        let options = SearchOptions {
+            decompress: true,
+            ..Default::default()
+        };
+        assert_eq!(search_text_with(&query_group, &paths, &options).len(), 1);
+        // the complaints of the decompressor tell why it failed
+        fs::write(&path, b"\x1f\x8b\x08\x00truncated").unwrap();
+        let error = read_decompressed(&path, None).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("gzip failed to decompress"));
+        fs::remove_file(&path).unwrap();
+    }
+}
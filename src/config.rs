@@ -0,0 +1,173 @@
+//! Defaults of the searches, read once from a configuration file and the environment, so that
+//! e.g. the thread count or the encodings of a deployment are set in one place.
+//!
+//! The file is `$TEXTSEARCHER_CONFIG`, or else `textsearcher/config.toml` in `$XDG_CONFIG_HOME`
+//! (by default `~/.config`) if it exists:
+//!
+//! ```toml
+//! threads = 4
+//! max_file_size = 10_000_000
+//! max_concurrent_reads = 8
+//! memory_budget = 1_000_000_000
+//! mmap_threshold = 100_000_000
+//! encodings = [["old_docs/**", "gbk"], ["jp/*.txt", "shift_jis"]]
+//! context_before = 50
+//! context_after = 50
+//! context_chars = true
+//! ```
+//!
+//! Each numeric setting can be overridden by an environment variable named after it, e.g.
+//! `TEXTSEARCHER_THREADS=2`.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use serde::Deserialize;
+use crate::encoding::EncodingHints;
+use crate::throttle::{MemoryBudget, ReadLimit};
+use crate::{ContextOptions, ContextUnit, SearchOptions};
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Number of threads files are searched on, by default one per core.
+    pub threads: Option<usize>,
+    /// Skip files larger than this many bytes.
+    pub max_file_size: Option<u64>,
+    /// Limit how many files are read at once.
+    pub max_concurrent_reads: Option<usize>,
+    /// Hold at most about this many bytes of files in memory at once.
+    pub memory_budget: Option<u64>,
+    /// Map the files at least this many bytes long into memory rather than copying them.
+    pub mmap_threshold: Option<u64>,
+    /// (pattern, encoding label) pairs, as passed to `EncodingHints::new`.
+    pub encodings: Vec<(String, String)>,
+    /// Units of context before the match, by default none.
+    pub context_before: Option<usize>,
+    /// Units of context after the match, by default none.
+    pub context_after: Option<usize>,
+    /// Count the context in characters rather than bytes.
+    pub context_chars: bool,
+    /// Trim contexts longer than this many characters.
+    pub max_context_chars: Option<usize>,
+}
+
+static CONFIG: OnceLock<Result<Config, String>> = OnceLock::new();
+
+impl Config {
+    pub fn from_toml(text: &str) -> PyResult<Self> {
+        let config: Config = toml::from_str(text).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        EncodingHints::new(&config.encodings)?;
+        Ok(config)
+    }
+
+    /// Override the settings with the environment variables `var` gives the value of.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> PyResult<()> {
+        fn parse<T: std::str::FromStr>(var: &impl Fn(&str) -> Option<String>, name: &str, setting: &mut Option<T>) -> PyResult<()> {
+            if let Some(value) = var(name) {
+                let value = value.trim().parse().map_err(|_| PyValueError::new_err(format!("{}={:?} isn't a number", name, value)))?;
+                *setting = Some(value);
+            }
+            Ok(())
+        }
+        parse(&var, "TEXTSEARCHER_THREADS", &mut self.threads)?;
+        parse(&var, "TEXTSEARCHER_MAX_FILE_SIZE", &mut self.max_file_size)?;
+        parse(&var, "TEXTSEARCHER_MAX_CONCURRENT_READS", &mut self.max_concurrent_reads)?;
+        parse(&var, "TEXTSEARCHER_MEMORY_BUDGET", &mut self.memory_budget)?;
+        parse(&var, "TEXTSEARCHER_MMAP_THRESHOLD", &mut self.mmap_threshold)?;
+        parse(&var, "TEXTSEARCHER_CONTEXT_BEFORE", &mut self.context_before)?;
+        parse(&var, "TEXTSEARCHER_CONTEXT_AFTER", &mut self.context_after)?;
+        parse(&var, "TEXTSEARCHER_MAX_CONTEXT_CHARS", &mut self.max_context_chars)
+    }
+
+    /// The configuration file and environment of this process.
+    pub fn load() -> PyResult<Self> {
+        let path = match env::var_os("TEXTSEARCHER_CONFIG") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+                .map(|dir| dir.join("textsearcher").join("config.toml"))
+                .filter(|path| path.exists()),
+        };
+        let mut config = match path {
+            None => Config::default(),
+            Some(path) => {
+                let text = fs::read_to_string(&path).map_err(|e| PyValueError::new_err(format!("{}: {}", path.display(), e)))?;
+                Config::from_toml(&text).map_err(|e| PyValueError::new_err(format!("{}: {}", path.display(), e)))?
+            }
+        };
+        config.apply_env(|name| env::var(name).ok())?;
+        Ok(config)
+    }
+
+    /// The configuration loaded on first use, after which searches run on `threads` threads.
+    pub fn global() -> PyResult<&'static Config> {
+        let config = CONFIG.get_or_init(|| {
+            // kept as the message, since the error can't be cloned without the GIL
+            let config = Config::load().map_err(|e| e.to_string().trim_start_matches("ValueError: ").to_string())?;
+            if let Some(threads) = config.threads {
+                // fails only if searches already ran on the default pool, which then stays
+                let _ = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global();
+            }
+            Ok(config)
+        });
+        config.as_ref().map_err(|e| PyValueError::new_err(e.clone()))
+    }
+
+    /// The window of context given to a Python function, or the configured one if none is.
+    pub(crate) fn window(&self, a: Option<usize>, b: Option<usize>, chars: bool, before_lines: Option<usize>, after_lines: Option<usize>) -> (Option<usize>, Option<usize>, bool) {
+        match (a, b, before_lines, after_lines) {
+            (None, None, None, None) if self.context_before.is_some() || self.context_after.is_some() => (
+                Some(self.context_before.unwrap_or(0)),
+                Some(self.context_after.unwrap_or(0)),
+                chars || self.context_chars,
+            ),
+            _ => (a, b, chars),
+        }
+    }
+
+    /// Search options with the configured defaults.
+    pub fn search_options(&self) -> PyResult<SearchOptions> {
+        let context = (self.context_before.is_some() || self.context_after.is_some()).then(|| ContextOptions {
+            before: self.context_before.unwrap_or(0),
+            after: self.context_after.unwrap_or(0),
+            unit: if self.context_chars { ContextUnit::Chars } else { ContextUnit::Bytes },
+            max_chars: self.max_context_chars,
+            ..Default::default()
+        });
+        Ok(SearchOptions {
+            context,
+            encoding_hints: EncodingHints::new(&self.encodings)?,
+            read_limit: self.max_concurrent_reads.map(ReadLimit::new),
+            max_file_size: self.max_file_size,
+            memory_budget: self.memory_budget.map(MemoryBudget::new),
+            mmap_threshold: self.mmap_threshold,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::Config;
+
+    #[test]
+    fn test_config() {
+        let mut config = Config::from_toml("threads = 4\ncontext_before = 10\nencodings = [[\"jp/*.txt\", \"shift_jis\"]]\n").unwrap();
+        let env = HashMap::from([("TEXTSEARCHER_THREADS", "2"), ("TEXTSEARCHER_MAX_FILE_SIZE", "1000")]);
+        config.apply_env(|name| env.get(name).map(|value| value.to_string())).unwrap();
+        assert_eq!((config.threads, config.max_file_size), (Some(2), Some(1000)));
+        assert_eq!(config.window(None, None, false, None, None), (Some(10), Some(0), false));
+        assert_eq!(config.window(Some(1), Some(2), true, None, None), (Some(1), Some(2), true));
+        assert!(config.search_options().unwrap().encoding_hints.encoding_for("jp/a.txt").is_some());
+
+        assert!(Config::from_toml("thread = 4").is_err());
+        assert!(Config::from_toml("encodings = [[\"*.txt\", \"klingon\"]]").is_err());
+        assert!(config.apply_env(|_| Some("four".to_string())).is_err());
+    }
+}
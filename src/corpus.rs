@@ -0,0 +1,194 @@
+//! Named corpora registered once per process and searched repeatedly.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Instant;
+use rayon::prelude::*;
+use crate::cache::fnv1a;
+use crate::exclude::Excludes;
+use crate::report::ScanStats;
+use crate::tags::Tags;
+use crate::{finish_match, match_contents, read_contents, score, search_text_with, FileMatchResult, QueryGroup, SearchOptions};
+
+/// The contents of the files of a corpus, by the order of its paths, as read with the options
+/// of a search.
+struct CachedContents {
+    read: u64,
+    contents: Arc<Vec<Option<String>>>,
+}
+
+/// A set of text files, optionally with their contents cached in memory after the first search.
+/// They're read again by a search reading them otherwise, e.g. with other encodings.
+pub struct Corpus {
+    paths: Vec<String>,
+    cache: Option<RwLock<Option<CachedContents>>>,
+}
+
+/// Hash of the options `read_contents` reads a file with.
+fn read_key(options: &SearchOptions) -> u64 {
+    let read = format!(
+        "{:?} {:?} {:?} {:?} {:?} {:?} {:?} {}",
+        options.encoding_hints.key(),
+        options.binary,
+        options.code,
+        options.head_bytes,
+        options.tail_bytes,
+        options.ranges.as_ref().map(|ranges| (ranges.unit, ranges.sorted())),
+        options.max_file_size,
+        options.decompress,
+    );
+    fnv1a(0xcbf29ce484222325, read.as_bytes())
+}
+
+impl Corpus {
+    pub fn new(paths: Vec<String>, cache: bool) -> Self {
+        Corpus {
+            paths,
+            cache: cache.then(|| RwLock::new(None)),
+        }
+    }
+
+    /// All the files below `root`, recursively.
+    pub fn from_dir(root: &str, cache: bool) -> io::Result<Self> {
+        Corpus::from_dir_excluding(root, &Excludes::default(), cache)
+    }
+
+    /// Like `from_dir`, but leaving out the files and directories `excludes` excludes.
+    pub fn from_dir_excluding(root: &str, excludes: &Excludes, cache: bool) -> io::Result<Self> {
+        let mut paths = Vec::new();
+        collect_files(Path::new(root), excludes, &mut paths)?;
+        paths.sort();
+        Ok(Corpus::new(paths, cache))
+    }
+
+    pub fn paths(&self) -> &[String] {
+        &self.paths
+    }
+
+    /// The cached contents of the file at `path`, if the corpus caches them and they have been
+    /// read by a search with the read options of `options`.
+    pub fn cached_contents(&self, path: &str, options: &SearchOptions) -> Option<String> {
+        let cached = self.cache.as_ref()?.read().unwrap();
+        let cached = cached.as_ref().filter(|cached| cached.read == read_key(options))?;
+        let i = self.paths.iter().position(|p| p == path)?;
+        cached.contents[i].clone()
+    }
+
+    /// The contents of the files, read with `options` unless cached already, if they're cached.
+    fn contents(&self, options: &SearchOptions) -> Option<Arc<Vec<Option<String>>>> {
+        let cache = self.cache.as_ref()?;
+        let read = read_key(options);
+        if let Some(cached) = cache.read().unwrap().as_ref().filter(|cached| cached.read == read) {
+            return Some(cached.contents.clone());
+        }
+        // unreadable files are cached as `None` so that they aren't retried every search
+        let contents: Arc<Vec<_>> = Arc::new(self.paths.par_iter().map(|path| read_contents(path, options).ok()).collect());
+        *cache.write().unwrap() = Some(CachedContents {
+            read,
+            contents: contents.clone(),
+        });
+        Some(contents)
+    }
+
+    pub fn search(&self, query_group: &QueryGroup, options: &SearchOptions) -> Vec<FileMatchResult> {
+        if self.cache.is_none() {
+            return search_text_with(query_group, &self.paths, options);
+        }
+        let indices: Vec<_> = (0..self.paths.len()).collect();
+        let mut results: Vec<_> = self
+            .search_among(query_group, options, &indices)
+            .into_iter()
+            .map(|(_, result)| result)
+            .collect();
+        if let Some(scoring) = &options.scoring {
+            score::rank(&mut results, scoring.top_k);
+        }
+        results
+    }
+
+    /// Search only the files at `indices` of `paths`, returning the index of each matching file
+    /// with its result, in the order of `indices`. The results aren't ranked.
+    pub fn search_among(&self, query_group: &QueryGroup, options: &SearchOptions, indices: &[usize]) -> Vec<(usize, FileMatchResult)> {
+        let started = Instant::now();
+        let stats = ScanStats::default();
+        let contents = self.contents(options);
+        let no_tags = Tags::new();
+        let search_file = |&i: &usize| {
+            let path = &self.paths[i];
+            let read;
+            let contents = match &contents {
+                Some(contents) => contents[i].as_deref()?,
+                None => match read_contents(path, options) {
+                    Ok(contents) => {
+                        read = contents;
+                        read.as_str()
+                    }
+                    Err(e) => {
+                        stats.record_skip(path, &e);
+                        return None;
+                    }
+                },
+            };
+            stats.record_read(contents.len());
+            let (result, match_stats) = match_contents(query_group, path, contents, options)?;
+            Some((i, finish_match(result, match_stats, &no_tags, options)))
+        };
+        let results: Vec<_> = if options.parallel {
+            indices.par_iter().filter_map(search_file).collect()
+        } else {
+            indices.iter().filter_map(search_file).collect()
+        };
+        stats.finish(indices.len(), results.len(), started.elapsed());
+        results
+    }
+}
+
+/// Collect the files below `dir` into `paths`. Links to files are collected like them, but
+/// links to directories aren't followed, so that a link looping back to a directory above
+/// doesn't.
+pub(crate) fn collect_files(dir: &Path, excludes: &Excludes, paths: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let Some(name) = path.to_str() else {
+            continue;
+        };
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if !excludes.is_excluded_dir(name) {
+                collect_files(&path, excludes, paths)?;
+            }
+        } else if (file_type.is_file() || (file_type.is_symlink() && path.is_file())) && !excludes.is_excluded(name) {
+            paths.push(name.to_string());
+        }
+    }
+    Ok(())
+}
+
+fn registry() -> &'static RwLock<HashMap<String, Arc<Corpus>>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, Arc<Corpus>>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Register `corpus` under `name`, replacing any corpus previously registered under it.
+pub fn register_corpus(name: &str, corpus: Corpus) {
+    registry().write().unwrap().insert(name.to_string(), Arc::new(corpus));
+}
+
+/// Returns whether a corpus was registered under `name`.
+pub fn unregister_corpus(name: &str) -> bool {
+    registry().write().unwrap().remove(name).is_some()
+}
+
+pub fn get_corpus(name: &str) -> Option<Arc<Corpus>> {
+    registry().read().unwrap().get(name).cloned()
+}
+
+pub fn corpus_names() -> Vec<String> {
+    let mut names: Vec<_> = registry().read().unwrap().keys().cloned().collect();
+    names.sort();
+    names
+}
@@ -0,0 +1,83 @@
+//! Collapsing of results with the same context, e.g. matches in headers repeated in every file.
+
+use std::collections::HashMap;
+use pyo3::prelude::*;
+use crate::FileMatchResult;
+
+/// A result standing for the results with the same context.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CollapsedResult {
+    /// The first of them.
+    #[pyo3(get)]
+    pub result: FileMatchResult,
+
+    /// Paths of the others, folded into it.
+    #[pyo3(get)]
+    pub duplicates: Vec<String>,
+}
+
+#[pymethods]
+impl CollapsedResult {
+    /// Number of results folded into this one.
+    #[getter]
+    fn folded(&self) -> usize {
+        self.duplicates.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("CollapsedResult(path={:?}, folded={})", self.result.path(), self.duplicates.len())
+    }
+}
+
+/// What contexts are compared by: the context itself, or if `near`, the context with letters
+/// lowercased, digits (e.g. page numbers or dates) left out and whitespace collapsed.
+fn context_key(context: &str, near: bool) -> String {
+    if !near {
+        return context.to_string();
+    }
+    let mut key = String::with_capacity(context.len());
+    for word in context.split_whitespace() {
+        let word: String = word.chars().filter(|ch| !ch.is_numeric()).flat_map(char::to_lowercase).collect();
+        if !word.is_empty() {
+            if !key.is_empty() {
+                key.push(' ');
+            }
+            key.push_str(&word);
+        }
+    }
+    key
+}
+
+/// Collapse the results with identical, or if `near`, nearly identical contexts into the first
+/// of them, keeping the order of the first results. Results without context are kept as is.
+pub fn collapse_duplicates(results: Vec<FileMatchResult>, near: bool) -> Vec<CollapsedResult> {
+    let mut collapsed: Vec<CollapsedResult> = Vec::new();
+    let mut first_of: HashMap<String, usize> = HashMap::new();
+    for result in results.into_iter() {
+        if let Some(key) = result.context().map(|context| context_key(context, near)) {
+            if let Some(&i) = first_of.get(&key) {
+                collapsed[i].duplicates.push(result.path().to_string());
+                continue;
+            }
+            first_of.insert(key, collapsed.len());
+        }
+        collapsed.push(CollapsedResult {
+            result,
+            duplicates: Vec::new(),
+        });
+    }
+    collapsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::context_key;
+
+    #[test]
+    fn test_context_key() {
+        assert_eq!(context_key("Page 12 -  ACME Corp.", false), "Page 12 -  ACME Corp.");
+        assert_eq!(context_key("Page 12 -  ACME Corp.", true), "page - acme corp.");
+        assert_eq!(context_key("page 3\n- acme  corp.", true), "page - acme corp.");
+    }
+}
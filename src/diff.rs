@@ -0,0 +1,76 @@
+//! Comparison of the results of two runs, e.g. to monitor a growing document collection.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use pyo3::prelude::*;
+use crate::FileMatchResult;
+
+#[pyclass]
+#[derive(Clone, Default)]
+pub struct ResultDiff {
+    /// Files matching now but not before.
+    #[pyo3(get)]
+    pub added: Vec<FileMatchResult>,
+
+    /// Files matching before but not now.
+    #[pyo3(get)]
+    pub removed: Vec<FileMatchResult>,
+
+    /// Files matching in both runs, but with a different context, as (before, now) pairs.
+    #[pyo3(get)]
+    pub changed: Vec<(FileMatchResult, FileMatchResult)>,
+}
+
+/// Compare the results of a previous run with the current ones. Results are keyed by path.
+pub fn diff_results(previous: &[FileMatchResult], current: &[FileMatchResult]) -> ResultDiff {
+    let previous_by_path: HashMap<_, _> = previous.iter().map(|r| (r.path(), r)).collect();
+    let current_by_path: HashMap<_, _> = current.iter().map(|r| (r.path(), r)).collect();
+    let mut diff = ResultDiff::default();
+    for result in current.iter() {
+        match previous_by_path.get(result.path()) {
+            None => diff.added.push(result.clone()),
+            Some(old) if old.context() != result.context() => diff.changed.push(((*old).clone(), result.clone())),
+            Some(_) => (),
+        }
+    }
+    for result in previous.iter() {
+        if !current_by_path.contains_key(result.path()) {
+            diff.removed.push(result.clone());
+        }
+    }
+    diff
+}
+
+/// Write results as JSON lines, one object per result.
+fn write_jsonl(results: &[FileMatchResult], mut writer: impl Write) -> io::Result<()> {
+    for result in results.iter() {
+        serde_json::to_writer(&mut writer, result)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()
+}
+
+/// Save results as JSON lines, e.g. to diff against a later run.
+pub fn save_results<P: AsRef<Path>>(results: &[FileMatchResult], path: P) -> io::Result<()> {
+    write_jsonl(results, BufWriter::new(fs::File::create(path)?))
+}
+
+/// The results as JSON lines, as saved by `save_results`, e.g. to pipe into `jq`.
+pub fn results_to_jsonl(results: &[FileMatchResult]) -> String {
+    let mut jsonl = Vec::new();
+    write_jsonl(results, &mut jsonl).expect("writing to memory doesn't fail");
+    String::from_utf8(jsonl).expect("JSON is UTF-8")
+}
+
+pub fn load_results<P: AsRef<Path>>(path: P) -> io::Result<Vec<FileMatchResult>> {
+    let mut results = Vec::new();
+    for line in BufReader::new(fs::File::open(path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            results.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(results)
+}
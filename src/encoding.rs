@@ -0,0 +1,252 @@
+//! Reading files whose encoding isn't UTF-8.
+
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use chardetng::EncodingDetector;
+use encoding_rs::{Decoder, Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+/// Encodings of specific files, keyed by path or glob pattern (e.g. `old_docs/**`).
+/// When several patterns match a path, the first one wins.
+#[derive(Clone, Debug, Default)]
+pub struct EncodingHints {
+    globs: GlobSet,
+    /// The patterns of `globs`, which it doesn't keep.
+    patterns: Vec<String>,
+    encodings: Vec<&'static Encoding>,
+    /// Whether the encoding of files without a hint that aren't valid UTF-8 is detected.
+    detect: bool,
+    /// The encodings they may be detected in, any if empty.
+    detectable: Vec<&'static Encoding>,
+}
+
+impl EncodingHints {
+    /// `hints` are (pattern, encoding label) pairs, e.g. `("jp/*.txt", "shift_jis")`.
+    pub fn new(hints: &[(String, String)]) -> PyResult<Self> {
+        let mut builder = GlobSetBuilder::new();
+        let mut encodings = Vec::new();
+        let mut patterns = Vec::new();
+        for (pattern, label) in hints.iter() {
+            let glob = Glob::new(pattern)
+                .map_err(|e| PyValueError::new_err(format!("invalid path pattern {:?}: {}", pattern, e)))?;
+            let encoding = Encoding::for_label(label.as_bytes())
+                .ok_or_else(|| PyValueError::new_err(format!("unknown encoding {:?}", label)))?;
+            builder.add(glob);
+            encodings.push(encoding);
+            patterns.push(pattern.clone());
+        }
+        let globs = builder
+            .build()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(EncodingHints {
+            globs,
+            patterns,
+            encodings,
+            ..Default::default()
+        })
+    }
+
+    /// These hints, also detecting the encoding of the files without a hint that aren't valid
+    /// UTF-8, e.g. GBK, Shift_JIS or Latin-1, instead of skipping them. Files detected in
+    /// another encoding than those labelled `allowed`, if any, are skipped still.
+    pub fn detecting(self, allowed: &[String]) -> PyResult<Self> {
+        let detectable = allowed
+            .iter()
+            .map(|label| Encoding::for_label(label.as_bytes()).ok_or_else(|| PyValueError::new_err(format!("unknown encoding {:?}", label))))
+            .collect::<PyResult<_>>()?;
+        Ok(EncodingHints {
+            detect: true,
+            detectable,
+            ..self
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.encodings.is_empty()
+    }
+
+    /// What tells these hints from others, for the keys of cached outcomes: the patterns and
+    /// their encodings, and those detected, or nothing for hints reading every file as UTF-8.
+    pub(crate) fn key(&self) -> Option<String> {
+        if self.is_empty() && !self.detect {
+            return None;
+        }
+        let hinted: Vec<_> = self.patterns.iter().zip(self.encodings.iter()).map(|(pattern, encoding)| format!("{}={}", pattern, encoding.name())).collect();
+        let detectable: Vec<_> = self.detectable.iter().map(|encoding| encoding.name()).collect();
+        Some(format!("{:?} {} {:?}", hinted, self.detect, detectable))
+    }
+
+    /// The hinted encoding of `path`, if any.
+    pub fn encoding_for(&self, path: &str) -> Option<&'static Encoding> {
+        if self.is_empty() {
+            return None;
+        }
+        self.globs
+            .matches(path)
+            .into_iter()
+            .min()
+            .map(|i| self.encodings[i])
+    }
+
+    /// The encoding `bytes` read from `path`, which aren't valid UTF-8, are most likely in,
+    /// failing if it isn't allowed.
+    fn detect(&self, path: &str, bytes: &[u8]) -> io::Result<&'static Encoding> {
+        let mut detector = EncodingDetector::new();
+        detector.feed(bytes, true);
+        let encoding = detector.guess(None, false);
+        if !self.detectable.is_empty() && !self.detectable.contains(&encoding) {
+            let message = format!("{} seems to be in {}, which isn't among the allowed encodings", path, encoding.name());
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        Ok(encoding)
+    }
+}
+
+/// Read `path` into a string, decoding with the hinted encoding if any, or else as UTF-8.
+pub(crate) fn read_to_string(path: &str, hints: &EncodingHints) -> io::Result<String> {
+    match hints.encoding_for(path) {
+        Some(encoding) => {
+            let bytes = fs::read(path)?;
+            let (contents, _, _) = encoding.decode(&bytes);
+            Ok(contents.into_owned())
+        }
+        None if hints.detect => decode(path, fs::read(path)?, hints),
+        None => fs::read_to_string(path),
+    }
+}
+
+/// Read the first `max_bytes` bytes of `path` into a string, like `read_to_string`, leaving out
+/// a character they cut short.
+pub(crate) fn read_head(path: &str, hints: &EncodingHints, max_bytes: u64) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    fs::File::open(path)?.take(max_bytes).read_to_end(&mut bytes)?;
+    decode_head(path, bytes, hints, max_bytes)
+}
+
+/// Decode the first `max_bytes` bytes read from `path`, or the whole file if shorter, like
+/// `read_head` does.
+pub(crate) fn decode_head(path: &str, mut bytes: Vec<u8>, hints: &EncodingHints, max_bytes: u64) -> io::Result<String> {
+    if (bytes.len() as u64) < max_bytes {
+        return decode(path, bytes, hints);
+    }
+    match hints.encoding_for(path) {
+        Some(encoding) => {
+            // not being the last bytes, an incomplete character at the end isn't replaced
+            let mut decoder = encoding.new_decoder();
+            let mut contents = String::with_capacity(decoder.max_utf8_buffer_length(bytes.len()).unwrap_or(bytes.len()));
+            let _ = decoder.decode_to_string(&bytes, &mut contents, false);
+            Ok(contents)
+        }
+        None => {
+            if let Err(e) = std::str::from_utf8(&bytes) {
+                if e.error_len().is_none() {
+                    bytes.truncate(e.valid_up_to());
+                }
+            }
+            decode(path, bytes, hints)
+        }
+    }
+}
+
+/// Read the last `max_bytes` bytes of `path` into a string, like `read_to_string`, leaving out
+/// a character they cut short.
+pub(crate) fn read_tail(path: &str, hints: &EncodingHints, max_bytes: u64) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let start = file.metadata()?.len().saturating_sub(max_bytes);
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    decode_tail(path, bytes, hints, start)
+}
+
+/// Decode the bytes read from `path` from offset `start` to its end, like `read_tail` does. In
+/// encodings of several bytes per character other than UTF-8 and UTF-16, where a character can't
+/// be told from the end of another, the line cut short is left out.
+pub(crate) fn decode_tail(path: &str, mut bytes: Vec<u8>, hints: &EncodingHints, start: u64) -> io::Result<String> {
+    if start == 0 {
+        return decode(path, bytes, hints);
+    }
+    let skipped = match hints.encoding_for(path) {
+        None => bytes.iter().take_while(|&&b| b & 0xc0 == 0x80).count(),
+        Some(encoding) if encoding == UTF_8 => bytes.iter().take_while(|&&b| b & 0xc0 == 0x80).count(),
+        Some(encoding) if encoding == UTF_16LE || encoding == UTF_16BE => {
+            let aligned = (start % 2) as usize;
+            let unit = bytes.get(aligned..aligned + 2).map(|unit| match encoding == UTF_16LE {
+                true => u16::from_le_bytes([unit[0], unit[1]]),
+                false => u16::from_be_bytes([unit[0], unit[1]]),
+            });
+            // the second half of a surrogate pair
+            match unit {
+                Some(unit) if (0xdc00..0xe000).contains(&unit) => aligned + 2,
+                _ => aligned,
+            }
+        }
+        Some(encoding) if encoding.is_single_byte() => 0,
+        Some(_) => bytes.iter().position(|&b| b == b'\n').map_or(bytes.len(), |i| i + 1),
+    };
+    bytes.drain(..skipped.min(bytes.len()));
+    decode(path, bytes, hints)
+}
+
+/// Decode the `bytes` read from `path` like `read_to_string` does.
+pub(crate) fn decode(path: &str, bytes: Vec<u8>, hints: &EncodingHints) -> io::Result<String> {
+    match hints.encoding_for(path) {
+        Some(encoding) => Ok(encoding.decode(&bytes).0.into_owned()),
+        None => match String::from_utf8(bytes) {
+            Ok(contents) => Ok(contents),
+            Err(e) if hints.detect => {
+                let bytes = e.into_bytes();
+                let encoding = hints.detect(path, &bytes)?;
+                Ok(encoding.decode_without_bom_handling(&bytes).0.into_owned())
+            }
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        },
+    }
+}
+
+/// Decode the `bytes` read from `path` like `decode` does, but keeping a byte order mark as
+/// U+FEFF rather than following it, so that encoding the text again gives back the same bytes.
+/// Returns the encoding they were decoded from, `None` for UTF-8 without a hint, and whether
+/// they decoded without errors.
+pub(crate) fn decode_exact(path: &str, bytes: &[u8], hints: &EncodingHints) -> io::Result<(String, Option<&'static Encoding>, bool)> {
+    let encoding = match hints.encoding_for(path) {
+        Some(encoding) => encoding,
+        None => match std::str::from_utf8(bytes) {
+            Ok(contents) => return Ok((contents.to_string(), None, true)),
+            Err(_) if hints.detect => hints.detect(path, bytes)?,
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        },
+    };
+    let (contents, had_errors) = encoding.decode_without_bom_handling(bytes);
+    Ok((contents.into_owned(), Some(encoding), !had_errors))
+}
+
+/// Encode `text` in `encoding`, UTF-8 if `None`, unless it has characters that `encoding` can't
+/// represent. UTF-16 is encoded here, since `Encoding::encode` only outputs UTF-8 for it.
+pub(crate) fn encode(text: &str, encoding: Option<&'static Encoding>) -> Option<Vec<u8>> {
+    match encoding {
+        None => Some(text.as_bytes().to_vec()),
+        Some(encoding) if encoding == UTF_16LE => Some(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+        Some(encoding) if encoding == UTF_16BE => Some(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+        Some(encoding) => match encoding.encode(text) {
+            (_, _, true) => None,
+            (bytes, _, false) => Some(bytes.into_owned()),
+        },
+    }
+}
+
+/// A decoder of the contents of `path`, given their `first` bytes, which a detected encoding is
+/// told from, for decoding them a part at a time, and whether it's a UTF-8 decoder that must fail
+/// on invalid UTF-8, as `decode` does.
+pub(crate) fn stream_decoder(path: &str, first: &[u8], hints: &EncodingHints) -> io::Result<(Decoder, bool)> {
+    if let Some(encoding) = hints.encoding_for(path) {
+        return Ok((encoding.new_decoder(), false));
+    }
+    match std::str::from_utf8(first) {
+        // more than a character cut short at the end
+        Err(e) if hints.detect && e.error_len().is_some() => Ok((hints.detect(path, first)?.new_decoder_without_bom_handling(), false)),
+        _ => Ok((UTF_8.new_decoder_without_bom_handling(), true)),
+    }
+}
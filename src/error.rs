@@ -0,0 +1,38 @@
+//! The error of a query, pattern or glob that can't be compiled, e.g. an unclosed parenthesis
+//! or an invalid regex, kept in Rust so that a program without Python can report it.
+
+use std::fmt;
+use pyo3::exceptions::PyValueError;
+use pyo3::PyErr;
+
+/// Why a query, a pattern or an exclude glob is invalid; raised in Python as a `ValueError`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryError {
+    message: String,
+}
+
+impl QueryError {
+    pub fn new<S: Into<String>>(message: S) -> Self {
+        QueryError {
+            message: message.into(),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl From<QueryError> for PyErr {
+    fn from(e: QueryError) -> Self {
+        PyValueError::new_err(e.message)
+    }
+}
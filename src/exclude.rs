@@ -0,0 +1,93 @@
+//! Glob patterns of the paths left out when collecting files, e.g. `**/node_modules/**` or
+//! `*.min.js`, applied while walking directories so that excluded ones aren't walked at all.
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use crate::error::QueryError;
+
+/// Paths to leave out. A pattern without `/` is matched against the file or directory name, and
+/// any other against the whole path, where `*` doesn't match across directories but `**` does.
+#[derive(Clone, Debug, Default)]
+pub struct Excludes {
+    /// Matched against the whole path.
+    paths: GlobSet,
+    /// Matched against the name.
+    names: GlobSet,
+    /// The directories whose contents a pattern ending with `/**` excludes, so that they're not
+    /// walked.
+    dirs: GlobSet,
+}
+
+impl Excludes {
+    pub fn new(patterns: &[String]) -> Result<Self, QueryError> {
+        let glob = |pattern: &str| {
+            GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| QueryError::new(format!("invalid exclude pattern {:?}: {}", pattern, e)))
+        };
+        let (mut paths, mut names, mut dirs) = (GlobSetBuilder::new(), GlobSetBuilder::new(), GlobSetBuilder::new());
+        for pattern in patterns.iter() {
+            match pattern.contains('/') {
+                true => {
+                    paths.add(glob(pattern)?);
+                    if let Some(dir) = pattern.strip_suffix("/**") {
+                        dirs.add(glob(dir)?);
+                    }
+                }
+                false => {
+                    names.add(glob(pattern)?);
+                }
+            }
+        }
+        let build = |builder: GlobSetBuilder| builder.build().map_err(|e| QueryError::new(e.to_string()));
+        Ok(Excludes {
+            paths: build(paths)?,
+            names: build(names)?,
+            dirs: build(dirs)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty() && self.names.is_empty()
+    }
+
+    fn matches(&self, path: &str, globs: &GlobSet) -> bool {
+        let path = path.strip_prefix("./").unwrap_or(path);
+        let name = path.rsplit('/').next().unwrap_or(path);
+        globs.is_match(path) || self.names.is_match(name)
+    }
+
+    /// Whether the file at `path` is excluded.
+    pub fn is_excluded(&self, path: &str) -> bool {
+        !self.is_empty() && self.is_match(path)
+    }
+
+    /// Whether a pattern matches the file at `path`, e.g. of patterns selecting the files
+    /// searched rather than leaving them out.
+    pub fn is_match(&self, path: &str) -> bool {
+        self.matches(path, &self.paths)
+    }
+
+    /// Whether the directory at `path` is excluded with everything below it.
+    pub fn is_excluded_dir(&self, path: &str) -> bool {
+        !self.is_empty() && (self.matches(path, &self.dirs) || self.paths.is_match(path.strip_prefix("./").unwrap_or(path)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Excludes;
+
+    #[test]
+    fn test_excludes() {
+        let excludes = Excludes::new(&["**/node_modules/**".to_string(), "*.min.js".to_string(), "build".to_string()]).unwrap();
+        assert!(excludes.is_excluded("web/node_modules/a/index.js"));
+        assert!(excludes.is_excluded_dir("./web/node_modules"));
+        assert!(excludes.is_excluded("web/app.min.js"));
+        assert!(excludes.is_excluded_dir("web/build"));
+        assert!(!excludes.is_excluded("web/app.js"));
+        assert!(!excludes.is_excluded_dir("web/src"));
+        assert!(!Excludes::default().is_excluded("a"));
+        assert!(Excludes::new(&["a/[".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+//! A breakdown of how a query matches a file, to debug why a document unexpectedly does or
+//! doesn't appear in the results.
+
+use std::time::Instant;
+use pyo3::exceptions::PyIOError;
+use pyo3::prelude::*;
+use crate::error::QueryError;
+use crate::pattern::Pattern;
+use crate::{get_regex_for_atoms, is_match_str, normalize, read_contents, QueryGroup, SearchOptions};
+
+/// How an OR group of a query matches a file.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroupExplanation {
+    /// The atoms of the group.
+    #[pyo3(get)]
+    pub atoms: Vec<String>,
+
+    /// Whether any of them matches.
+    #[pyo3(get)]
+    pub matched: bool,
+
+    /// The atom of the first match.
+    #[pyo3(get)]
+    pub alternative: Option<String>,
+
+    /// The atoms that match anywhere in the file, in the order of the group.
+    #[pyo3(get)]
+    pub alternatives_matched: Vec<String>,
+
+    /// Byte offsets of the first match.
+    #[pyo3(get)]
+    pub span: Option<(usize, usize)>,
+
+    /// Number of matches of the group.
+    #[pyo3(get)]
+    pub count: usize,
+
+    /// Seconds spent matching the group.
+    #[pyo3(get)]
+    pub elapsed: f64,
+}
+
+#[pymethods]
+impl GroupExplanation {
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+/// How a query matches a file: each of its OR groups, then the constraints on them.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explanation {
+    #[pyo3(get)]
+    pub path: String,
+
+    /// Whether the file matches the query, i.e. would be in the results of a search.
+    #[pyo3(get)]
+    pub matched: bool,
+
+    #[pyo3(get)]
+    pub groups: Vec<GroupExplanation>,
+
+    /// The excluded atoms that match, any of which rules the file out.
+    #[pyo3(get)]
+    pub excluded: Vec<String>,
+
+    /// Whether the proximity and separation constraints, if any, hold.
+    #[pyo3(get)]
+    pub placed: bool,
+
+    /// Whether enough alternatives of each OR group match, under `minimum_should_match`.
+    #[pyo3(get)]
+    pub enough_alternatives: bool,
+
+    /// Seconds spent explaining the match, after reading the file.
+    #[pyo3(get)]
+    pub elapsed: f64,
+}
+
+#[pymethods]
+impl Explanation {
+    fn __repr__(&self) -> String {
+        format!("Explanation(path={:?}, matched={})", self.path, self.matched)
+    }
+}
+
+/// Each atom of `atoms` compiled on its own.
+fn compile_each(atoms: &[String], query_group: &QueryGroup) -> Result<Vec<Pattern>, QueryError> {
+    atoms
+        .iter()
+        .map(|atom| get_regex_for_atoms(std::slice::from_ref(atom), &query_group.query_options))
+        .collect()
+}
+
+fn explain_group(pattern: &Pattern, atoms: &[String], alternatives: &[Pattern], contents: &str) -> GroupExplanation {
+    let started = Instant::now();
+    let first = pattern.find(contents);
+    let count = first.map_or(0, |_| pattern.find_iter(contents).count());
+    let elapsed = started.elapsed().as_secs_f64();
+    let firsts: Vec<_> = alternatives.iter().map(|pat| pat.find(contents)).collect();
+    // the earliest alternative to match where the group first does is the one regex picks
+    let alternative = first.and_then(|m| {
+        let i = firsts.iter().position(|f| f.is_some_and(|f| f.start() == m.start()))?;
+        Some(atoms[i].clone())
+    });
+    GroupExplanation {
+        atoms: atoms.to_vec(),
+        matched: first.is_some(),
+        alternative,
+        alternatives_matched: atoms.iter().zip(firsts.iter()).filter(|(_, f)| f.is_some()).map(|(atom, _)| atom.clone()).collect(),
+        span: first.map(|m| (m.start(), m.end())),
+        count,
+        elapsed,
+    }
+}
+
+/// Explain how `query_group` matches `contents`, read from `path`.
+pub fn explain_contents(query_group: &QueryGroup, path: &str, contents: &str) -> PyResult<Explanation> {
+    let started = Instant::now();
+    let mut groups = Vec::with_capacity(query_group.patterns.len());
+    for (pattern, atoms) in query_group.patterns.iter().zip(query_group.atoms.iter()) {
+        groups.push(explain_group(pattern, atoms, &compile_each(atoms, query_group)?, contents));
+    }
+    let mut excluded = Vec::new();
+    for atoms in query_group.excluded_atoms.iter() {
+        let patterns = compile_each(atoms, query_group)?;
+        excluded.extend(atoms.iter().zip(patterns.iter()).filter(|(_, pat)| pat.is_match(contents)).map(|(atom, _)| atom.clone()));
+    }
+    Ok(Explanation {
+        path: path.to_string(),
+        matched: is_match_str(query_group, contents),
+        groups,
+        excluded,
+        placed: query_group.is_placed(contents),
+        enough_alternatives: query_group.has_enough_alternatives(contents),
+        elapsed: started.elapsed().as_secs_f64(),
+    })
+}
+
+/// Explain how `query_group` matches the file at `path`, read and normalized like in a search
+/// with `options`.
+pub fn explain(query_group: &QueryGroup, path: &str, options: &SearchOptions) -> PyResult<Explanation> {
+    let contents = read_contents(path, options).map_err(|e| PyIOError::new_err(format!("{}: {}", path, e)))?;
+    let contents = normalize::normalize(&options.normalizers, &contents);
+    explain_contents(query_group, path, &contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{QueryGroup, SearchOptions};
+    use super::{explain, explain_contents};
+
+    #[test]
+    fn test_explain() {
+        let atoms = vec![vec!["disk".to_string(), "drive".to_string()], vec!["full".to_string()]];
+        let query_group = QueryGroup::with_exclusions(atoms, vec![vec!["test".to_string()]], Default::default()).unwrap();
+        let explanation = explain_contents(&query_group, "a.txt", "the drive is full, the disk too").unwrap();
+        assert!(explanation.matched);
+        let group = &explanation.groups[0];
+        assert_eq!((group.alternative.as_deref(), group.span, group.count), (Some("drive"), Some((4, 9)), 2));
+        assert_eq!(group.alternatives_matched, vec!["disk", "drive"]);
+
+        let explanation = explain_contents(&query_group, "b.txt", "a test of the disk").unwrap();
+        assert!(!explanation.matched);
+        assert!(!explanation.groups[1].matched);
+        assert_eq!(explanation.excluded, vec!["test"]);
+
+        assert!(explain(&query_group, "sample_texts/missing.txt", &SearchOptions::default()).is_err());
+    }
+}
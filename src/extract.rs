@@ -0,0 +1,100 @@
+//! Extraction of the text captured by raw-regex atoms, e.g. DOIs or dates, from matching files,
+//! and of what each pattern of a query matches in a file.
+
+use std::collections::HashMap;
+use std::io;
+use pyo3::prelude::*;
+use crate::{is_match_str, normalize, read_contents, scan_files, QueryGroup, SearchOptions};
+
+/// The captures of every match of the query group's first pattern in a matching file.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Extraction {
+    path: String,
+    /// Per match, the text of each capture group, or `None` where the group didn't participate.
+    /// A pattern without capture groups yields the whole match as its only group.
+    captures: Vec<Vec<Option<String>>>,
+    /// Per match, the text of each named capture group, e.g. from `(?P<year>\d{4})`.
+    named: Vec<HashMap<String, Option<String>>>,
+}
+
+impl Extraction {
+    fn extract(query_group: &QueryGroup, path: &str, contents: &str, options: &SearchOptions) -> Option<Self> {
+        let contents = normalize::normalize(&options.normalizers, contents);
+        if !is_match_str(query_group, &contents) {
+            return None;
+        }
+        let pattern = &query_group.patterns[0];
+        let names = pattern.capture_names();
+        let (captures, named) = pattern
+            .captures_iter(&contents)
+            .map(|caps| {
+                let text = |i| caps.get(i).map(|m| m.as_str().to_string());
+                let groups = if caps.len() > 1 { 1..caps.len() } else { 0..1 };
+                let named = names
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, name)| Some((name.clone()?, text(i))))
+                    .collect();
+                (groups.map(text).collect(), named)
+            })
+            .unzip();
+        Some(Extraction {
+            path: path.to_string(),
+            captures,
+            named,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn captures(&self) -> &[Vec<Option<String>>] {
+        &self.captures
+    }
+
+    pub fn named(&self) -> &[HashMap<String, Option<String>>] {
+        &self.named
+    }
+}
+
+#[pymethods]
+impl Extraction {
+    #[getter(path)]
+    fn py_path(&self) -> String {
+        self.path.clone()
+    }
+
+    #[getter(captures)]
+    fn py_captures(&self) -> Vec<Vec<Option<String>>> {
+        self.captures.clone()
+    }
+
+    #[getter(named)]
+    fn py_named(&self) -> Vec<HashMap<String, Option<String>>> {
+        self.named.clone()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Extraction(path={:?}, captures={:?})", self.path, self.captures)
+    }
+}
+
+/// Every match of each pattern of `query_group` in the file at `path`, in the order of the
+/// patterns, as the byte offsets of the match in the normalized contents and its text. The file
+/// needn't match the whole group, so that e.g. what an OR group matches can be checked alone.
+pub fn find_all(query_group: &QueryGroup, path: &str, options: &SearchOptions) -> io::Result<Vec<Vec<(usize, usize, String)>>> {
+    let contents = read_contents(path, options)?;
+    let contents = normalize::normalize(&options.normalizers, &contents);
+    Ok(query_group
+        .patterns
+        .iter()
+        .map(|pattern| pattern.find_iter(&contents).map(|m| (m.start(), m.end(), m.as_str().to_string())).collect())
+        .collect())
+}
+
+/// The captures of the first pattern of `query_group` in each file matching the whole group.
+pub fn extract(query_group: &QueryGroup, paths: &[String], options: &SearchOptions) -> Vec<Extraction> {
+    scan_files(paths, options, |path, contents| Extraction::extract(query_group, path, contents, options).into_iter().collect()).0
+}
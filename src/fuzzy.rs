@@ -0,0 +1,108 @@
+//! Matching of words despite OCR errors, e.g. "hel1o wor|d" for "hello world".
+//!
+//! Each character of a word is compiled into the class of the characters OCR mistakes it for,
+//! e.g. `l` into `[l1i|!]`, and "rn" into `(?:rn|m)` and back. Beyond those, a few arbitrary
+//! edits per word are tolerated, each a character replaced, dropped or inserted, so that the
+//! regex accepts the spellings within that edit distance, like Lucene's `AUTO` fuzziness.
+
+/// Edits per word tolerated at most.
+pub const MAX_EDITS: usize = 2;
+
+/// Characters OCR mistakes for each other, by lowercase character. A character's class is the
+/// union of the groups it's in.
+const CONFUSED: [&str; 11] = ["il1|!", "o0", "s5", "z2", "b6", "b8", "g9", "gq", "ce", "ft", "uv"];
+
+/// Sequences of characters OCR mistakes for each other.
+const CONFUSED_SEQUENCES: [(&str, &str); 3] = [("rn", "m"), ("cl", "d"), ("vv", "w")];
+
+/// Regex of what OCR may have made of `ch`.
+fn char_class(ch: char) -> String {
+    let lower = ch.to_lowercase().next().unwrap_or(ch);
+    let mut class = vec![ch];
+    for confused in CONFUSED.iter().filter(|group| group.contains(lower)).flat_map(|group| group.chars()) {
+        if !class.contains(&confused) {
+            class.push(confused);
+        }
+    }
+    match class.len() {
+        1 => regex::escape(&ch.to_string()),
+        _ => format!("[{}]", class.iter().map(|c| regex::escape(&c.to_string())).collect::<String>()),
+    }
+}
+
+/// The regexes of the characters of `word`, and of the sequences OCR confuses with others.
+fn tokens(word: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut rest = word;
+    while let Some(ch) = rest.chars().next() {
+        let lower = rest.to_lowercase();
+        let sequence = CONFUSED_SEQUENCES.iter().find_map(|&(a, b)| match (lower.starts_with(a), lower.starts_with(b)) {
+            (true, _) => Some((a, b)),
+            (_, true) => Some((b, a)),
+            _ => None,
+        });
+        match sequence {
+            // only ASCII sequences are listed, so they're as long in `rest`
+            Some((seen, confused)) => {
+                tokens.push(format!("(?:{}|{})", regex::escape(&rest[..seen.len()]), confused));
+                rest = &rest[seen.len()..];
+            }
+            None => {
+                tokens.push(char_class(ch));
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+    }
+    tokens
+}
+
+/// Edits tolerated in a word of `chars` characters: none in words of 1 or 2, 1 in words of 3 to
+/// 5, and 2 in longer ones, at most `max_edits`.
+fn edits_for(chars: usize, max_edits: usize) -> usize {
+    let edits = match chars {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2,
+    };
+    edits.min(max_edits).min(MAX_EDITS)
+}
+
+/// Regex of the spellings of `tokens` within `edits` edits.
+fn with_edits(tokens: &[String], edits: usize) -> String {
+    if edits == 0 {
+        return tokens.concat();
+    }
+    let Some((first, rest)) = tokens.split_first() else {
+        return format!("\\S{{0,{}}}", edits);
+    };
+    // the first token kept, replaced or dropped, or a character inserted before it
+    format!("(?:{}{}|\\S?{}|\\S{})", first, with_edits(rest, edits), with_edits(rest, edits - 1), with_edits(tokens, edits - 1))
+}
+
+/// Regex of the spellings of `word` OCR may have made, within `max_edits` edits besides the
+/// characters it mistakes for others.
+pub fn word_regex(word: &str, max_edits: usize) -> String {
+    with_edits(&tokens(word), edits_for(word.chars().count(), max_edits))
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+    use super::word_regex;
+
+    #[test]
+    fn test_word_regex() {
+        let matches = |word: &str, max_edits: usize, text: &str| Regex::new(&format!("(?i)^{}$", word_regex(word, max_edits))).unwrap().is_match(text);
+        assert!(matches("hello", 0, "hel1o"));
+        assert!(matches("World", 0, "wor|d"));
+        assert!(matches("modern", 0, "rnodem"));
+        assert!(!matches("hello", 0, "helpo"));
+        assert!(matches("hello", 1, "helpo"));
+        assert!(matches("hello", 1, "helo"));
+        assert!(matches("hello", 1, "hel-lo"));
+        assert!(!matches("hello", 1, "hxlpo"));
+        assert!(matches("experiment", 2, "exqerirnent"));
+        // short words are matched exactly
+        assert!(!matches("at", 2, "an"));
+    }
+}
@@ -0,0 +1,86 @@
+//! Searches of groups of files each taken as one document, e.g. the chapters of a book, so that
+//! the atoms of a query may match in different files of a group.
+
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use crate::{match_contents, normalize, read_contents, QueryGroup, SearchOptions};
+
+/// What separates the files of a group, so that no atom matches across two of them.
+const FILE_SEPARATOR: &str = "\n\n";
+
+/// A group of files matching a query.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroupMatch {
+    #[pyo3(get)]
+    pub id: String,
+
+    /// The files of the group where any OR group of the query matches, in the order of the group.
+    #[pyo3(get)]
+    pub paths: Vec<String>,
+
+    #[pyo3(get)]
+    pub context: Option<String>,
+}
+
+#[pymethods]
+impl GroupMatch {
+    fn __repr__(&self) -> String {
+        format!("GroupMatch(id={:?}, paths={:?})", self.id, self.paths)
+    }
+}
+
+fn search_group(query_group: &QueryGroup, id: &str, paths: &[String], options: &SearchOptions) -> Option<GroupMatch> {
+    let files: Vec<_> = paths.iter().filter_map(|path| Some((path, read_contents(path, options).ok()?))).collect();
+    let document = files.iter().map(|(_, contents)| contents.as_str()).collect::<Vec<_>>().join(FILE_SEPARATOR);
+    let (result, _) = match_contents(query_group, id, &document, options)?;
+    let contributing = files
+        .iter()
+        .filter(|(_, contents)| {
+            let contents = normalize::normalize(&options.normalizers, contents);
+            query_group.patterns.iter().any(|pattern| pattern.is_match(&contents))
+        })
+        .map(|(path, _)| path.to_string())
+        .collect();
+    Some(GroupMatch {
+        id: id.to_string(),
+        paths: contributing,
+        context: result.context().map(String::from),
+    })
+}
+
+/// The groups of `groups`, pairs of an id and the paths of the files of the group, matching
+/// `query_group` once their files are concatenated, in order. The files that can't be read are
+/// left out of their group.
+pub fn search_groups(query_group: &QueryGroup, groups: &[(String, Vec<String>)], options: &SearchOptions) -> Vec<GroupMatch> {
+    if options.parallel {
+        groups
+            .par_iter()
+            .filter_map(|(id, paths)| search_group(query_group, id, paths, options))
+            .collect()
+    } else {
+        groups
+            .iter()
+            .filter_map(|(id, paths)| search_group(query_group, id, paths, options))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{QueryGroup, SearchOptions};
+    use super::search_groups;
+
+    #[test]
+    fn test_search_groups() {
+        let groups = vec![
+            ("book".to_string(), vec!["sample_texts/hello.txt".to_string(), "sample_texts/world.txt".to_string(), "sample_texts/missing.txt".to_string()]),
+            ("pamphlet".to_string(), vec!["sample_texts/hello.txt".to_string()]),
+        ];
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string()], vec!["world".to_string()]]).unwrap();
+        let matches = search_groups(&query_group, &groups, &SearchOptions::default());
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].id, "book");
+        assert_eq!(matches[0].paths, vec!["sample_texts/hello.txt", "sample_texts/world.txt"]);
+    }
+}
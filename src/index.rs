@@ -0,0 +1,674 @@
+//! Persistent trigram index of a corpus searched again and again, ruling out the files that
+//! can't match a query before any of them is read.
+//!
+//! The index records which trigrams of ASCII characters, lowercased, each file contains. A file
+//! is then searched only if, for every AND group, all the trigrams of the literal ASCII words of
+//! some atom are in it. Atoms that aren't made of such words, e.g. `re:` ones, short ones or
+//! those of CJK characters, and queries with romaji or phonetic alternatives, tolerating hyphens
+//! or OCR errors, don't rule out any file, and neither do files changed since they were indexed.
+//!
+//! Saved, the posting lists are compressed as the differences between consecutive file ids, in
+//! varints, and looked up in a table of the trigrams in order, each list decoded when a query
+//! needs it. A saved index is loaded by mapping it into memory, so that only its header is
+//! parsed and its table checked, the lists read from the pages a query touches. `save` replaces
+//! the file rather than writing over it, so the indexes loaded from it stay valid, but like for
+//! `mmap_threshold` nothing guards against the file being truncated while it's mapped.
+
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+use pyo3::exceptions::PyIOError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use serde::{Deserialize, Serialize};
+use crate::cache::Fingerprint;
+use crate::mmap::Mmap;
+use crate::normalize;
+use crate::options::{ReadArgs, SearchKwargs};
+use crate::{encoding, search_text_with, FilePaths, FileMatchResult, QueryGroup, QueryOptions, SearchOptions};
+
+/// A trigram, its three ASCII bytes packed into the low bytes.
+type Trigram = u32;
+
+/// What a saved index starts with, the last byte its format's version.
+const MAGIC: &[u8; 8] = b"TSINDEX\x01";
+
+/// The bytes of an entry of the table of a saved index: the trigram, the offset of its posting
+/// list after the table and the length of the list.
+const ENTRY_LEN: usize = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct IndexedFile {
+    path: String,
+    /// As indexed, or `None` if the file couldn't be read then.
+    fingerprint: Option<Fingerprint>,
+}
+
+/// What a saved index records besides its posting lists, as JSON.
+#[derive(Serialize, Deserialize)]
+struct Header<'a> {
+    files: Cow<'a, [IndexedFile]>,
+    normalizers: Cow<'a, [String]>,
+}
+
+/// An index saved as JSON, before posting lists were compressed.
+#[derive(Deserialize)]
+struct JsonIndex {
+    files: Vec<IndexedFile>,
+    normalizers: Vec<String>,
+    postings: HashMap<Trigram, Vec<u32>>,
+}
+
+/// The bytes of a saved index, mapped into memory, or read if it can't be, e.g. off Unix.
+enum Bytes {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+}
+
+impl Bytes {
+    fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut file = fs::File::open(path)?;
+        // an empty file can't be mapped
+        if file.metadata()?.len() > 0 {
+            if let Ok(mapped) = Mmap::map(&file) {
+                return Ok(Bytes::Mapped(mapped));
+            }
+        }
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        Ok(Bytes::Read(bytes))
+    }
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Bytes::Mapped(mapped) => mapped,
+            Bytes::Read(bytes) => bytes,
+        }
+    }
+}
+
+/// The posting lists of a saved index: the table of its trigrams, in order, and the lists after
+/// it.
+#[derive(Clone)]
+struct Saved {
+    bytes: Arc<Bytes>,
+    table: usize,
+    trigrams: usize,
+    lists: usize,
+}
+
+impl fmt::Debug for Saved {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Saved").field("trigrams", &self.trigrams).finish()
+    }
+}
+
+/// The files containing each trigram, by their index in the files indexed, in order.
+#[derive(Clone, Debug)]
+enum Postings {
+    Built(HashMap<Trigram, Vec<u32>>),
+    Saved(Saved),
+}
+
+impl Default for Postings {
+    fn default() -> Self {
+        Postings::Built(HashMap::new())
+    }
+}
+
+fn u32_at(bytes: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], i: usize) -> u64 {
+    u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("not a saved index: {}", message))
+}
+
+/// Append `ids`, in order, to `bytes` as the first and then the differences between consecutive
+/// ones, each a varint of 7 bits a byte, the lowest first and the high bit set on all but the last.
+fn encode(ids: &[u32], bytes: &mut Vec<u8>) {
+    let mut last = 0;
+    for (i, &id) in ids.iter().enumerate() {
+        let mut n = if i == 0 { id } else { id - last };
+        last = id;
+        while n >= 0x80 {
+            bytes.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        bytes.push(n as u8);
+    }
+}
+
+/// The ids encoded by `encode` in `bytes`.
+fn decode(bytes: &[u8]) -> Vec<u32> {
+    let mut ids = Vec::new();
+    let (mut n, mut shift, mut last) = (0u32, 0, 0u32);
+    for &byte in bytes.iter() {
+        // the bits past 32 of a corrupted list dropped rather than overflowing
+        n |= ((byte & 0x7f) as u32).checked_shl(shift).unwrap_or(0);
+        shift = shift.saturating_add(7);
+        if byte & 0x80 == 0 {
+            last = if ids.is_empty() { n } else { last.wrapping_add(n) };
+            ids.push(last);
+            (n, shift) = (0, 0);
+        }
+    }
+    ids
+}
+
+impl Saved {
+    /// The posting lists saved in `bytes` from `start`, checked to be in bounds.
+    fn parse(bytes: Arc<Bytes>, start: usize) -> io::Result<Self> {
+        let trigrams = bytes.get(start..start + 8).ok_or_else(|| invalid("truncated"))?;
+        let trigrams = u64::from_le_bytes(trigrams.try_into().unwrap()) as usize;
+        let table = start + 8;
+        let lists = trigrams.checked_mul(ENTRY_LEN).and_then(|len| table.checked_add(len)).filter(|&lists| lists <= bytes.len()).ok_or_else(|| invalid("truncated"))?;
+        let saved = Saved {
+            bytes,
+            table,
+            trigrams,
+            lists,
+        };
+        let mut last = None;
+        for i in 0..trigrams {
+            let (trigram, range) = saved.entry(i);
+            if last.is_some_and(|last| last >= trigram) || range.end > saved.bytes.len() - lists {
+                return Err(invalid("corrupted table"));
+            }
+            last = Some(trigram);
+        }
+        Ok(saved)
+    }
+
+    /// The trigram of the `i`th entry of the table and where its list is after the table.
+    fn entry(&self, i: usize) -> (Trigram, std::ops::Range<usize>) {
+        let at = self.table + i * ENTRY_LEN;
+        let offset = u64_at(&self.bytes, at + 4) as usize;
+        (u32_at(&self.bytes, at), offset..offset.saturating_add(u32_at(&self.bytes, at + 12) as usize))
+    }
+
+    fn list(&self, range: std::ops::Range<usize>) -> Vec<u32> {
+        decode(&self.bytes[self.lists + range.start..self.lists + range.end])
+    }
+
+    fn get(&self, trigram: Trigram) -> Option<Vec<u32>> {
+        let (mut low, mut high) = (0, self.trigrams);
+        while low < high {
+            let middle = (low + high) / 2;
+            let (found, range) = self.entry(middle);
+            match found.cmp(&trigram) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Some(self.list(range)),
+            }
+        }
+        None
+    }
+}
+
+impl Postings {
+    fn get(&self, trigram: Trigram) -> Cow<'_, [u32]> {
+        match self {
+            Postings::Built(postings) => Cow::Borrowed(postings.get(&trigram).map_or(&[][..], Vec::as_slice)),
+            Postings::Saved(saved) => saved.get(trigram).map_or(Cow::Borrowed(&[][..]), Cow::Owned),
+        }
+    }
+
+    /// Call `f` with each trigram, in order, and its posting list.
+    fn for_each(&self, mut f: impl FnMut(Trigram, &[u32])) {
+        match self {
+            Postings::Built(postings) => {
+                let mut trigrams: Vec<_> = postings.keys().copied().collect();
+                trigrams.sort_unstable();
+                for trigram in trigrams {
+                    f(trigram, &postings[&trigram]);
+                }
+            }
+            Postings::Saved(saved) => {
+                for i in 0..saved.trigrams {
+                    let (trigram, range) = saved.entry(i);
+                    f(trigram, &saved.list(range));
+                }
+            }
+        }
+    }
+}
+
+/// The trigrams of a corpus, built with `Index::build` and persisted with `save` and `load`.
+#[pyclass]
+#[derive(Clone, Debug, Default)]
+pub struct Index {
+    files: Vec<IndexedFile>,
+    /// The names of the normalizers the contents were indexed with; searches normalizing them
+    /// otherwise aren't filtered.
+    normalizers: Vec<String>,
+    postings: Postings,
+}
+
+/// Fold `ch` like case-insensitive matching of ASCII letters does, e.g. the Kelvin sign to `k`,
+/// or `None` if it's another non-ASCII character.
+fn fold(ch: char) -> Option<u8> {
+    match ch {
+        '\u{17f}' => Some(b's'),
+        '\u{212a}' => Some(b'k'),
+        ch if ch.is_ascii() => Some(ch.to_ascii_lowercase() as u8),
+        _ => None,
+    }
+}
+
+/// The trigrams of the runs of ASCII characters of `text`, folded.
+fn trigrams(text: &str) -> BTreeSet<Trigram> {
+    let mut trigrams = BTreeSet::new();
+    let mut last = 0u32;
+    let mut run = 0;
+    for ch in text.chars() {
+        match fold(ch) {
+            Some(byte) => {
+                last = ((last << 8) | byte as u32) & 0xff_ffff;
+                run += 1;
+                if run >= 3 {
+                    trigrams.insert(last);
+                }
+            }
+            None => run = 0,
+        }
+    }
+    trigrams
+}
+
+/// The trigrams any match of `atom` contains, or `None` if it has none that are certain.
+fn atom_trigrams(atom: &str, options: &QueryOptions) -> Option<BTreeSet<Trigram>> {
+    if let Some(sequence) = atom.strip_prefix(crate::THEN_PREFIX) {
+        // each atom of the sequence is matched
+        let trigrams: BTreeSet<_> = sequence.split(crate::THEN_SEPARATOR).filter_map(|part| atom_trigrams(part.trim(), options)).flatten().collect();
+        return (!trigrams.is_empty()).then_some(trigrams);
+    }
+    if [crate::REGEX_PREFIX, crate::FANCY_PREFIX, crate::PCRE_PREFIX].iter().any(|prefix| atom.starts_with(prefix)) {
+        return None;
+    }
+    let folded;
+    let atom = match &options.case_folding {
+        Some(case_fold) => {
+            folded = case_fold.fold(atom);
+            folded.as_str()
+        }
+        None => atom,
+    };
+    // whitespace matches any whitespace, so the words are matched on their own
+    let trigrams: BTreeSet<_> = atom.split([' ', '\t', '\n', '\r']).flat_map(trigrams).collect();
+    (!trigrams.is_empty()).then_some(trigrams)
+}
+
+impl Index {
+    /// Index the files at `paths`, read with the encoding hints and normalized with the
+    /// normalizers of `options`. Files that can't be read are searched every time.
+    pub fn build(paths: &[String], options: &SearchOptions) -> Self {
+        let mut files = Vec::new();
+        let mut postings: HashMap<Trigram, Vec<u32>> = HashMap::new();
+        for path in paths.iter() {
+            let id = files.len() as u32;
+            let fingerprint = Fingerprint::of(path).ok().and_then(|fingerprint| {
+                let contents = encoding::read_to_string(path, &options.encoding_hints).ok()?;
+                for trigram in trigrams(&normalize::normalize(&options.normalizers, &contents)) {
+                    postings.entry(trigram).or_default().push(id);
+                }
+                Some(fingerprint)
+            });
+            files.push(IndexedFile {
+                path: path.clone(),
+                fingerprint,
+            });
+        }
+        Index {
+            files,
+            normalizers: options.normalizers.iter().map(|n| n.name()).collect(),
+            postings: Postings::Built(postings),
+        }
+    }
+
+    /// Index the files at `paths` like `build`, saving each `segment_len` of them indexed as a
+    /// segment in the directory `checkpoints`, so that a build interrupted resumes after the last
+    /// segment saved. Segments are reused while they're of the next files of `paths` indexed with
+    /// the same normalizers, and left in `checkpoints` for the index to be saved before they're
+    /// removed.
+    pub fn build_resumable<P: AsRef<Path>>(paths: &[String], options: &SearchOptions, checkpoints: P, segment_len: usize) -> io::Result<Self> {
+        let checkpoints = checkpoints.as_ref();
+        fs::create_dir_all(checkpoints)?;
+        let segment_path = |i: usize| checkpoints.join(format!("segment-{:08}.index", i));
+        let mut index = Index {
+            normalizers: options.normalizers.iter().map(|n| n.name()).collect(),
+            ..Default::default()
+        };
+        let mut segments = 0;
+        while let Ok(segment) = Index::load(segment_path(segments)) {
+            let next = paths.get(index.files.len()..index.files.len() + segment.len());
+            let reused = !segment.is_empty() && segment.normalizers == index.normalizers && next.is_some_and(|next| segment.files.iter().map(|file| &file.path).eq(next.iter()));
+            if !reused {
+                break;
+            }
+            index.append(segment);
+            segments += 1;
+        }
+        for chunk in paths[index.files.len()..].chunks(segment_len.max(1)) {
+            let segment = Index::build(chunk, options);
+            segment.save(segment_path(segments))?;
+            index.append(segment);
+            segments += 1;
+        }
+        Ok(index)
+    }
+
+    /// Add the files of `segment` after those of the index.
+    fn append(&mut self, segment: Index) {
+        let offset = self.files.len() as u32;
+        if let Postings::Saved(_) = self.postings {
+            let mut postings = HashMap::new();
+            self.postings.for_each(|trigram, ids| {
+                postings.insert(trigram, ids.to_vec());
+            });
+            self.postings = Postings::Built(postings);
+        }
+        let Postings::Built(postings) = &mut self.postings else {
+            unreachable!("saved postings were just decoded");
+        };
+        segment.postings.for_each(|trigram, ids| {
+            postings.entry(trigram).or_default().extend(ids.iter().map(|id| id + offset));
+        });
+        self.files.extend(segment.files);
+    }
+
+    /// Load the index saved at `path`, or saved as JSON by an older version.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let bytes = Bytes::open(path)?;
+        if !bytes.starts_with(MAGIC) {
+            let index: JsonIndex = serde_json::from_slice(&bytes)?;
+            return Ok(Index {
+                files: index.files,
+                normalizers: index.normalizers,
+                postings: Postings::Built(index.postings),
+            });
+        }
+        let header_len = bytes.get(MAGIC.len()..MAGIC.len() + 8).ok_or_else(|| invalid("truncated"))?;
+        let header_start = MAGIC.len() + 8;
+        let header_end = header_start.saturating_add(u64::from_le_bytes(header_len.try_into().unwrap()) as usize);
+        let header: Header = serde_json::from_slice(bytes.get(header_start..header_end).ok_or_else(|| invalid("truncated"))?)?;
+        Ok(Index {
+            files: header.files.into_owned(),
+            normalizers: header.normalizers.into_owned(),
+            postings: Postings::Saved(Saved::parse(Arc::new(bytes), header_end)?),
+        })
+    }
+
+    /// Save the index at `path`, replacing the file atomically.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let header = serde_json::to_vec(&Header {
+            files: Cow::Borrowed(&self.files),
+            normalizers: Cow::Borrowed(&self.normalizers),
+        })?;
+        let mut table = Vec::new();
+        let mut lists = Vec::new();
+        self.postings.for_each(|trigram, ids| {
+            let offset = lists.len();
+            encode(ids, &mut lists);
+            table.extend(trigram.to_le_bytes());
+            table.extend((offset as u64).to_le_bytes());
+            table.extend(((lists.len() - offset) as u32).to_le_bytes());
+        });
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+            writer.write_all(MAGIC)?;
+            writer.write_all(&(header.len() as u64).to_le_bytes())?;
+            writer.write_all(&header)?;
+            writer.write_all(&((table.len() / ENTRY_LEN) as u64).to_le_bytes())?;
+            writer.write_all(&table)?;
+            writer.write_all(&lists)?;
+            writer.flush()?;
+        }
+        fs::rename(&tmp_path, path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// The files indexed containing all of `trigrams`.
+    fn containing(&self, trigrams: &BTreeSet<Trigram>) -> BTreeSet<u32> {
+        let mut files: Option<BTreeSet<u32>> = None;
+        for trigram in trigrams.iter() {
+            let posting = self.postings.get(*trigram);
+            files = Some(match files {
+                None => posting.iter().copied().collect(),
+                Some(files) => posting.iter().copied().filter(|id| files.contains(id)).collect(),
+            });
+            if files.as_ref().is_some_and(BTreeSet::is_empty) {
+                break;
+            }
+        }
+        files.unwrap_or_default()
+    }
+
+    /// The files indexed that may match `query_group` when searched with `options`, in the
+    /// order they were indexed.
+    pub fn candidates(&self, query_group: &QueryGroup, options: &SearchOptions) -> Vec<String> {
+        let all = || self.files.iter().map(|file| file.path.clone()).collect();
+        let names: Vec<_> = options.normalizers.iter().map(|n| n.name()).collect();
+        let query_options = &query_group.query_options;
+        if options.invert || names != self.normalizers || query_options.romaji || query_options.phonetic || query_options.separators.hyphens || query_options.fuzzy.is_some() {
+            return all();
+        }
+        let mut candidates: Option<BTreeSet<u32>> = None;
+        for atoms in query_group.atoms.iter() {
+            let Some(atoms): Option<Vec<_>> = atoms.iter().map(|atom| atom_trigrams(atom, query_options)).collect() else {
+                continue;
+            };
+            let group: BTreeSet<u32> = atoms.iter().flat_map(|trigrams| self.containing(trigrams)).collect();
+            candidates = Some(match candidates {
+                None => group,
+                Some(candidates) => candidates.intersection(&group).copied().collect(),
+            });
+        }
+        let Some(candidates) = candidates else {
+            return all();
+        };
+        self.files
+            .iter()
+            .enumerate()
+            .filter(|(id, file)| {
+                candidates.contains(&(*id as u32)) || file.fingerprint.is_none() || file.fingerprint != Fingerprint::of(&file.path).ok()
+            })
+            .map(|(_, file)| file.path.clone())
+            .collect()
+    }
+}
+
+/// Search the files of `index` that may match `query_group`.
+pub fn search_index(query_group: &QueryGroup, index: &Index, options: &SearchOptions) -> Vec<FileMatchResult> {
+    search_text_with(query_group, &index.candidates(query_group, options), options)
+}
+
+#[pymethods]
+impl Index {
+    /// Index the files, read with `encodings` and normalized with `normalizers` like a search
+    /// of them would be; with `checkpoints`, a directory, resuming from the segments of
+    /// `segment_len` files saved there by a build interrupted.
+    #[staticmethod]
+    #[pyo3(name = "build")]
+    #[pyo3(signature = (textfile_paths, encodings = None, normalizers = None, checkpoints = None, segment_len = 1000))]
+    fn py_build(py: Python<'_>, textfile_paths: &FilePaths, encodings: Option<&PyDict>, normalizers: Option<&PyAny>, checkpoints: Option<&str>, segment_len: usize) -> PyResult<Self> {
+        let read = ReadArgs {
+            encodings,
+            normalizers,
+            ..Default::default()
+        };
+        let search = SearchKwargs::new(None, Some(textfile_paths), read, Default::default(), Default::default(), Default::default())?;
+        search.normalizers.check_named("an index")?;
+        let index = py.allow_threads(|| match checkpoints {
+            Some(checkpoints) => Index::build_resumable(&textfile_paths.paths, &search.options, checkpoints, segment_len).map_err(|e| PyIOError::new_err(format!("{}: {}", checkpoints, e))),
+            None => Ok(Index::build(&textfile_paths.paths, &search.options)),
+        });
+        match search.take_error() {
+            Some(e) => Err(e),
+            None => index,
+        }
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "load")]
+    fn py_load(path: &str) -> PyResult<Self> {
+        Index::load(path).map_err(|e| PyIOError::new_err(format!("{}: {}", path, e)))
+    }
+
+    #[pyo3(name = "save")]
+    fn py_save(&self, path: &str) -> PyResult<()> {
+        self.save(path).map_err(|e| PyIOError::new_err(format!("{}: {}", path, e)))
+    }
+
+    fn __len__(&self) -> usize {
+        self.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use crate::{QueryGroup, QueryOptions, SearchOptions};
+    use super::{atom_trigrams, decode, encode, search_index, trigrams, Index, Trigram};
+
+    fn lists(index: &Index) -> Vec<(Trigram, Vec<u32>)> {
+        let mut lists = Vec::new();
+        index.postings.for_each(|trigram, ids| lists.push((trigram, ids.to_vec())));
+        lists
+    }
+
+    #[test]
+    fn test_build_resumable() {
+        let dir = std::env::temp_dir().join(format!("textsearcher-checkpoints-{}", std::process::id()));
+        let checkpoints = dir.join("checkpoints");
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<_> = ["alpha beta", "gamma", "delta"]
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let path = dir.join(format!("{}.txt", i));
+                std::fs::write(&path, text).unwrap();
+                path.to_str().unwrap().to_string()
+            })
+            .collect();
+        let options = SearchOptions::default();
+        let index = Index::build_resumable(&paths, &options, &checkpoints, 2).unwrap();
+        let built = Index::build(&paths, &options);
+        assert_eq!((&index.files, lists(&index)), (&built.files, lists(&built)));
+        assert_eq!(std::fs::read_dir(&checkpoints).unwrap().count(), 2);
+        // resumed from the segments saved, as if interrupted after them, the second rebuilt
+        std::fs::write(&paths[0], "epsilon").unwrap();
+        std::fs::remove_file(checkpoints.join("segment-00000001.index")).unwrap();
+        let resumed = Index::build_resumable(&paths, &options, &checkpoints, 2).unwrap();
+        assert_eq!(lists(&resumed), lists(&built));
+        // and not from the segments of other files
+        let others = vec![paths[0].clone(), paths[2].clone()];
+        let index = Index::build_resumable(&others, &options, &checkpoints, 2).unwrap();
+        assert_eq!(lists(&index), lists(&Index::build(&others, &options)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_saved() {
+        let mut bytes = Vec::new();
+        encode(&[3, 130, 70000, u32::MAX], &mut bytes);
+        assert_eq!(bytes.len(), 1 + 1 + 3 + 5);
+        assert_eq!(decode(&bytes), vec![3, 130, 70000, u32::MAX]);
+        let paths = vec!["sample_texts/hello.txt".to_string(), "sample_texts/world.txt".to_string(), "sample_texts/missing.txt".to_string()];
+        let index = Index::build(&paths, &SearchOptions::default());
+        let path = std::env::temp_dir().join(format!("textsearcher-saved-{}.index", std::process::id()));
+        index.save(&path).unwrap();
+        let loaded = Index::load(&path).unwrap();
+        assert_eq!(loaded.files, index.files);
+        let expected = lists(&index);
+        assert_eq!(lists(&loaded), expected);
+        assert!(expected.iter().all(|(trigram, ids)| loaded.postings.get(*trigram) == ids.as_slice()));
+        assert!(loaded.postings.get(0x7a7a7a).is_empty());
+        // mapped, and still valid once the file is saved over
+        #[cfg(unix)]
+        match &loaded.postings {
+            super::Postings::Saved(saved) => assert!(matches!(*saved.bytes, super::Bytes::Mapped(_))),
+            super::Postings::Built(_) => panic!("loaded as built"),
+        }
+        Index::build(&paths[..1], &SearchOptions::default()).save(&path).unwrap();
+        assert!(expected.iter().all(|(trigram, ids)| loaded.postings.get(*trigram) == ids.as_slice()));
+        // cut short, or saved as JSON before lists were compressed
+        let saved = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &saved[..saved.len() - 1]).unwrap();
+        assert_eq!(Index::load(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        std::fs::write(&path, r#"{"files": [{"path": "a.txt", "fingerprint": null}], "normalizers": [], "postings": {"6382179": [0]}}"#).unwrap();
+        assert_eq!(Index::load(&path).unwrap().postings.get(0x616263).as_ref(), &[0]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_index() {
+        assert_eq!(trigrams("Abcdé\u{212a}xy"), BTreeSet::from([0x616263, 0x626364, 0x6b7879]));
+        assert!(atom_trigrams("to 你好", &QueryOptions::default()).is_none());
+        assert!(atom_trigrams("fancy:foo(?=bar)", &QueryOptions::default()).is_none());
+        assert!(atom_trigrams("re:foo|bar", &QueryOptions::default()).is_none());
+        let paths = vec!["sample_texts/hello.txt".to_string(), "sample_texts/world.txt".to_string()];
+        let index = Index::build(&paths, &SearchOptions::default());
+        let path = std::env::temp_dir().join(format!("textsearcher-index-{}.json", std::process::id()));
+        index.save(&path).unwrap();
+        let index = Index::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let candidates = |atoms: Vec<Vec<&str>>| {
+            let query_group = QueryGroup::new(atoms.iter().map(|atoms| atoms.iter().map(|atom| atom.to_string()).collect()).collect()).unwrap();
+            index.candidates(&query_group, &SearchOptions::default())
+        };
+        assert_eq!(candidates(vec![vec!["FOO bar"]]), vec!["sample_texts/hello.txt"]);
+        assert_eq!(candidates(vec![vec!["baz", "world"]]), paths);
+        assert!(candidates(vec![vec!["world"], vec!["baz"]]).is_empty());
+        // too short to rule anything out
+        assert_eq!(candidates(vec![vec!["ba"]]), paths);
+        let query_group = QueryGroup::new(vec![vec!["world".to_string()]]).unwrap();
+        assert_eq!(search_index(&query_group, &index, &SearchOptions::default()).len(), 1);
+        // the misspellings tolerated have trigrams of their own
+        let fuzzy = QueryOptions {
+            fuzzy: Some(1),
+            ..Default::default()
+        };
+        let query_group = QueryGroup::with_options(vec![vec!["wor1d".to_string()]], fuzzy).unwrap();
+        assert_eq!(search_index(&query_group, &index, &SearchOptions::default()).len(), 1);
+        // and the other rewrites of the atoms keep the trigrams of their words
+        let whole_words = QueryOptions {
+            whole_words: true,
+            ..Default::default()
+        };
+        let word_gap = QueryOptions {
+            separators: crate::Separators {
+                words: Some("[\\s_]+".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for (atom, options) in [("bar", whole_words), ("foo bar", word_gap), ("then:foo ... baz", QueryOptions::default())] {
+            let query_group = QueryGroup::with_options(vec![vec![atom.to_string()]], options).unwrap();
+            let searched = crate::search_text_with(&query_group, &paths, &SearchOptions::default()).len();
+            assert_eq!((search_index(&query_group, &index, &SearchOptions::default()).len(), searched), (1, 1));
+        }
+    }
+}
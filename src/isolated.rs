@@ -0,0 +1,153 @@
+//! Searches run in a supervised child process, so that a crash, running out of memory or a
+//! runaway pattern can't take down a long-running process that starts them.
+//!
+//! The child is any program that calls `serve` with its stdin and stdout, e.g. Python running
+//! `textsearcher.serve_worker()`. It's sent a line of JSON with the query, written like a query
+//! of a suite, and the paths to search, and replies with a line of JSON per matching file,
+//! then a last line once done.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::{search_text_with, suite, ContextOptions, FileMatchResult, QueryGroup, SearchOptions};
+
+#[derive(Serialize, Deserialize)]
+struct Request {
+    query: Value,
+    paths: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Reply {
+    Result(Box<FileMatchResult>),
+    Done,
+    Error(String),
+}
+
+fn write_reply<W: Write>(writer: &mut W, reply: &Reply) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, reply)?;
+    writer.write_all(b"\n")
+}
+
+/// Serve the search request read from `reader`, replying to `writer`.
+pub fn serve<R: BufRead, W: Write>(mut reader: R, mut writer: W) -> io::Result<()> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let request: Request = serde_json::from_str(&line)?;
+    match suite::compile_query("isolated", request.query) {
+        Err(e) => write_reply(&mut writer, &Reply::Error(e.to_string()))?,
+        Ok(query) => {
+            let options = SearchOptions {
+                context: query.context,
+                invert: query.invert,
+                ..Default::default()
+            };
+            for result in search_text_with(&query.query_group, &request.paths, &options) {
+                write_reply(&mut writer, &Reply::Result(Box::new(result)))?;
+            }
+            write_reply(&mut writer, &Reply::Done)?;
+        }
+    }
+    writer.flush()
+}
+
+/// Kill the worker, if still running, and fail with `error`.
+fn abandon<T>(child: &mut Child, error: io::Error) -> io::Result<T> {
+    let _ = child.kill();
+    let _ = child.wait();
+    Err(error)
+}
+
+/// Search the files in a worker started with `command`, which is killed if it takes longer than
+/// `timeout`. Fails with `TimedOut` then, and if the worker exits before replying, e.g. having
+/// crashed or been killed for running out of memory.
+pub fn search_isolated(command: &mut Command, query_group: &QueryGroup, textfile_paths: &[String], context: Option<&ContextOptions>, invert: bool, timeout: Option<Duration>) -> io::Result<Vec<FileMatchResult>> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+    let request = Request {
+        query: suite::query_spec(query_group, context, invert),
+        paths: textfile_paths.to_vec(),
+    };
+    let mut stdin = child.stdin.take().unwrap();
+    // written on its own thread, since a worker that doesn't read it would block the write past
+    // the timeout once the request outgrows the pipe; a worker that died already fails the
+    // write, and how it exited is reported below instead
+    thread::spawn(move || {
+        let _ = serde_json::to_writer(&mut stdin, &request).map_err(io::Error::from).and_then(|_| stdin.write_all(b"\n"));
+    });
+    let stdout = child.stdout.take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let reply = line.and_then(|line| serde_json::from_str::<Reply>(&line).map_err(io::Error::from));
+            if sender.send(reply).is_err() {
+                break;
+            }
+        }
+    });
+    let mut results = Vec::new();
+    loop {
+        let reply = match deadline {
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            Some(deadline) => receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+        };
+        match reply {
+            Ok(Ok(Reply::Result(result))) => results.push(*result),
+            Ok(Ok(Reply::Done)) => break,
+            Ok(Ok(Reply::Error(message))) => return abandon(&mut child, io::Error::new(io::ErrorKind::InvalidInput, message)),
+            Ok(Err(e)) => return abandon(&mut child, e),
+            Err(RecvTimeoutError::Timeout) => {
+                let message = format!("the worker didn't finish within {:?}", timeout.unwrap_or_default());
+                return abandon(&mut child, io::Error::new(io::ErrorKind::TimedOut, message));
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                let status = child.wait()?;
+                return Err(io::Error::other(format!("the worker exited before finishing: {}", status)));
+            }
+        }
+    }
+    child.wait()?;
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+    use crate::{suite, QueryGroup};
+    use super::{search_isolated, serve};
+
+    #[test]
+    fn test_serve() {
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string()], vec!["bar".to_string()]]).unwrap().within(10);
+        let request = serde_json::json!({
+            "query": suite::query_spec(&query_group, None, false),
+            "paths": ["sample_texts/hello.txt", "sample_texts/world.txt"],
+        });
+        let mut replies = Vec::new();
+        serve(format!("{}\n", request).as_bytes(), &mut replies).unwrap();
+        assert_eq!(String::from_utf8(replies).unwrap(), "{\"result\":{\"path\":\"sample_texts/hello.txt\",\"context\":null,\"position\":{\"line\":1,\"column\":1,\"span\":[0,3]}}}\n\"done\"\n");
+    }
+
+    #[test]
+    fn test_search_isolated() {
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string()]]).unwrap();
+        let paths = vec!["sample_texts/hello.txt".to_string()];
+        let crashed = search_isolated(Command::new("sh").args(["-c", "exit 3"]), &query_group, &paths, None, false, None).unwrap_err();
+        assert!(crashed.to_string().contains("exit status: 3"));
+        let stuck = search_isolated(Command::new("sleep").arg("5"), &query_group, &paths, None, false, Some(Duration::from_millis(50))).unwrap_err();
+        assert_eq!(stuck.kind(), io::ErrorKind::TimedOut);
+        // a request too large for the pipe, never read
+        let started = Instant::now();
+        let many = vec!["sample_texts/hello.txt".to_string(); 100_000];
+        let stuck = search_isolated(Command::new("sleep").arg("5"), &query_group, &many, None, false, Some(Duration::from_millis(50))).unwrap_err();
+        assert_eq!(stuck.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+}
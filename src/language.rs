@@ -0,0 +1,66 @@
+//! Detection of the dominant language of a document from the scripts of its letters.
+
+use std::collections::HashMap;
+use unicode_script::{Script, UnicodeScript};
+
+/// Number of letters looked at, enough to tell the script of a document.
+const SAMPLE_LETTERS: usize = 10000;
+
+/// The dominant language of `contents`: `ja`, `zh` or `ko` for CJK text, a language code for
+/// scripts used by essentially one language (e.g. `th` or `el`), otherwise the lowercase name of
+/// the script (e.g. `latin` or `cyrillic`), or `und` without letters. Japanese is told from
+/// Chinese by its kana, which even kanji-heavy Japanese has plenty of.
+pub fn detect(contents: &str) -> String {
+    let mut counts: HashMap<Script, usize> = HashMap::new();
+    for ch in contents.chars().filter(|ch| ch.is_alphabetic()).take(SAMPLE_LETTERS) {
+        let script = match ch.script() {
+            Script::Hiragana | Script::Katakana => Script::Hiragana,
+            script => script,
+        };
+        *counts.entry(script).or_default() += 1;
+    }
+    let count = |script: Script| counts.get(&script).copied().unwrap_or(0);
+    let (kana, han) = (count(Script::Hiragana), count(Script::Han));
+    let dominant = counts
+        .iter()
+        .filter(|(script, _)| !matches!(script, Script::Common | Script::Inherited | Script::Unknown))
+        .map(|(&script, &n)| match script {
+            Script::Hiragana | Script::Han => (Script::Han, kana + han),
+            _ => (script, n),
+        })
+        .max_by_key(|&(script, n)| (n, script.full_name()));
+    match dominant {
+        None => "und".to_string(),
+        Some((Script::Han, _)) if kana * 20 >= kana + han => "ja".to_string(),
+        Some((script, _)) => match script {
+            Script::Han => "zh",
+            Script::Hangul => "ko",
+            Script::Thai => "th",
+            Script::Lao => "lo",
+            Script::Khmer => "km",
+            Script::Myanmar => "my",
+            Script::Greek => "el",
+            Script::Hebrew => "he",
+            Script::Georgian => "ka",
+            Script::Armenian => "hy",
+            script => return script.full_name().to_lowercase(),
+        }
+        .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::detect;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect("新しいサーバーを設定する"), "ja");
+        assert_eq!(detect("配置新的服务器"), "zh");
+        assert_eq!(detect("새 서버를 설정"), "ko");
+        assert_eq!(detect("Configure the new server (サーバー)"), "latin");
+        assert_eq!(detect("Настроить сервер"), "cyrillic");
+        assert_eq!(detect("ภาษาไทย"), "th");
+        assert_eq!(detect("12345 ..."), "und");
+    }
+}
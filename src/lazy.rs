@@ -0,0 +1,354 @@
+//! Searching files on demand, so that consumers taking only the first few matches don't pay for
+//! the whole set of files, and streams of the matches as they're found, so that e.g. a UI shows
+//! the first ones while a slow corpus is still searched.
+
+use std::collections::BTreeMap;
+use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use pyo3::prelude::*;
+use crate::progress::{CancelToken, SIGNAL_INTERVAL};
+use crate::report::{ScanStats, SearchReport};
+use crate::tags::Tags;
+use crate::{cache, scan_entries, search_entry, FileMatchResult, QueryGroup, SearchOptions};
+
+/// Number of matches a `SearchStream` finds ahead of its consumer before the search waits.
+const STREAM_BUFFER: usize = 256;
+/// Number of files searched together by the threads of a `SearchStream`.
+const STREAM_CHUNK: usize = 1024;
+
+/// The matches of a query group in files, in the order of the paths, searched as they're
+/// pulled. `SearchOptions::parallel` and the ranking of `SearchOptions::scoring` don't apply,
+/// although results are still scored. The search is counted in the metrics once dropped.
+pub struct SearchIter<'a> {
+    inner: Inner<'a>,
+}
+
+enum Inner<'a> {
+    OnDemand {
+        query_group: &'a QueryGroup,
+        paths: slice::Iter<'a, String>,
+        options: &'a SearchOptions,
+        query: Option<u64>,
+        stats: ScanStats,
+        no_tags: Tags,
+        started: Instant,
+        files_total: usize,
+        files_matched: usize,
+    },
+    Prefetch(Prefetcher),
+}
+
+/// Search each file only when the next match is pulled.
+pub fn search_iter<'a>(query_group: &'a QueryGroup, paths: &'a [String], options: &'a SearchOptions) -> SearchIter<'a> {
+    SearchIter {
+        inner: Inner::OnDemand {
+            query_group,
+            paths: paths.iter(),
+            options,
+            query: options.cache.as_ref().map(|_| cache::query_key(query_group, options)),
+            stats: ScanStats::default(),
+            no_tags: Tags::new(),
+            started: Instant::now(),
+            files_total: paths.len(),
+            files_matched: 0,
+        },
+    }
+}
+
+/// Like `search_iter`, but up to `prefetch` files ahead of the consumer are searched by a pool
+/// of background threads meanwhile. The query group and options are cloned for the threads.
+pub fn search_iter_prefetch(query_group: &QueryGroup, paths: &[String], options: &SearchOptions, prefetch: usize) -> SearchIter<'static> {
+    SearchIter {
+        inner: Inner::Prefetch(Prefetcher::new(query_group, paths, options, prefetch.max(1))),
+    }
+}
+
+impl<'a> Iterator for SearchIter<'a> {
+    type Item = FileMatchResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.inner {
+            Inner::OnDemand { query_group, paths, options, query, stats, no_tags, files_matched, .. } => {
+                let result = paths.find_map(|path| search_entry(query_group, path, no_tags, options, *query, stats));
+                *files_matched += usize::from(result.is_some());
+                result
+            }
+            Inner::Prefetch(prefetcher) => prefetcher.next(),
+        }
+    }
+}
+
+impl Drop for SearchIter<'_> {
+    fn drop(&mut self) {
+        if let Inner::OnDemand { stats, started, files_total, files_matched, .. } = &mut self.inner {
+            std::mem::take(stats).finish(*files_total, *files_matched, started.elapsed());
+        }
+    }
+}
+
+/// State shared by the consumer and the threads of a `Prefetcher`.
+struct Window {
+    /// Number of files whose outcome the consumer has taken.
+    consumed: Mutex<usize>,
+    advanced: Condvar,
+    /// Next file to be claimed by a thread.
+    claimed: AtomicUsize,
+    stopped: AtomicBool,
+}
+
+struct Prefetcher {
+    total: usize,
+    next: usize,
+    /// Outcomes that arrived ahead of `next`.
+    pending: BTreeMap<usize, Option<FileMatchResult>>,
+    outcomes: Receiver<(usize, Option<FileMatchResult>)>,
+    window: Arc<Window>,
+    workers: Vec<JoinHandle<()>>,
+    stats: Arc<ScanStats>,
+    started: Instant,
+    files_matched: usize,
+}
+
+impl Prefetcher {
+    fn new(query_group: &QueryGroup, paths: &[String], options: &SearchOptions, prefetch: usize) -> Self {
+        let query_group = Arc::new(query_group.clone());
+        let paths = Arc::new(paths.to_vec());
+        let options = Arc::new(options.clone());
+        let query = options.cache.as_ref().map(|_| cache::query_key(&query_group, &options));
+        let started = Instant::now();
+        let stats = Arc::new(ScanStats::default());
+        let window = Arc::new(Window {
+            consumed: Mutex::new(0),
+            advanced: Condvar::new(),
+            claimed: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+        });
+        let (sender, outcomes) = mpsc::channel();
+        let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(prefetch);
+        let workers = (0..threads)
+            .map(|_| {
+                let (query_group, paths, options, stats, window, sender) =
+                    (query_group.clone(), paths.clone(), options.clone(), stats.clone(), window.clone(), sender.clone());
+                thread::spawn(move || {
+                    let no_tags = Tags::new();
+                    loop {
+                        let i = window.claimed.fetch_add(1, Ordering::SeqCst);
+                        if i >= paths.len() {
+                            return;
+                        }
+                        // don't get more than `prefetch` files ahead of the consumer
+                        let mut consumed = window.consumed.lock().unwrap();
+                        while i >= *consumed + prefetch && !window.stopped.load(Ordering::SeqCst) {
+                            consumed = window.advanced.wait(consumed).unwrap();
+                        }
+                        drop(consumed);
+                        if window.stopped.load(Ordering::SeqCst) {
+                            return;
+                        }
+                        let outcome = search_entry(&query_group, &paths[i], &no_tags, &options, query, &stats);
+                        if sender.send((i, outcome)).is_err() {
+                            return;
+                        }
+                    }
+                })
+            })
+            .collect();
+        Prefetcher {
+            total: paths.len(),
+            next: 0,
+            pending: BTreeMap::new(),
+            outcomes,
+            window,
+            workers,
+            stats,
+            started,
+            files_matched: 0,
+        }
+    }
+
+    fn next(&mut self) -> Option<FileMatchResult> {
+        while self.next < self.total {
+            let outcome = match self.pending.remove(&self.next) {
+                Some(outcome) => outcome,
+                None => {
+                    let (i, outcome) = self.outcomes.recv().ok()?;
+                    self.pending.insert(i, outcome);
+                    continue;
+                }
+            };
+            self.next += 1;
+            *self.window.consumed.lock().unwrap() = self.next;
+            self.window.advanced.notify_all();
+            if outcome.is_some() {
+                self.files_matched += 1;
+                return outcome;
+            }
+        }
+        None
+    }
+}
+
+impl Drop for Prefetcher {
+    fn drop(&mut self) {
+        self.window.stopped.store(true, Ordering::SeqCst);
+        {
+            // taking the lock ensures that no thread is between checking `stopped` and waiting
+            let _consumed = self.window.consumed.lock().unwrap();
+            self.window.advanced.notify_all();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        // the workers' clones dropped with them
+        let stats = Arc::try_unwrap(std::mem::take(&mut self.stats)).unwrap_or_default();
+        stats.finish(self.total, self.files_matched, self.started.elapsed());
+    }
+}
+
+/// The matches of a query group in files, in the order they're found by the threads searching
+/// them in the background, like `search_text_report` does. Dropping the stream cancels the
+/// search, with the token of `SearchOptions::cancel` if given. The results are scored but
+/// not ranked.
+pub struct SearchStream {
+    results: Option<Receiver<FileMatchResult>>,
+    cancel: CancelToken,
+    scan: Option<JoinHandle<SearchReport>>,
+    report: Option<SearchReport>,
+}
+
+/// Start searching the files at `paths` in the background. The query group and options are
+/// cloned for the threads.
+pub fn search_stream(query_group: &QueryGroup, paths: &[String], options: &SearchOptions) -> SearchStream {
+    let (query_group, paths, mut options) = (query_group.clone(), paths.to_vec(), options.clone());
+    let cancel = options.cancel.get_or_insert_with(CancelToken::new).clone();
+    let (sender, results) = mpsc::sync_channel(STREAM_BUFFER);
+    let scan = thread::spawn(move || {
+        let no_tags = Tags::new();
+        let entries: Vec<_> = paths.iter().map(|path| (path.as_str(), &no_tags)).collect();
+        // the results are sent as found, a failure to send meaning the stream was dropped
+        let send = |result: &FileMatchResult| {
+            let _ = sender.send(result.clone());
+        };
+        scan_entries(&query_group, &entries, &options, STREAM_CHUNK, send, |_| ())
+    });
+    SearchStream {
+        results: Some(results),
+        cancel,
+        scan: Some(scan),
+        report: None,
+    }
+}
+
+impl SearchStream {
+    /// The next match, waiting at most `timeout` for it, or `None` once all the files are
+    /// searched.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Result<Option<FileMatchResult>, RecvTimeoutError> {
+        let Some(results) = &self.results else {
+            return Ok(None);
+        };
+        match results.recv_timeout(timeout) {
+            Ok(result) => Ok(Some(result)),
+            Err(RecvTimeoutError::Timeout) => Err(RecvTimeoutError::Timeout),
+            Err(RecvTimeoutError::Disconnected) => {
+                self.finish();
+                Ok(None)
+            }
+        }
+    }
+
+    /// The report of the search, once all the matches are taken.
+    pub fn report(&self) -> Option<&SearchReport> {
+        self.report.as_ref()
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Wait for the thread of the search, which is done sending.
+    fn finish(&mut self) {
+        self.results = None;
+        if let Some(scan) = self.scan.take() {
+            self.report = scan.join().ok();
+        }
+    }
+}
+
+impl Iterator for SearchStream {
+    type Item = FileMatchResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.results.as_ref()?.recv() {
+            Ok(result) => Some(result),
+            Err(_) => {
+                self.finish();
+                None
+            }
+        }
+    }
+}
+
+impl Drop for SearchStream {
+    fn drop(&mut self) {
+        if self.scan.is_some() {
+            self.cancel.cancel();
+            // the threads blocked on sending fail once the receiver is gone
+            self.finish();
+        }
+    }
+}
+
+/// A `SearchStream` iterated from Python, waiting for each match with the GIL released.
+#[pyclass(name = "SearchStream")]
+pub struct PySearchStream {
+    stream: Mutex<SearchStream>,
+    cancel: CancelToken,
+}
+
+impl PySearchStream {
+    pub fn new(stream: SearchStream) -> Self {
+        PySearchStream {
+            cancel: stream.cancel.clone(),
+            stream: Mutex::new(stream),
+        }
+    }
+}
+
+#[pymethods]
+impl PySearchStream {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// The next match; a signal such as Ctrl-C received meanwhile cancels the search and is
+    /// raised.
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<FileMatchResult>> {
+        loop {
+            // the stream is locked only without the GIL, which its other users may be holding
+            match py.allow_threads(|| self.stream.lock().unwrap().next_timeout(SIGNAL_INTERVAL)) {
+                Ok(result) => return Ok(result),
+                Err(_) => {
+                    if let Err(e) = py.check_signals() {
+                        self.cancel.cancel();
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+
+    #[pyo3(name = "cancel")]
+    fn py_cancel(&self) {
+        self.cancel.cancel()
+    }
+
+    /// The report of the search, once all the matches are taken.
+    #[getter]
+    fn report(&self, py: Python<'_>) -> Option<SearchReport> {
+        py.allow_threads(|| self.stream.lock().unwrap().report().cloned())
+    }
+}
@@ -1,41 +1,1108 @@
-use std::fs;
-use regex::{Regex, RegexBuilder};
+pub mod aggregate;
+pub mod archive;
+pub mod asyncio;
+#[cfg(feature = "async")]
+pub mod async_search;
+pub mod binary;
+pub mod cache;
+pub mod checkpoint;
+pub mod chunked;
+pub mod code;
+pub mod compress;
+pub mod config;
+pub mod corpus;
+pub mod dedup;
+pub mod diff;
+pub mod encoding;
+pub mod error;
+pub mod exclude;
+pub mod explain;
+pub mod extract;
+pub mod groups;
+pub mod index;
+pub mod isolated;
+pub mod language;
+pub mod lazy;
+pub mod logs;
+pub mod metrics;
+mod mmap;
+pub mod paths;
+pub mod pattern;
+pub mod normalize;
+pub mod options;
+pub mod presets;
+pub mod progress;
+mod fuzzy;
+mod phonetic;
+pub mod query;
+pub mod ranges;
+pub mod records;
+pub mod replace;
+pub mod report;
+mod romaji;
+pub mod sample;
+pub mod score;
+pub mod snippet;
+pub mod spill;
+pub mod streams;
+pub mod suite;
+pub mod tags;
+pub mod throttle;
+mod trace;
+pub mod typeahead;
+pub mod walk;
+pub mod watch;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use regex::RegexBuilder;
 use rayon::prelude::*;
-use pyo3::exceptions::PyValueError;
+use unicode_script::{Script, UnicodeScript};
+use pyo3::exceptions::{PyIOError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use crate::cache::{Fingerprint, Outcome, ResultCache};
+use crate::encoding::EncodingHints;
+use crate::error::QueryError;
+use crate::normalize::{CaseFold, Normalizer};
+use crate::pattern::Pattern;
+use crate::report::{ScanStats, SearchReport};
+use crate::score::{MatchStats, ScoringOptions};
+use crate::tags::{TagFilter, TagValue, Tags};
+use crate::throttle::{MemoryBudget, ReadLimit};
+use serde::{Deserialize, Serialize};
+
+
+/// Options that control how the atoms of a `QueryGroup` are compiled into regexes.
+#[derive(Clone, Debug, Default)]
+pub struct QueryOptions {
+    /// Treat hiragana and katakana as equivalent, and the prolonged sound mark `ー` as optional,
+    /// so that e.g. "サーバー", "サーバ" and "さーば" match each other.
+    pub kana_folding: bool,
+    /// Also accept atoms written in romaji (e.g. "kikai gakushuu"), expanding each word into
+    /// its hiragana and katakana spellings.
+    pub romaji: bool,
+    /// Extra alternatives for romaji words or whole romaji atoms, typically kanji spellings,
+    /// e.g. "kikai" -> ["機械"]. Keys are lowercase.
+    pub romaji_dictionary: HashMap<String, Vec<String>>,
+    /// Also match words that sound like the ASCII words of an atom, e.g. "Katherine" matches
+    /// "Catherine" and "Kathryn". Meant for names.
+    pub phonetic: bool,
+    /// Fold the atoms with full case folding, to search contents folded alike, e.g. so that
+    /// "Straße" matches "STRASSE" once both are folded to "strasse".
+    pub case_folding: Option<CaseFold>,
+    /// Apply the NFKC Unicode normalization to the atoms, to search contents normalized alike,
+    /// e.g. so that full-width "ＡＢＣ" or half-width "ｶﾀｶﾅ" match "ABC" and "カタカナ" once
+    /// both are normalized. Searches from Python normalize the contents too.
+    pub nfkc: bool,
+    /// What may separate the words and characters of the atoms in the text.
+    pub separators: Separators,
+    /// Match the words of spaced scripts, e.g. Latin, only as whole words, so that "cat" doesn't
+    /// match inside "concatenate". Words next to characters of unspaced scripts, e.g. "GPU" in
+    /// "GPU加速", aren't anchored there.
+    pub whole_words: bool,
+    /// Tolerate OCR errors in the words of spaced scripts, e.g. so that "hello world" matches
+    /// "hel1o wor|d": each character also matches those OCR mistakes it for, and up to this many
+    /// characters per word, at most 2, may be replaced, dropped or inserted, fewer in short words.
+    /// Best combined with `whole_words`, since a word within an edit of "bell" is within "belly".
+    pub fuzzy: Option<usize>,
+    pub flags: RegexFlags,
+}
+
+/// What may separate the words and characters of atoms in the text, by default any whitespace:
+/// some between the words of spaced scripts, and possibly none between the characters of
+/// unspaced scripts.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Separators {
+    /// Regex of what separates the words of an atom, instead of any whitespace (`\s+`), e.g.
+    /// `[\s_]+` so that "user id" also matches "user_id".
+    pub words: Option<String>,
+    /// Regex of what may separate consecutive characters of scripts written without spaces,
+    /// instead of any whitespace (`\s*`), e.g. `\s*(?:\n.{0,8}\n)?\s*` to also skip a short
+    /// noise line such as a page number inserted by PDF extraction.
+    pub cjk: Option<String>,
+    /// Let a hyphen, and the whitespace after it, split the words of spaced scripts between any
+    /// two letters, e.g. so that "information" matches "infor-\nmation" as extracted from a PDF.
+    pub hyphens: bool,
+}
+
+/// What may split a word between two letters with `Separators::hyphens`.
+const HYPHEN_BREAK: &str = "(?:-\\s*)?";
+
+impl Separators {
+    /// Regex of what separates the words of an atom.
+    fn word_gap(&self) -> String {
+        self.words.as_ref().map_or_else(|| "\\s+".to_string(), |gap| format!("(?:{})", gap))
+    }
+
+    /// Regex of what may separate the characters of unspaced scripts.
+    fn cjk_gap(&self) -> String {
+        self.cjk.as_ref().map_or_else(|| "\\s*".to_string(), |gap| format!("(?:{})", gap))
+    }
+}
+
+/// Flags of the regexes the atoms are compiled into, by default case-insensitive, with `^` and
+/// `$` matching at every line, `.` not matching line breaks and Unicode-aware classes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegexFlags {
+    /// Match the case of the atoms exactly, e.g. to search code for identifiers.
+    pub case_sensitive: bool,
+    /// `^` and `$` match at the start and end of every line, rather than only of the file.
+    pub multi_line: bool,
+    pub dot_matches_new_line: bool,
+    /// Classes such as `\b`, `\w` and `\s` match in any script, rather than only ASCII; only
+    /// turned off for the default engine.
+    pub unicode: bool,
+}
+
+impl Default for RegexFlags {
+    fn default() -> Self {
+        RegexFlags {
+            case_sensitive: false,
+            multi_line: true,
+            dot_matches_new_line: false,
+            unicode: true,
+        }
+    }
+}
 
+impl RegexFlags {
+    /// The inline flags turning the default flags into these, e.g. `(?-i)`, or nothing.
+    fn inline(self, default_engine: bool) -> String {
+        let mut flags = String::from(if self.dot_matches_new_line { "s" } else { "" });
+        let off: String = [(self.case_sensitive, 'i'), (!self.multi_line, 'm'), (!self.unicode && default_engine, 'u')]
+            .iter()
+            .filter_map(|&(on, flag)| on.then_some(flag))
+            .collect();
+        if !off.is_empty() {
+            flags.push('-');
+            flags.push_str(&off);
+        }
+        match flags.is_empty() {
+            true => flags,
+            false => format!("(?{})", flags),
+        }
+    }
+}
 
 /// AND of patterns, where each pattern except for the first is the OR of some sub-patterns.
 #[pyclass]
+#[derive(Clone, Debug)]
 pub struct QueryGroup {
-    pub patterns: Vec<Regex>,
+    pub patterns: Vec<Pattern>,
+    /// Patterns none of which may match, each the OR of some sub-patterns.
+    pub excluded: Vec<Pattern>,
+    /// Number of characters within which all the patterns must match, rather than anywhere
+    /// in the file.
+    pub within: Option<usize>,
+    /// Number of lines within which all the patterns must match, 0 for the same line, rather
+    /// than anywhere in the file. Combined with `within`, both must hold for the same matches.
+    pub within_lines: Option<usize>,
+    /// Number of characters by which each pattern after the first must have a match apart from
+    /// a match of the first, e.g. to tell a term cited in the bibliography from its use in the body.
+    pub apart: Option<usize>,
+    /// The OR groups of atoms the patterns were compiled from.
+    pub atoms: Vec<Vec<String>>,
+    /// The OR groups of atoms the excluded patterns were compiled from.
+    pub excluded_atoms: Vec<Vec<String>>,
+    /// The options they were compiled with.
+    pub query_options: QueryOptions,
+    /// Number of the alternatives of each OR group that must match, usually 1.
+    pub minimum_should_match: Vec<usize>,
+    /// Weight of each alternative of each OR group in the default relevance score, usually 1,
+    /// e.g. less for a weak synonym.
+    pub atom_weights: Vec<Vec<f64>>,
+    /// Factor of each OR group in the default relevance score, usually 1.
+    pub group_boosts: Vec<f64>,
+    /// The preset the query was made with, whose normalizers a search from Python applies unless
+    /// given another preset.
+    pub preset: Option<presets::Preset>,
+    /// Each alternative of the OR groups requiring more than one or weighted, compiled on its
+    /// own.
+    alternatives: Vec<Vec<Pattern>>,
+    /// The query compiled with kana folding, for Japanese documents, once needed.
+    kana_folded: Arc<OnceLock<Option<QueryGroup>>>,
 }
 
-#[pymethods]
 impl QueryGroup {
-    #[new]
-    pub fn new(and_of_or_atoms: Vec<Vec<String>>) -> PyResult<Self> {
+    pub fn new(and_of_or_atoms: Vec<Vec<String>>) -> Result<Self, QueryError> {
+        QueryGroup::with_options(and_of_or_atoms, QueryOptions::default())
+    }
+
+    pub fn with_options(and_of_or_atoms: Vec<Vec<String>>, options: QueryOptions) -> Result<Self, QueryError> {
+        QueryGroup::with_exclusions(and_of_or_atoms, Vec::new(), options)
+    }
+
+    /// The query of a query string, e.g. `foo AND (bar OR "baz qux") AND NOT quux`, parsed with
+    /// `Query::parse`.
+    pub fn parse(query: &str) -> Result<Self, QueryError> {
+        query::Query::parse(query)?.compile(QueryOptions::default())
+    }
+
+    /// Like `with_options`, but files where any OR group of `excluded_or_atoms` matches don't
+    /// match.
+    pub fn with_exclusions(and_of_or_atoms: Vec<Vec<String>>, excluded_or_atoms: Vec<Vec<String>>, options: QueryOptions) -> Result<Self, QueryError> {
         let mut patterns = Vec::new();
         if and_of_or_atoms.is_empty() {
-            return Err(PyValueError::new_err("query group must not be empty"));
+            return Err(QueryError::new("query group must not be empty"));
         }
         for or_grp in and_of_or_atoms.iter() {
-            patterns.push(get_regex_for_atoms(or_grp));
+            patterns.push(get_regex_for_atoms(or_grp, &options)?);
+        }
+        let mut excluded = Vec::new();
+        for or_grp in excluded_or_atoms.iter() {
+            excluded.push(get_regex_for_atoms(or_grp, &options)?);
         }
 
+        let groups = patterns.len();
+        let atom_weights = and_of_or_atoms.iter().map(|atoms| vec![1.0; atoms.len()]).collect();
         Ok(QueryGroup {
             patterns,
+            excluded,
+            within: None,
+            within_lines: None,
+            apart: None,
+            atoms: and_of_or_atoms,
+            excluded_atoms: excluded_or_atoms,
+            query_options: options,
+            minimum_should_match: vec![1; groups],
+            atom_weights,
+            group_boosts: vec![1.0; groups],
+            preset: None,
+            alternatives: vec![Vec::new(); groups],
+            kana_folded: Arc::default(),
+        })
+    }
+
+    /// Require a match of every pattern within the same window of `chars` characters.
+    pub fn within(mut self, chars: usize) -> Self {
+        self.within = Some(chars);
+        self
+    }
+
+    /// Require a match of every pattern on lines at most `lines` lines apart.
+    pub fn within_lines(mut self, lines: usize) -> Self {
+        self.within_lines = Some(lines);
+        self
+    }
+
+    /// Require each pattern after the first to match `chars` characters apart from the first.
+    pub fn apart(mut self, chars: usize) -> Self {
+        self.apart = Some(chars);
+        self
+    }
+
+    /// Require at least `counts[i]` alternatives of the `i`-th OR group to match, e.g. 2 of 5
+    /// related terms, rather than any one of them.
+    pub fn minimum_should_match(mut self, counts: Vec<usize>) -> Result<Self, QueryError> {
+        if counts.len() != self.atoms.len() {
+            return Err(QueryError::new(format!("{} minimum_should_match counts for {} OR groups", counts.len(), self.atoms.len())));
+        }
+        if let Some((atoms, n)) = self.atoms.iter().zip(counts.iter()).find(|(atoms, &n)| n == 0 || n > atoms.len()) {
+            return Err(QueryError::new(format!("minimum_should_match of {} for an OR group of {} alternatives", n, atoms.len())));
+        }
+        self.minimum_should_match = counts;
+        self.alternatives = self.compile_alternatives()?;
+        Ok(self)
+    }
+
+    /// Weigh the matches of each alternative of each OR group, and of each OR group as a whole,
+    /// in the default relevance score: the total number of matches, each counting as its weight
+    /// times the boost of its group.
+    pub fn weighted(mut self, atom_weights: Vec<Vec<f64>>, group_boosts: Vec<f64>) -> Result<Self, QueryError> {
+        let shape: Vec<_> = self.atoms.iter().map(Vec::len).collect();
+        if atom_weights.iter().map(Vec::len).collect::<Vec<_>>() != shape {
+            return Err(QueryError::new("there must be a weight for each atom of each OR group"));
+        }
+        if group_boosts.len() != shape.len() {
+            return Err(QueryError::new("there must be a boost for each OR group"));
+        }
+        self.atom_weights = atom_weights;
+        self.group_boosts = group_boosts;
+        self.alternatives = self.compile_alternatives()?;
+        Ok(self)
+    }
+
+    /// Whether any atom or OR group weighs other than 1.
+    pub(crate) fn is_weighted(&self) -> bool {
+        self.group_boosts.iter().any(|&boost| boost != 1.0) || self.atom_weights.iter().flatten().any(|&weight| weight != 1.0)
+    }
+
+    /// Whether the alternatives of the OR group at `group_idx` are matched one by one.
+    fn splits_alternatives(&self, group_idx: usize) -> bool {
+        self.minimum_should_match[group_idx] > 1 || self.atom_weights[group_idx].iter().any(|&w| w != 1.0)
+    }
+
+    fn compile_alternatives(&self) -> Result<Vec<Vec<Pattern>>, QueryError> {
+        (0..self.atoms.len())
+            .map(|i| match self.splits_alternatives(i) {
+                true => self.atoms[i].iter().map(|atom| get_regex_for_atoms(std::slice::from_ref(atom), &self.query_options)).collect(),
+                false => Ok(Vec::new()),
+            })
+            .collect()
+    }
+
+    /// A query matching where both this one and `other` do: their patterns ANDed and their
+    /// exclusions ORed, under the tighter of their proximity and separation constraints. The
+    /// patterns are reused as compiled; the options are those of this query.
+    pub fn and_with(&self, other: &QueryGroup) -> QueryGroup {
+        let tighter = |a: Option<usize>, b: Option<usize>, pick: fn(usize, usize) -> usize| match (a, b) {
+            (Some(a), Some(b)) => Some(pick(a, b)),
+            (a, b) => a.or(b),
+        };
+        QueryGroup {
+            patterns: self.patterns.iter().chain(other.patterns.iter()).cloned().collect(),
+            excluded: self.excluded.iter().chain(other.excluded.iter()).cloned().collect(),
+            within: tighter(self.within, other.within, usize::min),
+            within_lines: tighter(self.within_lines, other.within_lines, usize::min),
+            apart: tighter(self.apart, other.apart, usize::max),
+            atoms: self.atoms.iter().chain(other.atoms.iter()).cloned().collect(),
+            excluded_atoms: self.excluded_atoms.iter().chain(other.excluded_atoms.iter()).cloned().collect(),
+            query_options: self.query_options.clone(),
+            minimum_should_match: self.minimum_should_match.iter().chain(other.minimum_should_match.iter()).copied().collect(),
+            atom_weights: self.atom_weights.iter().chain(other.atom_weights.iter()).cloned().collect(),
+            group_boosts: self.group_boosts.iter().chain(other.group_boosts.iter()).copied().collect(),
+            preset: self.preset.or(other.preset),
+            alternatives: self.alternatives.iter().chain(other.alternatives.iter()).cloned().collect(),
+            kana_folded: Arc::default(),
+        }
+    }
+
+    /// This query with `atom` as another alternative of its OR group at `group_idx`, only that
+    /// group being compiled again.
+    pub fn add_or_atom(&self, group_idx: usize, atom: String) -> Result<QueryGroup, QueryError> {
+        let mut atoms = self.atoms.clone();
+        let or_grp = atoms
+            .get_mut(group_idx)
+            .ok_or_else(|| QueryError::new(format!("no OR group {} in a query of {}", group_idx, self.atoms.len())))?;
+        or_grp.push(atom.clone());
+        let mut query_group = QueryGroup {
+            atoms,
+            kana_folded: Arc::default(),
+            ..self.clone()
+        };
+        query_group.patterns[group_idx] = get_regex_for_atoms(&query_group.atoms[group_idx], &self.query_options)?;
+        query_group.atom_weights[group_idx].push(1.0);
+        if query_group.splits_alternatives(group_idx) {
+            query_group.alternatives[group_idx].push(get_regex_for_atoms(&[atom], &self.query_options)?);
+        }
+        Ok(query_group)
+    }
+
+    /// The query to match documents in `language` with, i.e. with kana folding for Japanese.
+    fn for_language(&self, language: &str) -> &QueryGroup {
+        if language != "ja" || self.query_options.kana_folding {
+            return self;
+        }
+        let folded = self.kana_folded.get_or_init(|| {
+            let options = QueryOptions {
+                kana_folding: true,
+                ..self.query_options.clone()
+            };
+            let compiled = QueryGroup::with_exclusions(self.atoms.clone(), self.excluded_atoms.clone(), options).ok()?;
+            let mut folded = QueryGroup {
+                patterns: compiled.patterns,
+                excluded: compiled.excluded,
+                query_options: compiled.query_options,
+                kana_folded: Arc::default(),
+                ..self.clone()
+            };
+            folded.alternatives = folded.compile_alternatives().ok()?;
+            Some(folded)
+        });
+        folded.as_ref().unwrap_or(self)
+    }
+
+    /// Whether an excluded pattern matches `contents`.
+    fn is_excluded(&self, contents: &str) -> bool {
+        self.excluded.iter().any(|pat| pat.is_match(contents))
+    }
+
+    /// Whether the matches must lie within some number of characters or lines of each other.
+    fn is_proximate(&self) -> bool {
+        self.within.is_some() || self.within_lines.is_some()
+    }
+
+    /// The byte range of `contents` from the start to the end of the first matches, by where
+    /// they end, satisfying the proximity constraints, if there are any.
+    fn proximity_window(&self, contents: &str) -> Option<(usize, usize)> {
+        match self.is_proximate() {
+            true => proximity_window(&self.patterns, contents, self.within, self.within_lines),
+            false => None,
+        }
+    }
+
+    /// The byte range of the first match of the first pattern in `contents`, or in `window` of
+    /// it if given.
+    fn first_match(&self, contents: &str, window: Option<(usize, usize)>) -> Option<(usize, usize)> {
+        let (start, end) = window.unwrap_or((0, contents.len()));
+        self.patterns[0]
+            .find_iter(contents)
+            .find(|m| m.start() >= start && m.end() <= end)
+            .map(|m| (m.start(), m.end()))
+    }
+
+    /// Whether the proximity and separation constraints, if any, hold in `contents`.
+    fn is_placed(&self, contents: &str) -> bool {
+        (!self.is_proximate() || self.proximity_window(contents).is_some())
+            && self.apart.is_none_or(|chars| apart_from_first(&self.patterns, contents, chars))
+    }
+
+    /// Whether enough alternatives of each OR group match `contents`.
+    fn has_enough_alternatives(&self, contents: &str) -> bool {
+        self.alternatives
+            .iter()
+            .zip(self.minimum_should_match.iter())
+            .all(|(alternatives, &n)| n <= 1 || alternatives.iter().filter(|pat| pat.is_match(contents)).take(n).count() == n)
+    }
+}
+
+/// The first window of `contents`, by where it ends, containing a match of each pattern and
+/// spanning at most `chars` characters and `lines` lines, as a byte range.
+fn proximity_window(patterns: &[Pattern], contents: &str, chars: Option<usize>, lines: Option<usize>) -> Option<(usize, usize)> {
+    let mut matches: Vec<_> = patterns
+        .iter()
+        .enumerate()
+        .flat_map(|(i, pat)| pat.find_iter(contents).map(move |m| (m.end(), m.start(), i)))
+        .collect();
+    matches.sort_unstable();
+    let mut offsets: Vec<_> = matches.iter().flat_map(|&(end, start, _)| [start, end]).collect();
+    offsets.sort_unstable();
+    offsets.dedup();
+    let char_offset = char_offsets(contents, &offsets);
+    let line_offset = line_offsets(contents, &offsets);
+    let index = |byte: usize| offsets.binary_search(&byte).unwrap();
+    // sweeping the matches by end, the window ending there holds a match of each pattern iff
+    // the latest start of each pattern so far is inside it
+    let mut latest_start = vec![None; patterns.len()];
+    for &(end, start, i) in matches.iter() {
+        latest_start[i] = latest_start[i].max(Some(start));
+        let Some(first) = latest_start.iter().copied().min().flatten() else {
+            continue;
+        };
+        let (first_index, end_index) = (index(first), index(end));
+        if chars.is_none_or(|chars| char_offset[first_index] + chars >= char_offset[end_index])
+            && lines.is_none_or(|lines| line_offset[first_index] + lines >= line_offset[end_index])
+        {
+            return Some((first, end));
+        }
+    }
+    None
+}
+
+/// Whether each pattern after the first has a match at least `chars` characters apart from a
+/// match of the first, counting the characters between the two matches.
+fn apart_from_first(patterns: &[Pattern], contents: &str, chars: usize) -> bool {
+    // the matches found are in order, so the first ends earliest and the last starts latest
+    let extremes = |pat: &Pattern| {
+        let first_end = pat.find(contents)?.end();
+        let last_start = pat.find_iter(contents).last()?.start();
+        Some((first_end, last_start))
+    };
+    let gap = |end: usize, start: usize| if start > end { contents[end..start].chars().count() } else { 0 };
+    let Some((first_end, last_start)) = extremes(&patterns[0]) else {
+        return false;
+    };
+    patterns[1..].iter().all(|pat| {
+        extremes(pat).is_some_and(|(end, start)| gap(first_end, start) >= chars || gap(end, last_start) >= chars)
+    })
+}
+
+/// The line numbers, from 0, of the sorted byte `offsets` of `contents`.
+fn line_offsets(contents: &str, offsets: &[usize]) -> Vec<usize> {
+    let (mut line, mut last) = (0, 0);
+    offsets
+        .iter()
+        .map(|&offset| {
+            line += contents[last..offset].matches('\n').count();
+            last = offset;
+            line
+        })
+        .collect()
+}
+
+/// The character offsets of the sorted byte `offsets` of `contents`.
+fn char_offsets(contents: &str, offsets: &[usize]) -> Vec<usize> {
+    let mut result = Vec::with_capacity(offsets.len());
+    let mut chars = contents.char_indices().map(|(i, _)| i).chain([contents.len()]).enumerate();
+    for &offset in offsets.iter() {
+        for (n, i) in chars.by_ref() {
+            if i == offset {
+                result.push(n);
+                break;
+            }
+        }
+    }
+    result
+}
+
+#[pymethods]
+impl QueryGroup {
+    #[new]
+    #[pyo3(signature = (and_of_or_atoms, kana_folding = false, romaji = false, romaji_dictionary = None, phonetic = false, case_folding = None, cjk_gap = None, within = None, apart = None, minimum_should_match = None, weights = None, boosts = None, preset = None, exclude = None, case_sensitive = false, multi_line = true, dot_matches_new_line = false, unicode = true, within_lines = None, whole_words = false, nfkc = false, fuzzy = None, word_gap = None, hyphens = false))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        and_of_or_atoms: Vec<Vec<String>>,
+        kana_folding: bool,
+        romaji: bool,
+        romaji_dictionary: Option<HashMap<String, Vec<String>>>,
+        phonetic: bool,
+        case_folding: Option<&str>,
+        cjk_gap: Option<String>,
+        within: Option<usize>,
+        apart: Option<usize>,
+        minimum_should_match: Option<Vec<usize>>,
+        weights: Option<Vec<Vec<f64>>>,
+        boosts: Option<Vec<f64>>,
+        preset: Option<&str>,
+        exclude: Option<Vec<Vec<String>>>,
+        case_sensitive: bool,
+        multi_line: bool,
+        dot_matches_new_line: bool,
+        unicode: bool,
+        within_lines: Option<usize>,
+        whole_words: bool,
+        nfkc: bool,
+        fuzzy: Option<usize>,
+        word_gap: Option<String>,
+        hyphens: bool,
+    ) -> PyResult<Self> {
+        let preset = preset.map(presets::Preset::from_name).transpose()?;
+        let options = QueryOptions {
+            kana_folding,
+            romaji,
+            romaji_dictionary: romaji_dictionary.unwrap_or_default(),
+            phonetic,
+            case_folding: case_folding.map(CaseFold::from_name).transpose()?,
+            nfkc,
+            separators: Separators {
+                words: word_gap,
+                cjk: cjk_gap,
+                hyphens,
+            },
+            whole_words,
+            fuzzy,
+            flags: RegexFlags {
+                case_sensitive,
+                multi_line,
+                dot_matches_new_line,
+                unicode,
+            },
+        };
+        let options = match preset {
+            Some(preset) => preset.query_options(options),
+            None => options,
+        };
+        let query_group = QueryGroup::with_exclusions(and_of_or_atoms, exclude.unwrap_or_default(), options)?;
+        let query_group = match minimum_should_match {
+            Some(counts) => query_group.minimum_should_match(counts)?,
+            None => query_group,
+        };
+        let query_group = match (weights, boosts) {
+            (None, None) => query_group,
+            (weights, boosts) => {
+                let weights = weights.unwrap_or_else(|| query_group.atom_weights.clone());
+                let boosts = boosts.unwrap_or_else(|| query_group.group_boosts.clone());
+                query_group.weighted(weights, boosts)?
+            }
+        };
+        Ok(QueryGroup {
+            within,
+            within_lines,
+            apart,
+            preset,
+            ..query_group
         })
     }
+
+    /// The query of a query string, e.g. `foo AND (bar OR "baz qux") AND NOT quux`, with the
+    /// same options as a query given its OR groups.
+    #[staticmethod]
+    #[pyo3(name = "parse")]
+    #[pyo3(signature = (query, kana_folding = false, romaji = false, romaji_dictionary = None, phonetic = false, case_folding = None, cjk_gap = None, within = None, apart = None, preset = None, case_sensitive = false, multi_line = true, dot_matches_new_line = false, unicode = true, within_lines = None, whole_words = false, nfkc = false, fuzzy = None, word_gap = None, hyphens = false))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_parse(
+        query: &str,
+        kana_folding: bool,
+        romaji: bool,
+        romaji_dictionary: Option<HashMap<String, Vec<String>>>,
+        phonetic: bool,
+        case_folding: Option<&str>,
+        cjk_gap: Option<String>,
+        within: Option<usize>,
+        apart: Option<usize>,
+        preset: Option<&str>,
+        case_sensitive: bool,
+        multi_line: bool,
+        dot_matches_new_line: bool,
+        unicode: bool,
+        within_lines: Option<usize>,
+        whole_words: bool,
+        nfkc: bool,
+        fuzzy: Option<usize>,
+        word_gap: Option<String>,
+        hyphens: bool,
+    ) -> PyResult<Self> {
+        let (and_of_or_atoms, excluded) = query::Query::parse(query)?.to_or_groups()?;
+        QueryGroup::py_new(
+            and_of_or_atoms, kana_folding, romaji, romaji_dictionary, phonetic, case_folding, cjk_gap, within, apart, None, None, None, preset, Some(excluded),
+            case_sensitive, multi_line, dot_matches_new_line, unicode, within_lines, whole_words, nfkc, fuzzy, word_gap, hyphens,
+        )
+    }
+
+    /// A query matching where both this one and `other` do.
+    #[pyo3(name = "and_with")]
+    fn py_and_with(&self, other: &QueryGroup) -> QueryGroup {
+        self.and_with(other)
+    }
+
+    /// This query with `atom` as another alternative of its OR group at `group_idx`.
+    #[pyo3(name = "add_or_atom")]
+    fn py_add_or_atom(&self, group_idx: usize, atom: String) -> PyResult<QueryGroup> {
+        Ok(self.add_or_atom(group_idx, atom)?)
+    }
+
+    /// The OR groups of atoms of the query.
+    #[getter(atoms)]
+    fn py_atoms(&self) -> Vec<Vec<String>> {
+        self.atoms.clone()
+    }
+
+    /// The regex of each AND group.
+    #[getter]
+    fn regexes(&self) -> Vec<String> {
+        self.patterns.iter().map(|p| p.as_str().to_string()).collect()
+    }
+
+    /// The regex of each excluded OR group.
+    #[getter]
+    fn excluded_regexes(&self) -> Vec<String> {
+        self.excluded.iter().map(|p| p.as_str().to_string()).collect()
+    }
+
+    /// Number of characters within which all the patterns must match, if limited.
+    #[getter(within)]
+    fn py_within(&self) -> Option<usize> {
+        self.within
+    }
+
+    /// Number of lines within which all the patterns must match, if limited.
+    #[getter(within_lines)]
+    fn py_within_lines(&self) -> Option<usize> {
+        self.within_lines
+    }
+
+    /// Number of characters by which the patterns must match apart from the first, if required.
+    #[getter(apart)]
+    fn py_apart(&self) -> Option<usize> {
+        self.apart
+    }
+
+    /// Number of the alternatives of each OR group that must match.
+    #[getter(minimum_should_match)]
+    fn py_minimum_should_match(&self) -> Vec<usize> {
+        self.minimum_should_match.clone()
+    }
+
+    #[getter(weights)]
+    fn py_weights(&self) -> Vec<Vec<f64>> {
+        self.atom_weights.clone()
+    }
+
+    #[getter(boosts)]
+    fn py_boosts(&self) -> Vec<f64> {
+        self.group_boosts.clone()
+    }
+}
+
+/// Unit of the window of context around a match.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ContextUnit {
+    #[default]
+    Bytes,
+    /// Unicode characters, e.g. for CJK text where a character takes 3 bytes.
+    Chars,
+    /// Whole lines around the lines of the match, like `grep -B`/`-A`.
+    Lines,
+}
+
+/// How the context around the first pattern's match is extracted.
+#[derive(Clone, Debug, Default)]
+pub struct ContextOptions {
+    /// Number of units before the match.
+    pub before: usize,
+    /// Number of units after the match.
+    pub after: usize,
+    /// What `before` and `after` count.
+    pub unit: ContextUnit,
+    /// Trim contexts longer than this many characters to that many around the match, so that
+    /// long lines or large windows can't make the results too large.
+    pub max_chars: Option<usize>,
+    /// Inserted before each matched span in the context, e.g. `<mark>` or `**`.
+    pub highlight_begin: String,
+    /// Inserted after each matched span in the context, e.g. `</mark>` or `**`.
+    pub highlight_end: String,
+    /// Highlight the matches of all the patterns rather than only of the first, replacing
+    /// `{group}` in the markers with the index of the pattern, e.g. `<mark class="g{group}">`.
+    pub highlight_all: bool,
+    /// Only require the other patterns to match within the context, trying each match of the
+    /// first pattern in turn, to find passages about one thing that also mention another.
+    pub scoped: bool,
+    /// Prepend and append `…` to contexts that stop short of the start and the end of the file.
+    pub ellipsis: bool,
+    /// Tidy the contexts into snippets, e.g. to show them in a list of results: cut between
+    /// words rather than within them, with each run of whitespace, e.g. a line break, collapsed
+    /// into a space, and `…` where they stop short of the file, as with `ellipsis`. Their length
+    /// is capped with `max_chars`.
+    pub snippet: bool,
+    /// Also extract the contexts of the matches of all the patterns, in order, up to this many
+    /// per file, into `contexts`. Overlapping contexts are merged.
+    pub max_contexts: Option<usize>,
+    /// Also extract the context of the first match of each pattern, i.e. of each AND group, into
+    /// `clause_contexts`, e.g. to show the evidence for every part of the query.
+    pub clause_contexts: bool,
+}
+
+/// Options that control how files are read and what is reported for matching files.
+#[derive(Clone, Debug)]
+pub struct SearchOptions {
+    /// Search the files in parallel.
+    pub parallel: bool,
+    /// Search them on a pool of this many threads, built for the search, rather than on the
+    /// global pool of as many threads as CPUs, e.g. to leave some of the CPUs to other work.
+    pub num_threads: Option<usize>,
+    /// Extract a context around the first pattern's match in each matching file.
+    pub context: Option<ContextOptions>,
+    /// Encodings of specific files; other files are read as UTF-8.
+    pub encoding_hints: EncodingHints,
+    /// Reuse the outcomes of unchanged files from previous searches with the same query, and
+    /// record the new ones. Call `ResultCache::save` afterwards to persist them.
+    pub cache: Option<ResultCache>,
+    /// Applied in order to the contents of each file before matching.
+    pub normalizers: Vec<Arc<dyn Normalizer>>,
+    /// Rank the results by score, best first, instead of keeping them in the order of the paths.
+    pub scoring: Option<ScoringOptions>,
+    /// Report the files that don't match instead, like `grep -L`; their results have no context.
+    pub invert: bool,
+    /// Detect the dominant language of each file, recorded as its `language` tag, and match
+    /// Japanese files with kana folding.
+    pub detect_language: bool,
+    /// Limit how many files are read at once, e.g. on network storage, while the files read
+    /// are still matched on all the threads.
+    pub read_limit: Option<ReadLimit>,
+    /// Skip files larger than this many bytes, e.g. logs or dumps among documents.
+    pub max_file_size: Option<u64>,
+    /// Hold at most about this many bytes of files in memory at once, the threads waiting to
+    /// read theirs meanwhile, so that large files searched together don't exhaust the memory.
+    /// Files are counted by their size on disk; files read in chunks aren't counted.
+    pub memory_budget: Option<MemoryBudget>,
+    /// Map the files at least this many bytes long into memory and match them in place, rather
+    /// than copying them into a string, if they're UTF-8 and read whole as they are; the others
+    /// are read as usual. Mapped files aren't counted by `memory_budget`. A file changed while
+    /// it's matched is read again instead, but one truncated while it's mapped crashes the
+    /// process with SIGBUS, so corpora being written mustn't be searched this way.
+    pub mmap_threshold: Option<u64>,
+    /// Stop searching at this instant, returning the matches found so far; the report tells
+    /// whether the search timed out.
+    pub deadline: Option<Instant>,
+    /// Give up on the files taking this long to read and match, e.g. with a pathological
+    /// pattern, reported as skipped for being `timed_out`. Files are given up on between reading
+    /// and matching them, and between their chunks; a file that took too long to match is still
+    /// given up on once matched, so that its match doesn't depend on the load of the machine.
+    pub file_timeout: Option<Duration>,
+    /// Stop searching once this many files matched, e.g. to show the first page of hits of a
+    /// broad query; the report tells whether files were left unsearched. The matches are the
+    /// first ones in the order of the paths, unless searching in parallel, when they're those
+    /// found first.
+    pub max_results: Option<usize>,
+    /// Profile the search, recording in the report the time spent per pattern and this many of
+    /// the slowest files.
+    pub profile: Option<usize>,
+    /// Read and match only the first this many bytes of each file, e.g. to screen the titles and
+    /// abstracts of large documents.
+    pub head_bytes: Option<u64>,
+    /// Read and match only the last this many bytes of each file, e.g. to screen conclusions, or
+    /// the latest entries of growing logs. Not combined with `head_bytes`, which takes precedence.
+    pub tail_bytes: Option<u64>,
+    /// Read and match only these parts of the files they're given for.
+    pub ranges: Option<Arc<ranges::FileRanges>>,
+    /// Match only the comments and string literals of source files, or only the code without
+    /// them, for the languages known by their extension.
+    pub code: Option<code::CodeMode>,
+    /// Read and match the files a chunk at a time, so that files larger than memory can be
+    /// searched. Not combined with `head_bytes`, `tail_bytes` or `ranges`, which take precedence,
+    /// nor with `code`, scoring or scoped contexts.
+    pub chunked: Option<chunked::ChunkOptions>,
+    /// Tell how many of the files have been searched as the search goes.
+    pub progress: Option<progress::Progress>,
+    /// Stop searching once cancelled, returning the matches found so far; the report tells
+    /// whether the search was cancelled.
+    pub cancel: Option<progress::CancelToken>,
+    /// Decompress the files compressed with gzip, bzip2, xz or zstd, told from their first bytes,
+    /// with the system's decompressors. Their head or tail is that of the decompressed contents,
+    /// while their ranges aren't read, nor are they read in chunks; `max_file_size` applies to
+    /// their compressed size.
+    pub decompress: bool,
+    /// What is done with binary files, told by a NUL byte near their start. Files read in
+    /// chunks aren't told apart.
+    pub binary: binary::BinaryPolicy,
+    /// Search each file once however many of the paths lead to it, e.g. through symbolic links,
+    /// the first of them kept, and what is done with symbolic links; the report tells how many
+    /// paths were collapsed.
+    pub dedup_paths: Option<paths::SymlinkPolicy>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            parallel: true,
+            num_threads: None,
+            context: None,
+            encoding_hints: EncodingHints::default(),
+            cache: None,
+            normalizers: Vec::new(),
+            scoring: None,
+            invert: false,
+            detect_language: false,
+            read_limit: None,
+            max_file_size: None,
+            memory_budget: None,
+            mmap_threshold: None,
+            deadline: None,
+            file_timeout: None,
+            max_results: None,
+            profile: None,
+            head_bytes: None,
+            tail_bytes: None,
+            ranges: None,
+            code: None,
+            chunked: None,
+            progress: None,
+            cancel: None,
+            decompress: false,
+            binary: binary::BinaryPolicy::Skip,
+            dedup_paths: None,
+        }
+    }
+}
+
+/// Where the first match of the first AND group is in the contents searched, i.e. decoded and
+/// normalized, and only the parts searched of files searched in part.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchPosition {
+    /// Counted from 1.
+    pub line: usize,
+    /// In characters, counted from 1.
+    pub column: usize,
+    /// Byte offsets.
+    pub span: (usize, usize),
+}
+
+impl MatchPosition {
+    fn of(contents: &str, start: usize, end: usize) -> Self {
+        let line_start = contents[..start].rfind('\n').map_or(0, |i| i + 1);
+        MatchPosition {
+            line: contents.as_bytes()[..line_start].iter().filter(|&&b| b == b'\n').count() + 1,
+            column: contents[line_start..start].chars().count() + 1,
+            span: (start, end),
+        }
+    }
 }
 
 #[pyclass]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct FileMatchResult {
     #[pyo3(get)]
     path: String,
 
     #[pyo3(get)]
     context: Option<String>,
+
+    /// The tags of the file in `FilePaths`, if any.
+    #[pyo3(get)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    tags: Tags,
+
+    /// The score of the file when the results are ranked.
+    #[pyo3(get)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    score: Option<f64>,
+
+    /// Whether the context stops short of the start of the file.
+    #[pyo3(get)]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    truncated_before: bool,
+
+    /// Whether the context stops short of the end of the file.
+    #[pyo3(get)]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    truncated_after: bool,
+
+    /// Where the file first matches, unless inverted; for a proximity query, where the first
+    /// pattern matches among the first matches close enough to each other.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    position: Option<MatchPosition>,
+
+    /// The contexts of all the matches, when extracted with `max_contexts`.
+    #[pyo3(get)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    contexts: Vec<String>,
+
+    /// The index of each AND group with the context of its first match, when extracted with
+    /// `clause_contexts`; the match is highlighted, or all of them with `highlight_all`.
+    #[pyo3(get)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    clause_contexts: Vec<(usize, String)>,
+
+    /// Character offsets of the highlighted matches within `context`, markers left out, e.g. to
+    /// highlight them in a UI without matching again.
+    #[pyo3(get)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    highlights: Vec<(usize, usize)>,
+}
+
+impl FileMatchResult {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn context(&self) -> Option<&str> {
+        self.context.as_deref()
+    }
+
+    pub fn contexts(&self) -> &[String] {
+        &self.contexts
+    }
+
+    pub fn clause_contexts(&self) -> &[(usize, String)] {
+        &self.clause_contexts
+    }
+
+    pub fn highlights(&self) -> &[(usize, usize)] {
+        &self.highlights
+    }
+
+    pub fn tags(&self) -> &Tags {
+        &self.tags
+    }
+
+    pub fn score(&self) -> Option<f64> {
+        self.score
+    }
+
+    /// Whether the context stops short of the start and of the end of the file, apart from
+    /// whitespace.
+    pub fn truncated(&self) -> (bool, bool) {
+        (self.truncated_before, self.truncated_after)
+    }
+
+    pub fn position(&self) -> Option<MatchPosition> {
+        self.position
+    }
+
+    /// The result as a JSON object, as a line of `save_results` or of the command-line tool's
+    /// `--json` output.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("results serialize")
+    }
+
+    /// The context of the match of `query_group` in the file, extracted anew with `options`,
+    /// e.g. to show a larger window than searched with. The contents are taken from `corpus` if
+    /// it caches them, or else read again. `None` if the file doesn't match anymore.
+    pub fn expand_context(&self, query_group: &QueryGroup, options: &SearchOptions, corpus: Option<&corpus::Corpus>) -> PyResult<Option<String>> {
+        let contents = match corpus.and_then(|corpus| corpus.cached_contents(&self.path, options)) {
+            Some(contents) => contents,
+            None => read_contents(&self.path, options).map_err(|e| PyIOError::new_err(format!("{}: {}", self.path, e)))?,
+        };
+        Ok(match_contents(query_group, &self.path, &contents, options).and_then(|(result, _)| result.context))
+    }
+}
+
+#[pymethods]
+impl FileMatchResult {
+    /// The line of the first match, counted from 1.
+    #[getter]
+    fn line(&self) -> Option<usize> {
+        self.position.map(|position| position.line)
+    }
+
+    /// The column of the first match in characters, counted from 1.
+    #[getter]
+    fn column(&self) -> Option<usize> {
+        self.position.map(|position| position.column)
+    }
+
+    /// The byte offsets of the first match in the contents searched.
+    #[getter]
+    fn span(&self) -> Option<(usize, usize)> {
+        self.position.map(|position| position.span)
+    }
+
+    #[pyo3(name = "to_json")]
+    fn py_to_json(&self) -> String {
+        self.to_json()
+    }
+
+    /// The context of the match of `query_group` extracted anew, e.g. with a larger window
+    /// than searched with when a user expands a result. The keyword arguments are those of
+    /// `search_text`; `corpus` names a registered corpus to take cached contents from.
+    #[pyo3(name = "expand_context")]
+    #[pyo3(signature = (query_group, a = None, b = None, context_chars = false, before_lines = None, after_lines = None, highlight_begin = String::new(), highlight_end = String::new(), highlight_all = false, ellipsis = false, snippet = false, encodings = None, normalizers = None, corpus = None))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_expand_context(
+        &self,
+        py: Python<'_>,
+        query_group: &QueryGroup,
+        a: Option<usize>,
+        b: Option<usize>,
+        context_chars: bool,
+        before_lines: Option<usize>,
+        after_lines: Option<usize>,
+        highlight_begin: String,
+        highlight_end: String,
+        highlight_all: bool,
+        ellipsis: bool,
+        snippet: bool,
+        encodings: Option<&PyDict>,
+        normalizers: Option<&PyAny>,
+        corpus: Option<&str>,
+    ) -> PyResult<Option<String>> {
+        let read = options::ReadArgs {
+            encodings,
+            normalizers,
+            ..Default::default()
+        };
+        let context = options::ContextArgs {
+            a,
+            b,
+            context_chars,
+            before_lines,
+            after_lines,
+            highlight_begin,
+            highlight_end,
+            highlight_all,
+            ellipsis,
+            snippet,
+            ..Default::default()
+        };
+        let search = options::SearchKwargs::new(Some(query_group), None, read, context, Default::default(), Default::default())?;
+        if search.options.context.is_none() {
+            return Err(PyValueError::new_err("a and b, or a line context, are required"));
+        }
+        let corpus = match corpus {
+            Some(name) => Some(corpus::get_corpus(name).ok_or_else(|| PyValueError::new_err(format!("no corpus named {:?}", name)))?),
+            None => None,
+        };
+        let context = py.allow_threads(|| self.expand_context(query_group, &search.options, corpus.as_deref()))?;
+        match search.take_error() {
+            Some(e) => Err(e),
+            None => Ok(context),
+        }
+    }
 }
 
 fn is_match_str(query_group: &QueryGroup, contents: &str) -> bool {
@@ -44,383 +1111,4782 @@ fn is_match_str(query_group: &QueryGroup, contents: &str) -> bool {
             return false;
         }
     }
-    true
+    !query_group.is_excluded(contents) && query_group.is_placed(contents) && query_group.has_enough_alternatives(contents)
 }
 
 fn count_match_str(query_group: &QueryGroup, contents: &str) -> Option<usize> {
     if query_group.patterns.len() > 1 {
         return None
     }
+    if query_group.is_excluded(contents) || !query_group.has_enough_alternatives(contents) {
+        return Some(0);
+    }
     let pat = &query_group.patterns[0];
     let matches: Vec<_> = pat.find_iter(contents).collect();
     Some(matches.len())
 }
 
-fn is_match(query_group: &QueryGroup, path: &str) -> Option<FileMatchResult> {
-    match fs::read_to_string(path) {
-        Ok(contents) => {
-            for pat in query_group.patterns.iter() {
-                if !pat.is_match(&contents) {
-                    return None;
-                }
-            }
-            Some(FileMatchResult {
-                path: String::from(path),
-                context: None,
-            })
-        }
-        Err(_) => None
+fn is_match(query_group: &QueryGroup, path: &str, contents: &str) -> Option<FileMatchResult> {
+    if !is_match_str(query_group, contents) {
+        return None;
     }
+    let window = query_group.proximity_window(contents);
+    let position = query_group.first_match(contents, window).map(|(start, end)| MatchPosition::of(contents, start, end));
+    Some(FileMatchResult {
+        path: String::from(path),
+        context: None,
+        tags: Tags::new(),
+        score: None,
+        truncated_before: false,
+        truncated_after: false,
+        position,
+        contexts: Vec::new(),
+        clause_contexts: Vec::new(),
+        highlights: Vec::new(),
+    })
 }
 
-fn is_match_context(query_group: &QueryGroup, path: &str, a: usize, b: usize) -> Option<FileMatchResult> {
-    match fs::read_to_string(path) {
-        Ok(contents) => {
-            let mut context = None;
-            for (i, pat) in query_group.patterns.iter().enumerate() {
-                if i == 0 {
-                    match pat.find(&contents) {
-                        None => return None,
-                        Some(m) => {
-                            let approx_start = if m.start() < a { 0 } else { m.start() - a };
-                            let approx_end = if m.end() + b > contents.len() { contents.len() } else { m.end() + b };
-                            context = Some(String::from(approx_substring(&contents, approx_start, approx_end)));
-                        }
-                    }
-                } else {
-                    if !pat.is_match(&contents) {
-                        return None;
-                    }
-                }
-            }
-            Some(FileMatchResult {
-                path: String::from(path),
-                context,
-            })
+fn is_match_context(query_group: &QueryGroup, path: &str, contents: &str, options: &ContextOptions) -> Option<FileMatchResult> {
+    let first = &query_group.patterns[0];
+    let others = &query_group.patterns[1..];
+    let (span, (start, end)) = if options.scoped {
+        // the first match whose context holds matches of all the other patterns
+        first.find_iter(contents).find_map(|m| {
+            let (start, end) = context_range(contents, m.start(), m.end(), options);
+            others.iter().all(|pat| pat.is_match(&contents[start..end])).then_some(((m.start(), m.end()), (start, end)))
+        })?
+    } else {
+        if !others.iter().all(|pat| pat.is_match(contents)) {
+            return None;
         }
-        Err(_) => None
+        // the context of a proximity query is around the matches close enough to each other
+        let window = query_group.proximity_window(contents);
+        let span = query_group.first_match(contents, window)?;
+        let (start, end) = window.unwrap_or(span);
+        (span, context_range(contents, start, end, options))
+    };
+    if query_group.is_excluded(contents) || !query_group.is_placed(contents) || !query_group.has_enough_alternatives(contents) {
+        return None;
     }
+    Some(context_result(query_group, path, contents, span, (start, end), options))
 }
 
-pub fn search_text(query_group: &QueryGroup, textfile_paths: &[String], parallel: bool) -> Vec<FileMatchResult> {
-    if parallel {
-        textfile_paths
-            .par_iter()
-            .filter_map(|path| is_match(&query_group, path))
-            .collect()
-    } else {
-        textfile_paths
+/// The result of a file whose first match, at `span`, has its context at `start..end`.
+fn context_result(query_group: &QueryGroup, path: &str, contents: &str, span: (usize, usize), (start, end): (usize, usize), options: &ContextOptions) -> FileMatchResult {
+    let truncated_before = !contents[..start].trim().is_empty();
+    let truncated_after = !contents[end..].trim().is_empty();
+    let highlighted = |group: usize| if options.highlight_all { &query_group.patterns[..] } else { &query_group.patterns[group..=group] };
+    let extract = |start: usize, end: usize, highlighted: &[Pattern]| {
+        let (mut context, mut highlights) = highlight(contents, start, end, highlighted, options);
+        if options.ellipsis || options.snippet {
+            if !contents[..start].trim().is_empty() {
+                context.insert(0, '…');
+                shift_highlights(&mut highlights, 1);
+            }
+            if !contents[end..].trim().is_empty() {
+                context.push('…');
+            }
+        }
+        (context, highlights)
+    };
+    let contexts = match options.max_contexts {
+        Some(max) => context_ranges(contents, &query_group.patterns, options, max).into_iter().map(|(start, end)| extract(start, end, highlighted(0)).0).collect(),
+        None => Vec::new(),
+    };
+    let clause_contexts = match options.clause_contexts {
+        true => query_group
+            .patterns
             .iter()
-            .filter_map(|path| is_match(&query_group, path))
-            .collect()
+            .enumerate()
+            .filter_map(|(group, pat)| {
+                let m = pat.find(contents)?;
+                let (start, end) = context_range(contents, m.start(), m.end(), options);
+                Some((group, extract(start, end, highlighted(group)).0))
+            })
+            .collect(),
+        false => Vec::new(),
+    };
+    let (context, highlights) = extract(start, end, highlighted(0));
+    FileMatchResult {
+        path: String::from(path),
+        context: Some(context),
+        tags: Tags::new(),
+        score: None,
+        truncated_before,
+        truncated_after,
+        position: Some(MatchPosition::of(contents, span.0, span.1)),
+        contexts,
+        clause_contexts,
+        highlights,
     }
 }
 
-pub fn search_text_context(query_group: &QueryGroup, textfile_paths: &[String], a: usize, b: usize, parallel: bool) -> Vec<FileMatchResult> {
-    if parallel {
-        textfile_paths
-            .par_iter()
-            .filter_map(|path| is_match_context(&query_group, path, a, b))
-            .collect()
-    } else {
-        textfile_paths
-            .iter()
-            .filter_map(|path| is_match_context(&query_group, path, a, b))
-            .collect()
+/// Move the highlights by `chars` characters, once inserted before them.
+fn shift_highlights(highlights: &mut [(usize, usize)], chars: usize) {
+    for (start, end) in highlights.iter_mut() {
+        (*start, *end) = (*start + chars, *end + chars);
     }
 }
 
-fn approx_substring(
-    contents: &str,
-    approx_start_byte_index: usize,
-    approx_end_byte_index: usize,
-) -> &str {
-    let end = contents.len();
-    let mut start_byte_index = approx_start_byte_index;
-    let mut end_byte_index = approx_end_byte_index;
-    while start_byte_index <= end {
-        if contents.is_char_boundary(start_byte_index) {
+/// Byte ranges of the contexts around the matches of all the `patterns`, in order, overlapping
+/// ones merged, up to `max` of them.
+fn context_ranges(contents: &str, patterns: &[Pattern], options: &ContextOptions, max: usize) -> Vec<(usize, usize)> {
+    let mut spans: Vec<_> = patterns
+        .iter()
+        .flat_map(|pat| pat.find_iter(contents).filter(|m| m.start() < m.end()).map(|m| (m.start(), m.end())))
+        .collect();
+    spans.sort_unstable();
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in spans {
+        let (context_start, context_end) = context_range(contents, start, end, options);
+        if let Some(last) = ranges.last_mut().filter(|last| context_start <= last.1) {
+            last.1 = last.1.max(context_end);
+        } else if ranges.len() == max {
             break;
+        } else {
+            ranges.push((context_start, context_end));
         }
-        start_byte_index += 1;
     }
-    // when `end_byte_index` reaches 0, it must break out of the loop
-    loop {
-        if contents.is_char_boundary(end_byte_index) {
-            break;
+    ranges
+}
+
+/// Byte range of the context around the match at `start..end`.
+fn context_range(contents: &str, start: usize, end: usize, options: &ContextOptions) -> (usize, usize) {
+    // no window is wider than the whole file, whatever its unit
+    let (a, b) = (options.before.min(contents.len()), options.after.min(contents.len()));
+    let (context_start, context_end) = match options.unit {
+        ContextUnit::Bytes => {
+            let approx_start = start.saturating_sub(a);
+            let approx_end = (end + b).min(contents.len());
+            snippet::approx_substring_range(contents, approx_start, approx_end)
         }
-        end_byte_index -= 1;
+        ContextUnit::Chars => (chars_before(contents, start, a), chars_after(contents, end, b)),
+        ContextUnit::Lines => (lines_before(contents, start, a), lines_after(contents, start, end, b)),
+    };
+    let (context_start, context_end) = match options.max_chars {
+        Some(max_chars) => {
+            let (s, e) = snippet::window(&contents[context_start..context_end], (start - context_start, end - context_start), max_chars);
+            (context_start + s, context_start + e)
+        }
+        None => (context_start, context_end),
+    };
+    match options.snippet {
+        true => snippet::snap_to_words(contents, (context_start, context_end), (start, end)),
+        false => (context_start, context_end),
     }
-    if start_byte_index <= end_byte_index {
-        &contents[start_byte_index..end_byte_index]
-    } else {
-        ""
+}
+
+/// Byte index of the `n`-th character before `index`, or 0 if there are fewer.
+fn chars_before(contents: &str, index: usize, n: usize) -> usize {
+    if n == 0 {
+        return index;
     }
+    contents[..index].char_indices().rev().nth(n - 1).map_or(0, |(i, _)| i)
 }
 
-/// Build regex for atom query.
-/// See `test__get_regex_for_atom` for the transform rules.
-/// The regex is built so that it's robust to noise brought by pdf-to-text parsing.
-// fn get_regex_for_atom(atom: &str) -> Regex {
-//     let regex = _get_regex_for_atom(atom);
-//     RegexBuilder::new(&regex)
-//         .multi_line(true)
-//         .case_insensitive(true)
-//         .dot_matches_new_line(false)
-//         .build()
-//         .unwrap()
-// }
+/// Byte index just past the `n`-th character after `index`, or the length if there are fewer.
+fn chars_after(contents: &str, index: usize, n: usize) -> usize {
+    contents[index..].char_indices().nth(n).map_or(contents.len(), |(i, _)| index + i)
+}
 
-/// The difference from `get_regex_for_atom` is that this OR the atoms together.
-fn get_regex_for_atoms(atoms: &Vec<String>) -> Regex {
-    let regexes: Vec<_> = atoms
-        .into_iter()
-        .map(|a| _get_regex_for_atom(a))
-        .collect();
-    RegexBuilder::new(&regexes.join("|"))
-        .multi_line(true)
-        .case_insensitive(true)
-        .dot_matches_new_line(false)
-        .build()
-        .unwrap()
+/// Byte index of the start of the `n`-th line before the one containing `index`.
+fn lines_before(contents: &str, index: usize, n: usize) -> usize {
+    contents.as_bytes()[..index]
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, &b)| b == b'\n')
+        .nth(n)
+        .map_or(0, |(i, _)| i + 1)
 }
 
-#[pyclass]
-pub struct FilePaths {
-    pub paths: Vec<String>,
+/// Byte index of the end, without the newline, of the `n`-th line after the last one of the
+/// match at `start..end`.
+fn lines_after(contents: &str, start: usize, end: usize, n: usize) -> usize {
+    // a match ending with a newline ends on the line of that newline
+    let last = if end > start { end - 1 } else { end };
+    let line_end = contents.as_bytes()[last..]
+        .iter()
+        .enumerate()
+        .filter(|(_, &b)| b == b'\n')
+        .nth(n)
+        .map_or(contents.len(), |(i, _)| last + i)
+        .max(end);
+    if line_end > end && contents.as_bytes()[line_end - 1] == b'\r' {
+        line_end - 1
+    } else {
+        line_end
+    }
 }
 
-#[pymethods]
-impl FilePaths {
-    #[new]
-    pub fn new(paths: Vec<String>) -> Self {
-        FilePaths {
-            paths,
+/// Copy `contents[start..end]`, surrounding the matches of `patterns` that lie within it with
+/// the highlight markers, and the character offsets of those matches in the copy. Of overlapping
+/// matches, the one starting first is highlighted, or of those starting together, the one of the
+/// earlier pattern.
+fn highlight(contents: &str, start: usize, end: usize, patterns: &[Pattern], options: &ContextOptions) -> (String, Vec<(usize, usize)>) {
+    let mut spans = Vec::new();
+    for (group, pat) in patterns.iter().enumerate() {
+        let mut at = start;
+        while let Some(m) = pat.find_at(contents, at) {
+            if m.end() > end {
+                break;
+            }
+            if m.start() == m.end() {
+                // skip empty matches, which have nothing to highlight
+                at = m.end() + contents[m.end()..].chars().next().map_or(1, char::len_utf8);
+                continue;
+            }
+            spans.push((m.start(), m.end(), group));
+            at = m.end();
+        }
+    }
+    spans.sort_unstable_by_key(|&(span_start, _, group)| (span_start, group));
+    let marker = |marker: &str, group: usize| {
+        if options.highlight_all {
+            marker.replace("{group}", &group.to_string())
+        } else {
+            marker.to_string()
+        }
+    };
+    let (mut context, mut highlights, mut chars) = (String::new(), Vec::new(), 0);
+    let mut push = |context: &mut String, text: &str| {
+        let text = match options.snippet {
+            true => snippet::collapse_whitespace(text),
+            false => Cow::Borrowed(text),
+        };
+        context.push_str(&text);
+        chars += text.chars().count();
+        chars
+    };
+    let mut last = start;
+    for (span_start, span_end, group) in spans {
+        if span_start < last {
+            continue;
         }
+        push(&mut context, &contents[last..span_start]);
+        let highlight_start = push(&mut context, &marker(&options.highlight_begin, group));
+        let highlight_end = push(&mut context, &contents[span_start..span_end]);
+        highlights.push((highlight_start, highlight_end));
+        push(&mut context, &marker(&options.highlight_end, group));
+        last = span_end;
     }
+    push(&mut context, &contents[last..end]);
+    (context, highlights)
 }
 
-#[pyfunction]
-#[pyo3(name = "search_text")]
-pub fn py_search_text(query_group: &QueryGroup, textfile_paths: &FilePaths, a: Option<usize>, b: Option<usize>) -> Vec<FileMatchResult> {
-    match (a, b) {
-        (None, None) | (None, Some(_)) | (Some(_), None) => search_text(query_group, &textfile_paths.paths, true),
-        (Some(a), Some(b)) => search_text_context(query_group, &textfile_paths.paths, a, b, true),
-    }
+/// Read the file at `path` like `read_file`, unless it's a binary file skipped by the binary
+/// policy of `options`.
+fn read_contents(path: &str, options: &SearchOptions) -> std::io::Result<String> {
+    binary::apply(path, options.binary, read_file(path, options))
 }
 
-#[pyfunction]
-#[pyo3(name = "match_str")]
-pub fn py_match_str(query_group: &QueryGroup, contents: &str) -> bool {
-    is_match_str(query_group, contents)
+/// Read the file at `path`, or its head or ranges, with the encoding hints of `options`, within
+/// its read limit, unless it's larger than their maximum size.
+fn read_file(path: &str, options: &SearchOptions) -> std::io::Result<String> {
+    check_file_size(path, options)?;
+    let _permit = options.read_limit.as_ref().map(|limit| limit.acquire());
+    let decompressed = match options.decompress {
+        true => compress::read_decompressed(path, options.head_bytes)?,
+        false => None,
+    };
+    let contents = match (decompressed, options.ranges.as_ref().and_then(|file_ranges| Some((file_ranges.get(path)?, file_ranges.unit))), options.head_bytes, options.tail_bytes) {
+        (Some(bytes), _, head_bytes, tail_bytes) => decode_decompressed(path, bytes, &options.encoding_hints, head_bytes, tail_bytes)?,
+        (None, Some((ranges, unit)), _, _) => ranges::read_ranges(path, &options.encoding_hints, ranges, unit)?,
+        (None, None, Some(max_bytes), _) => encoding::read_head(path, &options.encoding_hints, max_bytes)?,
+        (None, None, None, Some(max_bytes)) => encoding::read_tail(path, &options.encoding_hints, max_bytes)?,
+        (None, None, None, None) => encoding::read_to_string(path, &options.encoding_hints)?,
+    };
+    Ok(match options.code {
+        Some(mode) => code::filter(path, mode, contents),
+        None => contents,
+    })
 }
 
-#[pyfunction]
-#[pyo3(name = "count_match_str")]
-pub fn py_count_match_str(query_group: &QueryGroup, contents: &str) -> Option<usize> {
-    count_match_str(query_group, contents)
+/// Decode the decompressed `bytes` of the file at `path`, only their head or tail if given, like
+/// `read_contents` does the bytes of a file.
+fn decode_decompressed(path: &str, mut bytes: Vec<u8>, hints: &EncodingHints, head_bytes: Option<u64>, tail_bytes: Option<u64>) -> std::io::Result<String> {
+    match (head_bytes, tail_bytes) {
+        (Some(max_bytes), _) => encoding::decode_head(path, bytes, hints, max_bytes),
+        (None, Some(max_bytes)) => {
+            let start = bytes.len().saturating_sub(max_bytes as usize);
+            bytes.drain(..start);
+            encoding::decode_tail(path, bytes, hints, start as u64)
+        }
+        (None, None) => encoding::decode(path, bytes, hints),
+    }
 }
 
-#[pymodule]
-#[pyo3(name = "textsearcher")]
-fn py_module(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
-    m.add_class::<QueryGroup>()?;
-    m.add_class::<FilePaths>()?;
-    m.add_function(wrap_pyfunction!(py_search_text, m)?)?;
-    m.add_function(wrap_pyfunction!(py_match_str, m)?)?;
-    m.add_function(wrap_pyfunction!(py_count_match_str, m)?)?;
+/// Fail if the file at `path` is larger than `max_file_size` bytes.
+fn check_file_size(path: &str, options: &SearchOptions) -> std::io::Result<()> {
+    if let Some(max) = options.max_file_size {
+        if std::fs::metadata(path)?.len() > max {
+            return Err(std::io::Error::new(std::io::ErrorKind::FileTooLarge, format!("{} is larger than {} bytes", path, max)));
+        }
+    }
     Ok(())
 }
 
-/// Returns String to make testing convenient
-fn _get_regex_for_atom(atom: &str) -> String {
-    let mut regex = String::new();
-    let mut word = String::new();
-    let mut prev_ch = '\u{0}';  // represents the beginning or the ending
-    let mut word_commited = false;
+/// Whether the whole of the file at `path` is read, rather than only some of its bytes.
+fn reads_whole(path: &str, options: &SearchOptions) -> bool {
+    options.head_bytes.is_none() && options.tail_bytes.is_none() && options.ranges.as_ref().is_none_or(|ranges| ranges.get(path).is_none())
+}
 
-    enum CharType {
-        /// beginning or ending
-        Term,
-        /// whitespace characters
-        Blank,
-        /// CJK characters
-        Hans,
-        /// e.g. ASCII
-        Other,
+/// A matching file, and the statistics to score it by when the results are ranked.
+type Matched = (FileMatchResult, Option<MatchStats>);
+
+/// Fail if the file at `path`, searched since `started`, has taken `file_timeout` or longer.
+fn check_file_time(path: &str, started: Instant, options: &SearchOptions) -> std::io::Result<()> {
+    match options.file_timeout.filter(|&timeout| started.elapsed() >= timeout) {
+        Some(timeout) => Err(std::io::Error::new(std::io::ErrorKind::TimedOut, format!("{} took longer than {:?} to search", path, timeout))),
+        None => Ok(()),
     }
+}
 
-    let get_char_type = |ch| {
-        if ch == '\u{0}' {
-            CharType::Term
-        } else if ch == ' ' || ch == '\t' || ch == '\n' || ch == '\r' {
-            CharType::Blank
-        } else if (ch >= '\u{4e00}' && ch <= '\u{9fa5}') || (ch >= '\u{3040}' && ch <= '\u{30ff}') {
-            CharType::Hans
-        } else {
-            CharType::Other
+fn match_file(query_group: &QueryGroup, path: &str, options: &SearchOptions, stats: &ScanStats) -> Option<Matched> {
+    match try_match_file(query_group, path, options, stats) {
+        Ok(matched) => matched,
+        Err(e) => {
+            stats.record_skip(path, &e);
+            None
         }
-    };
+    }
+}
 
-    for ch in atom.chars().chain("\0".chars()) {
-        match (get_char_type(prev_ch), get_char_type(ch)) {
-            (CharType::Term, CharType::Term) => (),
-            (CharType::Term, CharType::Blank) => (),
-            (CharType::Term, CharType::Hans) => {
-                // push ch to word
-                word.push(ch);
-                word_commited = false;
-                prev_ch = ch;
-            }
-            (CharType::Term, CharType::Other) => {
-                //regex.push_str("(\\b|[\\u4e00-\\u9fa5\\u3040-\\u30FF])");
-                // push ch to word
-                word.push(ch);
-                word_commited = false;
-                prev_ch = ch;
-            }
-            (CharType::Blank, _) => (),
-            (CharType::Hans, CharType::Term) => {
-                if !word_commited {
-                    // commit word to regex
-                    regex.push_str(&regex::escape(&word));
-                    word.clear();
-                    word_commited = true;
-                }
-            }
-            (CharType::Hans, CharType::Blank) => {
-                if !word_commited {
-                    // commit word to regex
-                    regex.push_str(&regex::escape(&word));
-                    word.clear();
-                    word_commited = true;
-                }
-            }
-            (CharType::Hans, CharType::Hans) => {
-                if word_commited {
-                    regex.push_str("\\s+");
-                } else {
-                    // commit word to regex
-                    regex.push_str(&regex::escape(&word));
-                    word.clear();
-                    //word_commited = true;
-                    regex.push_str("\\s*");
-                }
-                // push ch to word
-                word.push(ch);
-                word_commited = false;
-                prev_ch = ch;
-            }
-            (CharType::Hans, CharType::Other) => {
-                if !word_commited {
-                    // commit word to regex
-                    regex.push_str(&regex::escape(&word));
-                    word.clear();
-                    //word_commited = true;
-                }
-                regex.push_str("\\s*");
-                // push ch to word
-                word.push(ch);
-                word_commited = false;
-                prev_ch = ch;
-            }
-            (CharType::Other, CharType::Term) => {
-                if !word_commited {
-                    // commit word to regex
-                    regex.push_str(&regex::escape(&word));
-                    word.clear();
-                    word_commited = true;
-                }
-                //regex.push_str("(\\b|[\\u4e00-\\u9fa5\\u3040-\\u30FF])");
-                prev_ch = ch;
-            }
-            (CharType::Other, CharType::Blank) => {
-                if !word_commited {
-                    // commit word to regex
-                    regex.push_str(&regex::escape(&word));
-                    word.clear();
-                    word_commited = true;
-                }
-            }
-            (CharType::Other, CharType::Hans) => {
-                if !word_commited {
-                    // commit word to regex
-                    regex.push_str(&regex::escape(&word));
-                    word.clear();
-                    //word_commited = true;
-                }
-                regex.push_str("\\s*");
-                // push ch to word
-                word.push(ch);
-                word_commited = false;
-                prev_ch = ch;
+/// Match the file at `path`, failing with why it was skipped if it couldn't be read and matched
+/// whole, e.g. for being binary, too large, or taking longer than `file_timeout`.
+fn try_match_file(query_group: &QueryGroup, path: &str, options: &SearchOptions, stats: &ScanStats) -> std::io::Result<Option<Matched>> {
+    let started = Instant::now();
+    // compressed files are decompressed whole
+    let converted = || options.decompress && compress::compression_of(path).is_ok_and(|compression| compression.is_some());
+    if let Some(chunks) = options.chunked.as_ref().filter(|_| reads_whole(path, options) && !converted()) {
+        return chunked::match_chunked(query_group, path, options, chunks, stats, started);
+    }
+    if let Some((mapped, fingerprint)) = options.mmap_threshold.filter(|_| !converted()).and_then(|threshold| map_file(path, threshold, options)) {
+        let permit = options.read_limit.as_ref().map(|limit| limit.acquire());
+        // validating the contents reads them in
+        let contents = std::str::from_utf8(&mapped).ok();
+        drop(permit);
+        if let Some(contents) = contents {
+            binary::check_text(contents, options.binary)?;
+            let matched = match_read(query_group, path, contents, options, stats, started)?;
+            // the contents matched may have changed under the search, the file then read again
+            if Fingerprint::of(path).is_ok_and(|now| now == fingerprint) {
+                return Ok(matched);
             }
-            (CharType::Other, CharType::Other) => {
-                if word_commited {
-                    regex.push_str("\\s+");
-                }
-                // push ch to word
-                word.push(ch);
-                word_commited = false;
-                prev_ch = ch;
+        }
+    }
+    // held until the contents are matched
+    let _reservation = options.memory_budget.as_ref().map(|budget| budget.reserve(std::fs::metadata(path).map_or(0, |metadata| metadata.len())));
+    let contents = read_contents(path, options)?;
+    match_read(query_group, path, &contents, options, stats, started)
+}
+
+/// The file at `path` mapped into memory, if it's at least `threshold` bytes long and read
+/// whole as it is, without a hinted encoding.
+fn map_file(path: &str, threshold: u64, options: &SearchOptions) -> Option<(mmap::Mmap, Fingerprint)> {
+    if options.code.is_some() || options.encoding_hints.encoding_for(path).is_some() || !reads_whole(path, options) || check_file_size(path, options).is_err() {
+        return None;
+    }
+    mmap::map_large(path, threshold)
+}
+
+/// Match the `contents` read from the file at `path`, searched since `started`.
+fn match_read(query_group: &QueryGroup, path: &str, contents: &str, options: &SearchOptions, stats: &ScanStats, started: Instant) -> std::io::Result<Option<Matched>> {
+    stats.record_read(contents.len());
+    if stats.is_profiling() {
+        // each pattern on its own, rather than stopping at the first not matching
+        stats.record_pattern_times(&query_group.patterns, &normalize::normalize(&options.normalizers, contents));
+    }
+    check_file_time(path, started, options)?;
+    let matched = match_contents(query_group, path, contents, options);
+    check_file_time(path, started, options)?;
+    Ok(matched)
+}
+
+fn match_file_cached(query_group: &QueryGroup, path: &str, options: &SearchOptions, stats: &ScanStats, cache: &ResultCache, query: u64) -> Option<Matched> {
+    let fingerprint = match Fingerprint::of(path) {
+        Ok(fingerprint) => fingerprint,
+        Err(e) => {
+            stats.record_skip(path, &e);
+            return None;
+        }
+    };
+    if let Some(outcome) = cache.get(query, path, fingerprint) {
+        stats.record_cached();
+        return cached_match(path, outcome);
+    }
+    // only the outcomes of files read and matched, those skipped being searched again
+    let matched = match try_match_file(query_group, path, options, stats) {
+        Ok(matched) => matched,
+        Err(e) => {
+            stats.record_skip(path, &e);
+            return None;
+        }
+    };
+    // files given up on once the deadline passed may be searched in time the next time
+    if matched.is_none() && stats.timed_out() {
+        return None;
+    }
+    cache.insert(query, path, fingerprint, outcome_of(matched.as_ref()));
+    matched
+}
+
+/// The match of the file at `path` a cached `outcome` tells, if it matched.
+fn cached_match(path: &str, outcome: Outcome) -> Option<Matched> {
+    let tags = outcome.language.map(|language| ("language".to_string(), TagValue::Str(language)));
+    outcome.matched.then(|| (FileMatchResult {
+        path: String::from(path),
+        context: outcome.context,
+        tags: tags.into_iter().collect(),
+        score: None,
+        truncated_before: outcome.truncated.0,
+        truncated_after: outcome.truncated.1,
+        position: outcome.position,
+        contexts: outcome.contexts,
+        clause_contexts: outcome.clause_contexts,
+        highlights: outcome.highlights,
+    }, outcome.stats))
+}
+
+/// The outcome to cache of a file that was read and `matched`, or not.
+fn outcome_of(matched: Option<&Matched>) -> Outcome {
+    Outcome {
+        matched: matched.is_some(),
+        context: matched.and_then(|(r, _)| r.context.clone()),
+        stats: matched.and_then(|(_, s)| s.clone()),
+        truncated: matched.map_or((false, false), |(r, _)| r.truncated()),
+        position: matched.and_then(|(r, _)| r.position),
+        contexts: matched.map(|(r, _)| r.contexts.clone()).unwrap_or_default(),
+        clause_contexts: matched.map(|(r, _)| r.clause_contexts.clone()).unwrap_or_default(),
+        highlights: matched.map(|(r, _)| r.highlights.clone()).unwrap_or_default(),
+        language: matched.and_then(|(r, _)| match r.tags.get("language") {
+            Some(TagValue::Str(language)) => Some(language.clone()),
+            _ => None,
+        }),
+    }
+}
+
+/// Match the file at `path` against each of `query_groups`, read and normalized once for all of
+/// them, failing with why it was skipped like `try_match_file`. Files aren't read in chunks nor
+/// mapped into memory.
+fn try_match_file_multi(query_groups: &[QueryGroup], path: &str, options: &SearchOptions, stats: &ScanStats) -> std::io::Result<Vec<Option<Matched>>> {
+    let started = Instant::now();
+    // held until the contents are matched
+    let _reservation = options.memory_budget.as_ref().map(|budget| budget.reserve(std::fs::metadata(path).map_or(0, |metadata| metadata.len())));
+    let contents = read_contents(path, options)?;
+    stats.record_read(contents.len());
+    check_file_time(path, started, options)?;
+    let contents = normalize::normalize(&options.normalizers, &contents);
+    let matched = query_groups.iter().map(|query_group| match_normalized(query_group, path, &contents, options)).collect();
+    check_file_time(path, started, options)?;
+    Ok(matched)
+}
+
+/// Like `try_match_file_multi`, through the cache if any, `queries` being the cache keys of the
+/// query groups; the file is only read if any of them isn't cached. Files skipped are recorded.
+fn match_file_multi(query_groups: &[QueryGroup], path: &str, options: &SearchOptions, stats: &ScanStats, queries: Option<&[u64]>) -> Option<Vec<Option<Matched>>> {
+    let cached = match (&options.cache, queries) {
+        (Some(cache), Some(queries)) => match Fingerprint::of(path) {
+            Ok(fingerprint) => Some((cache, queries, fingerprint)),
+            Err(e) => {
+                stats.record_skip(path, &e);
+                return None;
             }
+        },
+        _ => None,
+    };
+    if let Some((cache, queries, fingerprint)) = cached {
+        let outcomes: Option<Vec<_>> = queries.iter().map(|query| cache.get(*query, path, fingerprint)).collect();
+        if let Some(outcomes) = outcomes {
+            stats.record_cached();
+            return Some(outcomes.into_iter().map(|outcome| cached_match(path, outcome)).collect());
+        }
+    }
+    let matched = match try_match_file_multi(query_groups, path, options, stats) {
+        Ok(matched) => matched,
+        Err(e) => {
+            stats.record_skip(path, &e);
+            return None;
+        }
+    };
+    if let Some((cache, queries, fingerprint)) = cached.filter(|_| !stats.timed_out()) {
+        for (query, matched) in queries.iter().zip(matched.iter()) {
+            cache.insert(*query, path, fingerprint, outcome_of(matched.as_ref()));
         }
     }
+    Some(matched)
+}
 
-    regex
+fn match_contents(query_group: &QueryGroup, path: &str, contents: &str, options: &SearchOptions) -> Option<Matched> {
+    match_normalized(query_group, path, &normalize::normalize(&options.normalizers, contents), options)
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::{_get_regex_for_atom, QueryGroup, search_text};
+/// Match `contents` already normalized with the normalizers of `options`.
+fn match_normalized(query_group: &QueryGroup, path: &str, contents: &str, options: &SearchOptions) -> Option<Matched> {
+    let language = options.detect_language.then(|| language::detect(contents));
+    let query_group = language.as_deref().map_or(query_group, |language| query_group.for_language(language));
+    let result = if options.invert {
+        (!is_match_str(query_group, contents)).then(|| FileMatchResult {
+            path: String::from(path),
+            context: None,
+            tags: Tags::new(),
+            score: None,
+            truncated_before: false,
+            truncated_after: false,
+            position: None,
+            contexts: Vec::new(),
+            clause_contexts: Vec::new(),
+            highlights: Vec::new(),
+        })
+    } else {
+        match &options.context {
+            None => is_match(query_group, path, contents),
+            Some(context_options) => is_match_context(query_group, path, contents, context_options),
+        }
+    }?;
+    let mut result = result;
+    if let Some(language) = language {
+        result.tags.insert("language".to_string(), TagValue::Str(language));
+    }
+    let stats = options.scoring.as_ref().map(|_| MatchStats::of(query_group, contents));
+    Some((result, stats))
+}
 
-    #[test]
-    fn test_get_regex_for_atom() {
-        assert_eq!(_get_regex_for_atom(""), "");
-        assert_eq!(_get_regex_for_atom("  "), "");
-        assert_eq!(_get_regex_for_atom("      "), "");
-        assert_eq!(_get_regex_for_atom("hello"), "hello");
-        assert_eq!(_get_regex_for_atom("hello world"), "hello\\s+world");
-        assert_eq!(_get_regex_for_atom("hello world    again"), "hello\\s+world\\s+again");
-        assert_eq!(_get_regex_for_atom("  hello world    "), "hello\\s+world");
-        assert_eq!(_get_regex_for_atom("中"), "中");
-        assert_eq!(_get_regex_for_atom("中文"), "中\\s*文");
-        assert_eq!(_get_regex_for_atom("   中 文"), "中\\s+文");
-        assert_eq!(_get_regex_for_atom("     中 文   "), "中\\s+文");
-        assert_eq!(_get_regex_for_atom("中hello"), "中\\s*hello");
-        assert_eq!(_get_regex_for_atom("中文hello"), "中\\s*文\\s*hello");
-        assert_eq!(_get_regex_for_atom("中文hello world"), "中\\s*文\\s*hello\\s+world");
-        assert_eq!(_get_regex_for_atom("中文 hello world"), "中\\s*文\\s*hello\\s+world");
-        assert_eq!(_get_regex_for_atom("中文    hello  world"), "中\\s*文\\s*hello\\s+world");
-        assert_eq!(_get_regex_for_atom("hello中"), "hello\\s*中");
-        assert_eq!(_get_regex_for_atom("hello中文"), "hello\\s*中\\s*文");
-        assert_eq!(_get_regex_for_atom("hello world中文"), "hello\\s+world\\s*中\\s*文");
-        assert_eq!(_get_regex_for_atom("hello   world 中文"), "hello\\s+world\\s*中\\s*文");
-        assert_eq!(_get_regex_for_atom("hello   world   中文"), "hello\\s+world\\s*中\\s*文");
-        assert_eq!(_get_regex_for_atom("hello world中文again"), "hello\\s+world\\s*中\\s*文\\s*again");
-        assert_eq!(_get_regex_for_atom("hello world  中文 again"), "hello\\s+world\\s*中\\s*文\\s*again");
-        assert_eq!(_get_regex_for_atom("  hello world  中文 again  "), "hello\\s+world\\s*中\\s*文\\s*again");
-        assert_eq!(_get_regex_for_atom("中文hello world世界"), "中\\s*文\\s*hello\\s+world\\s*世\\s*界");
-        assert_eq!(_get_regex_for_atom("  中文 hello world世界   "), "中\\s*文\\s*hello\\s+world\\s*世\\s*界");
-        assert_eq!(_get_regex_for_atom(" 中文hello world   世界"), "中\\s*文\\s*hello\\s+world\\s*世\\s*界");
+/// Score the result if ranking, now that its tags are known.
+fn finish_match(mut result: FileMatchResult, stats: Option<MatchStats>, tags: &Tags, options: &SearchOptions) -> FileMatchResult {
+    // the tags of the file, along with those recorded while matching
+    let detected = std::mem::replace(&mut result.tags, tags.clone());
+    result.tags.extend(detected);
+    if let (Some(scoring), Some(stats)) = (&options.scoring, stats) {
+        score::apply_score(scoring, &mut result, &stats);
     }
+    result
+}
 
-    // without Python package 'maturin', this test goes wrong false positively
-    #[test]
-    fn test_search_text() {
-        let query_group = QueryGroup::new(
-            vec![vec!["world".to_string()]]).unwrap();
-        let paths = vec![String::from("sample_texts/hello.txt"), String::from("sample_texts/world.txt")];
-        let result = search_text(&query_group, &paths, false);
-        assert_eq!(result.len(), 1);
-        assert_eq!(result.iter().next().unwrap().path, String::from("sample_texts/world.txt"));
+pub fn search_text(query_group: &QueryGroup, textfile_paths: &[String], parallel: bool) -> Vec<FileMatchResult> {
+    let options = SearchOptions {
+        parallel,
+        ..Default::default()
+    };
+    search_text_with(query_group, textfile_paths, &options)
+}
 
-        let query_group = QueryGroup::new(
-            vec![vec!["bar".to_string()],
-                 vec!["baz".to_string(), "xxxx哈哈".to_string()]]).unwrap();
-        let paths = vec![String::from("sample_texts/hello.txt"), String::from("sample_texts/world.txt")];
-        let result = search_text(&query_group, &paths, false);
-        assert_eq!(result.len(), 1);
-        assert_eq!(result.iter().next().unwrap().path, String::from("sample_texts/hello.txt"));
+/// The matches with `a` bytes of context before them and `b` after.
+pub fn search_text_context(query_group: &QueryGroup, textfile_paths: &[String], a: usize, b: usize, parallel: bool) -> Vec<FileMatchResult> {
+    search_text_context_with_unit(query_group, textfile_paths, a, b, ContextUnit::Bytes, parallel)
+}
+
+/// Like `search_text_context`, with `a` and `b` counted in `unit`, e.g. lines with
+/// `ContextUnit::Lines` like `grep -B a -A b`.
+pub fn search_text_context_with_unit(query_group: &QueryGroup, textfile_paths: &[String], a: usize, b: usize, unit: ContextUnit, parallel: bool) -> Vec<FileMatchResult> {
+    let options = SearchOptions {
+        parallel,
+        context: Some(ContextOptions {
+            before: a,
+            after: b,
+            unit,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    search_text_with(query_group, textfile_paths, &options)
+}
+
+/// Number of files matching the query group, without building their results, and if
+/// `occurrences` is set, the total number of matches of all the patterns in those files.
+/// With `SearchOptions::invert`, the files not matching are counted instead.
+pub fn count_matches(query_group: &QueryGroup, textfile_paths: &[String], options: &SearchOptions, occurrences: bool) -> (usize, Option<usize>) {
+    count_matches_report(query_group, textfile_paths, options, occurrences).0
+}
+
+/// Like `count_matches`, but also reports statistics about the scan.
+pub fn count_matches_report(query_group: &QueryGroup, textfile_paths: &[String], options: &SearchOptions, occurrences: bool) -> ((usize, Option<usize>), SearchReport) {
+    let options = SearchOptions {
+        context: None,
+        // the match statistics, counting the matches of each pattern, are only taken when scoring
+        scoring: (occurrences && !options.invert).then(|| ScoringOptions {
+            scorer: Arc::new(score::TotalCount),
+            top_k: None,
+        }),
+        ..options.clone()
+    };
+    let query = options.cache.as_ref().map(|_| cache::query_key(query_group, &options));
+    let (mut files, mut total) = (0, 0);
+    let report = scan(&path_entries(textfile_paths, &Tags::new()), &options, COUNT_CHUNK, |&(path, _), stats| {
+        let (_, match_stats) = match_entry(query_group, path, &options, query, stats)?;
+        Some(match_stats.map_or(0, |match_stats| match_stats.counts.iter().sum::<usize>()))
+    }, |_| (), |chunk| {
+        files += chunk.len();
+        total += chunk.into_iter().sum::<usize>();
+    });
+    ((files, occurrences.then_some(total)), report)
+}
+
+/// Files searched at a time by the searches not returning the matches, whose memory doesn't grow
+/// with the number of files.
+const COUNT_CHUNK: usize = 4096;
+
+/// Whether any file matches the query group (or doesn't, with `SearchOptions::invert`). The
+/// search stops as soon as one is found.
+pub fn exists_match(query_group: &QueryGroup, textfile_paths: &[String], options: &SearchOptions) -> bool {
+    exists_match_report(query_group, textfile_paths, options).0
+}
+
+/// Like `exists_match`, but also reports statistics about the scan.
+pub fn exists_match_report(query_group: &QueryGroup, textfile_paths: &[String], options: &SearchOptions) -> (bool, SearchReport) {
+    let options = SearchOptions {
+        context: None,
+        scoring: None,
+        max_results: Some(1),
+        ..options.clone()
+    };
+    let mut found = false;
+    let report = scan_entries(query_group, &path_entries(textfile_paths, &Tags::new()), &options, COUNT_CHUNK, |_| (), |chunk| found |= !chunk.is_empty());
+    (found, report)
+}
+
+/// The files matching any of the query groups, in the order of the paths, each with the indices
+/// of the groups it matches. Each file is read and normalized once for all the groups.
+/// `SearchOptions::invert` doesn't apply.
+pub fn search_any(query_groups: &[QueryGroup], textfile_paths: &[String], options: &SearchOptions) -> Vec<(String, Vec<usize>)> {
+    search_any_report(query_groups, textfile_paths, options).0
+}
+
+/// Like `search_any`, but also reports statistics about the scan.
+pub fn search_any_report(query_groups: &[QueryGroup], textfile_paths: &[String], options: &SearchOptions) -> (Vec<(String, Vec<usize>)>, SearchReport) {
+    let options = SearchOptions {
+        context: None,
+        scoring: None,
+        invert: false,
+        ..options.clone()
+    };
+    let (files, report) = search_multi(query_groups, textfile_paths, &options);
+    let files = files
+        .into_iter()
+        .map(|(path, results)| (path, results.iter().enumerate().filter(|(_, result)| result.is_some()).map(|(i, _)| i).collect()))
+        .collect();
+    (files, report)
+}
+
+/// Search the files for each of the query groups, each file read once for all of them, e.g. to
+/// run saved queries over the same files. The results of each group are those of
+/// `search_text_with`, in the order of the groups.
+pub fn search_text_multi(query_groups: &[QueryGroup], textfile_paths: &[String], options: &SearchOptions) -> Vec<Vec<FileMatchResult>> {
+    search_text_multi_report(query_groups, textfile_paths, options).0
+}
+
+/// Like `search_text_multi`, but also reports statistics about the scan, a file matching any
+/// of the groups counting once.
+pub fn search_text_multi_report(query_groups: &[QueryGroup], textfile_paths: &[String], options: &SearchOptions) -> (Vec<Vec<FileMatchResult>>, SearchReport) {
+    let (files, report) = search_multi(query_groups, textfile_paths, options);
+    let mut results = vec![Vec::new(); query_groups.len()];
+    for (_, file) in files {
+        for (group_results, result) in results.iter_mut().zip(file) {
+            group_results.extend(result);
+        }
+    }
+    if let Some(scoring) = &options.scoring {
+        results.iter_mut().for_each(|group_results| score::rank(group_results, scoring.top_k));
+    }
+    (results, report)
+}
+
+/// A file matching any of several query groups, with its match of each group if any.
+type MultiMatch = (String, Vec<Option<FileMatchResult>>);
+
+/// The files matching any of the query groups, in the order of the paths.
+fn search_multi(query_groups: &[QueryGroup], textfile_paths: &[String], options: &SearchOptions) -> (Vec<MultiMatch>, SearchReport) {
+    let no_tags = Tags::new();
+    let queries: Option<Vec<_>> = options.cache.as_ref().map(|_| query_groups.iter().map(|query_group| cache::query_key(query_group, options)).collect());
+    let mut files = Vec::new();
+    let report = scan(&path_entries(textfile_paths, &no_tags), options, textfile_paths.len(), |&(path, tags), stats| {
+        let matched = search_path(path, options, stats, || {
+            let matched = match_file_multi(query_groups, path, options, stats, queries.as_deref())?;
+            matched.iter().any(Option::is_some).then_some(matched)
+        })?;
+        let results = matched.into_iter().map(|matched| matched.map(|(result, match_stats)| finish_match(result, match_stats, tags, options))).collect();
+        Some((String::from(path), results))
+    }, |_| (), |chunk| files.extend(chunk));
+    (files, report)
+}
+
+/// Search the files for the query group. The results are in the order of `textfile_paths`,
+/// also when searching in parallel.
+pub fn search_text_with(query_group: &QueryGroup, textfile_paths: &[String], options: &SearchOptions) -> Vec<FileMatchResult> {
+    search_text_with_callback(query_group, textfile_paths, options, |_| ())
+}
+
+/// Like `search_text_with`, but also calls `on_result` with each match as soon as it's found,
+/// from the worker thread that found it.
+pub fn search_text_with_callback<F>(query_group: &QueryGroup, textfile_paths: &[String], options: &SearchOptions, on_result: F) -> Vec<FileMatchResult>
+where
+    F: Fn(&FileMatchResult) + Sync,
+{
+    search_text_report(query_group, textfile_paths, options, on_result).0
+}
+
+/// Like `search_text_with_callback`, but also reports statistics about the scan.
+pub fn search_text_report<F>(query_group: &QueryGroup, textfile_paths: &[String], options: &SearchOptions, on_result: F) -> (Vec<FileMatchResult>, SearchReport)
+where
+    F: Fn(&FileMatchResult) + Sync,
+{
+    search_entries(query_group, &path_entries(textfile_paths, &Tags::new()), options, on_result)
+}
+
+/// The files at `paths`, all with the same `tags`.
+fn path_entries<'a>(paths: &'a [String], tags: &'a Tags) -> Vec<Entry<'a>> {
+    paths.iter().map(|path| (path.as_str(), tags)).collect()
+}
+
+/// Search the files whose tags pass `filter`, echoing their tags on the results.
+pub fn search_files(query_group: &QueryGroup, files: &FilePaths, filter: &TagFilter, options: &SearchOptions) -> Vec<FileMatchResult> {
+    search_files_report(query_group, files, filter, options, |_| ()).0
+}
+
+pub fn search_files_with_callback<F>(query_group: &QueryGroup, files: &FilePaths, filter: &TagFilter, options: &SearchOptions, on_result: F) -> Vec<FileMatchResult>
+where
+    F: Fn(&FileMatchResult) + Sync,
+{
+    search_files_report(query_group, files, filter, options, on_result).0
+}
+
+/// Like `search_files_with_callback`, but also reports statistics about the scan.
+pub fn search_files_report<F>(query_group: &QueryGroup, files: &FilePaths, filter: &TagFilter, options: &SearchOptions, on_result: F) -> (Vec<FileMatchResult>, SearchReport)
+where
+    F: Fn(&FileMatchResult) + Sync,
+{
+    search_entries(query_group, &file_entries(files, filter), options, on_result)
+}
+
+/// The files whose tags pass `filter`.
+fn file_entries<'a>(files: &'a FilePaths, filter: &TagFilter) -> Vec<Entry<'a>> {
+    files.paths
+        .iter()
+        .zip(files.tags.iter())
+        .filter(|(_, tags)| filter.accepts(Some(tags)))
+        .map(|(path, tags)| (path.as_str(), tags))
+        .collect()
+}
+
+/// What `search` finds in the contents of each of the files at `paths`, read with `options`, in
+/// the order of the paths, e.g. their matching records. Unreadable files are reported.
+pub(crate) fn scan_files<T, M>(paths: &[String], options: &SearchOptions, search: M) -> (Vec<T>, SearchReport)
+where
+    T: Send,
+    M: Fn(&str, &str) -> Vec<T> + Sync,
+{
+    let no_tags = Tags::new();
+    let mut found = Vec::new();
+    let report = scan(&path_entries(paths, &no_tags), options, paths.len(), |&(path, _), stats| {
+        search_path(path, options, stats, || {
+            let contents = match read_contents(path, options) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    stats.record_skip(path, &e);
+                    return None;
+                }
+            };
+            stats.record_read(contents.len());
+            let matches = search(path, &contents);
+            (!matches.is_empty()).then_some(matches)
+        })
+    }, |_| (), |chunk| found.extend(chunk.into_iter().flatten()));
+    (found, report)
+}
+
+/// A file to search, and the tags to echo back on its match.
+type Entry<'a> = (&'a str, &'a Tags);
+
+/// Search one file, through the cache if any; `query` is the cache key of the query group.
+fn search_entry(query_group: &QueryGroup, path: &str, tags: &Tags, options: &SearchOptions, query: Option<u64>, stats: &ScanStats) -> Option<FileMatchResult> {
+    let (result, match_stats) = match_entry(query_group, path, options, query, stats)?;
+    Some(finish_match(result, match_stats, tags, options))
+}
+
+/// Match one file, through the cache if any, without scoring it yet.
+fn match_entry(query_group: &QueryGroup, path: &str, options: &SearchOptions, query: Option<u64>, stats: &ScanStats) -> Option<Matched> {
+    search_path(path, options, stats, || match (&options.cache, query) {
+        (Some(cache), Some(query)) => match_file_cached(query_group, path, options, stats, cache, query),
+        _ => match_file(query_group, path, options, stats),
+    })
+}
+
+/// Search the file at `path` with `search`, unless it's a link to skip, recording the time taken.
+fn search_path<T>(path: &str, options: &SearchOptions, stats: &ScanStats, search: impl FnOnce() -> Option<T>) -> Option<T> {
+    let file_started = Instant::now();
+    if let Some(Err(e)) = options.dedup_paths.map(|policy| paths::check_link(path, policy)) {
+        stats.record_skip(path, &e);
+        return None;
+    }
+    let result = search();
+    stats.record_busy(file_started.elapsed());
+    stats.record_file_time(path, file_started.elapsed());
+    trace::file_searched(path, file_started.elapsed(), result.is_some());
+    result
+}
+
+fn search_entries<F>(query_group: &QueryGroup, entries: &[Entry<'_>], options: &SearchOptions, on_result: F) -> (Vec<FileMatchResult>, SearchReport)
+where
+    F: Fn(&FileMatchResult) + Sync,
+{
+    let mut results = Vec::new();
+    let report = scan_entries(query_group, entries, options, entries.len(), on_result, |chunk| results.extend(chunk));
+    if let Some(scoring) = &options.scoring {
+        score::rank(&mut results, scoring.top_k);
+    }
+    (results, report)
+}
+
+/// Search the entries `chunk_size` at a time, handing the matches of each chunk to `sink` in
+/// the order of the entries.
+fn scan_entries<F, S>(query_group: &QueryGroup, entries: &[Entry<'_>], options: &SearchOptions, chunk_size: usize, on_result: F, sink: S) -> SearchReport
+where
+    F: Fn(&FileMatchResult) + Sync,
+    S: FnMut(Vec<FileMatchResult>),
+{
+    let query = options.cache.as_ref().map(|_| cache::query_key(query_group, options));
+    scan(entries, options, chunk_size, |&(path, tags), stats| search_entry(query_group, path, tags, options, query, stats), on_result, sink)
+}
+
+/// Search the entries `chunk_size` at a time with `search`, handing what it finds in each chunk
+/// to `sink` in the order of the entries. Every search goes through here, minding the deadline,
+/// cancellation, `max_results` and progress of `options`, and reporting on the scan.
+fn scan<T, M, F, S>(entries: &[Entry<'_>], options: &SearchOptions, chunk_size: usize, search: M, on_found: F, mut sink: S) -> SearchReport
+where
+    T: Send,
+    M: Fn(&Entry<'_>, &ScanStats) -> Option<T> + Sync,
+    F: Fn(&T) + Sync,
+    S: FnMut(Vec<T>),
+{
+    let started = Instant::now();
+    let stats = options.profile.map_or_else(ScanStats::default, ScanStats::profiling);
+    let deduped;
+    let entries = match options.dedup_paths {
+        Some(policy) => {
+            let collapsed;
+            (deduped, collapsed) = paths::dedup_entries(entries, policy, options.parallel);
+            stats.record_collapsed(collapsed);
+            &deduped[..]
+        }
+        None => entries,
+    };
+    let searched = AtomicUsize::new(0);
+    // matches found, counted by the workers as they find them to stop at `max_results`
+    let found = AtomicUsize::new(0);
+    let search_entry = |entry: &Entry<'_>| {
+        if options.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            stats.record_timeout();
+            return None;
+        }
+        if options.cancel.as_ref().is_some_and(progress::CancelToken::is_cancelled) {
+            stats.record_cancel();
+            return None;
+        }
+        if options.max_results.is_some_and(|max| found.load(Ordering::Relaxed) >= max) {
+            stats.record_limit();
+            return None;
+        }
+        let result = search(entry, &stats);
+        if let Some(progress) = &options.progress {
+            progress.report(searched.fetch_add(1, Ordering::Relaxed) + 1, entries.len());
+        }
+        let result = result?;
+        // others may have been found meanwhile
+        if options.max_results.is_some_and(|max| found.fetch_add(1, Ordering::Relaxed) >= max) {
+            stats.record_limit();
+            return None;
+        }
+        on_found(&result);
+        Some(result)
+    };
+    // on the global pool if this one can't be built
+    let pool = options.num_threads.filter(|_| options.parallel).and_then(|n| rayon::ThreadPoolBuilder::new().num_threads(n).build().ok());
+    let mut files_matched = 0;
+    for chunk in entries.chunks(chunk_size.max(1)) {
+        if stats.timed_out() || stats.cancelled() || stats.limited() {
+            break;
+        }
+        let results: Vec<_> = if options.parallel {
+            let search = || chunk.par_iter().filter_map(search_entry).collect();
+            match &pool {
+                Some(pool) => pool.install(search),
+                None => search(),
+            }
+        } else {
+            chunk
+                .iter()
+                .filter_map(search_entry)
+                .collect()
+        };
+        files_matched += results.len();
+        sink(results);
+    }
+    stats.finish(entries.len(), files_matched, started.elapsed())
+}
+
+// Build regex for atom query.
+// See `test__get_regex_for_atom` for the transform rules.
+// The regex is built so that it's robust to noise brought by pdf-to-text parsing.
+// fn get_regex_for_atom(atom: &str) -> Regex {
+//     let regex = _get_regex_for_atom(atom);
+//     RegexBuilder::new(&regex)
+//         .multi_line(true)
+//         .case_insensitive(true)
+//         .dot_matches_new_line(false)
+//         .build()
+//         .unwrap()
+// }
+
+/// Prefix of atoms that are taken verbatim as patterns of the default engine, e.g.
+/// `re:\bv\d+\.\d+\b`, rather than transformed to tolerate noise.
+const REGEX_PREFIX: &str = "re:";
+/// Prefix of atoms that are taken verbatim as `fancy-regex` patterns, e.g. `fancy:foo(?! bar)`.
+const FANCY_PREFIX: &str = "fancy:";
+/// Prefix of atoms that are taken verbatim as PCRE2 patterns, e.g. `pcre:\d++-\d++`.
+const PCRE_PREFIX: &str = "pcre:";
+/// Prefix of atoms that are a sequence of atoms, each matched after the previous one, e.g.
+/// `then:introduction ... methodology`.
+const THEN_PREFIX: &str = "then:";
+/// Separator of the atoms of a `then:` atom.
+const THEN_SEPARATOR: &str = " ... ";
+
+/// The difference from `get_regex_for_atom` is that this OR the atoms together.
+/// Groups with a `fancy:` or `pcre:` atom are compiled with that engine; the others keep the
+/// default engine.
+fn get_regex_for_atoms(atoms: &[String], options: &QueryOptions) -> Result<Pattern, QueryError> {
+    if options.fuzzy.is_some_and(|max_edits| max_edits > fuzzy::MAX_EDITS) {
+        return Err(QueryError::new(format!("fuzzy takes at most {} edits per word", fuzzy::MAX_EDITS)));
+    }
+    let fancy = atoms.iter().any(|a| a.starts_with(FANCY_PREFIX));
+    let pcre = atoms.iter().any(|a| a.starts_with(PCRE_PREFIX));
+    let regexes: Vec<_> = atoms
+        .iter()
+        .map(|a| atom_to_regex(a, options))
+        .collect();
+    let regex = format!("{}{}", options.flags.inline(!fancy && !pcre), regexes.join("|"));
+    match (fancy, pcre) {
+        (true, true) => Err(QueryError::new("fancy: and pcre: atoms can't be in the same OR group")),
+        (true, false) => fancy_regex::Regex::new(&format!("(?mi){}", regex))
+            .map(Pattern::Fancy)
+            .map_err(|e| QueryError::new(e.to_string())),
+        (false, true) => get_pcre2_regex(&regex),
+        (false, false) => RegexBuilder::new(&regex)
+            .multi_line(true)
+            .case_insensitive(true)
+            .dot_matches_new_line(false)
+            .build()
+            .map(Pattern::Regex)
+            .map_err(|e| QueryError::new(e.to_string())),
+    }
+}
+
+#[cfg(feature = "pcre2")]
+fn get_pcre2_regex(regex: &str) -> Result<Pattern, QueryError> {
+    pcre2::bytes::RegexBuilder::new()
+        .multi_line(true)
+        .caseless(true)
+        .utf(true)
+        .ucp(true)
+        .jit_if_available(true)
+        .build(regex)
+        .map(Pattern::Pcre2)
+        .map_err(|e| QueryError::new(e.to_string()))
+}
+
+#[cfg(not(feature = "pcre2"))]
+fn get_pcre2_regex(_regex: &str) -> Result<Pattern, QueryError> {
+    Err(QueryError::new("pcre: atoms require textsearcher to be built with the pcre2 feature"))
+}
+
+#[pyclass]
+pub struct FilePaths {
+    pub paths: Vec<String>,
+    /// The tags of each path, e.g. `{"year": 2021, "venue": "neurips"}`; as long as `paths`.
+    pub tags: Vec<Tags>,
+    /// Encodings of the paths matching some patterns, as (pattern, label) pairs like those of
+    /// `EncodingHints::new`, e.g. `("old_docs/**", "big5")`, for a corpus of mixed encodings.
+    /// Searches from Python consult those given to them first; the encoding of the other files
+    /// is detected if the search detects encodings.
+    pub encodings: Vec<(String, String)>,
+}
+
+impl FilePaths {
+    pub fn new(paths: Vec<String>) -> Self {
+        let tags = vec![Tags::new(); paths.len()];
+        FilePaths {
+            paths,
+            tags,
+            encodings: Vec::new(),
+        }
+    }
+
+    pub fn with_tags(paths: Vec<String>, tags: Vec<Tags>) -> PyResult<Self> {
+        if tags.len() != paths.len() {
+            return Err(PyValueError::new_err("there must be as many tags as paths"));
+        }
+        Ok(FilePaths {
+            paths,
+            tags,
+            encodings: Vec::new(),
+        })
+    }
+
+    /// These paths with the encodings of those matching the patterns of `encodings`, the first
+    /// pattern matching a path winning.
+    pub fn with_encodings(self, encodings: Vec<(String, String)>) -> PyResult<Self> {
+        EncodingHints::new(&encodings)?;
+        Ok(FilePaths {
+            encodings,
+            ..self
+        })
+    }
+
+    /// Remove the paths `excludes` excludes, with their tags.
+    pub fn exclude(&mut self, excludes: &exclude::Excludes) {
+        let kept: Vec<_> = self.paths.iter().map(|path| !excludes.is_excluded(path)).collect();
+        let mut kept_tags = kept.iter();
+        self.tags.retain(|_| *kept_tags.next().unwrap());
+        let mut kept_paths = kept.iter();
+        self.paths.retain(|_| *kept_paths.next().unwrap());
+    }
+
+    /// All the files below `root`, recursively, sorted.
+    pub fn from_dir(root: &str) -> std::io::Result<Self> {
+        FilePaths::from_dir_excluding(root, &exclude::Excludes::default())
+    }
+
+    /// Like `from_dir`, but leaving out the files and directories `excludes` excludes.
+    pub fn from_dir_excluding(root: &str, excludes: &exclude::Excludes) -> std::io::Result<Self> {
+        let mut paths = Vec::new();
+        corpus::collect_files(Path::new(root), excludes, &mut paths)?;
+        paths.sort();
+        Ok(FilePaths::new(paths))
+    }
+
+    /// The files matching the glob `pattern`, sorted, e.g. `docs/**/*.txt`; `*` doesn't match
+    /// across directories but `**` does.
+    pub fn from_glob(pattern: &str) -> PyResult<Self> {
+        FilePaths::from_glob_excluding(pattern, &exclude::Excludes::default())
+    }
+
+    /// Like `from_glob`, but leaving out the files and directories `excludes` excludes.
+    pub fn from_glob_excluding(pattern: &str, excludes: &exclude::Excludes) -> PyResult<Self> {
+        let matcher = globset::GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?
+            .compile_matcher();
+        // only the directory before the first wildcard is walked
+        let root: Vec<_> = pattern
+            .split('/')
+            .take_while(|component| !component.contains(['*', '?', '[', '{']))
+            .collect();
+        let root = match root[..root.len().min(pattern.split('/').count() - 1)].join("/") {
+            root if root.is_empty() && pattern.starts_with('/') => "/".to_string(),
+            root => root,
+        };
+        let mut paths = Vec::new();
+        match corpus::collect_files(Path::new(if root.is_empty() { "." } else { &root }), excludes, &mut paths) {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => return Err(PyIOError::new_err(e.to_string())),
+        }
+        let mut paths: Vec<_> = paths
+            .into_iter()
+            .map(|path| if root.is_empty() { path[2..].to_string() } else { path })
+            .filter(|path| matcher.is_match(path))
+            .collect();
+        paths.sort();
+        Ok(FilePaths::new(paths))
+    }
+}
+
+#[pymethods]
+impl FilePaths {
+    #[new]
+    #[pyo3(signature = (paths, tags = None, encodings = None))]
+    fn py_new(paths: Vec<String>, tags: Option<Vec<Tags>>, encodings: Option<&PyDict>) -> PyResult<Self> {
+        let files = match tags {
+            None => FilePaths::new(paths),
+            Some(tags) => FilePaths::with_tags(paths, tags)?,
+        };
+        files.with_encodings(encoding_pairs(encodings)?)
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "from_dir")]
+    #[pyo3(signature = (root, exclude = None))]
+    fn py_from_dir(root: &str, exclude: Option<Vec<String>>) -> PyResult<Self> {
+        let excludes = exclude::Excludes::new(&exclude.unwrap_or_default())?;
+        FilePaths::from_dir_excluding(root, &excludes).map_err(|e| PyIOError::new_err(format!("{}: {}", root, e)))
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "from_glob")]
+    #[pyo3(signature = (pattern, exclude = None))]
+    fn py_from_glob(pattern: &str, exclude: Option<Vec<String>>) -> PyResult<Self> {
+        FilePaths::from_glob_excluding(pattern, &exclude::Excludes::new(&exclude.unwrap_or_default())?)
+    }
+
+    /// Remove the paths matching any of the exclude patterns, with their tags.
+    #[pyo3(name = "exclude")]
+    fn py_exclude(&mut self, patterns: Vec<String>) -> PyResult<()> {
+        self.exclude(&exclude::Excludes::new(&patterns)?);
+        Ok(())
+    }
+
+    #[pyo3(signature = (path, tags = None))]
+    fn append(&mut self, path: String, tags: Option<Tags>) {
+        self.paths.push(path);
+        self.tags.push(tags.unwrap_or_default());
+    }
+
+    /// Read the paths matching `pattern`, which no pattern added before matches, in `encoding`.
+    fn add_encoding(&mut self, pattern: String, encoding: String) -> PyResult<()> {
+        let hint = [(pattern, encoding)];
+        EncodingHints::new(&hint)?;
+        self.encodings.extend(hint);
+        Ok(())
+    }
+
+    /// The (pattern, encoding) pairs of the paths, in order.
+    #[getter(encodings)]
+    fn py_encodings(&self) -> Vec<(String, String)> {
+        self.encodings.clone()
+    }
+
+    /// Append the paths of another `FilePaths`, with their tags and encodings, or of a list of
+    /// paths.
+    fn extend(&mut self, paths: &PyAny) -> PyResult<()> {
+        if let Ok(other) = paths.extract::<PyRef<FilePaths>>() {
+            self.paths.extend(other.paths.iter().cloned());
+            self.tags.extend(other.tags.iter().cloned());
+            self.encodings.extend(other.encodings.iter().cloned());
+        } else {
+            let paths: Vec<String> = paths.extract()?;
+            self.tags.extend(std::iter::repeat_n(Tags::new(), paths.len()));
+            self.paths.extend(paths);
+        }
+        Ok(())
+    }
+
+    fn __len__(&self) -> usize {
+        self.paths.len()
+    }
+
+    fn __contains__(&self, path: &str) -> bool {
+        self.paths.iter().any(|p| p == path)
+    }
+
+    fn __iter__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        self.paths.clone().into_py(py).call_method0(py, "__iter__")
+    }
+
+    fn __repr__(&self) -> String {
+        format!("FilePaths({} paths)", self.paths.len())
+    }
+}
+
+/// Collect the (pattern, encoding) pairs of a Python dict, preserving their order.
+fn extract_encoding_hints(encodings: Option<&PyDict>) -> PyResult<EncodingHints> {
+    EncodingHints::new(&encoding_pairs(encodings)?)
+}
+
+/// The (pattern, encoding label) pairs of a dict given to a Python function, in order.
+fn encoding_pairs(encodings: Option<&PyDict>) -> PyResult<Vec<(String, String)>> {
+    let mut hints = Vec::new();
+    for (pattern, label) in encodings.into_iter().flat_map(|d| d.iter()) {
+        hints.push((pattern.extract::<String>()?, label.extract::<String>()?));
+    }
+    Ok(hints)
+}
+
+/// The encoding hints given to a Python function, followed by those of the files it searches.
+fn file_encoding_hints(encodings: Option<&PyDict>, files: &FilePaths) -> PyResult<EncodingHints> {
+    let mut hints = encoding_pairs(encodings)?;
+    hints.extend(files.encodings.iter().cloned());
+    EncodingHints::new(&hints)
+}
+
+/// The encoding hints given to a Python function and to its files, or the configured ones if
+/// neither has any.
+fn configured_encoding_hints(encodings: Option<&PyDict>, files: Option<&FilePaths>, config: &config::Config) -> PyResult<EncodingHints> {
+    match files {
+        _ if encodings.is_none() && files.is_none_or(|files| files.encodings.is_empty()) => EncodingHints::new(&config.encodings),
+        Some(files) => file_encoding_hints(encodings, files),
+        None => extract_encoding_hints(encodings),
+    }
+}
+
+/// The window of context given to a Python function: `a` bytes (or characters with `chars`)
+/// before and `b` after the match, or whole lines, one of them defaulting to 0.
+fn context_window(a: Option<usize>, b: Option<usize>, chars: bool, before_lines: Option<usize>, after_lines: Option<usize>) -> PyResult<(Option<usize>, Option<usize>, ContextUnit)> {
+    match (before_lines, after_lines) {
+        (None, None) if chars => Ok((a, b, ContextUnit::Chars)),
+        (None, None) => Ok((a, b, ContextUnit::Bytes)),
+        _ if a.is_some() || b.is_some() || chars => Err(PyValueError::new_err("before_lines and after_lines can't be combined with a, b or context_chars")),
+        _ => Ok((before_lines.or(Some(0)), after_lines.or(Some(0)), ContextUnit::Lines)),
+    }
+}
+
+/// Context is only extracted when both `a` and `b` are given, which `scoped` requires.
+#[allow(clippy::too_many_arguments)]
+fn context_options(a: Option<usize>, b: Option<usize>, unit: ContextUnit, max_chars: Option<usize>, highlight_begin: String, highlight_end: String, highlight_all: bool, scoped: bool, ellipsis: bool) -> PyResult<Option<ContextOptions>> {
+    match (a, b) {
+        (Some(a), Some(b)) => Ok(Some(ContextOptions {
+            before: a,
+            after: b,
+            unit,
+            max_chars,
+            highlight_begin,
+            highlight_end,
+            highlight_all,
+            scoped,
+            ellipsis,
+            snippet: false,
+            max_contexts: None,
+            clause_contexts: false,
+        })),
+        _ if scoped => Err(PyValueError::new_err("scoped requires a and b, or a line context")),
+        _ => Ok(None),
+    }
+}
+
+/// Results are ranked when a score or `top_k` is given, by the total match count if no score
+/// is. The score is either a scoring function or the name of a built-in one. The Python scorer
+/// is also returned so that its errors can be re-raised.
+fn scoring_options(score: Option<PyObject>, top_k: Option<usize>) -> PyResult<(Option<ScoringOptions>, Option<Arc<score::PyScorer>>)> {
+    let name = score.as_ref().and_then(|score| Python::with_gil(|py| score.extract::<String>(py).ok()));
+    match (score, name, top_k) {
+        (None, _, None) => Ok((None, None)),
+        (None, _, Some(_)) => Ok((Some(ScoringOptions {
+            scorer: Arc::new(score::TotalCount),
+            top_k,
+        }), None)),
+        (Some(_), Some(name), _) => Ok((Some(ScoringOptions {
+            scorer: score::named(&name)?,
+            top_k,
+        }), None)),
+        (Some(callable), None, _) => {
+            let scorer = Arc::new(score::PyScorer::new(callable));
+            Ok((Some(ScoringOptions {
+                scorer: scorer.clone(),
+                top_k,
+            }), Some(scorer)))
+        }
+    }
+}
+
+#[pyfunction]
+#[pyo3(name = "search_text")]
+#[pyo3(signature = (query_group, textfile_paths, a = None, b = None, context_chars = false, before_lines = None, after_lines = None, max_context_chars = None, scoped = false, highlight_begin = String::new(), highlight_end = String::new(), highlight_all = false, ellipsis = false, snippet = false, max_contexts = None, clause_contexts = false, mode = None, on_result = None, tag_filter = None, cache = None, report = false, encodings = None, detect_encoding = false, allowed_encodings = None, normalizers = None, invert = false, max_concurrent_reads = None, max_file_size = None, head_bytes = None, tail_bytes = None, ranges = None, range_unit = "bytes", code = None, preset = None, decompress = false, binary = "skip", score = None, top_k = None, detect_language = false, deadline_ms = None, file_timeout_ms = None, max_results = None, profile = None, chunk_bytes = None, chunk_overlap = 1 << 16, progress = None, progress_every = 100, cancel = None, num_threads = None, memory_budget = None, mmap_threshold = None, dedup_paths = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_text(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    textfile_paths: &FilePaths,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    before_lines: Option<usize>,
+    after_lines: Option<usize>,
+    max_context_chars: Option<usize>,
+    scoped: bool,
+    highlight_begin: String,
+    highlight_end: String,
+    highlight_all: bool,
+    ellipsis: bool,
+    snippet: bool,
+    max_contexts: Option<usize>,
+    clause_contexts: bool,
+    mode: Option<options::PyResultMode>,
+    on_result: Option<PyObject>,
+    tag_filter: Option<&PyDict>,
+    cache: Option<ResultCache>,
+    report: bool,
+    encodings: Option<&PyDict>,
+    detect_encoding: bool,
+    allowed_encodings: Option<Vec<String>>,
+    normalizers: Option<&PyAny>,
+    invert: bool,
+    max_concurrent_reads: Option<usize>,
+    max_file_size: Option<u64>,
+    head_bytes: Option<u64>,
+    tail_bytes: Option<u64>,
+    ranges: Option<HashMap<String, Vec<(usize, usize)>>>,
+    range_unit: &str,
+    code: Option<&str>,
+    preset: Option<&str>,
+    decompress: bool,
+    binary: &str,
+    score: Option<PyObject>,
+    top_k: Option<usize>,
+    detect_language: bool,
+    deadline_ms: Option<u64>,
+    file_timeout_ms: Option<u64>,
+    max_results: Option<usize>,
+    profile: Option<usize>,
+    chunk_bytes: Option<usize>,
+    chunk_overlap: usize,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel: Option<crate::progress::CancelToken>,
+    num_threads: Option<usize>,
+    memory_budget: Option<u64>,
+    mmap_threshold: Option<u64>,
+    dedup_paths: Option<&str>,
+) -> PyResult<PyObject> {
+    let read = options::ReadArgs {
+        encodings,
+        detect_encoding,
+        allowed_encodings,
+        normalizers,
+        invert,
+        max_concurrent_reads,
+        max_file_size,
+        head_bytes,
+        tail_bytes,
+        ranges,
+        range_unit,
+        code,
+        preset,
+        decompress,
+        binary,
+    };
+    let context = options::ContextArgs {
+        a,
+        b,
+        context_chars,
+        before_lines,
+        after_lines,
+        max_context_chars,
+        scoped,
+        highlight_begin,
+        highlight_end,
+        highlight_all,
+        ellipsis,
+        snippet,
+        max_contexts,
+        clause_contexts,
+    };
+    let results = options::ResultArgs {
+        score,
+        top_k,
+        detect_language,
+    };
+    let scan = options::ScanArgs {
+        deadline_ms,
+        file_timeout_ms,
+        max_results,
+        profile,
+        chunk_bytes,
+        chunk_overlap,
+        progress,
+        progress_every,
+        cancel,
+        num_threads,
+        memory_budget,
+        mmap_threshold,
+        dedup_paths,
+    };
+    let mut search = options::SearchKwargs::new(Some(query_group), Some(textfile_paths), read, context, results, scan)?;
+    let mode = mode.map(|mode| mode.mode).unwrap_or_default();
+    if matches!(mode, options::ResultMode::Checkpoint { .. }) && search.options.deadline.is_some() {
+        return Err(PyValueError::new_err("a checkpoint can't be combined with deadline_ms"));
+    }
+    if matches!(mode, options::ResultMode::Checkpoint { .. }) && search.options.max_results.is_some() {
+        return Err(PyValueError::new_err("a checkpoint can't be combined with max_results"));
+    }
+    if cache.is_some() {
+        search.normalizers.check_named("a result cache")?;
+    }
+    search.options.cache = cache;
+    let options = &search.options;
+    let filter = match tag_filter {
+        None => TagFilter::default(),
+        Some(tag_filter) => TagFilter::from_py(tag_filter)?,
+    };
+    // the workers take the GIL to call back, normalize and score, so it must be released meanwhile
+    let cancel = &search.cancel;
+    let error = Mutex::new(None);
+    let call_back = |result: &FileMatchResult| {
+        if let Some(on_result) = &on_result {
+            Python::with_gil(|py| {
+                let outcome = Py::new(py, result.clone())
+                    .and_then(|result| on_result.call1(py, (result,)));
+                // the search is given up on, rather than calling back again for every match
+                if let Err(e) = outcome {
+                    cancel.cancel();
+                    error.lock().unwrap().get_or_insert(e);
+                }
+            })
+        }
+    };
+    let (outcome, interrupted) = crate::progress::interruptible(py, cancel, || match &mode {
+        options::ResultMode::Spill => spill::search_files_spilled(query_group, textfile_paths, &filter, options, call_back)
+            .map(|(results, search_report)| (Err(results), search_report)),
+        options::ResultMode::Checkpoint { path, every } => checkpoint::search_files_checkpointed(query_group, textfile_paths, &filter, options, path, *every, call_back)
+            .map(|(results, search_report)| (Ok(results), search_report)),
+        options::ResultMode::Sample { k, seed } => {
+            let (results, search_report) = sample::search_files_sample(query_group, textfile_paths, &filter, options, *k, *seed, call_back);
+            Ok((Ok(results), search_report))
+        }
+        options::ResultMode::Plain => {
+            let (results, search_report) = search_files_report(query_group, textfile_paths, &filter, options, call_back);
+            Ok((Ok(results), search_report))
+        }
+    });
+    let error = interrupted
+        .or_else(|| error.into_inner().unwrap())
+        .or_else(|| search.take_error());
+    if let Some(e) = error {
+        return Err(e);
+    }
+    let (results, search_report) = outcome.map_err(|e| PyIOError::new_err(e.to_string()))?;
+    if options.binary == binary::BinaryPolicy::Error {
+        let binaries: Vec<_> = search_report.errors.iter().filter(|(_, reason, _)| reason == "binary").map(|(path, _, _)| path.as_str()).collect();
+        if !binaries.is_empty() {
+            return Err(PyIOError::new_err(format!("binary files among those searched: {}", binaries.join(", "))));
+        }
+    }
+    let results = match results {
+        Ok(results) => results.into_py(py),
+        Err(spilled) => Py::new(py, spilled)?.into_py(py),
+    };
+    if let Some(cache) = &options.cache {
+        cache.save().map_err(|e| PyIOError::new_err(e.to_string()))?;
+    }
+    if report || options.profile.is_some() {
+        Ok((results, search_report).into_py(py))
+    } else {
+        Ok(results)
+    }
+}
+
+/// Register a corpus given as `FilePaths`, a list of paths, or a directory to walk recursively.
+#[pyfunction]
+#[pyo3(name = "register_corpus")]
+#[pyo3(signature = (name, paths, cache = true, exclude = None))]
+pub fn py_register_corpus(name: &str, paths: &PyAny, cache: bool, exclude: Option<Vec<String>>) -> PyResult<()> {
+    let excludes = exclude::Excludes::new(&exclude.unwrap_or_default())?;
+    let corpus = if let Ok(file_paths) = paths.extract::<PyRef<FilePaths>>() {
+        corpus::Corpus::new(file_paths.paths.iter().filter(|path| !excludes.is_excluded(path)).cloned().collect(), cache)
+    } else if let Ok(dir) = paths.extract::<String>() {
+        corpus::Corpus::from_dir_excluding(&dir, &excludes, cache)
+            .map_err(|e| PyValueError::new_err(format!("cannot walk {:?}: {}", dir, e)))?
+    } else {
+        let paths = paths.extract::<Vec<String>>()?;
+        corpus::Corpus::new(paths.into_iter().filter(|path| !excludes.is_excluded(path)).collect(), cache)
+    };
+    corpus::register_corpus(name, corpus);
+    Ok(())
+}
+
+#[pyfunction]
+#[pyo3(name = "unregister_corpus")]
+pub fn py_unregister_corpus(name: &str) -> bool {
+    corpus::unregister_corpus(name)
+}
+
+#[pyfunction]
+#[pyo3(name = "corpus_names")]
+pub fn py_corpus_names() -> Vec<String> {
+    corpus::corpus_names()
+}
+
+/// Search a corpus registered by `register_corpus`, with the arguments of `search_text` but
+/// those of its scan, e.g. `deadline_ms`.
+#[pyfunction]
+#[pyo3(name = "search")]
+#[pyo3(signature = (name, query_group, a = None, b = None, context_chars = false, before_lines = None, after_lines = None, max_context_chars = None, scoped = false, highlight_begin = String::new(), highlight_end = String::new(), highlight_all = false, ellipsis = false, snippet = false, max_contexts = None, clause_contexts = false, encodings = None, detect_encoding = false, allowed_encodings = None, normalizers = None, invert = false, max_concurrent_reads = None, max_file_size = None, head_bytes = None, tail_bytes = None, ranges = None, range_unit = "bytes", code = None, preset = None, decompress = false, binary = "skip", score = None, top_k = None, detect_language = false))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search(
+    py: Python<'_>,
+    name: &str,
+    query_group: &QueryGroup,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    before_lines: Option<usize>,
+    after_lines: Option<usize>,
+    max_context_chars: Option<usize>,
+    scoped: bool,
+    highlight_begin: String,
+    highlight_end: String,
+    highlight_all: bool,
+    ellipsis: bool,
+    snippet: bool,
+    max_contexts: Option<usize>,
+    clause_contexts: bool,
+    encodings: Option<&PyDict>,
+    detect_encoding: bool,
+    allowed_encodings: Option<Vec<String>>,
+    normalizers: Option<&PyAny>,
+    invert: bool,
+    max_concurrent_reads: Option<usize>,
+    max_file_size: Option<u64>,
+    head_bytes: Option<u64>,
+    tail_bytes: Option<u64>,
+    ranges: Option<HashMap<String, Vec<(usize, usize)>>>,
+    range_unit: &str,
+    code: Option<&str>,
+    preset: Option<&str>,
+    decompress: bool,
+    binary: &str,
+    score: Option<PyObject>,
+    top_k: Option<usize>,
+    detect_language: bool,
+) -> PyResult<Vec<FileMatchResult>> {
+    let corpus = corpus::get_corpus(name)
+        .ok_or_else(|| PyValueError::new_err(format!("no corpus named {:?}", name)))?;
+    let read = options::ReadArgs {
+        encodings,
+        detect_encoding,
+        allowed_encodings,
+        normalizers,
+        invert,
+        max_concurrent_reads,
+        max_file_size,
+        head_bytes,
+        tail_bytes,
+        ranges,
+        range_unit,
+        code,
+        preset,
+        decompress,
+        binary,
+    };
+    let context = options::ContextArgs {
+        a,
+        b,
+        context_chars,
+        before_lines,
+        after_lines,
+        max_context_chars,
+        scoped,
+        highlight_begin,
+        highlight_end,
+        highlight_all,
+        ellipsis,
+        snippet,
+        max_contexts,
+        clause_contexts,
+    };
+    let results = options::ResultArgs {
+        score,
+        top_k,
+        detect_language,
+    };
+    let search = options::SearchKwargs::new(Some(query_group), None, read, context, results, Default::default())?;
+    let results = py.allow_threads(|| corpus.search(query_group, &search.options));
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => Ok(results),
+    }
+}
+
+#[pyfunction]
+#[pyo3(name = "explain_atom")]
+#[pyo3(signature = (atom, kana_folding = false, romaji = false, romaji_dictionary = None, phonetic = false, case_folding = None, cjk_gap = None, whole_words = false, nfkc = false, fuzzy = None, word_gap = None, hyphens = false))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_explain_atom(
+    atom: &str,
+    kana_folding: bool,
+    romaji: bool,
+    romaji_dictionary: Option<HashMap<String, Vec<String>>>,
+    phonetic: bool,
+    case_folding: Option<&str>,
+    cjk_gap: Option<String>,
+    whole_words: bool,
+    nfkc: bool,
+    fuzzy: Option<usize>,
+    word_gap: Option<String>,
+    hyphens: bool,
+) -> PyResult<String> {
+    let options = QueryOptions {
+        kana_folding,
+        romaji,
+        romaji_dictionary: romaji_dictionary.unwrap_or_default(),
+        phonetic,
+        case_folding: case_folding.map(CaseFold::from_name).transpose()?,
+        separators: Separators {
+            words: word_gap,
+            cjk: cjk_gap,
+            hyphens,
+        },
+        whole_words,
+        nfkc,
+        fuzzy,
+        ..Default::default()
+    };
+    Ok(explain_atom(atom, &options))
+}
+
+/// How the query matches the file at `path`: for each OR group, whether and where it matches,
+/// with which atom and how long it took, then the exclusions and other constraints.
+#[pyfunction]
+#[pyo3(name = "explain")]
+#[pyo3(signature = (query_group, path, encodings = None, normalizers = None))]
+pub fn py_explain(py: Python<'_>, query_group: &QueryGroup, path: &str, encodings: Option<&PyDict>, normalizers: Option<&PyAny>) -> PyResult<explain::Explanation> {
+    let read = options::ReadArgs {
+        encodings,
+        normalizers,
+        ..Default::default()
+    };
+    let search = options::SearchKwargs::new(Some(query_group), None, read, Default::default(), Default::default(), Default::default())?;
+    let explanation = py.allow_threads(|| explain::explain(query_group, path, &search.options));
+    if let Some(e) = search.take_error() {
+        return Err(e);
+    }
+    explanation
+}
+
+/// Search the files in a child Python process, so that a crash, running out of memory or a
+/// runaway pattern fails the search instead of ending this process. The child runs `python`, by
+/// default the interpreter of this process, and is killed after `timeout` seconds.
+#[pyfunction]
+#[pyo3(name = "search_isolated")]
+#[pyo3(signature = (query_group, textfile_paths, a = None, b = None, context_chars = false, before_lines = None, after_lines = None, invert = false, timeout = None, python = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_isolated(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    textfile_paths: &FilePaths,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    before_lines: Option<usize>,
+    after_lines: Option<usize>,
+    invert: bool,
+    timeout: Option<f64>,
+    python: Option<String>,
+) -> PyResult<Vec<FileMatchResult>> {
+    let context = options::ContextArgs {
+        a,
+        b,
+        context_chars,
+        before_lines,
+        after_lines,
+        ..Default::default()
+    };
+    let search = options::SearchKwargs::new(Some(query_group), Some(textfile_paths), Default::default(), context, Default::default(), Default::default())?;
+    let python = match python {
+        Some(python) => python,
+        None => py.import("sys")?.getattr("executable")?.extract()?,
+    };
+    let timeout = timeout.map(Duration::try_from_secs_f64).transpose().map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let mut command = std::process::Command::new(python);
+    command.args(["-c", "import textsearcher; textsearcher.serve_worker()"]);
+    py.allow_threads(|| isolated::search_isolated(&mut command, query_group, &textfile_paths.paths, search.options.context.as_ref(), invert, timeout))
+        .map_err(|e| PyIOError::new_err(e.to_string()))
+}
+
+/// Serve a search of `search_isolated` from stdin to stdout, in the child process.
+#[pyfunction]
+#[pyo3(name = "serve_worker")]
+pub fn py_serve_worker(py: Python<'_>) -> PyResult<()> {
+    py.allow_threads(|| isolated::serve(std::io::stdin().lock(), std::io::stdout().lock()))
+        .map_err(|e| PyIOError::new_err(e.to_string()))
+}
+
+/// The number of matching files, or with `occurrences=True`, a tuple of it and the total number
+/// of matches in those files. Takes the arguments of `search_text` on reading and matching the
+/// files; with `report=True`, a tuple of the count and the `SearchReport` of the scan.
+#[pyfunction]
+#[pyo3(name = "count_matches")]
+#[pyo3(signature = (query_group, textfile_paths, occurrences = false, encodings = None, detect_encoding = false, allowed_encodings = None, normalizers = None, invert = false, max_concurrent_reads = None, max_file_size = None, head_bytes = None, tail_bytes = None, ranges = None, range_unit = "bytes", code = None, preset = None, decompress = false, binary = "skip", report = false))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_count_matches(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    textfile_paths: &FilePaths,
+    occurrences: bool,
+    encodings: Option<&PyDict>,
+    detect_encoding: bool,
+    allowed_encodings: Option<Vec<String>>,
+    normalizers: Option<&PyAny>,
+    invert: bool,
+    max_concurrent_reads: Option<usize>,
+    max_file_size: Option<u64>,
+    head_bytes: Option<u64>,
+    tail_bytes: Option<u64>,
+    ranges: Option<HashMap<String, Vec<(usize, usize)>>>,
+    range_unit: &str,
+    code: Option<&str>,
+    preset: Option<&str>,
+    decompress: bool,
+    binary: &str,
+    report: bool,
+) -> PyResult<PyObject> {
+    let read = options::ReadArgs {
+        encodings,
+        detect_encoding,
+        allowed_encodings,
+        normalizers,
+        invert,
+        max_concurrent_reads,
+        max_file_size,
+        head_bytes,
+        tail_bytes,
+        ranges,
+        range_unit,
+        code,
+        preset,
+        decompress,
+        binary,
+    };
+    let search = options::SearchKwargs::new(Some(query_group), Some(textfile_paths), read, Default::default(), Default::default(), Default::default())?;
+    let ((files, total), search_report) = py.allow_threads(|| count_matches_report(query_group, &textfile_paths.paths, &search.options, occurrences));
+    if let Some(e) = search.take_error() {
+        return Err(e);
+    }
+    let count = match total {
+        None => files.into_py(py),
+        Some(total) => (files, total).into_py(py),
+    };
+    with_report(py, count, report.then_some(search_report))
+}
+
+/// `value`, or a tuple of it and the report of the scan if asked for.
+fn with_report(py: Python<'_>, value: PyObject, report: Option<SearchReport>) -> PyResult<PyObject> {
+    match report {
+        Some(report) => Ok((value, report).into_py(py)),
+        None => Ok(value),
+    }
+}
+
+/// Whether any of the files matches, stopping at the first one found. Takes the arguments of
+/// `count_matches`.
+#[pyfunction]
+#[pyo3(name = "exists_match")]
+#[pyo3(signature = (query_group, textfile_paths, encodings = None, detect_encoding = false, allowed_encodings = None, normalizers = None, invert = false, max_concurrent_reads = None, max_file_size = None, head_bytes = None, tail_bytes = None, ranges = None, range_unit = "bytes", code = None, preset = None, decompress = false, binary = "skip", report = false))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_exists_match(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    textfile_paths: &FilePaths,
+    encodings: Option<&PyDict>,
+    detect_encoding: bool,
+    allowed_encodings: Option<Vec<String>>,
+    normalizers: Option<&PyAny>,
+    invert: bool,
+    max_concurrent_reads: Option<usize>,
+    max_file_size: Option<u64>,
+    head_bytes: Option<u64>,
+    tail_bytes: Option<u64>,
+    ranges: Option<HashMap<String, Vec<(usize, usize)>>>,
+    range_unit: &str,
+    code: Option<&str>,
+    preset: Option<&str>,
+    decompress: bool,
+    binary: &str,
+    report: bool,
+) -> PyResult<PyObject> {
+    let read = options::ReadArgs {
+        encodings,
+        detect_encoding,
+        allowed_encodings,
+        normalizers,
+        invert,
+        max_concurrent_reads,
+        max_file_size,
+        head_bytes,
+        tail_bytes,
+        ranges,
+        range_unit,
+        code,
+        preset,
+        decompress,
+        binary,
+    };
+    let search = options::SearchKwargs::new(Some(query_group), Some(textfile_paths), read, Default::default(), Default::default(), Default::default())?;
+    let (exists, search_report) = py.allow_threads(|| exists_match_report(query_group, &textfile_paths.paths, &search.options));
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => with_report(py, exists.into_py(py), report.then_some(search_report)),
+    }
+}
+
+/// The files matching any of the query groups, as `(path, indices of the matching groups)`.
+/// Takes the arguments of `count_matches` but `invert`.
+#[pyfunction]
+#[pyo3(name = "search_any")]
+#[pyo3(signature = (query_groups, textfile_paths, encodings = None, detect_encoding = false, allowed_encodings = None, normalizers = None, max_concurrent_reads = None, max_file_size = None, head_bytes = None, tail_bytes = None, ranges = None, range_unit = "bytes", code = None, preset = None, decompress = false, binary = "skip", report = false))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_any(
+    py: Python<'_>,
+    query_groups: Vec<PyRef<QueryGroup>>,
+    textfile_paths: &FilePaths,
+    encodings: Option<&PyDict>,
+    detect_encoding: bool,
+    allowed_encodings: Option<Vec<String>>,
+    normalizers: Option<&PyAny>,
+    max_concurrent_reads: Option<usize>,
+    max_file_size: Option<u64>,
+    head_bytes: Option<u64>,
+    tail_bytes: Option<u64>,
+    ranges: Option<HashMap<String, Vec<(usize, usize)>>>,
+    range_unit: &str,
+    code: Option<&str>,
+    preset: Option<&str>,
+    decompress: bool,
+    binary: &str,
+    report: bool,
+) -> PyResult<PyObject> {
+    let query_groups: Vec<QueryGroup> = query_groups.iter().map(|qg| (**qg).clone()).collect();
+    let read = options::ReadArgs {
+        encodings,
+        detect_encoding,
+        allowed_encodings,
+        normalizers,
+        max_concurrent_reads,
+        max_file_size,
+        head_bytes,
+        tail_bytes,
+        ranges,
+        range_unit,
+        code,
+        preset,
+        decompress,
+        binary,
+        ..Default::default()
+    };
+    let search = options::SearchKwargs::new(None, Some(textfile_paths), read, Default::default(), Default::default(), Default::default())?;
+    let (results, search_report) = py.allow_threads(|| search_any_report(&query_groups, &textfile_paths.paths, &search.options));
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => with_report(py, results.into_py(py), report.then_some(search_report)),
+    }
+}
+
+/// The results of each of the query groups, in their order, each file read once for all of them.
+/// Takes the arguments of `search` on a corpus, and `report` like `count_matches`.
+#[pyfunction]
+#[pyo3(name = "search_text_multi")]
+#[pyo3(signature = (query_groups, textfile_paths, parallel = true, a = None, b = None, context_chars = false, before_lines = None, after_lines = None, max_context_chars = None, scoped = false, highlight_begin = String::new(), highlight_end = String::new(), highlight_all = false, ellipsis = false, snippet = false, max_contexts = None, clause_contexts = false, encodings = None, detect_encoding = false, allowed_encodings = None, normalizers = None, invert = false, max_concurrent_reads = None, max_file_size = None, head_bytes = None, tail_bytes = None, ranges = None, range_unit = "bytes", code = None, preset = None, decompress = false, binary = "skip", score = None, top_k = None, detect_language = false, report = false))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_text_multi(
+    py: Python<'_>,
+    query_groups: Vec<PyRef<QueryGroup>>,
+    textfile_paths: &FilePaths,
+    parallel: bool,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    before_lines: Option<usize>,
+    after_lines: Option<usize>,
+    max_context_chars: Option<usize>,
+    scoped: bool,
+    highlight_begin: String,
+    highlight_end: String,
+    highlight_all: bool,
+    ellipsis: bool,
+    snippet: bool,
+    max_contexts: Option<usize>,
+    clause_contexts: bool,
+    encodings: Option<&PyDict>,
+    detect_encoding: bool,
+    allowed_encodings: Option<Vec<String>>,
+    normalizers: Option<&PyAny>,
+    invert: bool,
+    max_concurrent_reads: Option<usize>,
+    max_file_size: Option<u64>,
+    head_bytes: Option<u64>,
+    tail_bytes: Option<u64>,
+    ranges: Option<HashMap<String, Vec<(usize, usize)>>>,
+    range_unit: &str,
+    code: Option<&str>,
+    preset: Option<&str>,
+    decompress: bool,
+    binary: &str,
+    score: Option<PyObject>,
+    top_k: Option<usize>,
+    detect_language: bool,
+    report: bool,
+) -> PyResult<PyObject> {
+    let query_groups: Vec<QueryGroup> = query_groups.iter().map(|qg| (**qg).clone()).collect();
+    let read = options::ReadArgs {
+        encodings,
+        detect_encoding,
+        allowed_encodings,
+        normalizers,
+        invert,
+        max_concurrent_reads,
+        max_file_size,
+        head_bytes,
+        tail_bytes,
+        ranges,
+        range_unit,
+        code,
+        preset,
+        decompress,
+        binary,
+    };
+    let context = options::ContextArgs {
+        a,
+        b,
+        context_chars,
+        before_lines,
+        after_lines,
+        max_context_chars,
+        scoped,
+        highlight_begin,
+        highlight_end,
+        highlight_all,
+        ellipsis,
+        snippet,
+        max_contexts,
+        clause_contexts,
+    };
+    let results = options::ResultArgs {
+        score,
+        top_k,
+        detect_language,
+    };
+    let mut search = options::SearchKwargs::new(None, Some(textfile_paths), read, context, results, Default::default())?;
+    search.options.parallel = parallel;
+    let (results, search_report) = py.allow_threads(|| search_text_multi_report(&query_groups, &textfile_paths.paths, &search.options));
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => with_report(py, results.into_py(py), report.then_some(search_report)),
+    }
+}
+
+#[pyfunction]
+#[pyo3(name = "match_str")]
+pub fn py_match_str(query_group: &QueryGroup, contents: &str) -> bool {
+    is_match_str(query_group, contents)
+}
+
+#[pyfunction]
+#[pyo3(name = "count_match_str")]
+pub fn py_count_match_str(query_group: &QueryGroup, contents: &str) -> Option<usize> {
+    count_match_str(query_group, contents)
+}
+
+#[pyfunction]
+#[pyo3(name = "html_snippet")]
+#[pyo3(signature = (query_group, text, max_chars = None))]
+pub fn py_html_snippet(query_group: &QueryGroup, text: &str, max_chars: Option<usize>) -> String {
+    snippet::html_snippet(&query_group.patterns, text, max_chars)
+}
+
+/// `text[start:end]` in UTF-8 bytes, shrunk to the nearest character boundaries within.
+#[pyfunction]
+#[pyo3(name = "approx_substring")]
+pub fn py_approx_substring(text: &str, start: usize, end: usize) -> &str {
+    snippet::approx_substring(text, start, end)
+}
+
+/// The text `before` and `after` units (`bytes`, `chars` or `graphemes`) around the UTF-8 byte
+/// `offset`, which is moved back to a character boundary.
+#[pyfunction]
+#[pyo3(name = "slice_around")]
+#[pyo3(signature = (text, offset, before, after, unit = "chars"))]
+pub fn py_slice_around<'a>(text: &'a str, offset: usize, before: usize, after: usize, unit: &str) -> PyResult<&'a str> {
+    let unit = snippet::SliceUnit::from_name(unit)?;
+    let (start, end) = snippet::slice_around(text, offset, before, after, unit);
+    Ok(&text[start..end])
+}
+
+/// Render the contexts of many results at once, in parallel. Results without context render as
+/// `None`.
+#[pyfunction]
+#[pyo3(name = "html_snippets")]
+#[pyo3(signature = (query_group, results, max_chars = None))]
+pub fn py_html_snippets(query_group: &QueryGroup, results: Vec<PyRef<FileMatchResult>>, max_chars: Option<usize>) -> Vec<Option<String>> {
+    let contexts: Vec<_> = results.iter().map(|r| r.context.clone()).collect();
+    contexts
+        .par_iter()
+        .map(|context| context.as_ref().map(|c| snippet::html_snippet(&query_group.patterns, c, max_chars)))
+        .collect()
+}
+
+/// The queries of the suite at `path`, by name.
+#[pyfunction]
+#[pyo3(name = "load_query_suite")]
+pub fn py_load_query_suite(path: &str) -> PyResult<HashMap<String, suite::SuiteQuery>> {
+    Ok(suite::load_suite(path)?
+        .into_iter()
+        .map(|query| (query.name.clone(), query))
+        .collect())
+}
+
+/// Group results by directory, or by the first `depth` components of their directory, with up to
+/// `examples` results of each group.
+#[pyfunction]
+#[pyo3(name = "aggregate_by_directory")]
+#[pyo3(signature = (results, depth = None, examples = 1))]
+pub fn py_aggregate_by_directory(results: Vec<FileMatchResult>, depth: Option<usize>, examples: usize) -> Vec<aggregate::DirectoryGroup> {
+    aggregate::aggregate_by_directory(&results, depth, examples)
+}
+
+/// Collapse results with the same context, or nearly the same if `near`, e.g. the matches in a
+/// boilerplate header, into the first of them, which tells how many were folded into it.
+#[pyfunction]
+#[pyo3(name = "collapse_duplicates")]
+#[pyo3(signature = (results, near = false))]
+pub fn py_collapse_duplicates(results: Vec<FileMatchResult>, near: bool) -> Vec<dedup::CollapsedResult> {
+    dedup::collapse_duplicates(results, near)
+}
+
+/// Bucket results by which atom of the `group`-th OR group matches in each file, e.g. to see
+/// which of some synonyms are used the most.
+#[pyfunction]
+#[pyo3(name = "group_by_alternative")]
+#[pyo3(signature = (query_group, results, group = 0, encodings = None, normalizers = None))]
+pub fn py_group_by_alternative(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    results: Vec<FileMatchResult>,
+    group: usize,
+    encodings: Option<&PyDict>,
+    normalizers: Option<&PyAny>,
+) -> PyResult<Vec<aggregate::AlternativeGroup>> {
+    let read = options::ReadArgs {
+        encodings,
+        normalizers,
+        ..Default::default()
+    };
+    let search = options::SearchKwargs::new(Some(query_group), None, read, Default::default(), Default::default(), Default::default())?;
+    let groups = py.allow_threads(|| aggregate::group_by_alternative(query_group, group, &results, &search.options))?;
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => Ok(groups),
+    }
+}
+
+#[pyfunction]
+#[pyo3(name = "diff_results")]
+pub fn py_diff_results(previous: Vec<FileMatchResult>, current: Vec<FileMatchResult>) -> diff::ResultDiff {
+    diff::diff_results(&previous, &current)
+}
+
+#[pyfunction]
+#[pyo3(name = "save_results")]
+pub fn py_save_results(results: Vec<FileMatchResult>, path: &str) -> PyResult<()> {
+    diff::save_results(&results, path).map_err(|e| PyIOError::new_err(e.to_string()))
+}
+
+/// The results as JSON lines, one object per result.
+#[pyfunction]
+#[pyo3(name = "results_to_jsonl")]
+pub fn py_results_to_jsonl(results: Vec<FileMatchResult>) -> String {
+    diff::results_to_jsonl(&results)
+}
+
+#[pyfunction]
+#[pyo3(name = "load_results")]
+pub fn py_load_results(path: &str) -> PyResult<Vec<FileMatchResult>> {
+    diff::load_results(path).map_err(|e| PyIOError::new_err(e.to_string()))
+}
+
+/// Plan the replacement of the matches of the first pattern of `query_group` by `template`, which
+/// may refer to capture groups as `$1` or `${name}`, in the files matching the whole group.
+#[pyfunction]
+#[pyo3(name = "plan_replace")]
+#[pyo3(signature = (query_group, textfile_paths, template, encodings = None))]
+pub fn py_plan_replace(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    textfile_paths: &FilePaths,
+    template: &str,
+    encodings: Option<&PyDict>,
+) -> PyResult<Vec<replace::FileEdit>> {
+    let read = options::ReadArgs {
+        encodings,
+        ..Default::default()
+    };
+    let search = options::SearchKwargs::new(Some(query_group), Some(textfile_paths), read, Default::default(), Default::default(), Default::default())?;
+    Ok(py.allow_threads(|| replace::plan_replace(query_group, &textfile_paths.paths, template, &search.options)))
+}
+
+/// Write the edits planned by `plan_replace`; pass an empty `backup_suffix` to not keep backups.
+#[pyfunction]
+#[pyo3(name = "apply_replace")]
+#[pyo3(signature = (edits, backup_suffix = ".bak"))]
+pub fn py_apply_replace(edits: Vec<replace::FileEdit>, backup_suffix: &str) -> PyResult<()> {
+    let backup_suffix = Some(backup_suffix).filter(|s| !s.is_empty());
+    replace::apply_edits(&edits, backup_suffix).map_err(|e| PyIOError::new_err(e.to_string()))
+}
+
+/// The captures of the first pattern of `query_group` in each file matching the whole group, e.g.
+/// with the atom `fancy:(10\.\d{4,}/\S+)` to extract DOIs.
+#[pyfunction]
+#[pyo3(name = "extract")]
+#[pyo3(signature = (query_group, textfile_paths, encodings = None, normalizers = None))]
+pub fn py_extract(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    textfile_paths: &FilePaths,
+    encodings: Option<&PyDict>,
+    normalizers: Option<&PyAny>,
+) -> PyResult<Vec<extract::Extraction>> {
+    let read = options::ReadArgs {
+        encodings,
+        normalizers,
+        ..Default::default()
+    };
+    let search = options::SearchKwargs::new(Some(query_group), Some(textfile_paths), read, Default::default(), Default::default(), Default::default())?;
+    let extractions = py.allow_threads(|| extract::extract(query_group, &textfile_paths.paths, &search.options));
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => Ok(extractions),
+    }
+}
+
+/// Every match of each pattern of `query_group` in the file at `path`, a list per pattern of
+/// `(start, end, text)` tuples, the byte offsets being those in the normalized contents.
+#[pyfunction]
+#[pyo3(name = "find_all")]
+#[pyo3(signature = (query_group, path, encodings = None, normalizers = None))]
+pub fn py_find_all(py: Python<'_>, query_group: &QueryGroup, path: &str, encodings: Option<&PyDict>, normalizers: Option<&PyAny>) -> PyResult<Vec<Vec<(usize, usize, String)>>> {
+    let read = options::ReadArgs {
+        encodings,
+        normalizers,
+        ..Default::default()
+    };
+    let search = options::SearchKwargs::new(Some(query_group), None, read, Default::default(), Default::default(), Default::default())?;
+    let found = py.allow_threads(|| extract::find_all(query_group, path, &search.options));
+    if let Some(e) = search.take_error() {
+        return Err(e);
+    }
+    found.map_err(|e| PyIOError::new_err(format!("{}: {}", path, e)))
+}
+
+/// The rows of the CSV (or TSV) files whose `columns`, given by name or index, match, each row
+/// matched on its own; the rows are counted from 0, not counting the header. The context is the
+/// whole row with `whole_row=True`, or else taken around the match in its columns.
+#[pyfunction]
+#[pyo3(name = "search_csv")]
+#[pyo3(signature = (query_group, textfile_paths, columns = None, delimiter = None, headers = true, whole_row = false, a = None, b = None, context_chars = false, encodings = None, normalizers = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_csv(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    textfile_paths: &FilePaths,
+    columns: Option<Vec<records::Column>>,
+    delimiter: Option<char>,
+    headers: bool,
+    whole_row: bool,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    encodings: Option<&PyDict>,
+    normalizers: Option<&PyAny>,
+) -> PyResult<Vec<records::RecordMatch>> {
+    let columns = columns.unwrap_or_default();
+    if !headers && columns.iter().any(|column| matches!(column, records::Column::Name(_))) {
+        return Err(PyValueError::new_err("columns can only be given by name with headers"));
+    }
+    let delimiter = delimiter
+        .map(|delimiter| u8::try_from(delimiter).ok().filter(u8::is_ascii).ok_or_else(|| PyValueError::new_err(format!("the delimiter {:?} isn't ASCII", delimiter))))
+        .transpose()?;
+    let read = options::ReadArgs {
+        encodings,
+        normalizers,
+        ..Default::default()
+    };
+    let context = options::ContextArgs {
+        a,
+        b,
+        context_chars,
+        ..Default::default()
+    };
+    let search = options::SearchKwargs::new(Some(query_group), Some(textfile_paths), read, context, Default::default(), Default::default())?;
+    let csv_options = records::CsvOptions {
+        delimiter,
+        headers,
+        columns,
+        whole_row,
+    };
+    let matches = py.allow_threads(|| records::search_csv(query_group, &textfile_paths.paths, &search.options, &csv_options));
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => Ok(matches),
+    }
+}
+
+/// The lines of the JSONL files matching, each line matched on its own as a record; the records
+/// are counted from 0. With `fields`, JSON pointers like `/title`, only those fields of each
+/// record are matched, before the other `normalizers`.
+#[pyfunction]
+#[pyo3(name = "search_jsonl")]
+#[pyo3(signature = (query_group, textfile_paths, fields = None, a = None, b = None, context_chars = false, encodings = None, normalizers = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_jsonl(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    textfile_paths: &FilePaths,
+    fields: Option<Vec<String>>,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    encodings: Option<&PyDict>,
+    normalizers: Option<&PyAny>,
+) -> PyResult<Vec<records::RecordMatch>> {
+    let read = options::ReadArgs {
+        encodings,
+        normalizers,
+        ..Default::default()
+    };
+    let context = options::ContextArgs {
+        a,
+        b,
+        context_chars,
+        ..Default::default()
+    };
+    let mut search = options::SearchKwargs::new(Some(query_group), Some(textfile_paths), read, context, Default::default(), Default::default())?;
+    if let Some(fields) = fields {
+        search.options.normalizers.insert(0, Arc::new(normalize::JsonFields::new(fields)?));
+    }
+    let matches = py.allow_threads(|| records::search_jsonl(query_group, &textfile_paths.paths, &search.options));
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => Ok(matches),
+    }
+}
+
+/// The messages of the mbox files, or the emails (e.g. `.eml` files), matching, each message
+/// matched on its own, on its decoded subject, sender, recipients and date, then text parts; the
+/// messages are counted from 0.
+#[pyfunction]
+#[pyo3(name = "search_email")]
+#[pyo3(signature = (query_group, textfile_paths, a = None, b = None, context_chars = false, encodings = None, normalizers = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_email(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    textfile_paths: &FilePaths,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    encodings: Option<&PyDict>,
+    normalizers: Option<&PyAny>,
+) -> PyResult<Vec<records::RecordMatch>> {
+    let read = options::ReadArgs {
+        encodings,
+        normalizers,
+        ..Default::default()
+    };
+    let context = options::ContextArgs {
+        a,
+        b,
+        context_chars,
+        ..Default::default()
+    };
+    let search = options::SearchKwargs::new(Some(query_group), Some(textfile_paths), read, context, Default::default(), Default::default())?;
+    let matches = py.allow_threads(|| records::search_email(query_group, &textfile_paths.paths, &search.options));
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => Ok(matches),
+    }
+}
+
+/// The entries of the log files matching, each entry, a line with a leading timestamp and the
+/// lines without one after it, matched on its own. With `since` and `until`, naive datetimes,
+/// only the entries from `since` and before `until` are matched. The timestamps are parsed with
+/// the `strftime`-like `formats`, tried in order, by default ISO 8601, Python's `logging` and
+/// the common log format.
+#[pyfunction]
+#[pyo3(name = "search_logs")]
+#[pyo3(signature = (query_group, textfile_paths, since = None, until = None, formats = None, encodings = None, normalizers = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_logs(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    textfile_paths: &FilePaths,
+    since: Option<chrono::NaiveDateTime>,
+    until: Option<chrono::NaiveDateTime>,
+    formats: Option<Vec<String>>,
+    encodings: Option<&PyDict>,
+    normalizers: Option<&PyAny>,
+) -> PyResult<Vec<logs::LogEntry>> {
+    let log_options = logs::LogOptions::new(formats.unwrap_or_default(), since, until)?;
+    let read = options::ReadArgs {
+        encodings,
+        normalizers,
+        ..Default::default()
+    };
+    let search = options::SearchKwargs::new(Some(query_group), Some(textfile_paths), read, Default::default(), Default::default(), Default::default())?;
+    let entries = py.allow_threads(|| logs::search_logs(query_group, &textfile_paths.paths, &search.options, &log_options));
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => Ok(entries),
+    }
+}
+
+/// The groups of files matching, each group taken as one document made of its files in order,
+/// e.g. the chapters of a book, so that the OR groups may match in different files. `groups`
+/// maps the id of each group to its paths; the matches are in its order and give the files of
+/// the group where any OR group matches.
+#[pyfunction]
+#[pyo3(name = "search_groups")]
+#[pyo3(signature = (query_group, groups, a = None, b = None, context_chars = false, encodings = None, normalizers = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_groups(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    groups: &PyDict,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    encodings: Option<&PyDict>,
+    normalizers: Option<&PyAny>,
+) -> PyResult<Vec<groups::GroupMatch>> {
+    let groups = groups
+        .iter()
+        .map(|(id, paths)| Ok((id.extract::<String>()?, paths.extract::<Vec<String>>()?)))
+        .collect::<PyResult<Vec<_>>>()?;
+    let read = options::ReadArgs {
+        encodings,
+        normalizers,
+        ..Default::default()
+    };
+    let context = options::ContextArgs {
+        a,
+        b,
+        context_chars,
+        ..Default::default()
+    };
+    let search = options::SearchKwargs::new(Some(query_group), None, read, context, Default::default(), Default::default())?;
+    let matches = py.allow_threads(|| groups::search_groups(query_group, &groups, &search.options));
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => Ok(matches),
+    }
+}
+
+/// The files below `root` matching: those matching `include` patterns, if any, but no `exclude`
+/// pattern, which are matched like those of `FilePaths.from_dir`, nor with `gitignore=True`
+/// ignored by a `.gitignore` file.
+#[pyfunction]
+#[pyo3(name = "search_dir")]
+#[pyo3(signature = (query_group, root, include = None, exclude = None, gitignore = false, parallel = true, a = None, b = None, context_chars = false, encodings = None, normalizers = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_dir(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    root: &str,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    gitignore: bool,
+    parallel: bool,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    encodings: Option<&PyDict>,
+    normalizers: Option<&PyAny>,
+) -> PyResult<Vec<FileMatchResult>> {
+    let walk = walk::WalkOptions {
+        include: exclude::Excludes::new(&include.unwrap_or_default())?,
+        exclude: exclude::Excludes::new(&exclude.unwrap_or_default())?,
+        gitignore,
+    };
+    let read = options::ReadArgs {
+        encodings,
+        normalizers,
+        ..Default::default()
+    };
+    let context = options::ContextArgs {
+        a,
+        b,
+        context_chars,
+        ..Default::default()
+    };
+    let mut search = options::SearchKwargs::new(Some(query_group), None, read, context, Default::default(), Default::default())?;
+    search.options.parallel = parallel;
+    let results = py
+        .allow_threads(|| walk::search_dir(query_group, root, &walk, &search.options))
+        .map_err(|e| PyIOError::new_err(format!("{}: {}", root, e)))?;
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => Ok(results),
+    }
+}
+
+/// The matches of the files below `root` created or modified from now on, polled every
+/// `interval` seconds, as an iterator waiting for each of them until `cancel` is cancelled. The
+/// files watched are selected like those of `search_dir`.
+#[pyfunction]
+#[pyo3(name = "watch")]
+#[pyo3(signature = (query_group, root, interval = 1.0, include = None, exclude = None, gitignore = false, a = None, b = None, context_chars = false, encodings = None, cancel = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_watch(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    root: &str,
+    interval: f64,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    gitignore: bool,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    encodings: Option<&PyDict>,
+    cancel: Option<progress::CancelToken>,
+) -> PyResult<watch::PyWatch> {
+    let interval = Duration::try_from_secs_f64(interval).map_err(|e| PyValueError::new_err(format!("invalid interval: {}", e)))?;
+    let walk = walk::WalkOptions {
+        include: exclude::Excludes::new(&include.unwrap_or_default())?,
+        exclude: exclude::Excludes::new(&exclude.unwrap_or_default())?,
+        gitignore,
+    };
+    let read = options::ReadArgs {
+        encodings,
+        ..Default::default()
+    };
+    let context = options::ContextArgs {
+        a,
+        b,
+        context_chars,
+        ..Default::default()
+    };
+    let scan = options::ScanArgs {
+        cancel,
+        ..Default::default()
+    };
+    let search = options::SearchKwargs::new(Some(query_group), None, read, context, Default::default(), scan)?;
+    let watcher = py
+        .allow_threads(|| watch::Watcher::new(root, walk))
+        .map_err(|e| PyIOError::new_err(format!("{}: {}", root, e)))?;
+    Ok(watch::PyWatch::new(query_group, watcher, search.options, interval))
+}
+
+/// The files of `index` matching, only those of them that may match being read. The
+/// normalizers must be those the index was built with for it to rule out any file.
+#[pyfunction]
+#[pyo3(name = "search_index")]
+#[pyo3(signature = (query_group, index, parallel = true, a = None, b = None, context_chars = false, encodings = None, normalizers = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_index(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    index: &index::Index,
+    parallel: bool,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    encodings: Option<&PyDict>,
+    normalizers: Option<&PyAny>,
+) -> PyResult<Vec<FileMatchResult>> {
+    let read = options::ReadArgs {
+        encodings,
+        normalizers,
+        ..Default::default()
+    };
+    let context = options::ContextArgs {
+        a,
+        b,
+        context_chars,
+        ..Default::default()
+    };
+    let mut search = options::SearchKwargs::new(Some(query_group), None, read, context, Default::default(), Default::default())?;
+    search.options.parallel = parallel;
+    search.normalizers.check_named("an index")?;
+    let results = py.allow_threads(|| index::search_index(query_group, index, &search.options));
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => Ok(results),
+    }
+}
+
+/// A stream of the files matching, iterated as they're found by the threads searching them in
+/// the background, so that the first matches of a slow search can be shown already. The stream
+/// is cancelled with its `cancel` method or with `cancel`, or once garbage collected.
+#[pyfunction]
+#[pyo3(name = "search_stream")]
+#[pyo3(signature = (query_group, textfile_paths, a = None, b = None, context_chars = false, encodings = None, cancel = None, num_threads = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_stream(
+    query_group: &QueryGroup,
+    textfile_paths: &FilePaths,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    encodings: Option<&PyDict>,
+    cancel: Option<progress::CancelToken>,
+    num_threads: Option<usize>,
+) -> PyResult<lazy::PySearchStream> {
+    let options = background_options(query_group, a, b, context_chars, encodings, textfile_paths, cancel, num_threads)?;
+    Ok(lazy::PySearchStream::new(lazy::search_stream(query_group, &textfile_paths.paths, &options)))
+}
+
+/// The options of the searches of `files` run in the background, with the configured defaults.
+#[allow(clippy::too_many_arguments)]
+fn background_options(
+    query_group: &QueryGroup,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    encodings: Option<&PyDict>,
+    files: &FilePaths,
+    cancel: Option<progress::CancelToken>,
+    num_threads: Option<usize>,
+) -> PyResult<SearchOptions> {
+    let read = options::ReadArgs {
+        encodings,
+        ..Default::default()
+    };
+    let context = options::ContextArgs {
+        a,
+        b,
+        context_chars,
+        ..Default::default()
+    };
+    let scan = options::ScanArgs {
+        cancel,
+        num_threads,
+        ..Default::default()
+    };
+    Ok(options::SearchKwargs::new(Some(query_group), Some(files), read, context, Default::default(), scan)?.options)
+}
+
+/// An awaitable of the files matching, searched on background threads so that the event loop
+/// awaiting it, which must be running, isn't blocked. Cancelling it cancels the search.
+#[pyfunction]
+#[pyo3(name = "search_text_async")]
+#[pyo3(signature = (query_group, textfile_paths, a = None, b = None, context_chars = false, encodings = None, cancel = None, num_threads = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_text_async(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    textfile_paths: &FilePaths,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    encodings: Option<&PyDict>,
+    cancel: Option<progress::CancelToken>,
+    num_threads: Option<usize>,
+) -> PyResult<PyObject> {
+    let options = background_options(query_group, a, b, context_chars, encodings, textfile_paths, cancel, num_threads)?;
+    asyncio::search_text_future(py, query_group, &textfile_paths.paths, options)
+}
+
+/// Like `search_stream`, but iterated with `async for` on the running event loop.
+#[pyfunction]
+#[pyo3(name = "search_stream_async")]
+#[pyo3(signature = (query_group, textfile_paths, a = None, b = None, context_chars = false, encodings = None, cancel = None, num_threads = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_stream_async(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    textfile_paths: &FilePaths,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    encodings: Option<&PyDict>,
+    cancel: Option<progress::CancelToken>,
+    num_threads: Option<usize>,
+) -> PyResult<asyncio::PyAsyncSearchStream> {
+    let options = background_options(query_group, a, b, context_chars, encodings, textfile_paths, cancel, num_threads)?;
+    asyncio::PyAsyncSearchStream::new(py, query_group, &textfile_paths.paths, options)
+}
+
+/// The files matching, except that the zip and tar archives among them, e.g. `logs.tar.gz`,
+/// have their member files searched instead, reported as `logs.tar.gz!inner/path.txt`.
+#[pyfunction]
+#[pyo3(name = "search_archives")]
+#[pyo3(signature = (query_group, textfile_paths, parallel = true, a = None, b = None, context_chars = false, encodings = None, normalizers = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_archives(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    textfile_paths: &FilePaths,
+    parallel: bool,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    encodings: Option<&PyDict>,
+    normalizers: Option<&PyAny>,
+) -> PyResult<Vec<FileMatchResult>> {
+    let read = options::ReadArgs {
+        encodings,
+        normalizers,
+        ..Default::default()
+    };
+    let context = options::ContextArgs {
+        a,
+        b,
+        context_chars,
+        ..Default::default()
+    };
+    let mut search = options::SearchKwargs::new(Some(query_group), Some(textfile_paths), read, context, Default::default(), Default::default())?;
+    search.options.parallel = parallel;
+    let results = py.allow_threads(|| archive::search_archives(query_group, &textfile_paths.paths, &search.options));
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => Ok(results),
+    }
+}
+
+/// The file-like objects matching, read with their `read` method, e.g. `io.StringIO`, sockets
+/// made into files or streams from other libraries, matched like files with those contents. The
+/// results are named after `names`, or else the `name` attribute of the streams, or
+/// `<stream i>`. Binary streams are decoded with `encoding`, by default UTF-8. Only the first
+/// `max_bytes` bytes of each stream are read and matched.
+#[pyfunction]
+#[pyo3(name = "search_streams")]
+#[pyo3(signature = (query_group, streams, names = None, a = None, b = None, context_chars = false, encoding = None, max_bytes = 64 << 20, normalizers = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_streams(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    streams: Vec<&PyAny>,
+    names: Option<Vec<String>>,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    encoding: Option<&str>,
+    max_bytes: usize,
+    normalizers: Option<&PyAny>,
+) -> PyResult<Vec<FileMatchResult>> {
+    if names.as_ref().is_some_and(|names| names.len() != streams.len()) {
+        return Err(PyValueError::new_err("there must be as many names as streams"));
+    }
+    let encoding = encoding
+        .map(|label| encoding_rs::Encoding::for_label(label.as_bytes()).ok_or_else(|| PyValueError::new_err(format!("unknown encoding {:?}", label))))
+        .transpose()?;
+    let mut documents = Vec::with_capacity(streams.len());
+    for (i, stream) in streams.iter().enumerate() {
+        let name = match &names {
+            Some(names) => names[i].clone(),
+            None => stream
+                .getattr("name")
+                .and_then(|name| name.extract::<String>())
+                .unwrap_or_else(|_| format!("<stream {}>", i)),
+        };
+        let (bytes, cut) = streams::read_stream(stream, max_bytes)?;
+        let contents = streams::decode(&name, bytes, encoding, cut)?;
+        documents.push((name, contents));
+    }
+    let read = options::ReadArgs {
+        normalizers,
+        ..Default::default()
+    };
+    let context = options::ContextArgs {
+        a,
+        b,
+        context_chars,
+        ..Default::default()
+    };
+    let search = options::SearchKwargs::new(Some(query_group), None, read, context, Default::default(), Default::default())?;
+    let results = py.allow_threads(|| streams::search_contents(query_group, &documents, &search.options));
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => Ok(results),
+    }
+}
+
+/// The documents of `docs` matching, matched like files with those texts, the results named
+/// after their ids, without touching the filesystem.
+#[pyfunction]
+#[pyo3(name = "search_documents")]
+#[pyo3(signature = (query_group, docs, parallel = true, a = None, b = None, context_chars = false, normalizers = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn py_search_documents(
+    py: Python<'_>,
+    query_group: &QueryGroup,
+    docs: &streams::DocumentSet,
+    parallel: bool,
+    a: Option<usize>,
+    b: Option<usize>,
+    context_chars: bool,
+    normalizers: Option<&PyAny>,
+) -> PyResult<Vec<FileMatchResult>> {
+    let read = options::ReadArgs {
+        normalizers,
+        ..Default::default()
+    };
+    let context = options::ContextArgs {
+        a,
+        b,
+        context_chars,
+        ..Default::default()
+    };
+    let mut search = options::SearchKwargs::new(Some(query_group), None, read, context, Default::default(), Default::default())?;
+    search.options.parallel = parallel;
+    let results = py.allow_threads(|| streams::search_contents(query_group, &docs.documents, &search.options));
+    match search.take_error() {
+        Some(e) => Err(e),
+        None => Ok(results),
+    }
+}
+
+/// What this build of the crate can do, to feature-detect rather than catch errors: `version`,
+/// the optional cargo `features` enabled, the `regex_engines` available to atoms, and the number
+/// of `threads` files are searched on.
+#[pyfunction]
+#[pyo3(name = "build_info")]
+pub fn py_build_info(py: Python<'_>) -> PyResult<&PyDict> {
+    let info = PyDict::new(py);
+    info.set_item("version", env!("CARGO_PKG_VERSION"))?;
+    info.set_item("features", enabled_features())?;
+    info.set_item("regex_engines", regex_engines())?;
+    info.set_item("threads", rayon::current_num_threads())?;
+    Ok(info)
+}
+
+/// Counters of the searches of files done by the process, e.g. files scanned, search durations
+/// and result cache hits, in the Prometheus text format, for a service to serve on its own
+/// `/metrics` endpoint.
+#[pyfunction]
+#[pyo3(name = "metrics")]
+pub fn py_metrics() -> String {
+    metrics::render()
+}
+
+/// Serve `metrics()` on `GET /metrics` at `address`, e.g. "0.0.0.0:9184" or port 0 for any,
+/// from a background thread for the rest of the process, returning the address bound.
+#[pyfunction]
+#[pyo3(name = "serve_metrics", signature = (address = "127.0.0.1:9184"))]
+pub fn py_serve_metrics(address: &str) -> PyResult<String> {
+    metrics::serve(address).map(|address| address.to_string()).map_err(|e| PyIOError::new_err(format!("{}: {}", address, e)))
+}
+
+/// Log the events of the searches up to `level` ("off", "error", "warn", "info", "debug" or
+/// "trace") to the standard error, e.g. "warn" for files skipped or taking at least `slow_file`
+/// seconds to match, and "debug" for each file searched. Requires the `logging` feature.
+#[pyfunction]
+#[pyo3(name = "set_log_level", signature = (level, slow_file = 1.0))]
+pub fn py_set_log_level(level: &str, slow_file: f64) -> PyResult<()> {
+    let slow_file = Duration::try_from_secs_f64(slow_file).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    trace::set_level(level, slow_file)
+}
+
+/// The optional cargo features the crate was built with.
+pub fn enabled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "pcre2") {
+        features.push("pcre2");
+    }
+    if cfg!(feature = "async") {
+        features.push("async");
+    }
+    if cfg!(feature = "html") {
+        features.push("html");
+    }
+    if cfg!(feature = "logging") {
+        features.push("logging");
+    }
+    features
+}
+
+/// The regex engines atoms can be compiled with: the default one, `fancy-regex` for `fancy:`
+/// atoms and, if built with it, PCRE2 for `pcre:` atoms.
+pub fn regex_engines() -> Vec<&'static str> {
+    let mut engines = vec!["regex", "fancy-regex"];
+    if cfg!(feature = "pcre2") {
+        engines.push("pcre2");
+    }
+    engines
+}
+
+#[pymodule]
+#[pyo3(name = "textsearcher")]
+fn py_module(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add_class::<QueryGroup>()?;
+    m.add_class::<FilePaths>()?;
+    m.add_class::<ResultCache>()?;
+    m.add_class::<diff::ResultDiff>()?;
+    m.add_class::<SearchReport>()?;
+    m.add_class::<replace::FileEdit>()?;
+    m.add_class::<extract::Extraction>()?;
+    m.add_class::<normalize::PyBuiltinNormalizer>()?;
+    m.add_class::<normalize::PyPipeline>()?;
+    m.add_class::<score::PyCandidate>()?;
+    m.add_class::<spill::SpilledResults>()?;
+    m.add_class::<options::PyResultMode>()?;
+    m.add_class::<aggregate::DirectoryGroup>()?;
+    m.add_class::<aggregate::AlternativeGroup>()?;
+    m.add_class::<dedup::CollapsedResult>()?;
+    m.add_class::<explain::Explanation>()?;
+    m.add_class::<explain::GroupExplanation>()?;
+    m.add_class::<records::RecordMatch>()?;
+    m.add_class::<logs::LogEntry>()?;
+    m.add_class::<groups::GroupMatch>()?;
+    m.add_class::<suite::PyQuerySpec>()?;
+    m.add_class::<query::PyQuery>()?;
+    m.add_class::<suite::SuiteQuery>()?;
+    m.add_class::<typeahead::PyTypeAhead>()?;
+    m.add_class::<index::Index>()?;
+    m.add_class::<progress::CancelToken>()?;
+    m.add("Q", m.getattr("Query")?)?;
+    m.add_class::<spill::PySpilledIter>()?;
+    m.add_class::<lazy::PySearchStream>()?;
+    m.add_class::<watch::PyWatch>()?;
+    m.add_class::<streams::DocumentSet>()?;
+    m.add_class::<asyncio::PyAsyncSearchStream>()?;
+    m.add_function(wrap_pyfunction!(py_search_text, m)?)?;
+    m.add_function(wrap_pyfunction!(py_explain, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_isolated, m)?)?;
+    m.add_function(wrap_pyfunction!(py_serve_worker, m)?)?;
+    m.add_function(wrap_pyfunction!(py_count_matches, m)?)?;
+    m.add_function(wrap_pyfunction!(py_exists_match, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_any, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_text_multi, m)?)?;
+    m.add_function(wrap_pyfunction!(py_match_str, m)?)?;
+    m.add_function(wrap_pyfunction!(py_count_match_str, m)?)?;
+    m.add_function(wrap_pyfunction!(py_explain_atom, m)?)?;
+    m.add_function(wrap_pyfunction!(py_html_snippet, m)?)?;
+    m.add_function(wrap_pyfunction!(py_html_snippets, m)?)?;
+    m.add_function(wrap_pyfunction!(py_approx_substring, m)?)?;
+    m.add_function(wrap_pyfunction!(py_slice_around, m)?)?;
+    m.add_function(wrap_pyfunction!(py_register_corpus, m)?)?;
+    m.add_function(wrap_pyfunction!(py_unregister_corpus, m)?)?;
+    m.add_function(wrap_pyfunction!(py_corpus_names, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search, m)?)?;
+    m.add_function(wrap_pyfunction!(py_aggregate_by_directory, m)?)?;
+    m.add_function(wrap_pyfunction!(py_group_by_alternative, m)?)?;
+    m.add_function(wrap_pyfunction!(py_collapse_duplicates, m)?)?;
+    m.add_function(wrap_pyfunction!(py_build_info, m)?)?;
+    m.add_function(wrap_pyfunction!(py_metrics, m)?)?;
+    m.add_function(wrap_pyfunction!(py_serve_metrics, m)?)?;
+    m.add_function(wrap_pyfunction!(py_set_log_level, m)?)?;
+    m.add_function(wrap_pyfunction!(py_load_query_suite, m)?)?;
+    m.add_function(wrap_pyfunction!(py_diff_results, m)?)?;
+    m.add_function(wrap_pyfunction!(py_save_results, m)?)?;
+    m.add_function(wrap_pyfunction!(py_load_results, m)?)?;
+    m.add_function(wrap_pyfunction!(py_results_to_jsonl, m)?)?;
+    m.add_function(wrap_pyfunction!(py_plan_replace, m)?)?;
+    m.add_function(wrap_pyfunction!(py_apply_replace, m)?)?;
+    m.add_function(wrap_pyfunction!(py_extract, m)?)?;
+    m.add_function(wrap_pyfunction!(py_find_all, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_csv, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_jsonl, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_email, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_logs, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_groups, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_dir, m)?)?;
+    m.add_function(wrap_pyfunction!(py_watch, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_streams, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_documents, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_index, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_stream, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_text_async, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_stream_async, m)?)?;
+    m.add_function(wrap_pyfunction!(py_search_archives, m)?)?;
+    Ok(())
+}
+
+/// The regex an atom is compiled into, to understand why a query does or doesn't match.
+pub fn explain_atom(atom: &str, options: &QueryOptions) -> String {
+    atom_to_regex(atom, options)
+}
+
+/// Compile a single atom, expanding it into its alternative spellings where enabled.
+fn atom_to_regex(atom: &str, options: &QueryOptions) -> String {
+    if let Some(sequence) = atom.strip_prefix(THEN_PREFIX) {
+        // anything in between, across lines too, as few characters as possible
+        let parts: Vec<_> = sequence.split(THEN_SEPARATOR).map(|part| atom_to_regex(part.trim(), options)).collect();
+        return format!("(?:{})", parts.join("(?s:.*?)"));
+    }
+    if let Some(raw) = [REGEX_PREFIX, FANCY_PREFIX, PCRE_PREFIX].iter().find_map(|prefix| atom.strip_prefix(prefix)) {
+        return format!("(?:{})", raw);
+    }
+    let normalized = match options.nfkc {
+        true => normalize::Nfkc.normalize(atom),
+        false => Cow::Borrowed(atom),
+    };
+    let folded;
+    let atom = match &options.case_folding {
+        Some(case_fold) => {
+            folded = case_fold.fold(&normalized);
+            folded.as_str()
+        }
+        None => &normalized,
+    };
+    let mut regexes = vec![_get_regex_for_atom_with(atom, options)];
+    if options.romaji {
+        regexes.extend(get_regex_for_romaji(atom, options));
+    }
+    if options.phonetic {
+        regexes.extend(get_regex_for_phonetic(atom, options));
+    }
+    if regexes.len() == 1 {
+        return regexes.pop().unwrap();
+    }
+    format!("(?:{})", regexes.join("|"))
+}
+
+/// Regex matching the words sounding like those of an ASCII atom, or `None` if the atom isn't
+/// made of plain ASCII words.
+fn get_regex_for_phonetic(atom: &str, options: &QueryOptions) -> Option<String> {
+    let words: Option<Vec<_>> = atom.split_whitespace().map(phonetic::phonetic_regex).collect();
+    match words {
+        Some(words) if !words.is_empty() => Some(words.join(&options.separators.word_gap())),
+        _ => None,
+    }
+}
+
+/// Regex matching the kana (and dictionary) spellings of a romaji atom, or `None` if the atom
+/// isn't romaji.
+fn get_regex_for_romaji(atom: &str, options: &QueryOptions) -> Option<String> {
+    let lookup = |key: &str| options.romaji_dictionary.get(&key.to_ascii_lowercase());
+    let mut parts = Vec::new();
+    for word in atom.split_whitespace() {
+        let hiragana = romaji::to_hiragana(word)?;
+        let mut alternatives = vec![romaji::to_katakana(&hiragana), hiragana];
+        alternatives.extend(lookup(word).into_iter().flatten().cloned());
+        let regexes: Vec<_> = alternatives
+            .iter()
+            .map(|a| _get_regex_for_atom_with(a, options))
+            .collect();
+        parts.push(format!("(?:{})", regexes.join("|")));
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    let mut regexes = vec![parts.join("\\s*")];
+    let normalized_atom = atom.split_whitespace().collect::<Vec<_>>().join(" ");
+    for alternative in lookup(&normalized_atom).into_iter().flatten() {
+        regexes.push(_get_regex_for_atom_with(alternative, options));
+    }
+    Some(regexes.join("|"))
+}
+
+/// Katakana that have a hiragana counterpart (ァ..ヶ) sit exactly 0x60 code points above it.
+const KANA_OFFSET: u32 = 0x60;
+
+/// Map katakana to hiragana, leaving every other character untouched.
+fn fold_kana(ch: char) -> char {
+    if ('\u{30a1}'..='\u{30f6}').contains(&ch) {
+        char::from_u32(ch as u32 - KANA_OFFSET).unwrap_or(ch)
+    } else {
+        ch
+    }
+}
+
+/// Escape a word for use in regex, honoring the character-level options.
+fn escape_word(word: &str, options: &QueryOptions) -> String {
+    if let Some(max_edits) = options.fuzzy.filter(|_| !word.chars().any(is_unspaced)) {
+        return fuzzy::word_regex(word, max_edits);
+    }
+    if !options.kana_folding && !options.separators.hyphens {
+        return regex::escape(word);
+    }
+    let mut escaped = String::new();
+    let mut after_letter = false;
+    for ch in word.chars() {
+        let letter = ch.is_alphabetic() && !is_unspaced(ch);
+        if options.separators.hyphens && after_letter && letter {
+            escaped.push_str(HYPHEN_BREAK);
+        }
+        after_letter = letter;
+        let hira = fold_kana(ch);
+        if options.kana_folding && ('\u{3041}'..='\u{3096}').contains(&hira) {
+            let kata = char::from_u32(hira as u32 + KANA_OFFSET).unwrap();
+            escaped.push('[');
+            escaped.push(hira);
+            escaped.push(kata);
+            escaped.push(']');
+        } else {
+            escaped.push_str(&regex::escape(&ch.to_string()));
+        }
+    }
+    escaped
+}
+
+/// Make word-final prolonged sound marks optional, i.e. those not followed by (folded) kana,
+/// so that "サーバー" matches "サーバ" while "さーば" still doesn't match "さば".
+fn make_prolonged_marks_optional(regex: &str, gap: &str, word_gap: &str) -> String {
+    let mut result = String::new();
+    let mut rest = regex;
+    while let Some(i) = rest.find('ー') {
+        let (head, tail) = rest.split_at(i + 'ー'.len_utf8());
+        result.push_str(head);
+        let next = tail.trim_start_matches(gap).trim_start_matches(word_gap);
+        if !next.starts_with('[') && !next.starts_with('ー') {
+            result.push('?');
+        }
+        rest = tail;
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Scripts written without spaces between words, whose characters may be separated only by line
+/// breaks (or nothing) in the text.
+const UNSPACED_SCRIPTS: [Script; 10] = [
+    Script::Han,
+    Script::Hiragana,
+    Script::Katakana,
+    Script::Hangul,
+    Script::Bopomofo,
+    Script::Yi,
+    Script::Thai,
+    Script::Lao,
+    Script::Khmer,
+    Script::Myanmar,
+];
+
+/// Whether `ch` belongs to one of `UNSPACED_SCRIPTS`, also through its script extensions, so
+/// that e.g. the prolonged sound mark `ー` counts as kana.
+fn is_unspaced(ch: char) -> bool {
+    let scripts = ch.script_extension();
+    // the extensions of Common and Inherited characters contain every script
+    !scripts.is_common() && !scripts.is_inherited() && UNSPACED_SCRIPTS.iter().any(|&s| scripts.contains_script(s))
+}
+
+/// Returns String to make testing convenient
+fn _get_regex_for_atom(atom: &str) -> String {
+    _get_regex_for_atom_with(atom, &QueryOptions::default())
+}
+
+fn _get_regex_for_atom_with(atom: &str, options: &QueryOptions) -> String {
+    let mut regex = String::new();
+    let mut word = String::new();
+    let mut prev_ch = '\u{0}';  // represents the beginning or the ending
+    let mut word_commited = false;
+    let gap = options.separators.cjk_gap();
+    let word_gap = options.separators.word_gap();
+    // anchors the words of spaced scripts with `whole_words`, at their ends that are word characters
+    let word_boundary = |ch: char| match options.whole_words && (ch.is_alphanumeric() || ch == '_') {
+        true => "\\b",
+        false => "",
+    };
+
+    enum CharType {
+        /// beginning or ending
+        Term,
+        /// whitespace characters
+        Blank,
+        /// characters of scripts written without spaces between words, e.g. CJK
+        Hans,
+        /// e.g. ASCII
+        Other,
+    }
+
+    let get_char_type = |ch| {
+        if ch == '\u{0}' {
+            CharType::Term
+        } else if ch == ' ' || ch == '\t' || ch == '\n' || ch == '\r' {
+            CharType::Blank
+        } else if is_unspaced(ch) {
+            CharType::Hans
+        } else {
+            CharType::Other
+        }
+    };
+
+    for ch in atom.chars().chain("\0".chars()) {
+        match (get_char_type(prev_ch), get_char_type(ch)) {
+            (CharType::Term, CharType::Term) => (),
+            (CharType::Term, CharType::Blank) => (),
+            (CharType::Term, CharType::Hans) => {
+                // push ch to word
+                word.push(ch);
+                word_commited = false;
+                prev_ch = ch;
+            }
+            (CharType::Term, CharType::Other) => {
+                //regex.push_str("(\\b|[\\u4e00-\\u9fa5\\u3040-\\u30FF])");
+                regex.push_str(word_boundary(ch));
+                // push ch to word
+                word.push(ch);
+                word_commited = false;
+                prev_ch = ch;
+            }
+            (CharType::Blank, _) => (),
+            (CharType::Hans, CharType::Term) => {
+                if !word_commited {
+                    // commit word to regex
+                    regex.push_str(&escape_word(&word, options));
+                    word.clear();
+                    word_commited = true;
+                }
+            }
+            (CharType::Hans, CharType::Blank) => {
+                if !word_commited {
+                    // commit word to regex
+                    regex.push_str(&escape_word(&word, options));
+                    word.clear();
+                    word_commited = true;
+                }
+            }
+            (CharType::Hans, CharType::Hans) => {
+                if word_commited {
+                    regex.push_str(&word_gap);
+                } else {
+                    // commit word to regex
+                    regex.push_str(&escape_word(&word, options));
+                    word.clear();
+                    //word_commited = true;
+                    regex.push_str(&gap);
+                }
+                // push ch to word
+                word.push(ch);
+                word_commited = false;
+                prev_ch = ch;
+            }
+            (CharType::Hans, CharType::Other) => {
+                if !word_commited {
+                    // commit word to regex
+                    regex.push_str(&escape_word(&word, options));
+                    word.clear();
+                    //word_commited = true;
+                }
+                regex.push_str(&gap);
+                // push ch to word
+                word.push(ch);
+                word_commited = false;
+                prev_ch = ch;
+            }
+            (CharType::Other, CharType::Term) => {
+                if !word_commited {
+                    // commit word to regex
+                    regex.push_str(&escape_word(&word, options));
+                    regex.push_str(word_boundary(prev_ch));
+                    word.clear();
+                    word_commited = true;
+                }
+                //regex.push_str("(\\b|[\\u4e00-\\u9fa5\\u3040-\\u30FF])");
+                prev_ch = ch;
+            }
+            (CharType::Other, CharType::Blank) => {
+                if !word_commited {
+                    // commit word to regex
+                    regex.push_str(&escape_word(&word, options));
+                    regex.push_str(word_boundary(prev_ch));
+                    word.clear();
+                    word_commited = true;
+                }
+            }
+            (CharType::Other, CharType::Hans) => {
+                if !word_commited {
+                    // commit word to regex
+                    regex.push_str(&escape_word(&word, options));
+                    word.clear();
+                    //word_commited = true;
+                }
+                regex.push_str(&gap);
+                // push ch to word
+                word.push(ch);
+                word_commited = false;
+                prev_ch = ch;
+            }
+            (CharType::Other, CharType::Other) => {
+                if word_commited {
+                    regex.push_str(&word_gap);
+                    regex.push_str(word_boundary(ch));
+                }
+                // push ch to word
+                word.push(ch);
+                word_commited = false;
+                prev_ch = ch;
+            }
+        }
+    }
+
+    if options.kana_folding {
+        return make_prolonged_marks_optional(&regex, &gap, &word_gap);
+    }
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use crate::aggregate::aggregate_by_directory;
+    use crate::binary::BinaryPolicy;
+    use crate::query::Query;
+    use crate::cache::{Fingerprint, Outcome, ResultCache};
+    use crate::chunked::ChunkOptions;
+    use crate::corpus::{get_corpus, register_corpus, unregister_corpus, Corpus};
+    use crate::diff::{diff_results, load_results, results_to_jsonl, save_results};
+    use crate::encoding::EncodingHints;
+    use crate::extract::{extract, find_all};
+    use crate::lazy::{search_iter, search_iter_prefetch, search_stream};
+    use crate::normalize::{normalize, CaseFold, Nfkc, StripLines};
+    use crate::progress::{CancelToken, Progress};
+    use crate::replace::{apply_edits, plan_replace};
+    use crate::sample::search_text_sample;
+    use crate::score::{ScoringOptions, TotalCount};
+    use crate::spill::search_text_spilled;
+    use crate::tags::{TagCondition, TagFilter, TagValue, Tags};
+    use crate::{_get_regex_for_atom, _get_regex_for_atom_with, count_matches, count_matches_report, exists_match, exists_match_report, search_any_report, search_text_multi_report, file_encoding_hints, ContextOptions, ContextUnit, QueryGroup, QueryOptions, RegexFlags, Separators, is_match_context, is_match_str, match_contents, MatchPosition, search_any, search_files, FileMatchResult, search_text, search_text_context, search_text_context_with_unit, search_text_multi, search_text_report, search_text_with, FilePaths, SearchOptions};
+
+    #[test]
+    fn test_get_regex_for_atom() {
+        assert_eq!(_get_regex_for_atom(""), "");
+        assert_eq!(_get_regex_for_atom("  "), "");
+        assert_eq!(_get_regex_for_atom("      "), "");
+        assert_eq!(_get_regex_for_atom("hello"), "hello");
+        assert_eq!(_get_regex_for_atom("hello world"), "hello\\s+world");
+        assert_eq!(_get_regex_for_atom("hello world    again"), "hello\\s+world\\s+again");
+        assert_eq!(_get_regex_for_atom("  hello world    "), "hello\\s+world");
+        assert_eq!(_get_regex_for_atom("中"), "中");
+        assert_eq!(_get_regex_for_atom("中文"), "中\\s*文");
+        assert_eq!(_get_regex_for_atom("   中 文"), "中\\s+文");
+        assert_eq!(_get_regex_for_atom("     中 文   "), "中\\s+文");
+        assert_eq!(_get_regex_for_atom("中hello"), "中\\s*hello");
+        assert_eq!(_get_regex_for_atom("中文hello"), "中\\s*文\\s*hello");
+        assert_eq!(_get_regex_for_atom("中文hello world"), "中\\s*文\\s*hello\\s+world");
+        assert_eq!(_get_regex_for_atom("中文 hello world"), "中\\s*文\\s*hello\\s+world");
+        assert_eq!(_get_regex_for_atom("中文    hello  world"), "中\\s*文\\s*hello\\s+world");
+        assert_eq!(_get_regex_for_atom("hello中"), "hello\\s*中");
+        assert_eq!(_get_regex_for_atom("hello中文"), "hello\\s*中\\s*文");
+        assert_eq!(_get_regex_for_atom("hello world中文"), "hello\\s+world\\s*中\\s*文");
+        assert_eq!(_get_regex_for_atom("hello   world 中文"), "hello\\s+world\\s*中\\s*文");
+        assert_eq!(_get_regex_for_atom("hello   world   中文"), "hello\\s+world\\s*中\\s*文");
+        assert_eq!(_get_regex_for_atom("hello world中文again"), "hello\\s+world\\s*中\\s*文\\s*again");
+        assert_eq!(_get_regex_for_atom("hello world  中文 again"), "hello\\s+world\\s*中\\s*文\\s*again");
+        assert_eq!(_get_regex_for_atom("  hello world  中文 again  "), "hello\\s+world\\s*中\\s*文\\s*again");
+        assert_eq!(_get_regex_for_atom("中文hello world世界"), "中\\s*文\\s*hello\\s+world\\s*世\\s*界");
+        assert_eq!(_get_regex_for_atom("  中文 hello world世界   "), "中\\s*文\\s*hello\\s+world\\s*世\\s*界");
+        assert_eq!(_get_regex_for_atom(" 中文hello world   世界"), "中\\s*文\\s*hello\\s+world\\s*世\\s*界");
+        // other scripts written without spaces, and ideographs outside the basic block
+        assert_eq!(_get_regex_for_atom("한국"), "한\\s*국");
+        assert_eq!(_get_regex_for_atom("ไทย"), "ไ\\s*ท\\s*ย");
+        assert_eq!(_get_regex_for_atom("㐀𠀀"), "㐀\\s*𠀀");
+        assert_eq!(_get_regex_for_atom("サーバ"), "サ\\s*ー\\s*バ");
+        assert_eq!(_get_regex_for_atom("中1"), "中\\s*1");
+    }
+
+    // without Python package 'maturin', this test goes wrong false positively
+    #[test]
+    #[allow(clippy::iter_next_slice)]
+    fn test_search_text() {
+        let query_group = QueryGroup::new(
+            vec![vec!["world".to_string()]]).unwrap();
+        let paths = vec![String::from("sample_texts/hello.txt"), String::from("sample_texts/world.txt")];
+        let result = search_text(&query_group, &paths, false);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result.iter().next().unwrap().path, String::from("sample_texts/world.txt"));
+
+        let query_group = QueryGroup::new(
+            vec![vec!["bar".to_string()],
+                 vec!["baz".to_string(), "xxxx哈哈".to_string()]]).unwrap();
+        let paths = vec![String::from("sample_texts/hello.txt"), String::from("sample_texts/world.txt")];
+        let result = search_text(&query_group, &paths, false);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result.iter().next().unwrap().path, String::from("sample_texts/hello.txt"));
+    }
+
+    #[test]
+    fn test_kana_folding() {
+        let options = QueryOptions {
+            kana_folding: true,
+            ..Default::default()
+        };
+        let query_group = QueryGroup::with_options(
+            vec![vec!["サーバー".to_string()]], options.clone()).unwrap();
+        assert!(is_match_str(&query_group, "さーば"));
+        assert!(is_match_str(&query_group, "サーバ"));
+        assert!(is_match_str(&query_group, "新しいサーバーを"));
+        assert!(!is_match_str(&query_group, "さば"));
+
+        let query_group = QueryGroup::with_options(
+            vec![vec!["さーば".to_string()]], options).unwrap();
+        assert!(is_match_str(&query_group, "サーバー"));
+
+        let query_group = QueryGroup::new(vec![vec!["サーバー".to_string()]]).unwrap();
+        assert!(!is_match_str(&query_group, "さーば"));
+    }
+
+    #[test]
+    fn test_romaji() {
+        let options = QueryOptions {
+            romaji: true,
+            romaji_dictionary: HashMap::from([
+                ("kikai".to_string(), vec!["機械".to_string()]),
+                ("gakushuu".to_string(), vec!["学習".to_string()]),
+            ]),
+            ..Default::default()
+        };
+        let query_group = QueryGroup::with_options(
+            vec![vec!["kikai gakushuu".to_string()]], options).unwrap();
+        assert!(is_match_str(&query_group, "機械学習の入門"));
+        assert!(is_match_str(&query_group, "きかい がくしゅう"));
+        assert!(is_match_str(&query_group, "キカイ学習"));
+        assert!(is_match_str(&query_group, "Kikai gakushuu"));
+        assert!(!is_match_str(&query_group, "機械翻訳"));
+    }
+
+    #[test]
+    fn test_phonetic() {
+        let options = QueryOptions {
+            phonetic: true,
+            ..Default::default()
+        };
+        let query_group = QueryGroup::with_options(
+            vec![vec!["Katherine Smith".to_string()]], options).unwrap();
+        assert!(is_match_str(&query_group, "Dear Catherine  Smyth,"));
+        assert!(is_match_str(&query_group, "kathryn smith"));
+        assert!(!is_match_str(&query_group, "Karen Smith"));
+    }
+
+    #[test]
+    fn test_regex_flags() {
+        let with_flags = |atom: &str, flags: RegexFlags| {
+            let options = QueryOptions {
+                flags,
+                ..Default::default()
+            };
+            QueryGroup::with_options(vec![vec![atom.to_string()]], options).unwrap()
+        };
+        let exact = RegexFlags {
+            case_sensitive: true,
+            ..Default::default()
+        };
+        assert!(is_match_str(&with_flags("parseHeader", RegexFlags::default()), "PARSEHEADER"));
+        assert!(!is_match_str(&with_flags("parseHeader", exact), "PARSEHEADER"));
+        assert!(!is_match_str(&with_flags("fancy:parse(?=Header)", exact), "parseheader"));
+        assert_eq!(with_flags("foo", RegexFlags::default()).patterns[0].as_str(), "foo");
+        let ascii = RegexFlags {
+            unicode: false,
+            ..Default::default()
+        };
+        assert!(is_match_str(&with_flags("full disk", RegexFlags::default()), "full\u{3000}disk"));
+        assert!(!is_match_str(&with_flags("full disk", ascii), "full\u{3000}disk"));
+        let whole_file = RegexFlags {
+            multi_line: false,
+            dot_matches_new_line: true,
+            ..Default::default()
+        };
+        assert!(is_match_str(&with_flags("fancy:^bar", RegexFlags::default()), "foo\nbar"));
+        assert!(!is_match_str(&with_flags("fancy:^bar", whole_file), "foo\nbar"));
+        assert!(is_match_str(&with_flags("fancy:foo.bar", whole_file), "foo\nbar"));
+    }
+
+    #[test]
+    fn test_fancy_atoms() {
+        let query_group = QueryGroup::new(
+            vec![vec!["fancy:foo(?! bar)".to_string(), "hello world".to_string()]]).unwrap();
+        assert!(is_match_str(&query_group, "foo baz"));
+        assert!(is_match_str(&query_group, "HELLO\n world"));
+        assert!(!is_match_str(&query_group, "Foo bar"));
+
+        let query_group = QueryGroup::new(vec![vec![r"fancy:\b(\w+) \1\b".to_string()]]).unwrap();
+        assert!(is_match_str(&query_group, "the the"));
+        assert!(!is_match_str(&query_group, "the then"));
+
+        assert!(QueryGroup::new(vec![vec!["fancy:(".to_string()]]).is_err());
+    }
+
+    #[test]
+    fn test_regex_atoms() {
+        let query_group = QueryGroup::new(vec![vec![r"re:\bv\d+\.\d+\b".to_string()], vec!["release".to_string()]]).unwrap();
+        assert_eq!(query_group.patterns[0].as_str(), r"(?:\bv\d+\.\d+\b)");
+        assert!(is_match_str(&query_group, "Release v2.10 is out"));
+        assert!(!is_match_str(&query_group, "release v2 is out"));
+        assert!(matches!(query_group.patterns[0], crate::pattern::Pattern::Regex(_)));
+        assert!(QueryGroup::new(vec![vec!["re:(?=x)".to_string()]]).is_err());
+    }
+
+    #[cfg(feature = "pcre2")]
+    #[test]
+    fn test_pcre_atoms() {
+        let query_group = QueryGroup::new(
+            vec![vec![r"pcre:\d++-(?<w>\w+)-\k<w>".to_string(), "中文".to_string()]]).unwrap();
+        assert!(is_match_str(&query_group, "2021-AB-ab"));
+        assert!(is_match_str(&query_group, "中 文"));
+        assert!(!is_match_str(&query_group, "2021-ab-cd"));
+        assert!(QueryGroup::new(vec![vec!["pcre:(".to_string()]]).is_err());
+    }
+
+    #[cfg(not(feature = "pcre2"))]
+    #[test]
+    fn test_pcre_atoms() {
+        assert!(QueryGroup::new(vec![vec![r"pcre:\d++".to_string()]]).is_err());
+    }
+
+    #[test]
+    fn test_highlight() {
+        let query_group = QueryGroup::new(vec![vec!["bar".to_string(), "baz".to_string()]]).unwrap();
+        let paths = vec![String::from("sample_texts/hello.txt")];
+        let options = SearchOptions {
+            parallel: false,
+            context: Some(ContextOptions {
+                before: 4,
+                after: 4,
+                unit: ContextUnit::Bytes,
+                max_chars: None,
+                highlight_begin: "<mark>".to_string(),
+                highlight_end: "</mark>".to_string(),
+                highlight_all: false,
+                scoped: false,
+                ellipsis: false,
+                snippet: false,
+                max_contexts: None,
+                clause_contexts: false,
+            }),
+            ..Default::default()
+        };
+        let result = search_text_with(&query_group, &paths, &options);
+        assert_eq!(result[0].context.as_deref(), Some("foo <mark>bar</mark>\n<mark>baz</mark>"));
+        assert_eq!(result[0].highlights(), [(10, 13), (27, 30)]);
+
+        let options = SearchOptions {
+            parallel: false,
+            context: Some(ContextOptions {
+                before: 4,
+                after: 2,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let result = search_text_with(&query_group, &paths, &options);
+        assert_eq!(result[0].context.as_deref(), Some("foo bar\nb"));
+        assert_eq!(result[0].highlights(), [(4, 7)]);
+    }
+
+    #[test]
+    fn test_encoding_hints() {
+        let query_group = QueryGroup::new(vec![vec!["中文".to_string()]]).unwrap();
+        let paths = vec![String::from("sample_texts/gbk.txt")];
+        let result = search_text(&query_group, &paths, false);
+        assert!(result.is_empty());
+
+        let options = SearchOptions {
+            parallel: false,
+            encoding_hints: EncodingHints::new(&[("sample_texts/gbk*".to_string(), "gbk".to_string())]).unwrap(),
+            ..Default::default()
+        };
+        let result = search_text_with(&query_group, &paths, &options);
+        assert_eq!(result.len(), 1);
+        assert!(EncodingHints::new(&[("*".to_string(), "no-such-encoding".to_string())]).is_err());
+    }
+
+    #[test]
+    fn test_file_encodings() {
+        let query_group = QueryGroup::new(vec![vec!["中文".to_string()]]).unwrap();
+        let hint = |pattern: &str, label: &str| vec![(pattern.to_string(), label.to_string())];
+        let files = FilePaths::new(vec![String::from("sample_texts/gbk.txt")]).with_encodings(hint("sample_texts/gbk*", "gbk")).unwrap();
+        let options = SearchOptions {
+            encoding_hints: file_encoding_hints(None, &files).unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(search_files(&query_group, &files, &TagFilter::default(), &options).len(), 1);
+        assert!(FilePaths::new(Vec::new()).with_encodings(hint("*", "no-such-encoding")).is_err());
+    }
+
+    #[test]
+    fn test_detect_encoding() {
+        let query_group = QueryGroup::new(vec![vec!["中文".to_string()]]).unwrap();
+        let paths = vec![String::from("sample_texts/gbk.txt"), String::from("sample_texts/world.txt")];
+        let search = |encoding_hints: EncodingHints| {
+            let options = SearchOptions {
+                encoding_hints,
+                ..Default::default()
+            };
+            search_text_with(&query_group, &paths, &options).len()
+        };
+        assert_eq!(search(EncodingHints::default().detecting(&[]).unwrap()), 1);
+        assert_eq!(search(EncodingHints::default().detecting(&["shift_jis".to_string()]).unwrap()), 0);
+        assert!(EncodingHints::default().detecting(&["no-such-encoding".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_parallel_order() {
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()]]).unwrap();
+        let paths: Vec<_> = (0..200)
+            .map(|i| if i % 2 == 0 { "sample_texts/hello.txt" } else { "sample_texts/world.txt" })
+            .map(String::from)
+            .collect();
+        let result = search_text(&query_group, &paths, true);
+        let result_paths: Vec<_> = result.iter().map(|r| r.path.clone()).collect();
+        assert_eq!(result_paths, paths);
+    }
+
+    #[test]
+    fn test_corpus_registry() {
+        let corpus = Corpus::from_dir("sample_texts", true).unwrap();
+        assert!(corpus.paths().contains(&String::from("sample_texts/world.txt")));
+        register_corpus("samples", corpus);
+        let query_group = QueryGroup::new(vec![vec!["world".to_string()]]).unwrap();
+        for _ in 0..2 {
+            let corpus = get_corpus("samples").unwrap();
+            let result = corpus.search(&query_group, &SearchOptions::default());
+            assert_eq!(result.len(), 1);
+            assert_eq!(result[0].path, String::from("sample_texts/world.txt"));
+        }
+        assert!(unregister_corpus("samples"));
+        assert!(get_corpus("samples").is_none());
+    }
+
+    #[test]
+    fn test_corpus_contents() {
+        let root = std::env::temp_dir().join(format!("textsearcher-corpus-{}", std::process::id()));
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::copy("sample_texts/gbk.txt", root.join("docs/gbk.txt")).unwrap();
+        // a link back to the directory above isn't followed
+        std::os::unix::fs::symlink(&root, root.join("docs/loop")).unwrap();
+        std::os::unix::fs::symlink(root.join("docs/gbk.txt"), root.join("link.txt")).unwrap();
+        let corpus = Corpus::from_dir(root.to_str().unwrap(), true).unwrap();
+        assert_eq!(corpus.paths().len(), 2);
+        let query_group = QueryGroup::new(vec![vec!["中文".to_string()]]).unwrap();
+        assert!(corpus.search(&query_group, &SearchOptions::default()).is_empty());
+        // the contents cached are read again with the encodings of the next search
+        let options = SearchOptions {
+            encoding_hints: EncodingHints::new(&[("**/*.txt".to_string(), "gbk".to_string())]).unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(corpus.search(&query_group, &options).len(), 2);
+        let path = &corpus.paths()[0];
+        assert!(corpus.cached_contents(path, &options).unwrap().contains("中文"));
+        assert!(corpus.cached_contents(path, &SearchOptions::default()).is_none());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_on_result_error() {
+        pyo3::prepare_freethreaded_python();
+        pyo3::Python::with_gil(|py| {
+            let module = pyo3::types::PyModule::new(py, "textsearcher").unwrap();
+            crate::py_module(py, module).unwrap();
+            let globals = pyo3::types::PyDict::new(py);
+            globals.set_item("__builtins__", py.import("builtins").unwrap()).unwrap();
+            globals.set_item("t", module).unwrap();
+            py.run(
+                r#"
+calls = []
+
+def on_result(result):
+    calls.append(result.path)
+    raise ValueError('stop')
+
+files = t.FilePaths(['sample_texts/hello.txt'] * 2000)
+try:
+    t.search_text(t.QueryGroup([['o']]), files, on_result=on_result)
+    raised = False
+except ValueError:
+    raised = True
+"#,
+                Some(globals),
+                None,
+            )
+            .unwrap();
+            assert!(globals.get_item("raised").unwrap().extract::<bool>().unwrap());
+            // the search is cancelled once the callback fails, the files in flight aside
+            assert!(globals.get_item("calls").unwrap().len().unwrap() < 2000);
+        });
+    }
+
+    #[test]
+    fn test_search_files_with_tags() {
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()]]).unwrap();
+        let tags = |year| Tags::from([("year".to_string(), TagValue::Int(year))]);
+        let files = FilePaths::with_tags(
+            vec![String::from("sample_texts/hello.txt"), String::from("sample_texts/world.txt")],
+            vec![tags(2019), tags(2021)]).unwrap();
+        let filter = TagFilter {
+            conditions: vec![("year".to_string(), TagCondition::Ge(TagValue::Int(2020)))],
+        };
+        let result = search_files(&query_group, &files, &filter, &SearchOptions::default());
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].path, String::from("sample_texts/world.txt"));
+        assert_eq!(result[0].tags, tags(2021));
+    }
+
+    #[test]
+    fn test_result_cache() {
+        let dir = std::env::temp_dir().join(format!("textsearcher-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let text_path = dir.join("doc.txt").to_string_lossy().into_owned();
+        let cache_path = dir.join("cache.jsonl");
+        fs::write(&text_path, "hello world").unwrap();
+        let query_group = QueryGroup::new(vec![vec!["world".to_string()]]).unwrap();
+        let paths = vec![text_path.clone()];
+
+        let options = SearchOptions {
+            cache: Some(ResultCache::open(&cache_path).unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(search_text_with(&query_group, &paths, &options).len(), 1);
+        options.cache.as_ref().unwrap().save().unwrap();
+
+        let cache = ResultCache::open(&cache_path).unwrap();
+        assert_eq!(cache.len(), 1);
+        let query = crate::cache::query_key(&query_group, &SearchOptions::default());
+        let fingerprint = Fingerprint::of(&text_path).unwrap();
+        assert!(cache.get(query, &text_path, fingerprint).unwrap().matched);
+        // a cached outcome is trusted as long as the fingerprint is unchanged
+        cache.insert(query, &text_path, fingerprint, Outcome { matched: false, context: None, stats: None, language: None, truncated: (false, false), position: None, contexts: Vec::new(), clause_contexts: Vec::new(), highlights: Vec::new() });
+        let options = SearchOptions {
+            cache: Some(cache.clone()),
+            ..Default::default()
+        };
+        assert!(search_text_with(&query_group, &paths, &options).is_empty());
+        cache.invalidate(&text_path);
+        assert!(cache.is_empty());
+        assert_eq!(search_text_with(&query_group, &paths, &options).len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_query_key() {
+        let query_group = QueryGroup::new(vec![vec!["world".to_string()]]).unwrap();
+        let key = |encoding_hints: EncodingHints, binary: BinaryPolicy| {
+            let options = SearchOptions {
+                encoding_hints,
+                binary,
+                ..Default::default()
+            };
+            crate::cache::query_key(&query_group, &options)
+        };
+        let hints = |pairs: &[(&str, &str)]| EncodingHints::new(&pairs.iter().map(|&(pattern, label)| (pattern.to_string(), label.to_string())).collect::<Vec<_>>()).unwrap();
+        let keys = [
+            key(EncodingHints::default(), BinaryPolicy::Skip),
+            key(EncodingHints::default(), BinaryPolicy::Error),
+            key(hints(&[("*.txt", "gbk")]), BinaryPolicy::Skip),
+            key(hints(&[("*.txt", "big5")]), BinaryPolicy::Skip),
+            key(hints(&[("old/*.txt", "gbk")]), BinaryPolicy::Skip),
+            key(EncodingHints::default().detecting(&[]).unwrap(), BinaryPolicy::Skip),
+            key(EncodingHints::default().detecting(&["gbk".to_string()]).unwrap(), BinaryPolicy::Skip),
+        ];
+        // the outcomes of files read otherwise aren't shared
+        assert_eq!(keys.iter().collect::<HashSet<_>>().len(), keys.len());
+        assert_eq!(keys[2], key(hints(&[("*.txt", "gbk")]), BinaryPolicy::Skip));
+    }
+
+    #[test]
+    fn test_result_cache_max_file_size() {
+        let dir = std::env::temp_dir().join(format!("textsearcher-cache-size-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let text_path = dir.join("doc.txt").to_string_lossy().into_owned();
+        fs::write(&text_path, "hello world").unwrap();
+        let query_group = QueryGroup::new(vec![vec!["world".to_string()]]).unwrap();
+        let paths = vec![text_path];
+        let cache = ResultCache::open(dir.join("cache.jsonl")).unwrap();
+        let search = |max_file_size: Option<u64>| {
+            let options = SearchOptions {
+                cache: Some(cache.clone()),
+                max_file_size,
+                ..Default::default()
+            };
+            search_text_with(&query_group, &paths, &options).len()
+        };
+        // the file too large for the first search is searched by the next
+        assert_eq!(search(Some(10)), 0);
+        assert_eq!(search(None), 1);
+        assert_eq!(search(Some(100)), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_result_cache_skips() {
+        let dir = std::env::temp_dir().join(format!("textsearcher-cache-skips-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let binary_path = dir.join("data.bin").to_string_lossy().into_owned();
+        fs::write(&binary_path, b"hello\0world").unwrap();
+        let query_group = QueryGroup::new(vec![vec!["world".to_string()]]).unwrap();
+        let paths = vec![binary_path, dir.join("missing.txt").to_string_lossy().into_owned()];
+        let options = SearchOptions {
+            cache: Some(ResultCache::open(dir.join("cache.jsonl")).unwrap()),
+            ..Default::default()
+        };
+        // the files skipped are searched, and skipped, again rather than taken as not matching
+        for _ in 0..2 {
+            let (results, report) = search_text_report(&query_group, &paths, &options, |_| ());
+            assert!(results.is_empty());
+            assert_eq!((report.files_skipped.get("binary"), report.files_skipped.get("not_found")), (Some(&1), Some(&1)));
+            assert_eq!((report.errors.len(), report.files_cached), (2, 0));
+        }
+        assert!(options.cache.as_ref().unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_diff_results() {
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()]]).unwrap();
+        let hello = vec![String::from("sample_texts/hello.txt")];
+        let both = vec![String::from("sample_texts/hello.txt"), String::from("sample_texts/world.txt")];
+        let previous = search_text(&query_group, &hello, false);
+        let current = search_text_context(&query_group, &both, 1, 1, false);
+
+        let path = std::env::temp_dir().join(format!("textsearcher-results-{}.jsonl", std::process::id()));
+        save_results(&previous, &path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), results_to_jsonl(&previous));
+        let previous = load_results(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(previous[0].to_json(), r#"{"path":"sample_texts/hello.txt","context":null,"position":{"line":1,"column":2,"span":[1,2]}}"#);
+
+        let diff = diff_results(&previous, &current);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].path(), "sample_texts/world.txt");
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].1.context(), Some("foo"));
+        assert_eq!(diff_results(&current, &previous[..0]).removed.len(), 2);
+    }
+
+    #[test]
+    fn test_search_report() {
+        let query_group = QueryGroup::new(vec![vec!["world".to_string()]]).unwrap();
+        let paths = vec![
+            String::from("sample_texts/hello.txt"),
+            String::from("sample_texts/world.txt"),
+            String::from("sample_texts/gbk.txt"),
+            String::from("sample_texts/missing.txt"),
+        ];
+        let (result, report) = search_text_report(&query_group, &paths, &SearchOptions::default(), |_| ());
+        assert_eq!(result.len(), 1);
+        assert_eq!(report.files_total, 4);
+        assert_eq!(report.files_scanned, 2);
+        assert_eq!(report.files_matched, 1);
+        assert_eq!(report.bytes_read, 24);
+        assert_eq!(report.files_skipped, HashMap::from([
+            ("invalid_data".to_string(), 1),
+            ("not_found".to_string(), 1),
+        ]));
+        let errors: Vec<_> = report.errors.iter().map(|(path, reason, _)| (path.as_str(), reason.as_str())).collect();
+        assert_eq!(errors, vec![("sample_texts/gbk.txt", "invalid_data"), ("sample_texts/missing.txt", "not_found")]);
+    }
+
+    #[test]
+    fn test_replace() {
+        let dir = std::env::temp_dir().join(format!("textsearcher-replace-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let text_path = dir.join("doc.txt").to_string_lossy().into_owned();
+        let other_path = dir.join("other.txt").to_string_lossy().into_owned();
+        fs::write(&text_path, "Dated 2021-03.\nno date\nfrom 1999-12 to 2000-01").unwrap();
+        fs::write(&other_path, "no date").unwrap();
+        let query_group = QueryGroup::new(vec![vec![r"fancy:(?P<y>\d{4})-(?P<m>\d{2})".to_string()]]).unwrap();
+        let paths = vec![text_path.clone(), other_path.clone()];
+
+        let edits = plan_replace(&query_group, &paths, "$m/$y", &SearchOptions::default());
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].replacements(), 3);
+        assert_eq!(edits[0].diff(), format!(
+            "--- {0}\n+++ {0}\n@@ -1 +1 @@\n-Dated 2021-03.\n+Dated 03/2021.\n\
+             @@ -3 +3 @@\n-from 1999-12 to 2000-01\n\\ No newline at end of file\n\
+             +from 12/1999 to 01/2000\n\\ No newline at end of file\n",
+            text_path));
+        // the edits are only planned so far
+        assert!(fs::read_to_string(&text_path).unwrap().starts_with("Dated 2021-03."));
+
+        apply_edits(&edits, Some(".bak")).unwrap();
+        assert_eq!(fs::read_to_string(&text_path).unwrap(), "Dated 03/2021.\nno date\nfrom 12/1999 to 01/2000");
+        assert!(fs::read_to_string(format!("{}.bak", text_path)).unwrap().starts_with("Dated 2021-03."));
+        // applying again is refused, since the file changed since the edits were planned
+        assert!(apply_edits(&edits, None).is_err());
+        // so is rewriting a file that doesn't decode in its hinted encoding
+        fs::write(&other_path, b"1999-12 \xff").unwrap();
+        let options = SearchOptions {
+            encoding_hints: EncodingHints::new(&[("*.txt".to_string(), "utf-8".to_string())]).unwrap(),
+            ..Default::default()
+        };
+        let edits = plan_replace(&query_group, &paths[1..], "$m/$y", &options);
+        assert_eq!(edits[0].replaced(), "12/1999 \u{fffd}");
+        assert_eq!(apply_edits(&edits, None).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&other_path).unwrap(), b"1999-12 \xff");
+        // nor is a backup overwritten
+        fs::write(&other_path, "1999-12").unwrap();
+        fs::write(format!("{}.bak", other_path), "an older backup").unwrap();
+        let edits = plan_replace(&query_group, &paths, "$m/$y", &SearchOptions::default());
+        assert_eq!(apply_edits(&edits, Some(".bak")).unwrap_err().kind(), std::io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&other_path).unwrap(), "1999-12");
+
+        // a replacement GBK can't represent fails before any file is written
+        let gbk_path = dir.join("gbk.txt").to_string_lossy().into_owned();
+        fs::write(&gbk_path, encoding_rs::GBK.encode("日期 1999-12").0).unwrap();
+        let options = SearchOptions {
+            encoding_hints: EncodingHints::new(&[("*/gbk.txt".to_string(), "gbk".to_string())]).unwrap(),
+            ..Default::default()
+        };
+        let paths = vec![other_path.clone(), gbk_path.clone()];
+        let edits = plan_replace(&query_group, &paths, "$m/$y 🙂", &options);
+        assert_eq!(edits.len(), 2);
+        assert_eq!(apply_edits(&edits, None).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(fs::read_to_string(&other_path).unwrap(), "1999-12");
+        let edits = plan_replace(&query_group, &paths, "$m/$y 年", &options);
+        apply_edits(&edits, None).unwrap();
+        assert_eq!(fs::read_to_string(&other_path).unwrap(), "12/1999 年");
+        assert_eq!(fs::read(&gbk_path).unwrap(), encoding_rs::GBK.encode("日期 12/1999 年").0.into_owned());
+
+        // UTF-16 is written back in UTF-16, its byte order mark kept
+        let utf16 = |text: &str| [0xff, 0xfe].into_iter().chain(text.encode_utf16().flat_map(u16::to_le_bytes)).collect::<Vec<u8>>();
+        let utf16_path = dir.join("utf16.txt").to_string_lossy().into_owned();
+        fs::write(&utf16_path, utf16("from 1999-12")).unwrap();
+        let options = SearchOptions {
+            encoding_hints: EncodingHints::new(&[("*/utf16.txt".to_string(), "utf-16le".to_string())]).unwrap(),
+            ..Default::default()
+        };
+        let edits = plan_replace(&query_group, std::slice::from_ref(&utf16_path), "$m/$y 🙂", &options);
+        apply_edits(&edits, None).unwrap();
+        assert_eq!(fs::read(&utf16_path).unwrap(), utf16("from 12/1999 🙂"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_extract() {
+        let paths = vec![String::from("sample_texts/hello.txt"), String::from("sample_texts/world.txt")];
+        let query_group = QueryGroup::new(vec![vec!["fancy:(ba)(r)|(ba)z".to_string()]]).unwrap();
+        let extractions = extract(&query_group, &paths, &SearchOptions::default());
+        assert_eq!(extractions.len(), 1);
+        assert_eq!(extractions[0].path(), "sample_texts/hello.txt");
+        let some = |s: &str| Some(s.to_string());
+        assert_eq!(extractions[0].captures(), &[
+            vec![some("ba"), some("r"), None],
+            vec![None, None, some("ba")],
+        ]);
+
+        assert!(extractions[0].named().iter().all(|named| named.is_empty()));
+
+        // without capture groups, the whole matches are extracted
+        let query_group = QueryGroup::new(vec![vec!["world".to_string()]]).unwrap();
+        let extractions = extract(&query_group, &paths, &SearchOptions::default());
+        assert_eq!(extractions[0].captures(), &[vec![some("world")]]);
+
+        let query_group = QueryGroup::new(vec![vec!["fancy:(?P<head>ba)(?P<tail>r)?".to_string()]]).unwrap();
+        let extractions = extract(&query_group, &paths, &SearchOptions::default());
+        assert_eq!(extractions[0].named(), &[
+            HashMap::from([("head".to_string(), some("ba")), ("tail".to_string(), some("r"))]),
+            HashMap::from([("head".to_string(), some("ba")), ("tail".to_string(), None)]),
+        ]);
+    }
+
+    #[test]
+    fn test_find_all() {
+        let query_group = QueryGroup::new(vec![vec!["bar".to_string(), "baz".to_string()], vec!["missing".to_string()]]).unwrap();
+        let found = find_all(&query_group, "sample_texts/hello.txt", &SearchOptions::default()).unwrap();
+        assert_eq!(found, vec![vec![(4, 7, "bar".to_string()), (8, 11, "baz".to_string())], vec![]]);
+        assert!(find_all(&query_group, "sample_texts/missing.txt", &SearchOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_normalizers() {
+        let query_group = QueryGroup::new(vec![vec!["bar".to_string()], vec!["baz".to_string()]]).unwrap();
+        let paths = vec![String::from("sample_texts/hello.txt")];
+        assert_eq!(search_text_with(&query_group, &paths, &SearchOptions::default()).len(), 1);
+        let options = SearchOptions {
+            normalizers: vec![Arc::new(StripLines::new("baz").unwrap())],
+            ..Default::default()
+        };
+        assert!(search_text_with(&query_group, &paths, &options).is_empty());
+        assert_ne!(crate::cache::query_key(&query_group, &options), crate::cache::query_key(&query_group, &SearchOptions::default()));
+    }
+
+    #[test]
+    fn test_scoring() {
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()]]).unwrap();
+        let paths = vec![String::from("sample_texts/world.txt"), String::from("sample_texts/hello.txt")];
+        let options = SearchOptions {
+            scoring: Some(ScoringOptions {
+                scorer: Arc::new(TotalCount),
+                top_k: Some(1),
+            }),
+            ..Default::default()
+        };
+        let result = search_text_with(&query_group, &paths, &options);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].path(), "sample_texts/hello.txt");
+        assert_eq!(result[0].score(), Some(2.0));
+    }
+
+    #[test]
+    fn test_search_iter() {
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()]]).unwrap();
+        let paths: Vec<_> = ["hello.txt", "gbk.txt", "world.txt", "missing.txt", "hello.txt"]
+            .iter()
+            .map(|name| format!("sample_texts/{}", name))
+            .collect();
+        let options = SearchOptions::default();
+        let eager = search_text_with(&query_group, &paths, &options);
+        assert_eq!(eager.len(), 3);
+        assert_eq!(search_iter(&query_group, &paths, &options).collect::<Vec<_>>(), eager);
+        assert_eq!(search_iter(&query_group, &paths, &options).take(1).collect::<Vec<_>>(), eager[..1]);
+        for prefetch in [1, 2, 16] {
+            assert_eq!(search_iter_prefetch(&query_group, &paths, &options, prefetch).collect::<Vec<_>>(), eager);
+            // dropping the iterator early stops the background threads
+            assert_eq!(search_iter_prefetch(&query_group, &paths, &options, prefetch).nth(1).as_ref(), eager.get(1));
+        }
+    }
+
+    #[test]
+    fn test_search_stream() {
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()]]).unwrap();
+        let paths: Vec<_> = ["hello.txt", "gbk.txt", "world.txt", "missing.txt", "hello.txt"]
+            .iter()
+            .map(|name| format!("sample_texts/{}", name))
+            .collect();
+        let options = SearchOptions::default();
+        let mut stream = search_stream(&query_group, &paths, &options);
+        // in the order found, which the threads decide
+        let mut streamed: Vec<_> = stream.by_ref().map(|result| result.path).collect();
+        streamed.sort();
+        let mut eager: Vec<_> = search_text_with(&query_group, &paths, &options).into_iter().map(|result| result.path).collect();
+        eager.sort();
+        assert_eq!(streamed, eager);
+        let report = stream.report().unwrap();
+        assert_eq!((report.files_total, report.files_matched), (5, 3));
+        // dropping the stream early cancels the search
+        assert!(search_stream(&query_group, &paths, &options).next().is_some());
+    }
+
+    #[test]
+    fn test_spilled_results() {
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()]]).unwrap();
+        let paths = vec![
+            String::from("sample_texts/hello.txt"),
+            String::from("sample_texts/missing.txt"),
+            String::from("sample_texts/world.txt"),
+        ];
+        let options = SearchOptions {
+            context: Some(ContextOptions::default()),
+            ..Default::default()
+        };
+        let (spilled, report) = search_text_spilled(&query_group, &paths, &options, |_| ()).unwrap();
+        assert_eq!(spilled.len(), 2);
+        assert_eq!(report.files_matched, 2);
+        let results: Vec<_> = spilled.iter().unwrap().map(Result::unwrap).collect();
+        assert_eq!(results, search_text_with(&query_group, &paths, &options));
+    }
+
+    #[test]
+    fn test_count_matches() {
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()], vec!["ba".to_string(), "w".to_string()]]).unwrap();
+        let paths = vec![
+            String::from("sample_texts/hello.txt"),
+            String::from("sample_texts/world.txt"),
+            String::from("sample_texts/missing.txt"),
+        ];
+        for parallel in [false, true] {
+            let options = SearchOptions {
+                parallel,
+                ..Default::default()
+            };
+            assert_eq!(count_matches(&query_group, &paths, &options, false), (2, None));
+            assert_eq!(count_matches(&query_group, &paths, &options, true), (2, Some(3 + 3)));
+            let (_, report) = count_matches_report(&query_group, &paths, &options, false);
+            assert_eq!((report.files_total, report.files_scanned, report.files_matched), (3, 2, 2));
+            assert_eq!(report.errors.iter().map(|(path, reason, _)| (path.as_str(), reason.as_str())).collect::<Vec<_>>(), vec![("sample_texts/missing.txt", "not_found")]);
+        }
+    }
+
+    #[test]
+    fn test_invert() {
+        let query_group = QueryGroup::new(vec![vec!["world".to_string()]]).unwrap();
+        let paths = vec![
+            String::from("sample_texts/hello.txt"),
+            String::from("sample_texts/world.txt"),
+            String::from("sample_texts/missing.txt"),
+        ];
+        let options = SearchOptions {
+            context: Some(ContextOptions::default()),
+            invert: true,
+            ..Default::default()
+        };
+        let result = search_text_with(&query_group, &paths, &options);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].path(), "sample_texts/hello.txt");
+        assert_eq!(result[0].context(), None);
+        assert_eq!(count_matches(&query_group, &paths, &options, true), (1, Some(0)));
+    }
+
+    #[test]
+    fn test_exists_match() {
+        let paths = vec![String::from("sample_texts/missing.txt"), String::from("sample_texts/world.txt")];
+        for parallel in [false, true] {
+            let options = SearchOptions {
+                parallel,
+                ..Default::default()
+            };
+            assert!(exists_match(&QueryGroup::new(vec![vec!["world".to_string()]]).unwrap(), &paths, &options));
+            assert!(!exists_match(&QueryGroup::new(vec![vec!["bar".to_string()]]).unwrap(), &paths, &options));
+            let (found, report) = exists_match_report(&QueryGroup::new(vec![vec!["world".to_string()]]).unwrap(), &paths, &options);
+            assert!(found);
+            assert_eq!(report.files_skipped.get("not_found"), Some(&1));
+        }
+        // no more files searched once one matches
+        let paths = vec![String::from("sample_texts/world.txt"), String::from("sample_texts/hello.txt")];
+        let options = SearchOptions {
+            parallel: false,
+            ..Default::default()
+        };
+        let (found, report) = exists_match_report(&QueryGroup::new(vec![vec!["o".to_string()]]).unwrap(), &paths, &options);
+        assert!(found);
+        assert_eq!(report.files_scanned, 1);
+    }
+
+    #[test]
+    fn test_sample() {
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()]]).unwrap();
+        let paths: Vec<_> = (0..50).map(|i| format!("sample_texts/{}.txt", ["hello", "world"][i % 2])).collect();
+        let options = SearchOptions::default();
+        let (sample, report) = search_text_sample(&query_group, &paths, &options, 5, Some(7), |_| ());
+        assert_eq!(sample.len(), 5);
+        assert_eq!(report.files_matched, 50);
+        let sequential = SearchOptions {
+            parallel: false,
+            ..Default::default()
+        };
+        assert_eq!(search_text_sample(&query_group, &paths, &sequential, 5, Some(7), |_| ()).0, sample);
+    }
+
+    #[test]
+    fn test_aggregate_by_directory() {
+        let result = |path: &str| FileMatchResult {
+            path: path.to_string(),
+            context: Some(path.to_string()),
+            tags: Tags::new(),
+            score: None,
+            truncated_before: false,
+            truncated_after: false,
+            position: None,
+            contexts: Vec::new(),
+            clause_contexts: Vec::new(),
+            highlights: Vec::new(),
+        };
+        let results = vec![result("a/x/1.txt"), result("b/2.txt"), result("a/y/3.txt"), result("a/x/4.txt")];
+        let groups = aggregate_by_directory(&results, None, 1);
+        let summary: Vec<_> = groups.iter().map(|g| (g.directory.as_str(), g.files, g.examples.len())).collect();
+        assert_eq!(summary, vec![("a/x", 2, 1), ("a/y", 1, 1), ("b", 1, 1)]);
+        assert_eq!(groups[0].examples[0].context(), Some("a/x/1.txt"));
+        let groups = aggregate_by_directory(&results, Some(1), 5);
+        let summary: Vec<_> = groups.iter().map(|g| (g.directory.as_str(), g.files, g.examples.len())).collect();
+        assert_eq!(summary, vec![("a", 3, 3), ("b", 1, 1)]);
+    }
+
+    #[test]
+    fn test_context_chars() {
+        let query_group = QueryGroup::new(vec![vec!["搜索".to_string()]]).unwrap();
+        let context = |contents: &str, chars: bool| {
+            let options = SearchOptions {
+                context: Some(ContextOptions {
+                    before: 2,
+                    after: 3,
+                    unit: if chars { ContextUnit::Chars } else { ContextUnit::Bytes },
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            match_contents(&query_group, "-", contents, &options).and_then(|(result, _)| result.context)
+        };
+        assert_eq!(context("这是全文搜索工具啊", true).as_deref(), Some("全文搜索工具啊"));
+        assert_eq!(context("这是全文搜索工具啊", false).as_deref(), Some("搜索工"));
+        assert_eq!(context("搜索", true).as_deref(), Some("搜索"));
+        assert_eq!(context("ab搜索c", true).as_deref(), Some("ab搜索c"));
+    }
+
+    #[test]
+    fn test_max_context_chars() {
+        let query_group = QueryGroup::new(vec![vec!["needle".to_string()]]).unwrap();
+        let context = |max_chars: Option<usize>| {
+            let options = SearchOptions {
+                context: Some(ContextOptions {
+                    before: 100,
+                    after: 100,
+                    max_chars,
+                    highlight_begin: "[".to_string(),
+                    highlight_end: "]".to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            match_contents(&query_group, "-", "a long line with a needle in the haystack", &options).and_then(|(result, _)| result.context)
+        };
+        assert_eq!(context(None).as_deref(), Some("a long line with a [needle] in the haystack"));
+        assert_eq!(context(Some(12)).as_deref(), Some(" a [needle] in"));
+        assert_eq!(context(Some(1000)).as_deref(), Some("a long line with a [needle] in the haystack"));
+    }
+
+    #[test]
+    fn test_search_any() {
+        let query_groups = vec![
+            QueryGroup::new(vec![vec!["foo".to_string()]]).unwrap(),
+            QueryGroup::new(vec![vec!["nonexistent".to_string()]]).unwrap(),
+            QueryGroup::new(vec![vec!["bar".to_string()]]).unwrap(),
+        ];
+        let paths = vec![String::from("sample_texts/hello.txt"), String::from("sample_texts/world.txt")];
+        let options = SearchOptions::default();
+        let results = search_any(&query_groups, &paths, &options);
+        let expected: Vec<_> = paths
+            .iter()
+            .filter_map(|path| {
+                let groups: Vec<_> = (0..3).filter(|&i| !search_text(&query_groups[i], std::slice::from_ref(path), false).is_empty()).collect();
+                (!groups.is_empty()).then(|| (path.clone(), groups))
+            })
+            .collect();
+        assert_eq!(results, expected);
+        assert_eq!(results[0], (String::from("sample_texts/hello.txt"), vec![0, 2]));
+        let paths = vec![String::from("sample_texts/missing.txt"), String::from("sample_texts/hello.txt")];
+        let (results, report) = search_any_report(&query_groups, &paths, &options);
+        assert_eq!(results, vec![(String::from("sample_texts/hello.txt"), vec![0, 2])]);
+        assert_eq!((report.files_scanned, report.files_matched), (1, 1));
+        assert_eq!(report.errors[0].0, "sample_texts/missing.txt");
+    }
+
+    #[test]
+    fn test_search_text_multi() {
+        let query_groups = vec![
+            QueryGroup::new(vec![vec!["foo".to_string()]]).unwrap(),
+            QueryGroup::new(vec![vec!["nonexistent".to_string()]]).unwrap(),
+            QueryGroup::new(vec![vec!["bar".to_string()]]).unwrap(),
+        ];
+        let paths = vec![String::from("sample_texts/hello.txt"), String::from("sample_texts/world.txt"), String::from("sample_texts/missing.txt")];
+        let options = SearchOptions {
+            parallel: true,
+            ..Default::default()
+        };
+        let results = search_text_multi(&query_groups, &paths, &options);
+        let expected: Vec<_> = query_groups.iter().map(|query_group| search_text_with(query_group, &paths, &options)).collect();
+        assert_eq!(results, expected);
+        assert!(results[1].is_empty());
+        let (_, report) = search_text_multi_report(&query_groups, &paths, &options);
+        assert_eq!((report.files_total, report.files_scanned, report.bytes_read), (3, 2, 24));
+        assert_eq!(report.errors[0].0, "sample_texts/missing.txt");
+        // each group's matches cached, the files aren't read again
+        let options = SearchOptions {
+            cache: Some(ResultCache::open(std::env::temp_dir().join(format!("textsearcher-multi-cache-{}.jsonl", std::process::id()))).unwrap()),
+            ..options
+        };
+        search_text_multi(&query_groups, &paths, &options);
+        let (results, report) = search_text_multi_report(&query_groups, &paths, &options);
+        assert_eq!(results, expected);
+        assert_eq!((report.files_cached, report.bytes_read), (2, 0));
+    }
+
+    #[test]
+    fn test_excluded() {
+        let query_group = QueryGroup::with_exclusions(vec![vec!["foo".to_string()]], vec![vec!["baz".to_string()]], QueryOptions::default()).unwrap();
+        assert!(is_match_str(&query_group, "foo bar"));
+        assert!(!is_match_str(&query_group, "foo baz"));
+        let paths = vec![String::from("sample_texts/hello.txt")];
+        assert!(search_text(&query_group, &paths, false).is_empty());
+        assert!(search_text_context(&query_group, &paths, 1, 1, false).is_empty());
+
+        let query = Query::atom("foo").and(Query::atom("qux").negate()).compile(QueryOptions::default()).unwrap();
+        assert_eq!(search_text(&query, &paths, false).len(), 1);
+    }
+
+    #[test]
+    fn test_context_lines() {
+        let context = |pattern: &str, contents: &str, before: usize, after: usize| {
+            let query_group = QueryGroup::new(vec![vec![pattern.to_string()]]).unwrap();
+            let options = SearchOptions {
+                context: Some(ContextOptions {
+                    before,
+                    after,
+                    unit: ContextUnit::Lines,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            match_contents(&query_group, "-", contents, &options).and_then(|(result, _)| result.context)
+        };
+        let contents = "one\ntwo\nthe needle\nfour\nfive";
+        assert_eq!(context("needle", contents, 0, 0).as_deref(), Some("the needle"));
+        assert_eq!(context("needle", contents, 1, 1).as_deref(), Some("two\nthe needle\nfour"));
+        assert_eq!(context("needle", contents, 5, 5).as_deref(), Some(contents));
+        assert_eq!(context("needle", "a\r\nneedle\r\nb\r\n", 0, 1).as_deref(), Some("needle\r\nb"));
+        assert_eq!(context("fancy:needle\\n", contents, 0, 0).as_deref(), Some("the needle\n"));
+        assert_eq!(context("fancy:^", contents, 0, 1).as_deref(), Some("one\ntwo"));
+        let query_group = QueryGroup::new(vec![vec!["baz".to_string()]]).unwrap();
+        let results = search_text_context_with_unit(&query_group, &[String::from("sample_texts/hello.txt")], 1, 0, ContextUnit::Lines, false);
+        assert_eq!(results[0].context.as_deref(), Some("foo bar\nbaz"));
+    }
+
+    #[test]
+    fn test_unspaced_scripts() {
+        // words of Thai and Khmer text are only separated when wrapped across lines
+        let query_group = QueryGroup::new(vec![vec!["ภาษาไทย".to_string()], vec!["ភាសាខ្មែរ".to_string()]]).unwrap();
+        assert!(is_match_str(&query_group, "ภาษา\nไทย และ ភាសា\nខ្មែរ"));
+        assert!(is_match_str(&query_group, "ภาษาไทยភាសាខ្មែរ"));
+        assert!(!is_match_str(&query_group, "ภาษา"));
+    }
+
+    #[test]
+    fn test_within() {
+        let atoms = vec![vec!["machine".to_string()], vec!["learning".to_string()]];
+        let query_group = QueryGroup::new(atoms).unwrap().within(20);
+        assert!(is_match_str(&query_group, "learning a machine"));
+        assert!(is_match_str(&query_group, "machine ... learning"));
+        assert!(!is_match_str(&query_group, "machine, then nothing relevant, then learning"));
+        // the closest pair counts, not just the first matches
+        assert!(is_match_str(&query_group, "machine, then nothing relevant, then machine learning"));
+        // counted in characters
+        assert!(is_match_str(&query_group, "machine、、、、、learning"));
+        assert!(!is_match_str(&QueryGroup::new(vec![vec!["foo".to_string()]]).unwrap().within(2), "foo"));
+
+        let query_group = QueryGroup::new(vec![vec!["machine".to_string()], vec!["learning".to_string()]]).unwrap().within_lines(1);
+        assert!(is_match_str(&query_group, "machine\nlearning"));
+        assert!(!is_match_str(&query_group, "machine\n\nlearning"));
+        let contents = "machine\n\nlearning\n\nmachine\nlearning";
+        assert!(is_match_str(&query_group, contents));
+        assert!(!is_match_str(&query_group.clone().within(5), contents));
+        // the context is that of the window where the matches are close enough
+        let options = ContextOptions {
+            before: 0,
+            after: 0,
+            ..Default::default()
+        };
+        let result = is_match_context(&query_group, "a", contents, &options).unwrap();
+        assert_eq!(result.context(), Some("machine\nlearning"));
+        assert_eq!(result.position().map(|position| position.line), Some(5));
+    }
+
+    #[test]
+    fn test_apart() {
+        let atoms = vec![vec!["Smith".to_string()], vec!["model".to_string()]];
+        let query_group = QueryGroup::new(atoms).unwrap().apart(10);
+        assert!(is_match_str(&query_group, "the model, as described by Smith (1990)"));
+        assert!(!is_match_str(&query_group, "Smith's model"));
+        assert!(is_match_str(&query_group, "Smith's model, used by our model later"));
+        assert!(is_match_str(&query_group, "a model ... and later Smith"));
+        assert!(!is_match_str(&query_group, "Smith only"));
+        // counted in characters
+        assert!(!is_match_str(&query_group, "Smith、、、、、、model"));
+    }
+
+    #[test]
+    fn test_scoped() {
+        let atoms = vec![vec!["needle".to_string()], vec!["thread".to_string()]];
+        let query_group = QueryGroup::new(atoms).unwrap();
+        let options = SearchOptions {
+            context: Some(ContextOptions {
+                before: 0,
+                after: 0,
+                unit: ContextUnit::Lines,
+                scoped: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let context = |contents: &str| match_contents(&query_group, "-", contents, &options).and_then(|(result, _)| result.context);
+        assert_eq!(context("a needle
+thread
+needle and thread
+").as_deref(), Some("needle and thread"));
+        assert_eq!(context("a needle
+thread
+"), None);
+        assert_eq!(context("thread and needle"), Some("thread and needle".to_string()));
+    }
+
+    #[test]
+    fn test_build_info() {
+        assert_eq!(crate::enabled_features().contains(&"pcre2"), cfg!(feature = "pcre2"));
+        assert_eq!(crate::regex_engines()[0], "regex");
+    }
+
+    #[test]
+    fn test_detect_language() {
+        let query_group = QueryGroup::new(vec![vec!["サーバー".to_string()]]).unwrap();
+        let options = SearchOptions {
+            detect_language: true,
+            ..Default::default()
+        };
+        assert!(match_contents(&query_group, "-", "新しいさーばを設定する", &SearchOptions::default()).is_none());
+        let (result, _) = match_contents(&query_group, "-", "新しいさーばを設定する", &options).unwrap();
+        assert_eq!(result.tags.get("language"), Some(&TagValue::Str("ja".to_string())));
+        // kana folding is only for Japanese documents
+        assert!(match_contents(&query_group, "-", "the server (さーば) is set up", &options).is_none());
+    }
+
+    #[test]
+    fn test_case_folding() {
+        let options = QueryOptions {
+            case_folding: Some(CaseFold { turkic: false }),
+            ..Default::default()
+        };
+        let query_group = QueryGroup::with_options(vec![vec!["Straße".to_string()]], options).unwrap();
+        let search_options = SearchOptions {
+            normalizers: vec![Arc::new(CaseFold { turkic: false })],
+            ..Default::default()
+        };
+        assert!(match_contents(&query_group, "-", "DIE STRASSE", &search_options).is_some());
+        assert!(match_contents(&query_group, "-", "die Strasse", &search_options).is_some());
+        assert!(!is_match_str(&QueryGroup::new(vec![vec!["Straße".to_string()]]).unwrap(), "STRASSE"));
+    }
+
+    #[test]
+    fn test_cjk_gap() {
+        let options = QueryOptions {
+            separators: Separators {
+                cjk: Some(r"\s*(?:\n.{0,8}\n)?\s*".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let query_group = QueryGroup::with_options(vec![vec!["機械学習".to_string()]], options).unwrap();
+        assert!(is_match_str(&query_group, "機械学\n- 12 -\n習"));
+        assert!(is_match_str(&query_group, "機械\n学習"));
+        assert!(!is_match_str(&query_group, "機械学\nこれは長すぎる見出しの行です\n習"));
+        assert!(!is_match_str(&QueryGroup::new(vec![vec!["機械学習".to_string()]]).unwrap(), "機械学\n- 12 -\n習"));
+    }
+
+    #[test]
+    fn test_separators() {
+        let options = |words: Option<&str>, hyphens: bool| QueryOptions {
+            separators: Separators {
+                words: words.map(String::from),
+                hyphens,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(_get_regex_for_atom_with("user id", &options(Some(r"[\s_]+"), false)), r"user(?:[\s_]+)id");
+        assert_eq!(_get_regex_for_atom_with("中文 id", &options(None, true)), r"中\s*文\s*i(?:-\s*)?d");
+        let query_group = QueryGroup::with_options(vec![vec!["user id".to_string()]], options(Some(r"[\s_]+"), false)).unwrap();
+        assert!(is_match_str(&query_group, "the user_id column"));
+        let query_group = QueryGroup::with_options(vec![vec!["information theory".to_string()]], options(None, true)).unwrap();
+        assert!(is_match_str(&query_group, "an infor-\nmation theo-  ry"));
+        assert!(!is_match_str(&query_group, "an infor mation theory"));
+        assert!(!is_match_str(&QueryGroup::new(vec![vec!["information".to_string()]]).unwrap(), "infor-\nmation"));
+    }
+
+    #[test]
+    fn test_nfkc() {
+        let options = QueryOptions {
+            nfkc: true,
+            ..Default::default()
+        };
+        let query_group = QueryGroup::with_options(vec![vec!["ＧＰＵ ｶｰﾈﾙ".to_string()]], options).unwrap();
+        assert_eq!(query_group.atoms, vec![vec!["ＧＰＵ ｶｰﾈﾙ".to_string()]]);
+        let contents = normalize(&[Arc::new(Nfkc)], "ＧＰＵ カーネル");
+        assert!(is_match_str(&query_group, &contents));
+        assert!(is_match_str(&query_group, "gpu カーネル"));
+        assert!(!is_match_str(&QueryGroup::new(vec![vec!["ＧＰＵ ｶｰﾈﾙ".to_string()]]).unwrap(), "GPU カーネル"));
+    }
+
+    #[test]
+    fn test_whole_words() {
+        let options = QueryOptions {
+            whole_words: true,
+            ..Default::default()
+        };
+        let compile = |atom: &str| QueryGroup::with_options(vec![vec![atom.to_string()]], options.clone()).unwrap();
+        assert_eq!(_get_regex_for_atom_with("big cat", &options), r"\bbig\b\s+\bcat\b");
+        assert!(is_match_str(&compile("cat"), "the Cat sat"));
+        assert!(!is_match_str(&compile("cat"), "concatenate"));
+        assert!(is_match_str(&QueryGroup::new(vec![vec!["cat".to_string()]]).unwrap(), "concatenate"));
+        // anchored only at word characters, and not next to unspaced scripts
+        assert!(is_match_str(&compile("C++"), "in C++ code"));
+        assert!(!is_match_str(&compile("C++"), "in ObjC++ code"));
+        assert!(is_match_str(&compile("GPU加速"), "GPU加速の手法"));
+        assert!(!is_match_str(&compile("GPU加速"), "eGPU加速"));
+    }
+
+    #[test]
+    fn test_fuzzy() {
+        let compile = |atom: &str, fuzzy: usize| {
+            let options = QueryOptions {
+                fuzzy: Some(fuzzy),
+                whole_words: true,
+                ..Default::default()
+            };
+            QueryGroup::with_options(vec![vec![atom.to_string()]], options).unwrap()
+        };
+        assert!(is_match_str(&compile("hello world", 0), "say hel1o wor|d"));
+        assert!(!is_match_str(&compile("hello world", 0), "say helpo world"));
+        assert!(is_match_str(&compile("hello world", 1), "say helpo world"));
+        assert!(!is_match_str(&compile("hello world", 1), "say help world"));
+        // the characters of unspaced scripts are left as they are
+        assert!(is_match_str(&compile("GPU加速", 1), "GPV加速"));
+        assert!(!is_match_str(&compile("GPU加速", 1), "GPU加遠"));
+        assert!(is_match_str(&compile("internationalization", 2), "intemationalizatior"));
+        let options = QueryOptions {
+            fuzzy: Some(3),
+            ..Default::default()
+        };
+        assert!(QueryGroup::with_options(vec![vec!["hello".to_string()]], options).is_err());
+    }
+
+    #[test]
+    fn test_then() {
+        let query_group = QueryGroup::new(vec![vec!["then:introduction ... data set ... re:result\\w*".to_string()]]).unwrap();
+        assert!(is_match_str(&query_group, "1 Introduction\n2 Our data\nset\n3 Results"));
+        assert!(!is_match_str(&query_group, "1 Introduction\n2 Results\n3 Data set"));
+        assert!(!is_match_str(&query_group, "data set, results and the introduction"));
+    }
+
+    #[test]
+    fn test_expand_context() {
+        let query_group = QueryGroup::new(vec![vec!["bar".to_string()]]).unwrap();
+        let paths = vec![String::from("sample_texts/hello.txt")];
+        let result = &search_text_context(&query_group, &paths, 0, 0, false)[0];
+        assert_eq!(result.context(), Some("bar"));
+        let options = SearchOptions {
+            context: Some(ContextOptions {
+                before: 4,
+                after: 4,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(result.expand_context(&query_group, &options, None).unwrap().as_deref(), Some("foo bar
+baz"));
+        let corpus = crate::corpus::Corpus::new(paths, true);
+        corpus.search(&query_group, &SearchOptions::default());
+        assert_eq!(result.expand_context(&query_group, &options, Some(&corpus)).unwrap().as_deref(), Some("foo bar
+baz"));
+        let other = QueryGroup::new(vec![vec!["qux".to_string()]]).unwrap();
+        assert_eq!(result.expand_context(&other, &options, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_max_file_size() {
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()]]).unwrap();
+        let paths = vec!["sample_texts/hello.txt".to_string()];
+        let options = SearchOptions {
+            max_file_size: Some(1),
+            ..Default::default()
+        };
+        let (results, report) = search_text_report(&query_group, &paths, &options, |_| ());
+        assert!(results.is_empty());
+        assert_eq!(report.files_skipped.get("too_large"), Some(&1));
+    }
+
+    #[test]
+    fn test_file_paths_from_glob() {
+        let files = FilePaths::from_glob("sample_texts/*o*.txt").unwrap();
+        assert_eq!(files.paths, vec!["sample_texts/hello.txt", "sample_texts/world.txt"]);
+        assert_eq!(files.tags.len(), 2);
+        let files = FilePaths::from_glob("sample_*/**/gbk.txt").unwrap();
+        assert_eq!(files.paths, vec!["sample_texts/gbk.txt"]);
+        assert!(FilePaths::from_glob("missing/*.txt").unwrap().paths.is_empty());
+        assert_eq!(FilePaths::from_dir("sample_texts").unwrap().paths.len(), 3);
+        let excludes = crate::exclude::Excludes::new(&["gbk*".to_string()]).unwrap();
+        let mut files = FilePaths::from_dir_excluding("sample_texts", &excludes).unwrap();
+        assert_eq!(files.paths, vec!["sample_texts/hello.txt", "sample_texts/world.txt"]);
+        files.exclude(&crate::exclude::Excludes::new(&["sample_texts/h*".to_string()]).unwrap());
+        assert_eq!((files.paths.len(), files.tags.len()), (1, 1));
+    }
+
+    #[test]
+    fn test_compose_query_groups() {
+        let servers = QueryGroup::new(vec![vec!["server".to_string()]]).unwrap().within(50);
+        let config = QueryGroup::with_exclusions(vec![vec!["config".to_string()]], vec![vec!["draft".to_string()]], QueryOptions::default())
+            .unwrap()
+            .within(20);
+        let both = servers.and_with(&config);
+        assert_eq!(both.atoms, vec![vec!["server".to_string()], vec!["config".to_string()]]);
+        assert_eq!(both.within, Some(20));
+        assert!(is_match_str(&both, "configure the server"));
+        assert!(!is_match_str(&both, "configure the server (draft)"));
+        assert!(!is_match_str(&both, "the server"));
+
+        let refined = both.add_or_atom(0, "host".to_string()).unwrap();
+        assert!(is_match_str(&refined, "configure the host"));
+        assert!(!is_match_str(&both, "configure the host"));
+        assert!(both.add_or_atom(2, "host".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_context_truncation() {
+        let query_group = QueryGroup::new(vec![vec!["beta".to_string()]]).unwrap();
+        let result = |a: usize, b: usize| {
+            let options = SearchOptions {
+                context: Some(ContextOptions {
+                    before: a,
+                    after: b,
+                    ellipsis: true,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            match_contents(&query_group, "-", "alpha beta gamma\n", &options).unwrap().0
+        };
+        let truncated = result(2, 2);
+        assert_eq!(truncated.context(), Some("…a beta g…"));
+        assert_eq!(truncated.truncated(), (true, true));
+        let whole = result(usize::MAX, usize::MAX);
+        assert_eq!(whole.context(), Some("alpha beta gamma\n"));
+        assert_eq!(whole.truncated(), (false, false));
+        assert_eq!(result(6, 6).truncated(), (false, false));
+    }
+
+    #[test]
+    fn test_snippet() {
+        let query_group = QueryGroup::new(vec![vec!["beta".to_string()]]).unwrap();
+        let options = SearchOptions {
+            context: Some(ContextOptions {
+                before: 12,
+                after: 9,
+                highlight_begin: "[".to_string(),
+                highlight_end: "]".to_string(),
+                snippet: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let result = match_contents(&query_group, "-", "first alpha\n   beta gamma delta", &options).unwrap().0;
+        assert_eq!(result.context(), Some("…alpha [beta] gamma…"));
+        assert_eq!(result.highlights(), [(8, 12)]);
+    }
+
+    #[test]
+    fn test_highlight_all() {
+        let atoms = vec![vec!["server".to_string()], vec!["configure".to_string(), "config".to_string()]];
+        let query_group = QueryGroup::new(atoms).unwrap();
+        let context = |highlight_all: bool| {
+            let options = SearchOptions {
+                context: Some(ContextOptions {
+                    before: 100,
+                    after: 100,
+                    highlight_begin: "<g{group}>".to_string(),
+                    highlight_end: "</g{group}>".to_string(),
+                    highlight_all,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            match_contents(&query_group, "-", "configure the server's config", &options).unwrap().0.context
+        };
+        assert_eq!(context(true).as_deref(), Some("<g1>configure</g1> the <g0>server</g0>'s <g1>config</g1>"));
+        assert_eq!(context(false).as_deref(), Some("configure the <g{group}>server</g{group}>'s config"));
+    }
+
+    #[test]
+    fn test_max_contexts() {
+        let query_group = QueryGroup::new(vec![vec!["disk".to_string()], vec!["full".to_string()]]).unwrap();
+        let contexts = |max_contexts: usize| {
+            let options = SearchOptions {
+                context: Some(ContextOptions {
+                    before: 2,
+                    after: 2,
+                    max_contexts: Some(max_contexts),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            match_contents(&query_group, "-", "a full disk, then another disk, and the disk is full", &options).unwrap().0.contexts
+        };
+        // overlapping contexts are merged
+        assert_eq!(contexts(10), vec!["a full disk, ", "r disk, ", "e disk is full"]);
+        assert_eq!(contexts(2), vec!["a full disk, ", "r disk, "]);
+    }
+
+    #[test]
+    fn test_clause_contexts() {
+        let query_group = QueryGroup::new(vec![vec!["disk".to_string()], vec!["quota".to_string()]]).unwrap();
+        let options = SearchOptions {
+            context: Some(ContextOptions {
+                before: 4,
+                after: 4,
+                highlight_begin: "[".to_string(),
+                highlight_end: "]".to_string(),
+                clause_contexts: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let result = match_contents(&query_group, "-", "the disk is full, so the quota was raised", &options).unwrap().0;
+        assert_eq!(result.context(), Some("the [disk] is "));
+        assert_eq!(result.clause_contexts(), [(0, "the [disk] is ".to_string()), (1, "the [quota] was".to_string())]);
+    }
+
+    #[test]
+    fn test_deadline() {
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()]]).unwrap();
+        let paths = vec!["sample_texts/hello.txt".to_string(), "sample_texts/world.txt".to_string()];
+        let options = SearchOptions {
+            deadline: Some(Instant::now()),
+            ..Default::default()
+        };
+        let (results, report) = search_text_report(&query_group, &paths, &options, |_| ());
+        assert!(results.is_empty());
+        assert!(report.timed_out);
+        let options = SearchOptions {
+            deadline: Some(Instant::now() + Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let (results, report) = search_text_report(&query_group, &paths, &options, |_| ());
+        assert_eq!(results.len(), 2);
+        assert!(!report.timed_out);
+
+        for chunked in [None, Some(ChunkOptions { chunk_bytes: 4, overlap: 2 })] {
+            let options = SearchOptions {
+                file_timeout: Some(Duration::ZERO),
+                chunked,
+                ..Default::default()
+            };
+            let (results, report) = search_text_report(&query_group, &paths, &options, |_| ());
+            assert!(results.is_empty());
+            assert_eq!(report.files_skipped.get("timed_out"), Some(&2));
+        }
+        let options = SearchOptions {
+            file_timeout: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        assert_eq!(search_text_with(&query_group, &paths, &options).len(), 2);
+    }
+
+    #[test]
+    fn test_max_results() {
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()]]).unwrap();
+        let paths = vec!["sample_texts/hello.txt".to_string(), "sample_texts/gbk.txt".to_string(), "sample_texts/world.txt".to_string()];
+        let search = |parallel: bool, max_results: usize| {
+            let options = SearchOptions {
+                parallel,
+                max_results: Some(max_results),
+                ..Default::default()
+            };
+            let (results, report) = search_text_report(&query_group, &paths, &options, |_| ());
+            (results.iter().map(|result| result.path().to_string()).collect::<Vec<_>>(), report.limited)
+        };
+        assert_eq!(search(false, 1), (vec![paths[0].clone()], true));
+        assert_eq!(search(false, 2), (vec![paths[0].clone(), paths[2].clone()], false));
+        assert_eq!(search(true, 1).0.len(), 1);
+        assert_eq!(search(true, 5).0.len(), 2);
+    }
+
+    #[test]
+    fn test_progress_and_cancel() {
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()]]).unwrap();
+        let paths = vec!["sample_texts/hello.txt".to_string(), "sample_texts/world.txt".to_string(), "sample_texts/gbk.txt".to_string()];
+        let (cancel, calls) = (CancelToken::new(), Arc::new(Mutex::new(Vec::new())));
+        let (token, seen) = (cancel.clone(), calls.clone());
+        let options = SearchOptions {
+            parallel: false,
+            progress: Some(Progress::new(1, move |done, total| {
+                seen.lock().unwrap().push((done, total));
+                token.cancel();
+            })),
+            cancel: Some(cancel),
+            ..Default::default()
+        };
+        let (results, report) = search_text_report(&query_group, &paths, &options, |_| ());
+        assert_eq!(results.len(), 1);
+        assert!(report.cancelled);
+        assert_eq!(*calls.lock().unwrap(), [(1, 3)]);
+        // every other file, and the last one
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let seen = calls.clone();
+        let options = SearchOptions {
+            progress: Some(Progress::new(2, move |done, total| seen.lock().unwrap().push((done, total)))),
+            ..Default::default()
+        };
+        assert!(!search_text_report(&query_group, &paths, &options, |_| ()).1.cancelled);
+        let mut calls = calls.lock().unwrap().clone();
+        calls.sort_unstable();
+        assert_eq!(calls, [(2, 3), (3, 3)]);
+    }
+
+    #[test]
+    fn test_num_threads() {
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()]]).unwrap();
+        let paths = vec!["sample_texts/hello.txt".to_string(); 64];
+        let options = SearchOptions {
+            num_threads: Some(2),
+            ..Default::default()
+        };
+        let (results, report) = search_text_report(&query_group, &paths, &options, |_| ());
+        assert_eq!(results.len(), 64);
+        assert!(report.thread_utilization.keys().all(|&thread| thread < 2));
+    }
+
+    #[test]
+    fn test_minimum_should_match() {
+        let atoms = vec![vec!["gpu".to_string(), "cuda".to_string(), "kernel".to_string()], vec!["speed".to_string()]];
+        let query_group = QueryGroup::new(atoms).unwrap().minimum_should_match(vec![2, 1]).unwrap();
+        assert!(is_match_str(&query_group, "cuda kernel speed"));
+        assert!(!is_match_str(&query_group, "gpu speed, gpu again"));
+        assert!(is_match_str(&query_group.and_with(&QueryGroup::new(vec![vec!["fast".to_string()]]).unwrap()), "fast gpu cuda speed"));
+        let refined = query_group.add_or_atom(0, "tensor".to_string()).unwrap();
+        assert!(is_match_str(&refined, "gpu tensor speed"));
+        assert!(QueryGroup::new(vec![vec!["a".to_string()]]).unwrap().minimum_should_match(vec![2]).is_err());
+        assert!(QueryGroup::new(vec![vec!["a".to_string()]]).unwrap().minimum_should_match(vec![1, 1]).is_err());
+    }
+
+    #[test]
+    fn test_pyclasses_are_thread_safe() {
+        // shared by the threads of a free-threaded interpreter, classes must be Sync as well
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<QueryGroup>();
+        assert_send_sync::<FileMatchResult>();
+        assert_send_sync::<FilePaths>();
+        assert_send_sync::<crate::report::SearchReport>();
+        assert_send_sync::<ResultCache>();
+        assert_send_sync::<crate::aggregate::DirectoryGroup>();
+        assert_send_sync::<crate::aggregate::AlternativeGroup>();
+        assert_send_sync::<crate::dedup::CollapsedResult>();
+        assert_send_sync::<crate::diff::ResultDiff>();
+        assert_send_sync::<crate::explain::Explanation>();
+        assert_send_sync::<crate::extract::Extraction>();
+        assert_send_sync::<crate::groups::GroupMatch>();
+        assert_send_sync::<crate::logs::LogEntry>();
+        assert_send_sync::<crate::normalize::PyBuiltinNormalizer>();
+        assert_send_sync::<crate::normalize::PyPipeline>();
+        assert_send_sync::<crate::query::PyQuery>();
+        assert_send_sync::<crate::records::RecordMatch>();
+        assert_send_sync::<crate::replace::FileEdit>();
+        assert_send_sync::<crate::score::PyCandidate>();
+        assert_send_sync::<crate::spill::SpilledResults>();
+        assert_send_sync::<crate::spill::PySpilledIter>();
+        assert_send_sync::<crate::lazy::PySearchStream>();
+        assert_send_sync::<crate::watch::PyWatch>();
+        assert_send_sync::<crate::streams::DocumentSet>();
+        assert_send_sync::<crate::asyncio::PyAsyncSearchStream>();
+        assert_send_sync::<crate::suite::PyQuerySpec>();
+        assert_send_sync::<crate::suite::SuiteQuery>();
+        assert_send_sync::<crate::typeahead::PyTypeAhead>();
+    }
+
+    #[test]
+    fn test_profile() {
+        let query_group = QueryGroup::new(vec![vec!["o".to_string()], vec!["zzz".to_string()]]).unwrap();
+        let paths: Vec<_> = ["hello.txt", "world.txt", "gbk.txt"].iter().map(|name| format!("sample_texts/{}", name)).collect();
+        let options = SearchOptions {
+            profile: Some(2),
+            ..Default::default()
+        };
+        let (_, report) = search_text_report(&query_group, &paths, &options, |_| ());
+        assert_eq!(report.slowest_files.len(), 2);
+        assert!(report.slowest_files[0].1 >= report.slowest_files[1].1);
+        assert_eq!(report.pattern_times.len(), 2);
+        assert_eq!(report.pattern_matches, vec![2, 0]);
+        let (_, report) = search_text_report(&query_group, &paths, &SearchOptions::default(), |_| ());
+        assert!(report.slowest_files.is_empty() && report.pattern_times.is_empty() && report.pattern_matches.is_empty());
+    }
+
+    #[test]
+    fn test_head_bytes() {
+        let options = SearchOptions {
+            head_bytes: Some(5),
+            encoding_hints: EncodingHints::new(&[("sample_texts/gbk*".to_string(), "gbk".to_string())]).unwrap(),
+            ..Default::default()
+        };
+        let matches = |atom: &str, path: &str| !search_text_with(&QueryGroup::new(vec![vec![atom.to_string()]]).unwrap(), &[path.to_string()], &options).is_empty();
+        assert!(matches("foo", "sample_texts/hello.txt"));
+        assert!(!matches("baz", "sample_texts/hello.txt"));
+        // the third character is cut short, and left out rather than replaced
+        assert!(matches("这是", "sample_texts/gbk.txt"));
+        assert!(!matches("\u{fffd}", "sample_texts/gbk.txt"));
+        let cut = "ab\u{e9}".as_bytes()[..3].to_vec();
+        assert_eq!(crate::encoding::decode_head("-", cut, &EncodingHints::default(), 3).unwrap(), "ab");
+    }
+
+    #[test]
+    fn test_tail_bytes() {
+        let options = SearchOptions {
+            tail_bytes: Some(4),
+            ..Default::default()
+        };
+        let matches = |atom: &str| !search_text_with(&QueryGroup::new(vec![vec![atom.to_string()]]).unwrap(), &["sample_texts/hello.txt".to_string()], &options).is_empty();
+        assert!(matches("baz"));
+        assert!(!matches("foo"));
+        let hints = EncodingHints::default();
+        // the first character is cut short, and left out rather than replaced
+        assert_eq!(crate::encoding::decode_tail("-", "\u{e9}ab".as_bytes()[1..].to_vec(), &hints, 1).unwrap(), "ab");
+        let gbk = EncodingHints::new(&[("sample_texts/gbk*".to_string(), "gbk".to_string())]).unwrap();
+        assert_eq!(crate::encoding::read_tail("sample_texts/gbk.txt", &gbk, 4).unwrap(), "");
+        let utf16 = EncodingHints::new(&[("*.utf16".to_string(), "utf-16le".to_string())]).unwrap();
+        let bytes: Vec<u8> = "a\u{1f600}b".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(crate::encoding::decode_tail("a.utf16", bytes[3..].to_vec(), &utf16, 3).unwrap(), "b");
+    }
+
+    #[test]
+    fn test_match_position() {
+        let query_group = QueryGroup::new(vec![vec!["gamma".to_string()], vec!["alpha".to_string()]]).unwrap();
+        let contents = "alpha\nbéta gamma\n";
+        let position = |options: &SearchOptions| match_contents(&query_group, "-", contents, options).unwrap().0.position();
+        let expected = MatchPosition {
+            line: 2,
+            column: 6,
+            span: (12, 17),
+        };
+        assert_eq!(position(&SearchOptions::default()), Some(expected));
+        let context = SearchOptions {
+            context: Some(ContextOptions::default()),
+            ..Default::default()
+        };
+        assert_eq!(position(&context), Some(expected));
     }
 }
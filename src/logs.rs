@@ -0,0 +1,152 @@
+//! Searches of log files restricted to a window of time, told by the timestamp leading each
+//! entry.
+
+use chrono::NaiveDateTime;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use crate::{match_contents, scan_files, QueryGroup, SearchOptions};
+
+/// The `chrono` formats of the timestamps tried when none are given: ISO 8601, Python's
+/// `logging`, and the common log format of web servers.
+pub const DEFAULT_FORMATS: [&str; 4] = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S,%3f", "%Y-%m-%d %H:%M:%S%.f", "%d/%b/%Y:%H:%M:%S"];
+
+/// An entry of a log file matching a query.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogEntry {
+    #[pyo3(get)]
+    pub path: String,
+
+    /// Index of the first line of the entry in the file, from 0.
+    #[pyo3(get)]
+    pub line: usize,
+
+    /// The timestamp leading the entry, or `None` for the lines before the first timestamp.
+    #[pyo3(get)]
+    pub timestamp: Option<NaiveDateTime>,
+
+    /// The lines of the entry.
+    #[pyo3(get)]
+    pub text: String,
+}
+
+#[pymethods]
+impl LogEntry {
+    fn __repr__(&self) -> String {
+        format!("LogEntry(path={:?}, line={}, timestamp={:?})", self.path, self.line, self.timestamp)
+    }
+}
+
+/// How the entries of log files are told apart and which are matched.
+#[derive(Clone, Debug, Default)]
+pub struct LogOptions {
+    /// The formats of the timestamps, tried in order, by default `DEFAULT_FORMATS`.
+    pub formats: Vec<String>,
+    /// Match only the entries from this time on.
+    pub since: Option<NaiveDateTime>,
+    /// Match only the entries before this time.
+    pub until: Option<NaiveDateTime>,
+}
+
+impl LogOptions {
+    pub fn new(formats: Vec<String>, since: Option<NaiveDateTime>, until: Option<NaiveDateTime>) -> PyResult<Self> {
+        if let (Some(since), Some(until)) = (since, until) {
+            if since > until {
+                return Err(PyValueError::new_err(format!("the window ends at {} before it starts at {}", until, since)));
+            }
+        }
+        Ok(LogOptions {
+            formats,
+            since,
+            until,
+        })
+    }
+
+    /// The timestamp leading `line`, possibly in brackets.
+    fn timestamp(&self, line: &str) -> Option<NaiveDateTime> {
+        let line = line.trim_start().trim_start_matches('[');
+        let parse = |format: &str| NaiveDateTime::parse_and_remainder(line, format).ok().map(|(timestamp, _)| timestamp);
+        match self.formats.is_empty() {
+            true => DEFAULT_FORMATS.iter().find_map(|format| parse(format)),
+            false => self.formats.iter().find_map(|format| parse(format)),
+        }
+    }
+
+    fn in_window(&self, timestamp: Option<NaiveDateTime>) -> bool {
+        match timestamp {
+            None => self.since.is_none() && self.until.is_none(),
+            Some(timestamp) => self.since.is_none_or(|since| timestamp >= since) && self.until.is_none_or(|until| timestamp < until),
+        }
+    }
+}
+
+/// The entries of `contents`: each line with a timestamp, followed by the lines without one,
+/// e.g. of a stack trace, as (index of the first line, timestamp, text).
+fn entries(contents: &str, log_options: &LogOptions) -> Vec<(usize, Option<NaiveDateTime>, String)> {
+    let mut entries: Vec<(usize, Option<NaiveDateTime>, String)> = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        match (log_options.timestamp(line), entries.last_mut()) {
+            (None, Some((_, _, text))) => {
+                text.push('\n');
+                text.push_str(line);
+            }
+            (timestamp, _) => entries.push((i, timestamp, line.to_string())),
+        }
+    }
+    entries
+}
+
+fn search_log_file(query_group: &QueryGroup, path: &str, contents: &str, options: &SearchOptions, log_options: &LogOptions) -> Vec<LogEntry> {
+    entries(contents, log_options)
+        .into_iter()
+        .filter(|(_, timestamp, text)| log_options.in_window(*timestamp) && match_contents(query_group, path, text, options).is_some())
+        .map(|(line, timestamp, text)| LogEntry {
+            path: path.to_string(),
+            line,
+            timestamp,
+            text,
+        })
+        .collect()
+}
+
+/// The entries of the log files, within the window of time of `log_options`, matching
+/// `query_group`, each entry matched on its own, in the order of the files and of their entries.
+pub fn search_logs(query_group: &QueryGroup, paths: &[String], options: &SearchOptions, log_options: &LogOptions) -> Vec<LogEntry> {
+    scan_files(paths, options, |path, contents| search_log_file(query_group, path, contents, options, log_options)).0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use chrono::NaiveDate;
+    use crate::{QueryGroup, SearchOptions};
+    use super::{search_logs, LogOptions};
+
+    #[test]
+    fn test_search_logs() {
+        let path = std::env::temp_dir().join(format!("textsearcher-logs-{}.log", std::process::id()));
+        fs::write(&path, concat!(
+            "starting\n",
+            "2024-03-01 09:59:58,120 ERROR disk full\n",
+            "2024-03-01 10:00:01,500 ERROR request failed\n",
+            "Traceback: disk full\n",
+            "[2024-03-01T10:05:00Z] INFO disk cleaned\n",
+        )).unwrap();
+        let paths = vec![path.to_string_lossy().into_owned()];
+        let query_group = QueryGroup::new(vec![vec!["disk".to_string()]]).unwrap();
+        let day = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let window = LogOptions::new(Vec::new(), day.and_hms_opt(10, 0, 0), day.and_hms_opt(10, 5, 0)).unwrap();
+        let entries = search_logs(&query_group, &paths, &SearchOptions::default(), &window);
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].line, entries[0].timestamp), (2, day.and_hms_milli_opt(10, 0, 1, 500)));
+        assert_eq!(entries[0].text, "2024-03-01 10:00:01,500 ERROR request failed\nTraceback: disk full");
+        let lines: Vec<_> = search_logs(&query_group, &paths, &SearchOptions::default(), &LogOptions::default()).iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![1, 2, 4]);
+
+        let iso = LogOptions::new(vec!["%Y-%m-%dT%H:%M:%S".to_string()], None, None).unwrap();
+        let timestamps: Vec<_> = search_logs(&query_group, &paths, &SearchOptions::default(), &iso).iter().map(|e| e.timestamp).collect();
+        assert_eq!(timestamps, vec![None, day.and_hms_opt(10, 5, 0)]);
+        assert!(LogOptions::new(Vec::new(), day.and_hms_opt(1, 0, 0), day.and_hms_opt(0, 0, 0)).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
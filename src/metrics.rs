@@ -0,0 +1,219 @@
+//! Process-wide counters of the searches done and of the lookups of result caches, in the
+//! Prometheus text format, served on `/metrics` by `serve` or rendered by `render` for services
+//! embedding the module to serve on their own endpoint.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::thread;
+use crate::report::SearchReport;
+
+/// Upper bounds, in seconds, of the buckets of the search duration histogram.
+const DURATION_BUCKETS: [f64; 12] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 60.0];
+
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics::new());
+
+struct Metrics {
+    searches: u64,
+    searches_timed_out: u64,
+    files_scanned: u64,
+    files_cached: u64,
+    files_matched: u64,
+    bytes_read: u64,
+    files_skipped: BTreeMap<String, u64>,
+    cache_hits: u64,
+    cache_misses: u64,
+    /// Number of searches that took at most each bound of `DURATION_BUCKETS`.
+    duration_buckets: [u64; DURATION_BUCKETS.len()],
+    duration_sum: f64,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            searches: 0,
+            searches_timed_out: 0,
+            files_scanned: 0,
+            files_cached: 0,
+            files_matched: 0,
+            bytes_read: 0,
+            files_skipped: BTreeMap::new(),
+            cache_hits: 0,
+            cache_misses: 0,
+            duration_buckets: [0; DURATION_BUCKETS.len()],
+            duration_sum: 0.0,
+        }
+    }
+
+    fn record(&mut self, report: &SearchReport) {
+        self.searches += 1;
+        self.searches_timed_out += report.timed_out as u64;
+        self.files_scanned += report.files_scanned as u64;
+        self.files_cached += report.files_cached as u64;
+        self.files_matched += report.files_matched as u64;
+        self.bytes_read += report.bytes_read;
+        for (reason, n) in report.files_skipped.iter() {
+            *self.files_skipped.entry(reason.clone()).or_default() += *n as u64;
+        }
+        for (bound, count) in DURATION_BUCKETS.iter().zip(self.duration_buckets.iter_mut()) {
+            if report.wall_time <= *bound {
+                *count += 1;
+            }
+        }
+        self.duration_sum += report.wall_time;
+    }
+
+    fn render(&self) -> String {
+        let mut text = String::new();
+        let mut counter = |name: &str, help: &str, value: u64| {
+            let _ = writeln!(text, "# HELP textsearcher_{name} {help}\n# TYPE textsearcher_{name} counter\ntextsearcher_{name} {value}");
+        };
+        counter("searches_total", "Searches done.", self.searches);
+        counter("searches_timed_out_total", "Searches whose deadline passed before all the files were searched.", self.searches_timed_out);
+        counter("files_scanned_total", "Files read and matched against a query.", self.files_scanned);
+        counter("files_cached_total", "Files whose outcome was taken from a result cache.", self.files_cached);
+        counter("files_matched_total", "Files matching a query.", self.files_matched);
+        counter("bytes_read_total", "Size of the text of the files scanned.", self.bytes_read);
+        counter("cache_hits_total", "Lookups of a result cache finding the outcome of an unchanged file.", self.cache_hits);
+        counter("cache_misses_total", "Lookups of a result cache finding no outcome, or that of a changed file.", self.cache_misses);
+        let _ = writeln!(text, "# HELP textsearcher_files_skipped_total Files that couldn't be searched, by reason.");
+        let _ = writeln!(text, "# TYPE textsearcher_files_skipped_total counter");
+        for (reason, n) in self.files_skipped.iter() {
+            let _ = writeln!(text, "textsearcher_files_skipped_total{{reason=\"{reason}\"}} {n}");
+        }
+        let _ = writeln!(text, "# HELP textsearcher_search_duration_seconds Wall time of searches.");
+        let _ = writeln!(text, "# TYPE textsearcher_search_duration_seconds histogram");
+        for (bound, count) in DURATION_BUCKETS.iter().zip(self.duration_buckets.iter()) {
+            let _ = writeln!(text, "textsearcher_search_duration_seconds_bucket{{le=\"{bound}\"}} {count}");
+        }
+        let _ = writeln!(text, "textsearcher_search_duration_seconds_bucket{{le=\"+Inf\"}} {}", self.searches);
+        let _ = writeln!(text, "textsearcher_search_duration_seconds_sum {}", self.duration_sum);
+        let _ = writeln!(text, "textsearcher_search_duration_seconds_count {}", self.searches);
+        text
+    }
+}
+
+/// Count a finished search.
+pub(crate) fn record(report: &SearchReport) {
+    METRICS.lock().unwrap().record(report);
+}
+
+/// Count a lookup of a result cache.
+pub(crate) fn record_cache_lookup(hit: bool) {
+    let mut metrics = METRICS.lock().unwrap();
+    match hit {
+        true => metrics.cache_hits += 1,
+        false => metrics.cache_misses += 1,
+    }
+}
+
+/// The counters of all the searches done so far, in the Prometheus text exposition format.
+pub fn render() -> String {
+    METRICS.lock().unwrap().render()
+}
+
+/// Serve `render` on `GET /metrics` at `address`, e.g. "127.0.0.1:9184" or port 0 for any, from
+/// a background thread for the rest of the process, returning the address bound.
+pub fn serve<A: ToSocketAddrs>(address: A) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(address)?;
+    let address = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // a client going away is its problem, not the server's
+            let _ = respond(stream);
+        }
+    });
+    Ok(address)
+}
+
+/// Answer the HTTP request on `stream`, closing it after.
+fn respond(mut stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // the headers, not needed
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let (status, body) = match request.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+        ["GET", "/metrics"] => ("200 OK", render()),
+        ["GET", _] => ("404 Not Found", String::from("not found\n")),
+        _ => ("405 Method Not Allowed", String::from("method not allowed\n")),
+    };
+    write!(stream, "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len())?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use crate::cache::{Fingerprint, ResultCache};
+    use crate::report::SearchReport;
+    use crate::streams::search_contents;
+    use crate::{search_any, QueryGroup, SearchOptions};
+    use super::{render, serve, Metrics};
+
+    #[test]
+    fn test_render() {
+        let mut metrics = Metrics::new();
+        metrics.record(&SearchReport {
+            files_scanned: 3,
+            files_cached: 1,
+            files_skipped: HashMap::from([("not_found".to_string(), 2)]),
+            wall_time: 0.2,
+            ..Default::default()
+        });
+        metrics.record(&SearchReport {
+            files_scanned: 2,
+            wall_time: 3.0,
+            ..Default::default()
+        });
+        let text = metrics.render();
+        assert!(text.contains("\ntextsearcher_searches_total 2\n"));
+        assert!(text.contains("\ntextsearcher_files_scanned_total 5\n"));
+        assert!(text.contains("\ntextsearcher_files_skipped_total{reason=\"not_found\"} 2\n"));
+        assert!(text.contains("\ntextsearcher_search_duration_seconds_bucket{le=\"0.25\"} 1\n"));
+        assert!(text.contains("\ntextsearcher_search_duration_seconds_bucket{le=\"5\"} 2\n"));
+        assert!(text.contains("\ntextsearcher_search_duration_seconds_sum 3.2\n"));
+    }
+
+    #[test]
+    fn test_recorded() {
+        // other tests search meanwhile, so the counters only tell a lower bound
+        let counter = |name: &str| {
+            let text = render();
+            let line = text.lines().find(|line| line.starts_with(&format!("textsearcher_{} ", name))).unwrap();
+            line.rsplit(' ').next().unwrap().parse::<u64>().unwrap()
+        };
+        let (searches, misses) = (counter("searches_total"), counter("cache_misses_total"));
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string()]]).unwrap();
+        search_contents(&query_group, &[("a".to_string(), "foo".to_string())], &SearchOptions::default());
+        search_any(&[query_group], &[String::from("sample_texts/hello.txt")], &SearchOptions::default());
+        assert!(counter("searches_total") >= searches + 2);
+        let cache = ResultCache::open(std::env::temp_dir().join(format!("textsearcher-metrics-cache-{}.jsonl", std::process::id()))).unwrap();
+        cache.get(0, "sample_texts/hello.txt", Fingerprint::of("sample_texts/hello.txt").unwrap());
+        assert!(counter("cache_misses_total") > misses);
+    }
+
+    #[test]
+    fn test_serve() {
+        let address = serve("127.0.0.1:0").unwrap();
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\r\n\r\n# HELP textsearcher_searches_total "));
+        assert!(response.contains("\ntextsearcher_cache_hits_total "));
+        assert!(get("/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
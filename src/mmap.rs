@@ -0,0 +1,110 @@
+//! Large files mapped into memory rather than copied into a string, e.g. for corpora on SSDs
+//! where copying the contents dominates the search. The mapped bytes are matched in place once
+//! found to be UTF-8; files that can't be mapped, or aren't UTF-8, are read as usual.
+//!
+//! A mapping isn't a snapshot: a file written while it's matched changes under the search, and
+//! one truncated raises SIGBUS on touching the pages past its end, which crashes the process.
+//! Files changed while they were matched are told by their fingerprint and read again instead,
+//! but nothing guards against truncation, so corpora being written mustn't be searched this way.
+
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use crate::cache::Fingerprint;
+
+/// The contents of a file, mapped read-only into memory.
+pub(crate) struct Mmap {
+    ptr: *mut std::ffi::c_void,
+    len: usize,
+}
+
+// the mapping is read-only and owned by the value
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// Map the whole of `file`, which mustn't be empty.
+    #[cfg(unix)]
+    pub(crate) fn map(file: &File) -> io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+        let len = usize::try_from(file.metadata()?.len()).map_err(|_| io::Error::new(io::ErrorKind::FileTooLarge, "file too large to map"))?;
+        // SAFETY: a private read-only mapping of a valid descriptor, checked for failure
+        let ptr = unsafe { libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0) };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mmap {
+            ptr,
+            len,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn map(_file: &File) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "memory-mapped files are only supported on Unix"))
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the mapping is `len` bytes long and lives as long as `self`
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        // SAFETY: unmapping the mapping made by `map`, which nothing borrows anymore
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+/// The file at `path` mapped into memory, if it's at least `threshold` bytes long and can be,
+/// with its fingerprint when mapped.
+pub(crate) fn map_large(path: &str, threshold: u64) -> Option<(Mmap, Fingerprint)> {
+    let file = File::open(path).ok()?;
+    let fingerprint = Fingerprint::of_metadata(&file.metadata().ok()?).ok()?;
+    // an empty file can't be mapped
+    if fingerprint.size < threshold.max(1) {
+        return None;
+    }
+    Some((Mmap::map(&file).ok()?, fingerprint))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::{search_text_report, QueryGroup, SearchOptions};
+    use super::map_large;
+
+    #[test]
+    fn test_mmap() {
+        let path = std::env::temp_dir().join(format!("textsearcher-mmap-{}.txt", std::process::id()));
+        fs::write(&path, format!("{}foo caf\u{e9}", "bar ".repeat(1000))).unwrap();
+        let path_str = path.to_str().unwrap().to_string();
+        assert!(map_large(&path_str, 1 << 20).is_none());
+        let (mapped, fingerprint) = map_large(&path_str, 0).unwrap();
+        assert!(mapped.ends_with("café".as_bytes()));
+        assert_eq!(fingerprint.size, mapped.len() as u64);
+        drop(mapped);
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string()], vec!["café".to_string()]]).unwrap();
+        let search = |mmap_threshold: Option<u64>| {
+            let options = SearchOptions {
+                mmap_threshold,
+                ..Default::default()
+            };
+            let (results, report) = search_text_report(&query_group, &[path_str.clone(), "sample_texts/gbk.txt".to_string()], &options, |_| ());
+            (results.len(), report.bytes_read, report.files_skipped)
+        };
+        // the same outcome as reading the files, the file that isn't UTF-8 read and skipped
+        assert_eq!(search(Some(0)), search(None));
+        fs::write(&path, b"foo caf\xc3\xa9\0").unwrap();
+        assert_eq!(search(Some(0)).2.get("binary"), Some(&1));
+        fs::remove_file(&path).unwrap();
+    }
+}
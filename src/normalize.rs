@@ -0,0 +1,706 @@
+//! Cleanup of file contents before matching, e.g. stripping boilerplate or fixing OCR quirks.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use regex::Regex;
+use serde_json::Value;
+use unicode_normalization::{is_nfkc_quick, IsNormalized, UnicodeNormalization};
+
+/// A transform of the contents of every file before it's matched. Contexts are extracted from
+/// the normalized contents.
+pub trait Normalizer: fmt::Debug + Send + Sync {
+    /// Identifies the normalizer in the keys of `ResultCache`, so it must change whenever the
+    /// behavior of the normalizer does.
+    fn name(&self) -> String;
+
+    fn normalize<'a>(&self, contents: &'a str) -> Cow<'a, str>;
+}
+
+/// Apply `normalizers` in order, without copying `contents` if none of them changes it.
+pub fn normalize<'a>(normalizers: &[Arc<dyn Normalizer>], contents: &'a str) -> Cow<'a, str> {
+    let mut contents = Cow::Borrowed(contents);
+    for normalizer in normalizers.iter() {
+        if let Cow::Owned(normalized) = normalizer.normalize(&contents) {
+            contents = Cow::Owned(normalized);
+        }
+    }
+    contents
+}
+
+/// Join the words hyphenated across line breaks, as is common in text parsed from PDFs, e.g.
+/// `experi-\nment` becomes `experiment`.
+#[derive(Debug)]
+pub struct Dehyphenate;
+
+impl Normalizer for Dehyphenate {
+    fn name(&self) -> String {
+        "dehyphenate".to_string()
+    }
+
+    fn normalize<'a>(&self, contents: &'a str) -> Cow<'a, str> {
+        static HYPHENATED: OnceLock<Regex> = OnceLock::new();
+        HYPHENATED
+            .get_or_init(|| Regex::new(r"(\p{L})-[ \t]*\r?\n[ \t]*(\p{Ll})").unwrap())
+            .replace_all(contents, "$1$2")
+    }
+}
+
+/// Remove the lines matching a regex, e.g. running headers like `Page 3 of 10`.
+#[derive(Debug)]
+pub struct StripLines {
+    pattern: Regex,
+}
+
+impl StripLines {
+    pub fn new(pattern: &str) -> PyResult<Self> {
+        let pattern = Regex::new(&format!("(?m)^(?:{})$\n?", pattern))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(StripLines {
+            pattern,
+        })
+    }
+}
+
+impl Normalizer for StripLines {
+    fn name(&self) -> String {
+        format!("strip_lines:{}", self.pattern.as_str())
+    }
+
+    fn normalize<'a>(&self, contents: &'a str) -> Cow<'a, str> {
+        self.pattern.replace_all(contents, "")
+    }
+}
+
+/// Turn `\r\n` and lone `\r` line breaks into `\n`, so that patterns anchored on line ends
+/// work on files from other platforms and contexts don't carry the `\r`.
+#[derive(Debug)]
+pub struct NormalizeNewlines;
+
+impl Normalizer for NormalizeNewlines {
+    fn name(&self) -> String {
+        "newlines".to_string()
+    }
+
+    fn normalize<'a>(&self, contents: &'a str) -> Cow<'a, str> {
+        if !contents.contains('\r') {
+            return Cow::Borrowed(contents);
+        }
+        Cow::Owned(contents.replace("\r\n", "\n").replace('\r', "\n"))
+    }
+}
+
+/// Replace the C0 control characters other than `\n` and `\t`, e.g. the form feeds output by
+/// `pdftotext`, with `replacement`, typically nothing or a space. `\r` is replaced too, so
+/// `NormalizeNewlines` should come first for files with lone `\r` line breaks.
+#[derive(Debug)]
+pub struct StripControls {
+    replacement: String,
+}
+
+impl StripControls {
+    pub fn new(replacement: &str) -> Self {
+        StripControls {
+            replacement: replacement.to_string(),
+        }
+    }
+}
+
+impl Normalizer for StripControls {
+    fn name(&self) -> String {
+        format!("strip_controls:{}", self.replacement)
+    }
+
+    fn normalize<'a>(&self, contents: &'a str) -> Cow<'a, str> {
+        let is_stripped = |ch: char| ch.is_ascii_control() && ch != '\n' && ch != '\t' && ch != '\x7f';
+        if !contents.contains(is_stripped) {
+            return Cow::Borrowed(contents);
+        }
+        Cow::Owned(contents.replace(is_stripped, &self.replacement))
+    }
+}
+
+/// Apply the NFKC Unicode normalization, which e.g. turns the ligature `ﬁ` into `fi` and
+/// full-width `Ａ１` into `A1`.
+#[derive(Debug)]
+pub struct Nfkc;
+
+impl Normalizer for Nfkc {
+    fn name(&self) -> String {
+        "nfkc".to_string()
+    }
+
+    fn normalize<'a>(&self, contents: &'a str) -> Cow<'a, str> {
+        if is_nfkc_quick(contents.chars()) == IsNormalized::Yes {
+            return Cow::Borrowed(contents);
+        }
+        Cow::Owned(contents.nfkc().collect())
+    }
+}
+
+/// Apply full Unicode case folding, which unlike the simple folding of case-insensitive regexes
+/// e.g. folds `ß` and `ẞ` into `ss` and `ﬁ` into `fi`. With `turkic`, the dotted and dotless i
+/// of Turkish and Azerbaijani fold as in those languages, i.e. `İ` to `i` and `I` to `ı`.
+/// Atoms are folded alike with the `case_folding` option of `QueryOptions`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CaseFold {
+    pub turkic: bool,
+}
+
+impl CaseFold {
+    /// The folding named `full` or `turkic`.
+    pub fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "full" => Ok(CaseFold { turkic: false }),
+            "turkic" => Ok(CaseFold { turkic: true }),
+            _ => Err(PyValueError::new_err(format!("unknown case folding {:?}, expected \"full\" or \"turkic\"", name))),
+        }
+    }
+
+    pub fn fold(&self, text: &str) -> String {
+        if self.turkic {
+            let text: String = text.chars().map(|ch| match ch {
+                'I' => 'ı',
+                'İ' => 'i',
+                ch => ch,
+            }).collect();
+            return caseless::default_case_fold_str(&text);
+        }
+        caseless::default_case_fold_str(text)
+    }
+}
+
+impl Normalizer for CaseFold {
+    fn name(&self) -> String {
+        if self.turkic { "case_fold:turkic" } else { "case_fold" }.to_string()
+    }
+
+    fn normalize<'a>(&self, contents: &'a str) -> Cow<'a, str> {
+        let folded = self.fold(contents);
+        if folded == contents {
+            return Cow::Borrowed(contents);
+        }
+        Cow::Owned(folded)
+    }
+}
+
+/// Keep only the values of some fields of JSON documents, selected by JSON pointers (e.g.
+/// `/title` or `/authors/0/name`), one per line, so that the query only matches those. Arrays and
+/// objects stand for all the strings and numbers in them. Contents that aren't JSON, and fields
+/// that are missing, leave nothing to match.
+#[derive(Debug)]
+pub struct JsonFields {
+    pointers: Vec<String>,
+}
+
+impl JsonFields {
+    pub fn new(pointers: Vec<String>) -> PyResult<Self> {
+        if let Some(pointer) = pointers.iter().find(|pointer| !pointer.is_empty() && !pointer.starts_with('/')) {
+            return Err(PyValueError::new_err(format!("{:?} isn't a JSON pointer, which starts with /", pointer)));
+        }
+        Ok(JsonFields {
+            pointers,
+        })
+    }
+}
+
+/// The strings and numbers in `value`, those of objects by the names of their fields.
+fn push_json_text(value: &Value, lines: &mut Vec<String>) {
+    match value {
+        Value::Null => (),
+        Value::String(text) => lines.push(text.clone()),
+        Value::Array(values) => values.iter().for_each(|value| push_json_text(value, lines)),
+        Value::Object(fields) => fields.values().for_each(|value| push_json_text(value, lines)),
+        scalar => lines.push(scalar.to_string()),
+    }
+}
+
+impl Normalizer for JsonFields {
+    fn name(&self) -> String {
+        format!("json_fields:{}", self.pointers.join(","))
+    }
+
+    fn normalize<'a>(&self, contents: &'a str) -> Cow<'a, str> {
+        let Ok(document) = serde_json::from_str::<Value>(contents) else {
+            return Cow::Borrowed("");
+        };
+        let mut lines = Vec::new();
+        for value in self.pointers.iter().filter_map(|pointer| document.pointer(pointer)) {
+            push_json_text(value, &mut lines);
+        }
+        Cow::Owned(lines.join("\n"))
+    }
+}
+
+/// Keep only the text of the elements of HTML documents that a CSS selector selects (e.g.
+/// `article p`), one per line, so that e.g. the navigation of saved web pages isn't matched. XML
+/// documents are parsed like HTML, so tag names are matched regardless of case.
+#[derive(Debug)]
+pub struct Elements {
+    source: String,
+    #[cfg(feature = "html")]
+    selector: scraper::Selector,
+}
+
+impl Elements {
+    #[cfg(feature = "html")]
+    pub fn new(selector: &str) -> PyResult<Self> {
+        let parsed = scraper::Selector::parse(selector).map_err(|e| PyValueError::new_err(format!("invalid selector {:?}: {}", selector, e)))?;
+        Ok(Elements {
+            source: selector.to_string(),
+            selector: parsed,
+        })
+    }
+
+    #[cfg(not(feature = "html"))]
+    pub fn new(_selector: &str) -> PyResult<Self> {
+        Err(PyValueError::new_err("the elements normalizer requires textsearcher to be built with the html feature"))
+    }
+}
+
+impl Normalizer for Elements {
+    fn name(&self) -> String {
+        format!("elements:{}", self.source)
+    }
+
+    #[cfg(feature = "html")]
+    fn normalize<'a>(&self, contents: &'a str) -> Cow<'a, str> {
+        let document = scraper::Html::parse_document(contents);
+        let texts: Vec<String> = document.select(&self.selector).map(|element| element.text().collect()).collect();
+        Cow::Owned(texts.join("\n"))
+    }
+
+    #[cfg(not(feature = "html"))]
+    fn normalize<'a>(&self, contents: &'a str) -> Cow<'a, str> {
+        Cow::Borrowed(contents)
+    }
+}
+
+/// Normalizers applied in order, built step by step, e.g.
+/// `Pipeline::new().dehyphenate().strip_controls("").nfkc()`. Convert it into
+/// `SearchOptions::normalizers` to search with it.
+#[derive(Clone, Debug, Default)]
+pub struct Pipeline {
+    steps: Vec<Arc<dyn Normalizer>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    pub fn then<N: Normalizer + 'static>(mut self, normalizer: N) -> Self {
+        self.steps.push(Arc::new(normalizer));
+        self
+    }
+
+    pub fn dehyphenate(self) -> Self {
+        self.then(Dehyphenate)
+    }
+
+    pub fn newlines(self) -> Self {
+        self.then(NormalizeNewlines)
+    }
+
+    pub fn strip_controls(self, replacement: &str) -> Self {
+        self.then(StripControls::new(replacement))
+    }
+
+    pub fn strip_lines(self, pattern: &str) -> PyResult<Self> {
+        Ok(self.then(StripLines::new(pattern)?))
+    }
+
+    pub fn nfkc(self) -> Self {
+        self.then(Nfkc)
+    }
+
+    pub fn case_fold(self, turkic: bool) -> Self {
+        self.then(CaseFold { turkic })
+    }
+
+    pub fn json_fields(self, pointers: Vec<String>) -> PyResult<Self> {
+        Ok(self.then(JsonFields::new(pointers)?))
+    }
+
+    pub fn elements(self, selector: &str) -> PyResult<Self> {
+        Ok(self.then(Elements::new(selector)?))
+    }
+
+    pub fn steps(&self) -> &[Arc<dyn Normalizer>] {
+        &self.steps
+    }
+}
+
+impl From<Pipeline> for Vec<Arc<dyn Normalizer>> {
+    fn from(pipeline: Pipeline) -> Self {
+        pipeline.steps
+    }
+}
+
+impl Normalizer for Pipeline {
+    fn name(&self) -> String {
+        let names: Vec<_> = self.steps.iter().map(|step| step.name()).collect();
+        format!("pipeline:[{}]", names.join(","))
+    }
+
+    fn normalize<'a>(&self, contents: &'a str) -> Cow<'a, str> {
+        normalize(&self.steps, contents)
+    }
+}
+
+/// A Python callable taking and returning a str. The first error it raises is kept, to be
+/// re-raised once the search is over; meanwhile the contents are left unchanged.
+#[derive(Debug)]
+pub struct PyNormalizer {
+    callable: PyObject,
+    name: String,
+    error: Mutex<Option<PyErr>>,
+}
+
+impl PyNormalizer {
+    pub fn new(callable: PyObject, name: String) -> Self {
+        PyNormalizer {
+            callable,
+            name,
+            error: Mutex::new(None),
+        }
+    }
+
+    pub fn take_error(&self) -> Option<PyErr> {
+        self.error.lock().unwrap().take()
+    }
+}
+
+impl Normalizer for PyNormalizer {
+    fn name(&self) -> String {
+        format!("python:{}", self.name)
+    }
+
+    fn normalize<'a>(&self, contents: &'a str) -> Cow<'a, str> {
+        let normalized = Python::with_gil(|py| {
+            self.callable
+                .call1(py, (contents,))
+                .and_then(|normalized| normalized.extract::<String>(py))
+        });
+        match normalized {
+            Ok(normalized) => Cow::Owned(normalized),
+            Err(e) => {
+                self.error.lock().unwrap().get_or_insert(e);
+                Cow::Borrowed(contents)
+            }
+        }
+    }
+}
+
+/// A built-in normalizer, to be passed to searches along with Python callables.
+#[pyclass(name = "Normalizer")]
+#[derive(Clone, Debug)]
+pub struct PyBuiltinNormalizer {
+    pub normalizer: Arc<dyn Normalizer>,
+}
+
+#[pymethods]
+impl PyBuiltinNormalizer {
+    #[staticmethod]
+    fn dehyphenate() -> Self {
+        PyBuiltinNormalizer {
+            normalizer: Arc::new(Dehyphenate),
+        }
+    }
+
+    #[staticmethod]
+    fn newlines() -> Self {
+        PyBuiltinNormalizer {
+            normalizer: Arc::new(NormalizeNewlines),
+        }
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (replacement = ""))]
+    fn strip_controls(replacement: &str) -> Self {
+        PyBuiltinNormalizer {
+            normalizer: Arc::new(StripControls::new(replacement)),
+        }
+    }
+
+    #[staticmethod]
+    fn strip_lines(pattern: &str) -> PyResult<Self> {
+        Ok(PyBuiltinNormalizer {
+            normalizer: Arc::new(StripLines::new(pattern)?),
+        })
+    }
+
+    #[staticmethod]
+    fn nfkc() -> Self {
+        PyBuiltinNormalizer {
+            normalizer: Arc::new(Nfkc),
+        }
+    }
+
+    /// Full case folding, to search with the `case_folding` option of the query set alike.
+    #[staticmethod]
+    #[pyo3(signature = (turkic = false))]
+    fn case_fold(turkic: bool) -> Self {
+        PyBuiltinNormalizer {
+            normalizer: Arc::new(CaseFold { turkic }),
+        }
+    }
+
+    /// Keep only the values of the fields of JSON documents selected by the pointers.
+    #[staticmethod]
+    fn json_fields(pointers: Vec<String>) -> PyResult<Self> {
+        Ok(PyBuiltinNormalizer {
+            normalizer: Arc::new(JsonFields::new(pointers)?),
+        })
+    }
+
+    /// Keep only the text of the HTML elements selected by the CSS selector.
+    #[staticmethod]
+    fn elements(selector: &str) -> PyResult<Self> {
+        Ok(PyBuiltinNormalizer {
+            normalizer: Arc::new(Elements::new(selector)?),
+        })
+    }
+
+    #[getter]
+    fn name(&self) -> String {
+        self.normalizer.name()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Normalizer({:?})", self.normalizer.name())
+    }
+}
+
+/// Normalizers, built-in or Python callables, applied in order, e.g.
+/// `Pipeline().dehyphenate().strip_controls().nfkc()`. Each step returns a new pipeline.
+#[pyclass(name = "Pipeline")]
+#[derive(Clone, Default)]
+pub struct PyPipeline {
+    steps: Vec<PyObject>,
+}
+
+impl PyPipeline {
+    fn with(&self, py: Python<'_>, normalizer: Arc<dyn Normalizer>) -> PyResult<Self> {
+        self.then(Py::new(py, PyBuiltinNormalizer {
+            normalizer,
+        })?.into_ref(py))
+    }
+}
+
+#[pymethods]
+impl PyPipeline {
+    #[new]
+    fn py_new() -> Self {
+        PyPipeline::default()
+    }
+
+    /// Append a built-in `Normalizer`, a callable taking and returning a str, or a pipeline.
+    fn then(&self, normalizer: &PyAny) -> PyResult<Self> {
+        if !normalizer.is_instance_of::<PyBuiltinNormalizer>()? && !normalizer.is_instance_of::<PyPipeline>()? && !normalizer.is_callable() {
+            return Err(PyValueError::new_err(format!("not a normalizer: {}", normalizer)));
+        }
+        let mut steps = self.steps.clone();
+        steps.push(normalizer.into());
+        Ok(PyPipeline {
+            steps,
+        })
+    }
+
+    fn dehyphenate(&self, py: Python<'_>) -> PyResult<Self> {
+        self.with(py, Arc::new(Dehyphenate))
+    }
+
+    fn newlines(&self, py: Python<'_>) -> PyResult<Self> {
+        self.with(py, Arc::new(NormalizeNewlines))
+    }
+
+    #[pyo3(signature = (replacement = ""))]
+    fn strip_controls(&self, py: Python<'_>, replacement: &str) -> PyResult<Self> {
+        self.with(py, Arc::new(StripControls::new(replacement)))
+    }
+
+    fn strip_lines(&self, py: Python<'_>, pattern: &str) -> PyResult<Self> {
+        self.with(py, Arc::new(StripLines::new(pattern)?))
+    }
+
+    fn nfkc(&self, py: Python<'_>) -> PyResult<Self> {
+        self.with(py, Arc::new(Nfkc))
+    }
+
+    #[pyo3(signature = (turkic = false))]
+    fn case_fold(&self, py: Python<'_>, turkic: bool) -> PyResult<Self> {
+        self.with(py, Arc::new(CaseFold { turkic }))
+    }
+
+    fn json_fields(&self, py: Python<'_>, pointers: Vec<String>) -> PyResult<Self> {
+        self.with(py, Arc::new(JsonFields::new(pointers)?))
+    }
+
+    fn elements(&self, py: Python<'_>, selector: &str) -> PyResult<Self> {
+        self.with(py, Arc::new(Elements::new(selector)?))
+    }
+
+    fn __len__(&self) -> usize {
+        self.steps.len()
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        let steps: PyResult<Vec<String>> = self.steps.iter().map(|step| Ok(step.as_ref(py).repr()?.to_string())).collect();
+        Ok(format!("Pipeline([{}])", steps?.join(", ")))
+    }
+}
+
+/// Built-in normalizers and Python callables given to a search, the latter kept apart so that
+/// their errors can be re-raised.
+#[derive(Default)]
+pub(crate) struct ExtractedNormalizers {
+    pub(crate) normalizers: Vec<Arc<dyn Normalizer>>,
+    python: Vec<Arc<PyNormalizer>>,
+    /// The names of the Python callables that others may share, e.g. lambdas or functions
+    /// defined within functions.
+    anonymous: Vec<String>,
+}
+
+impl ExtractedNormalizers {
+    /// Extract a list of normalizers, or a `Pipeline`.
+    pub(crate) fn from_py(normalizers: Option<&PyAny>) -> PyResult<Self> {
+        let mut extracted = ExtractedNormalizers::default();
+        if let Some(normalizers) = normalizers {
+            extracted.extend(normalizers.py(), normalizers.extract::<PyPipeline>().map_or_else(|_| normalizers.extract(), |p| Ok(p.steps))?)?;
+        }
+        Ok(extracted)
+    }
+
+    fn extend(&mut self, py: Python<'_>, normalizers: Vec<PyObject>) -> PyResult<()> {
+        for normalizer in normalizers {
+            let normalizer = normalizer.as_ref(py);
+            if let Ok(builtin) = normalizer.extract::<PyBuiltinNormalizer>() {
+                self.normalizers.push(builtin.normalizer);
+            } else if let Ok(pipeline) = normalizer.extract::<PyPipeline>() {
+                self.extend(py, pipeline.steps)?;
+            } else if normalizer.is_callable() {
+                let qualname = normalizer.getattr("__qualname__").and_then(|n| n.extract::<String>());
+                let module = normalizer.getattr("__module__").and_then(|n| n.extract::<String>());
+                let name = match (qualname, module) {
+                    (Ok(qualname), Ok(module)) => format!("{}.{}", module, qualname),
+                    (Ok(qualname), Err(_)) => qualname,
+                    // e.g. an object with a `__call__` method, told by its address
+                    (Err(_), _) => normalizer.to_string(),
+                };
+                // `<lambda>`, `<locals>` and the like
+                if name.contains('<') {
+                    self.anonymous.push(name.clone());
+                }
+                let normalizer = Arc::new(PyNormalizer::new(normalizer.into(), name));
+                self.python.push(normalizer.clone());
+                self.normalizers.push(normalizer);
+            } else {
+                return Err(PyValueError::new_err(format!("not a normalizer: {}", normalizer)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail if a Python callable can't be told apart from others by its name, e.g. a lambda,
+    /// which `purpose`, e.g. a result cache, would take for any other alike.
+    pub(crate) fn check_named(&self, purpose: &str) -> PyResult<()> {
+        match self.anonymous.first() {
+            Some(name) => Err(PyValueError::new_err(format!("the normalizer {} can't be told apart from others by {}; use a function defined at the top level of a module", name, purpose))),
+            None => Ok(()),
+        }
+    }
+
+    /// The first error raised by a Python normalizer, if any.
+    pub(crate) fn take_error(&self) -> Option<PyErr> {
+        self.python.iter().find_map(|normalizer| normalizer.take_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+    use super::{normalize, CaseFold, Dehyphenate, Elements, ExtractedNormalizers, JsonFields, Nfkc, NormalizeNewlines, Normalizer, Pipeline, StripControls, StripLines};
+
+    #[test]
+    fn test_normalize() {
+        let normalizers: Vec<Arc<dyn Normalizer>> = vec![
+            Arc::new(StripLines::new(r"Page \d+ of \d+").unwrap()),
+            Arc::new(Dehyphenate),
+        ];
+        assert_eq!(
+            normalize(&normalizers, "an experi-\nPage 2 of 9\nment on Fourier-\nTransform"),
+            "an experiment on Fourier-\nTransform",
+        );
+        assert_eq!(NormalizeNewlines.normalize("a\r\nb\rc\n\r\n"), "a\nb\nc\n\n");
+        assert!(matches!(NormalizeNewlines.normalize("a\nb"), std::borrow::Cow::Borrowed(_)));
+        assert_eq!(StripControls::new("").normalize("page\x0c\x00one\t\n\x7f"), "pageone\t\n\x7f");
+        assert_eq!(StripControls::new(" ").normalize("a\x0cb"), "a b");
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let pipeline = Pipeline::new().newlines().dehyphenate().strip_controls(" ").nfkc();
+        assert_eq!(pipeline.name(), "pipeline:[newlines,dehyphenate,strip_controls: ,nfkc]");
+        assert_eq!(pipeline.normalize("ﬁne experi-\r\nment\x0cＡ１"), "fine experiment A1");
+        assert_eq!(Nfkc.normalize("plain"), "plain");
+        let normalizers: Vec<Arc<dyn Normalizer>> = pipeline.into();
+        assert_eq!(normalizers.len(), 4);
+    }
+
+    #[test]
+    fn test_python_normalizer_names() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            globals.set_item("__name__", "cleanup").unwrap();
+            py.run("def upper(s):\n    return s.upper()\n\ndef make():\n    def inner(s):\n        return s\n    return inner\n", Some(globals), None).unwrap();
+            let normalizers = |source: &str| ExtractedNormalizers::from_py(Some(py.eval(source, Some(globals), None).unwrap())).unwrap();
+            let named = normalizers("[upper]");
+            assert_eq!(named.normalizers[0].name(), "python:cleanup.upper");
+            assert!(named.check_named("a result cache").is_ok());
+            assert!(normalizers("[upper, lambda s: s]").check_named("a result cache").is_err());
+            assert!(normalizers("[make()]").check_named("an index").is_err());
+        });
+    }
+
+    #[test]
+    fn test_case_fold() {
+        let full = CaseFold { turkic: false };
+        assert_eq!(full.normalize("STRASSE Straße ẞ ﬁne"), "strasse strasse ss fine");
+        assert_eq!(full.normalize("İstanbul ISTANBUL"), "i\u{307}stanbul istanbul");
+        assert_eq!(CaseFold { turkic: true }.normalize("İstanbul ISTANBUL"), "istanbul ıstanbul");
+        assert!(matches!(full.normalize("plain"), std::borrow::Cow::Borrowed(_)));
+        assert!(CaseFold::from_name("simple").is_err());
+    }
+
+    #[test]
+    fn test_json_fields() {
+        let fields = JsonFields::new(vec!["/title".to_string(), "/authors".to_string(), "/missing".to_string()]).unwrap();
+        let document = r#"{"title": "On servers", "authors": [{"name": "Ann", "id": 3}], "body": "clients"}"#;
+        assert_eq!(fields.normalize(document), "On servers\n3\nAnn");
+        assert_eq!(fields.normalize("not json"), "");
+        assert_eq!(fields.name(), "json_fields:/title,/authors,/missing");
+        assert!(JsonFields::new(vec!["title".to_string()]).is_err());
+    }
+
+    #[cfg(feature = "html")]
+    #[test]
+    fn test_elements() {
+        let paragraphs = Elements::new("article p").unwrap();
+        let page = "<nav><p>Home</p></nav><article><h1>Servers</h1><p>A <b>disk</b> full</p><p>Fixed</p></article>";
+        assert_eq!(paragraphs.normalize(page), "A disk full\nFixed");
+        assert_eq!(paragraphs.name(), "elements:article p");
+        assert!(Elements::new("article >").is_err());
+    }
+
+    #[cfg(not(feature = "html"))]
+    #[test]
+    fn test_elements() {
+        assert!(Elements::new("article p").is_err());
+    }
+}
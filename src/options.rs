@@ -0,0 +1,385 @@
+//! The options of the searches from Python: the arguments shared by the functions that search
+//! files, e.g. `search_text(q, files, 100, 100, encodings={'*.txt': 'gbk'})` or
+//! `count_matches(q, files, max_file_size=10_000_000)`, each function taking those of the groups
+//! below that apply to it in its signature and all of them parsed here, and how `search_text`
+//! collects its results.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use crate::normalize::{self, ExtractedNormalizers};
+use crate::progress::{CancelToken, Progress};
+use crate::score::PyScorer;
+use crate::throttle::{MemoryBudget, ReadLimit};
+use crate::{binary, chunked, code, config, configured_encoding_hints, context_options, context_window, paths, presets, ranges, scoring_options, ContextOptions, FilePaths, QueryGroup, SearchOptions};
+
+/// The arguments on how the files are read and matched, which every search takes.
+pub(crate) struct ReadArgs<'py> {
+    pub(crate) encodings: Option<&'py PyDict>,
+    pub(crate) detect_encoding: bool,
+    pub(crate) allowed_encodings: Option<Vec<String>>,
+    pub(crate) normalizers: Option<&'py PyAny>,
+    pub(crate) invert: bool,
+    pub(crate) max_concurrent_reads: Option<usize>,
+    pub(crate) max_file_size: Option<u64>,
+    pub(crate) head_bytes: Option<u64>,
+    pub(crate) tail_bytes: Option<u64>,
+    pub(crate) ranges: Option<HashMap<String, Vec<(usize, usize)>>>,
+    pub(crate) range_unit: &'py str,
+    pub(crate) code: Option<&'py str>,
+    pub(crate) preset: Option<&'py str>,
+    pub(crate) decompress: bool,
+    pub(crate) binary: &'py str,
+}
+
+impl Default for ReadArgs<'_> {
+    fn default() -> Self {
+        ReadArgs {
+            encodings: None,
+            detect_encoding: false,
+            allowed_encodings: None,
+            normalizers: None,
+            invert: false,
+            max_concurrent_reads: None,
+            max_file_size: None,
+            head_bytes: None,
+            tail_bytes: None,
+            ranges: None,
+            range_unit: "bytes",
+            code: None,
+            preset: None,
+            decompress: false,
+            binary: "skip",
+        }
+    }
+}
+
+/// The arguments on the context extracted around the first match.
+#[derive(Default)]
+pub(crate) struct ContextArgs {
+    pub(crate) a: Option<usize>,
+    pub(crate) b: Option<usize>,
+    pub(crate) context_chars: bool,
+    pub(crate) before_lines: Option<usize>,
+    pub(crate) after_lines: Option<usize>,
+    pub(crate) max_context_chars: Option<usize>,
+    pub(crate) scoped: bool,
+    pub(crate) highlight_begin: String,
+    pub(crate) highlight_end: String,
+    pub(crate) highlight_all: bool,
+    pub(crate) ellipsis: bool,
+    pub(crate) snippet: bool,
+    pub(crate) max_contexts: Option<usize>,
+    pub(crate) clause_contexts: bool,
+}
+
+/// The arguments on what the results tell besides the matching file, and how they're ranked.
+#[derive(Default)]
+pub(crate) struct ResultArgs {
+    pub(crate) score: Option<PyObject>,
+    pub(crate) top_k: Option<usize>,
+    pub(crate) detect_language: bool,
+}
+
+/// The arguments on a scan of the files: when it stops, how it's throttled and reported on, and
+/// how large files are read.
+pub(crate) struct ScanArgs<'py> {
+    pub(crate) deadline_ms: Option<u64>,
+    pub(crate) file_timeout_ms: Option<u64>,
+    pub(crate) max_results: Option<usize>,
+    pub(crate) profile: Option<usize>,
+    pub(crate) chunk_bytes: Option<usize>,
+    pub(crate) chunk_overlap: usize,
+    pub(crate) progress: Option<PyObject>,
+    pub(crate) progress_every: usize,
+    pub(crate) cancel: Option<CancelToken>,
+    pub(crate) num_threads: Option<usize>,
+    pub(crate) memory_budget: Option<u64>,
+    pub(crate) mmap_threshold: Option<u64>,
+    pub(crate) dedup_paths: Option<&'py str>,
+}
+
+impl Default for ScanArgs<'_> {
+    fn default() -> Self {
+        ScanArgs {
+            deadline_ms: None,
+            file_timeout_ms: None,
+            max_results: None,
+            profile: None,
+            chunk_bytes: None,
+            chunk_overlap: 1 << 16,
+            progress: None,
+            progress_every: 100,
+            cancel: None,
+            num_threads: None,
+            memory_budget: None,
+            mmap_threshold: None,
+            dedup_paths: None,
+        }
+    }
+}
+
+/// The options given to a search, with the Python callbacks among them kept apart so that their
+/// errors can be re-raised.
+pub(crate) struct SearchKwargs {
+    pub(crate) options: SearchOptions,
+    pub(crate) normalizers: ExtractedNormalizers,
+    scorer: Option<Arc<PyScorer>>,
+    progress_error: Arc<Mutex<Option<PyErr>>>,
+    /// Cancels the search on Ctrl-C, and once a callback raises, whether a token is given.
+    pub(crate) cancel: CancelToken,
+}
+
+impl SearchKwargs {
+    /// Parse the arguments given to a search of `query_group`, if a single one, in `files`, if
+    /// given as `FilePaths`, with the configured defaults. A function passes the default of the
+    /// groups of arguments it doesn't take.
+    pub(crate) fn new(query_group: Option<&QueryGroup>, files: Option<&FilePaths>, read: ReadArgs, context: ContextArgs, results: ResultArgs, scan: ScanArgs) -> PyResult<Self> {
+        if read.head_bytes.is_some() && read.tail_bytes.is_some() {
+            return Err(PyValueError::new_err("head_bytes and tail_bytes can't be combined"));
+        }
+        let config = config::Config::global()?;
+
+        let (a, b, context_chars) = config.window(context.a, context.b, context.context_chars, context.before_lines, context.after_lines);
+        let (a, b, unit) = context_window(a, b, context_chars, context.before_lines, context.after_lines)?;
+        let max_context_chars = context.max_context_chars.or(config.max_context_chars);
+        let context_options = context_options(a, b, unit, max_context_chars, context.highlight_begin, context.highlight_end, context.highlight_all, context.scoped, context.ellipsis)?;
+        if context.max_contexts.is_some() && context_options.is_none() {
+            return Err(PyValueError::new_err("max_contexts requires a and b, or a line context"));
+        }
+        if context.clause_contexts && context_options.is_none() {
+            return Err(PyValueError::new_err("clause_contexts requires a and b, or a line context"));
+        }
+
+        let encoding_hints = match (read.detect_encoding, read.allowed_encodings) {
+            (false, None) => configured_encoding_hints(read.encodings, files, config)?,
+            (_, allowed) => configured_encoding_hints(read.encodings, files, config)?.detecting(&allowed.unwrap_or_default())?,
+        };
+        let normalizers = ExtractedNormalizers::from_py(read.normalizers)?;
+        let preset = read.preset.map(presets::Preset::from_name).transpose()?.or(query_group.and_then(|query_group| query_group.preset));
+        let code = read.code.map(code::CodeMode::from_name).transpose()?.or(preset.and_then(presets::Preset::code));
+        // the contents normalized like the atoms, unless the preset does already
+        let mut search_normalizers = preset.map(presets::Preset::normalizers).unwrap_or_default();
+        if query_group.is_some_and(|query_group| query_group.query_options.nfkc) && !search_normalizers.iter().any(|normalizer| normalizer.name() == "nfkc") {
+            search_normalizers.push(Arc::new(normalize::Nfkc));
+        }
+        search_normalizers.extend(normalizers.normalizers.iter().cloned());
+        let range_unit = ranges::RangeUnit::from_name(read.range_unit)?;
+        let ranges = read.ranges
+            .map(|ranges| ranges::FileRanges::new(ranges, range_unit).map(Arc::new))
+            .transpose()?;
+        let (scoring, scorer) = scoring_options(results.score, results.top_k)?;
+
+        let scoped = context_options.as_ref().is_some_and(|context| context.scoped);
+        if scan.chunk_bytes.is_some() && (read.head_bytes.is_some() || read.tail_bytes.is_some() || ranges.is_some() || code.is_some() || scoring.is_some() || scoped) {
+            return Err(PyValueError::new_err("chunk_bytes can't be combined with head_bytes, tail_bytes, ranges, code, score, top_k or scoped"));
+        }
+        let cancel = scan.cancel.unwrap_or_default();
+        let progress_error = Arc::new(Mutex::new(None));
+        let progress = scan.progress.map(|callback| {
+            let (cancel, progress_error) = (cancel.clone(), progress_error.clone());
+            Progress::new(scan.progress_every, move |done, total| {
+                Python::with_gil(|py| {
+                    if let Err(e) = callback.call1(py, (done, total)) {
+                        cancel.cancel();
+                        progress_error.lock().unwrap().get_or_insert(e);
+                    }
+                })
+            })
+        });
+        let options = SearchOptions {
+            parallel: true,
+            num_threads: scan.num_threads,
+            context: context_options.map(|context_options| ContextOptions {
+                snippet: context.snippet,
+                max_contexts: context.max_contexts,
+                clause_contexts: context.clause_contexts,
+                ..context_options
+            }),
+            encoding_hints,
+            normalizers: search_normalizers,
+            scoring,
+            invert: read.invert,
+            detect_language: results.detect_language,
+            read_limit: read.max_concurrent_reads.or(config.max_concurrent_reads).map(ReadLimit::new),
+            max_file_size: read.max_file_size.or(config.max_file_size),
+            memory_budget: scan.memory_budget.or(config.memory_budget).map(MemoryBudget::new),
+            mmap_threshold: scan.mmap_threshold.or(config.mmap_threshold),
+            deadline: scan.deadline_ms.map(|ms| Instant::now() + Duration::from_millis(ms)),
+            file_timeout: scan.file_timeout_ms.map(Duration::from_millis),
+            max_results: scan.max_results,
+            profile: scan.profile,
+            head_bytes: read.head_bytes,
+            tail_bytes: read.tail_bytes,
+            ranges,
+            code,
+            chunked: scan.chunk_bytes.map(|chunk_bytes| chunked::ChunkOptions {
+                chunk_bytes,
+                overlap: scan.chunk_overlap,
+            }),
+            progress,
+            cancel: Some(cancel.clone()),
+            decompress: read.decompress,
+            binary: binary::BinaryPolicy::from_name(read.binary)?,
+            dedup_paths: scan.dedup_paths.map(paths::SymlinkPolicy::from_name).transpose()?,
+            ..Default::default()
+        };
+        Ok(SearchKwargs {
+            options,
+            normalizers,
+            scorer,
+            progress_error,
+            cancel,
+        })
+    }
+
+    /// The first error raised by a Python callback given, if any.
+    pub(crate) fn take_error(&self) -> Option<PyErr> {
+        self.progress_error
+            .lock()
+            .unwrap()
+            .take()
+            .or_else(|| self.normalizers.take_error())
+            .or_else(|| self.scorer.as_ref().and_then(|scorer| scorer.take_error()))
+    }
+}
+
+/// How `search_text` collects its results.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ResultMode {
+    /// In a list in memory.
+    #[default]
+    Plain,
+    /// In a temporary file, read back one at a time.
+    Spill,
+    /// In a checkpoint written every `every` files, which an interrupted search resumes from.
+    Checkpoint {
+        path: PathBuf,
+        every: usize,
+    },
+    /// `k` of them, sampled uniformly, seeded by `seed` if given.
+    Sample {
+        k: usize,
+        seed: Option<u64>,
+    },
+}
+
+/// How `search_text` collects its results, e.g. `mode=ResultMode.sample(20, seed=42)`.
+#[pyclass(name = "ResultMode")]
+#[derive(Clone, Debug, Default)]
+pub struct PyResultMode {
+    pub mode: ResultMode,
+}
+
+#[pymethods]
+impl PyResultMode {
+    /// All the results in a list, as by default.
+    #[staticmethod]
+    fn plain() -> Self {
+        PyResultMode::default()
+    }
+
+    /// The results spilled to a temporary file as they're found, returned as an iterator
+    /// reading them back, for broad searches whose results wouldn't fit in memory.
+    #[staticmethod]
+    fn spill() -> Self {
+        PyResultMode {
+            mode: ResultMode::Spill,
+        }
+    }
+
+    /// The results kept in a checkpoint at `path`, rewritten every `every` files searched; a
+    /// search interrupted, e.g. by Ctrl-C, resumes from it when run again.
+    #[staticmethod]
+    #[pyo3(signature = (path, every = 1000))]
+    fn checkpoint(path: PathBuf, every: usize) -> Self {
+        PyResultMode {
+            mode: ResultMode::Checkpoint {
+                path,
+                every,
+            },
+        }
+    }
+
+    /// `k` of the matching files, each as likely, reproducibly with the same `seed`.
+    #[staticmethod]
+    #[pyo3(signature = (k, seed = None))]
+    fn sample(k: usize, seed: Option<u64>) -> Self {
+        PyResultMode {
+            mode: ResultMode::Sample {
+                k,
+                seed,
+            },
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        match &self.mode {
+            ResultMode::Plain => "ResultMode.plain()".to_string(),
+            ResultMode::Spill => "ResultMode.spill()".to_string(),
+            ResultMode::Checkpoint { path, every } => format!("ResultMode.checkpoint({:?}, every={})", path.display().to_string(), every),
+            ResultMode::Sample { k, seed: None } => format!("ResultMode.sample({})", k),
+            ResultMode::Sample { k, seed: Some(seed) } => format!("ResultMode.sample({}, seed={})", k, seed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_search_kwargs() {
+        pyo3::prepare_freethreaded_python();
+        pyo3::Python::with_gil(|py| {
+            let module = pyo3::types::PyModule::new(py, "textsearcher").unwrap();
+            crate::py_module(py, module).unwrap();
+            let globals = pyo3::types::PyDict::new(py);
+            globals.set_item("__builtins__", py.import("builtins").unwrap()).unwrap();
+            globals.set_item("t", module).unwrap();
+            py.run(
+                r#"
+q = t.QueryGroup([['o']])
+files = t.FilePaths(['sample_texts/hello.txt', 'sample_texts/world.txt'])
+contexts = [r.context for r in t.search_text(q, files, 1, 1, context_chars=True, max_file_size=1000)]
+sampled = len(t.search_text(q, files, mode=t.ResultMode.sample(1, seed=1)))
+counts = t.count_matches(q, files, occurrences=True, invert=False)
+multi = [[r.context for r in results] for results in t.search_text_multi([q], files, a=1, b=1)]
+found, report = t.exists_match(q, t.FilePaths(['sample_texts/missing.txt', 'sample_texts/hello.txt']), report=True)
+skipped = (found, report.files_scanned, report.files_skipped)
+signature = t.count_matches.__text_signature__
+errors = []
+for call in [
+    lambda: t.count_matches(q, files, a=1),
+    lambda: t.search_any([q], files, invert=True),
+    lambda: t.search_text(q, files, colour=True),
+    lambda: t.search_text(q, files, max_file_size='big'),
+]:
+    try:
+        call()
+    except TypeError as e:
+        errors.append(str(e))
+"#,
+                Some(globals),
+                None,
+            )
+            .unwrap();
+            let get = |name: &str| globals.get_item(name).unwrap();
+            assert_eq!(get("contexts").extract::<Vec<String>>().unwrap(), vec!["foo", "wor"]);
+            assert_eq!(get("multi").extract::<Vec<Vec<String>>>().unwrap(), vec![vec!["foo", "wor"]]);
+            assert!(get("signature").extract::<String>().unwrap().contains(" max_file_size=...,"));
+            assert_eq!(get("sampled").extract::<usize>().unwrap(), 1);
+            assert_eq!(get("counts").extract::<(usize, usize)>().unwrap(), (2, 3));
+            let skipped = get("skipped").extract::<(bool, usize, std::collections::HashMap<String, usize>)>().unwrap();
+            assert_eq!(skipped, (true, 1, std::collections::HashMap::from([("not_found".to_string(), 1)])));
+            assert_eq!(get("errors").extract::<Vec<String>>().unwrap(), vec![
+                "count_matches() got an unexpected keyword argument 'a'",
+                "search_any() got an unexpected keyword argument 'invert'",
+                "search_text() got an unexpected keyword argument 'colour'",
+                "argument 'max_file_size': 'str' object cannot be interpreted as an integer",
+            ]);
+        });
+    }
+}
@@ -0,0 +1,154 @@
+//! Paths leading to the same file, e.g. `a.txt` and `./a.txt`, or a file and a symbolic link to
+//! it, collapsed so that each file is searched once, and what is done with symbolic links.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{self, Path, PathBuf};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use crate::Entry;
+
+/// Links followed from a path at most before it's taken to loop, like the kernel does.
+const MAX_LINKS: usize = 40;
+
+/// How the paths leading to the same file are told, and what is done with symbolic links.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Resolve the links, a link and the file it leads to being the same.
+    Follow,
+    /// Skip the links, reported as skipped for being `symlink`; the other paths are told apart
+    /// without resolving the links among their directories.
+    NoFollow,
+    /// Resolve the links like `Follow`, but skip those looping, reported as skipped for being
+    /// `symlink_loop`, before they're read.
+    DetectLoops,
+}
+
+impl SymlinkPolicy {
+    pub fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "follow" => Ok(SymlinkPolicy::Follow),
+            "no_follow" => Ok(SymlinkPolicy::NoFollow),
+            "detect_loops" => Ok(SymlinkPolicy::DetectLoops),
+            _ => Err(PyValueError::new_err(format!("unknown symlink policy {:?}, expected \"follow\", \"no_follow\" or \"detect_loops\"", name))),
+        }
+    }
+}
+
+/// The errors symbolic links are skipped with.
+#[derive(Debug)]
+enum SkippedLink {
+    NotFollowed,
+    Loop,
+}
+
+impl fmt::Display for SkippedLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkippedLink::NotFollowed => write!(f, "symbolic link not followed"),
+            SkippedLink::Loop => write!(f, "symbolic link loop"),
+        }
+    }
+}
+
+impl std::error::Error for SkippedLink {}
+
+/// The reason a file was skipped with `error` for being a symbolic link, if it was.
+pub(crate) fn skip_reason(error: &io::Error) -> Option<&'static str> {
+    match error.get_ref()?.downcast_ref::<SkippedLink>()? {
+        SkippedLink::NotFollowed => Some("symlink"),
+        SkippedLink::Loop => Some("symlink_loop"),
+    }
+}
+
+/// Fail if the file at `path` is a symbolic link skipped by `policy`.
+pub(crate) fn check_link(path: &str, policy: SymlinkPolicy) -> io::Result<()> {
+    let skipped = match policy {
+        SymlinkPolicy::Follow => None,
+        SymlinkPolicy::NoFollow => fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink()).then_some(SkippedLink::NotFollowed),
+        SymlinkPolicy::DetectLoops => loops(Path::new(path)).then_some(SkippedLink::Loop),
+    };
+    match skipped {
+        Some(skipped) => Err(io::Error::other(skipped)),
+        None => Ok(()),
+    }
+}
+
+/// Whether following the links from `path` leads back to one of them.
+fn loops(path: &Path) -> bool {
+    let mut seen = HashSet::new();
+    let mut current = path.to_path_buf();
+    for _ in 0..MAX_LINKS {
+        let Ok(target) = fs::read_link(&current) else {
+            return false;
+        };
+        // relative to the directory of the link
+        current = current.parent().map_or_else(|| target.clone(), |parent| parent.join(&target));
+        if !seen.insert(current.clone()) {
+            return true;
+        }
+    }
+    true
+}
+
+/// The path the file at `path` is told by: its absolute path, with the links resolved unless
+/// `policy` doesn't follow them. Paths that can't be resolved, e.g. of files missing, are told
+/// by their absolute path.
+fn identity(path: &str, policy: SymlinkPolicy) -> PathBuf {
+    let canonical = match policy {
+        SymlinkPolicy::Follow | SymlinkPolicy::DetectLoops => fs::canonicalize(path).ok(),
+        SymlinkPolicy::NoFollow => None,
+    };
+    canonical.or_else(|| path::absolute(path).ok()).unwrap_or_else(|| PathBuf::from(path))
+}
+
+/// The entries without those leading to the same file as one before them, and how many were
+/// left out. The paths are kept as given.
+pub(crate) fn dedup_entries<'a>(entries: &[Entry<'a>], policy: SymlinkPolicy, parallel: bool) -> (Vec<Entry<'a>>, usize) {
+    let identities: Vec<_> = match parallel {
+        true => entries.par_iter().map(|&(path, _)| identity(path, policy)).collect(),
+        false => entries.iter().map(|&(path, _)| identity(path, policy)).collect(),
+    };
+    let mut seen = HashSet::with_capacity(entries.len());
+    let deduped: Vec<_> = entries.iter().zip(identities).filter(|(_, identity)| seen.insert(identity.clone())).map(|(&entry, _)| entry).collect();
+    let collapsed = entries.len() - deduped.len();
+    (deduped, collapsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use crate::{search_text_report, QueryGroup, SearchOptions};
+    use super::SymlinkPolicy;
+
+    #[test]
+    fn test_dedup_paths() {
+        let root = std::env::temp_dir().join(format!("textsearcher-paths-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "foo").unwrap();
+        symlink(root.join("a.txt"), root.join("link.txt")).unwrap();
+        symlink(root.join("loop.txt"), root.join("loop.txt")).unwrap();
+        let root_str = root.to_str().unwrap();
+        let paths: Vec<_> = ["a.txt", "./a.txt", "link.txt", "loop.txt"].iter().map(|name| format!("{}/{}", root_str, name)).collect();
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string()]]).unwrap();
+        let search = |dedup_paths: Option<SymlinkPolicy>| {
+            let options = SearchOptions {
+                dedup_paths,
+                ..Default::default()
+            };
+            let (results, report) = search_text_report(&query_group, &paths, &options, |_| ());
+            let mut skipped: Vec<_> = report.errors.iter().map(|(_, reason, _)| reason.clone()).collect();
+            skipped.sort();
+            (results.len(), report.paths_collapsed, skipped)
+        };
+        assert_eq!(search(None).0, 3);
+        assert_eq!(search(Some(SymlinkPolicy::Follow)), (1, 2, vec!["filesystemloop".to_string()]));
+        assert_eq!(search(Some(SymlinkPolicy::DetectLoops)), (1, 2, vec!["symlink_loop".to_string()]));
+        assert_eq!(search(Some(SymlinkPolicy::NoFollow)), (1, 1, vec!["symlink".to_string(), "symlink".to_string()]));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+//! Compiled patterns of a `QueryGroup`, backed by one of several regex engines.
+
+/// The default engine is `regex`, which runs in linear time. `fancy-regex` additionally supports
+/// lookaround and backreferences, falling back to backtracking for those. PCRE2, behind the
+/// `pcre2` feature, takes patterns written for PCRE verbatim.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Regex(regex::Regex),
+    Fancy(fancy_regex::Regex),
+    #[cfg(feature = "pcre2")]
+    Pcre2(pcre2::bytes::Regex),
+}
+
+/// A match of a `Pattern` in a haystack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PatternMatch<'t> {
+    haystack: &'t str,
+    start: usize,
+    end: usize,
+}
+
+impl<'t> PatternMatch<'t> {
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn as_str(&self) -> &'t str {
+        &self.haystack[self.start..self.end]
+    }
+}
+
+/// The spans of the capture groups of a match, group 0 being the whole match.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatternCaptures<'t> {
+    haystack: &'t str,
+    spans: Vec<Option<(usize, usize)>>,
+}
+
+impl<'t> PatternCaptures<'t> {
+    /// Group `i`, if it exists and participated in the match.
+    pub fn get(&self, i: usize) -> Option<PatternMatch<'t>> {
+        let (start, end) = self.spans.get(i).copied().flatten()?;
+        Some(PatternMatch {
+            haystack: self.haystack,
+            start,
+            end,
+        })
+    }
+
+    /// Number of groups, including group 0.
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+}
+
+impl Pattern {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Pattern::Regex(regex) => regex.as_str(),
+            Pattern::Fancy(regex) => regex.as_str(),
+            #[cfg(feature = "pcre2")]
+            Pattern::Pcre2(regex) => regex.as_str(),
+        }
+    }
+
+    // `fancy-regex` and PCRE2 error out when their backtracking limits are exceeded, which is
+    // treated as no match.
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        match self {
+            Pattern::Regex(regex) => regex.is_match(haystack),
+            Pattern::Fancy(regex) => regex.is_match(haystack).unwrap_or(false),
+            #[cfg(feature = "pcre2")]
+            Pattern::Pcre2(regex) => regex.is_match(haystack.as_bytes()).unwrap_or(false),
+        }
+    }
+
+    pub fn find<'t>(&self, haystack: &'t str) -> Option<PatternMatch<'t>> {
+        self.find_at(haystack, 0)
+    }
+
+    /// The first match starting at or after `start`; the text before `start` is still taken into
+    /// account for anchors and lookbehind.
+    pub fn find_at<'t>(&self, haystack: &'t str, start: usize) -> Option<PatternMatch<'t>> {
+        let (start, end) = match self {
+            Pattern::Regex(regex) => regex.find_at(haystack, start).map(|m| (m.start(), m.end()))?,
+            Pattern::Fancy(regex) => regex.find_from_pos(haystack, start).ok()?.map(|m| (m.start(), m.end()))?,
+            #[cfg(feature = "pcre2")]
+            Pattern::Pcre2(regex) => regex.find_at(haystack.as_bytes(), start).ok()?.map(|m| (m.start(), m.end()))?,
+        };
+        Some(PatternMatch {
+            haystack,
+            start,
+            end,
+        })
+    }
+
+    /// Successive non-overlapping matches.
+    pub fn find_iter<'r, 't>(&'r self, haystack: &'t str) -> FindIter<'r, 't> {
+        FindIter {
+            pattern: self,
+            cursor: Cursor::new(haystack),
+        }
+    }
+
+    /// The names of the capture groups, indexed like the groups; unnamed groups are `None`.
+    pub fn capture_names(&self) -> Vec<Option<String>> {
+        match self {
+            Pattern::Regex(regex) => regex.capture_names().map(|n| n.map(String::from)).collect(),
+            Pattern::Fancy(regex) => regex.capture_names().map(|n| n.map(String::from)).collect(),
+            #[cfg(feature = "pcre2")]
+            Pattern::Pcre2(regex) => regex.capture_names().to_vec(),
+        }
+    }
+
+    /// Like `find_at`, but with the spans of all the capture groups.
+    pub fn captures_at<'t>(&self, haystack: &'t str, start: usize) -> Option<PatternCaptures<'t>> {
+        let spans = match self {
+            Pattern::Regex(regex) => {
+                let caps = regex.captures_at(haystack, start)?;
+                caps.iter().map(|m| m.map(|m| (m.start(), m.end()))).collect()
+            }
+            Pattern::Fancy(regex) => {
+                let caps = regex.captures_from_pos(haystack, start).ok()??;
+                caps.iter().map(|m| m.map(|m| (m.start(), m.end()))).collect()
+            }
+            #[cfg(feature = "pcre2")]
+            Pattern::Pcre2(regex) => {
+                let mut locations = regex.capture_locations();
+                regex.captures_read_at(&mut locations, haystack.as_bytes(), start).ok()??;
+                (0..locations.len()).map(|i| locations.get(i)).collect()
+            }
+        };
+        Some(PatternCaptures {
+            haystack,
+            spans,
+        })
+    }
+
+    /// Successive non-overlapping matches, with their capture groups.
+    pub fn captures_iter<'r, 't>(&'r self, haystack: &'t str) -> CapturesIter<'r, 't> {
+        CapturesIter {
+            pattern: self,
+            cursor: Cursor::new(haystack),
+        }
+    }
+}
+
+/// Where to search for the next match, skipping empty matches right after the previous match the
+/// way `regex` does.
+struct Cursor<'t> {
+    haystack: &'t str,
+    at: usize,
+    last_end: Option<usize>,
+}
+
+impl<'t> Cursor<'t> {
+    fn new(haystack: &'t str) -> Self {
+        Cursor {
+            haystack,
+            at: 0,
+            last_end: None,
+        }
+    }
+
+    /// Advance past the match `(start, end)`; returns whether the match should be yielded.
+    fn advance(&mut self, start: usize, end: usize) -> bool {
+        if start == end {
+            // step over the next char so that empty matches don't repeat forever
+            self.at = end + self.haystack[end..].chars().next().map_or(1, char::len_utf8);
+            if self.last_end == Some(end) {
+                return false;
+            }
+        } else {
+            self.at = end;
+        }
+        self.last_end = Some(end);
+        true
+    }
+}
+
+pub struct FindIter<'r, 't> {
+    pattern: &'r Pattern,
+    cursor: Cursor<'t>,
+}
+
+impl<'r, 't> Iterator for FindIter<'r, 't> {
+    type Item = PatternMatch<'t>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.cursor.at <= self.cursor.haystack.len() {
+            let m = self.pattern.find_at(self.cursor.haystack, self.cursor.at)?;
+            if self.cursor.advance(m.start, m.end) {
+                return Some(m);
+            }
+        }
+        None
+    }
+}
+
+pub struct CapturesIter<'r, 't> {
+    pattern: &'r Pattern,
+    cursor: Cursor<'t>,
+}
+
+impl<'r, 't> Iterator for CapturesIter<'r, 't> {
+    type Item = PatternCaptures<'t>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.cursor.at <= self.cursor.haystack.len() {
+            let caps = self.pattern.captures_at(self.cursor.haystack, self.cursor.at)?;
+            let m = caps.get(0)?;
+            if self.cursor.advance(m.start, m.end) {
+                return Some(caps);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pattern;
+
+    #[test]
+    fn test_find_iter() {
+        for pattern in [
+            Pattern::Regex(regex::Regex::new("a*").unwrap()),
+            Pattern::Fancy(fancy_regex::Regex::new("a*").unwrap()),
+            #[cfg(feature = "pcre2")]
+            Pattern::Pcre2(pcre2::bytes::RegexBuilder::new().utf(true).build("a*").unwrap()),
+        ] {
+            let spans: Vec<_> = pattern.find_iter("baaxa中").map(|m| (m.start(), m.end())).collect();
+            assert_eq!(spans, vec![(0, 0), (1, 3), (4, 5), (8, 8)]);
+        }
+        let pattern = Pattern::Fancy(fancy_regex::Regex::new("foo(?! bar)").unwrap());
+        let starts: Vec<_> = pattern.find_iter("foo bar foo baz").map(|m| m.start()).collect();
+        assert_eq!(starts, vec![8]);
+    }
+
+    #[test]
+    fn test_captures_iter() {
+        for pattern in [
+            Pattern::Regex(regex::Regex::new(r"(?P<y>\d{4})-(\d{2})?").unwrap()),
+            Pattern::Fancy(fancy_regex::Regex::new(r"(?P<y>\d{4})-(\d{2})?").unwrap()),
+            #[cfg(feature = "pcre2")]
+            Pattern::Pcre2(pcre2::bytes::RegexBuilder::new().utf(true).build(r"(?P<y>\d{4})-(\d{2})?").unwrap()),
+        ] {
+            assert_eq!(pattern.capture_names(), vec![None, Some("y".to_string()), None]);
+            let groups: Vec<Vec<_>> = pattern
+                .captures_iter("2021-03, 1999-")
+                .map(|caps| (0..caps.len()).map(|i| caps.get(i).map(|m| m.as_str())).collect())
+                .collect();
+            assert_eq!(groups, vec![
+                vec![Some("2021-03"), Some("2021"), Some("03")],
+                vec![Some("1999-"), Some("1999"), None],
+            ]);
+        }
+    }
+}
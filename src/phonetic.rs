@@ -0,0 +1,164 @@
+//! Phonetic matching of ASCII names, loosely following Metaphone.
+//!
+//! A name is reduced to a sequence of consonant sounds, which is then compiled back into a regex
+//! accepting the common spellings of each sound with arbitrary vowels in between. Hence
+//! "Katherine", "Catherine" and "Kathryn" all compile to regexes matching each other.
+
+/// Consonant sounds; `Vowel` marks a name that starts with a vowel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Sound {
+    Vowel,
+    B,
+    F,
+    J,
+    K,
+    L,
+    M,
+    N,
+    P,
+    R,
+    S,
+    T,
+    X,
+}
+
+impl Sound {
+    fn spellings(self) -> &'static str {
+        match self {
+            Sound::Vowel => "[aeiouy]",
+            Sound::B => "b+",
+            Sound::F => "(?:ph|f+|v+)",
+            Sound::J => "(?:dg|j|g)",
+            Sound::K => "(?:ck|ch|kh|gh|c+|k+|g+|q)",
+            Sound::L => "l+",
+            Sound::M => "m+",
+            Sound::N => "n+",
+            Sound::P => "p+",
+            Sound::R => "r+",
+            Sound::S => "(?:s+|z+|c)",
+            Sound::T => "(?:th|t+|d+)",
+            Sound::X => "(?:sch|sh|tch|ch)",
+        }
+    }
+}
+
+fn is_vowel(ch: u8) -> bool {
+    matches!(ch, b'a' | b'e' | b'i' | b'o' | b'u' | b'y')
+}
+
+/// Reduce a name to its consonant sounds, or `None` if it isn't a plain ASCII word.
+fn sounds(name: &str) -> Option<Vec<Sound>> {
+    if name.is_empty() || !name.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return None;
+    }
+    let name = name.to_ascii_lowercase();
+    let bytes = name.as_bytes();
+    let at = |i: usize| bytes.get(i).copied().unwrap_or(b'\0');
+    let mut sounds = Vec::new();
+    let mut i = 0;
+    // silent initial letters
+    if matches!((at(0), at(1)), (b'k', b'n') | (b'g', b'n') | (b'p', b'n') | (b'w', b'r')) {
+        i = 1;
+    }
+    if is_vowel(at(i)) {
+        sounds.push(Sound::Vowel);
+    }
+    while i < bytes.len() {
+        let (sound, len) = match (at(i), at(i + 1)) {
+            (b'b', _) => (Some(Sound::B), 1),
+            (b'c', b'k') => (Some(Sound::K), 2),
+            (b'c', b'h') if at(i + 2) == b'r' => (Some(Sound::K), 2),
+            (b'c', b'h') => (Some(Sound::X), 2),
+            (b'c', b'e' | b'i' | b'y') => (Some(Sound::S), 1),
+            (b'c', _) => (Some(Sound::K), 1),
+            (b'd', b'g') if matches!(at(i + 2), b'e' | b'i' | b'y') => (Some(Sound::J), 2),
+            (b'd', _) | (b't', _) if at(i + 1) == b'h' => (Some(Sound::T), 2),
+            (b'd', _) => (Some(Sound::T), 1),
+            (b'f', _) | (b'v', _) => (Some(Sound::F), 1),
+            (b'g', b'h') if i > 0 => (None, 2),
+            (b'g', b'e' | b'i' | b'y') => (Some(Sound::J), 1),
+            (b'g', _) => (Some(Sound::K), 1),
+            (b'j', _) => (Some(Sound::J), 1),
+            (b'k', _) | (b'q', _) => (Some(Sound::K), 1),
+            (b'l', _) => (Some(Sound::L), 1),
+            (b'm', _) => (Some(Sound::M), 1),
+            (b'n', _) => (Some(Sound::N), 1),
+            (b'p', b'h') => (Some(Sound::F), 2),
+            (b'p', _) => (Some(Sound::P), 1),
+            (b'r', _) => (Some(Sound::R), 1),
+            (b's', b'h') => (Some(Sound::X), 2),
+            (b's', b'c') if at(i + 2) == b'h' => (Some(Sound::X), 3),
+            (b's', _) | (b'z', _) => (Some(Sound::S), 1),
+            (b't', b'c') if at(i + 2) == b'h' => (Some(Sound::X), 3),
+            (b't', _) => (Some(Sound::T), 1),
+            (b'x', _) => {
+                push_sound(&mut sounds, Sound::K);
+                (Some(Sound::S), 1)
+            }
+            // vowels, "h", "w"
+            _ => (None, 1),
+        };
+        if let Some(sound) = sound {
+            push_sound(&mut sounds, sound);
+        }
+        i += len;
+    }
+    Some(sounds)
+}
+
+fn push_sound(sounds: &mut Vec<Sound>, sound: Sound) {
+    if sounds.last() != Some(&sound) {
+        sounds.push(sound);
+    }
+}
+
+/// Regex matching words that sound like `name`, or `None` if `name` isn't a plain ASCII word.
+pub(crate) fn phonetic_regex(name: &str) -> Option<String> {
+    let sounds = sounds(name)?;
+    // vowels and silent letters between the sounds
+    let gap = "(?:[aeiouyhw]|gh)*";
+    let mut regex = String::from("\\b");
+    if sounds.first() != Some(&Sound::Vowel) {
+        // the first letter may be silent, e.g. "Knight" vs "Night"
+        regex.push_str("[kgpw]?");
+    }
+    for (i, sound) in sounds.iter().enumerate() {
+        if i > 0 {
+            regex.push_str(gap);
+        }
+        regex.push_str(sound.spellings());
+    }
+    regex.push_str(gap);
+    regex.push_str("\\b");
+    Some(regex)
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::RegexBuilder;
+    use super::phonetic_regex;
+
+    fn sounds_like(query: &str, name: &str) -> bool {
+        let regex = RegexBuilder::new(&phonetic_regex(query).unwrap())
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        regex.is_match(name)
+    }
+
+    #[test]
+    fn test_phonetic_regex() {
+        for name in ["Katherine", "Catherine", "Kathryn", "Cathryn"] {
+            assert!(sounds_like("Katherine", name), "{}", name);
+            assert!(sounds_like(name, "Katherine"), "{}", name);
+        }
+        assert!(sounds_like("Philip", "Filip"));
+        assert!(sounds_like("Stephen", "Steven"));
+        assert!(sounds_like("Smith", "Smyth"));
+        assert!(sounds_like("Knight", "Night"));
+        assert!(sounds_like("Ann", "Anne"));
+        assert!(!sounds_like("Katherine", "Karen"));
+        assert!(!sounds_like("Smith", "Smithson"));
+        assert_eq!(phonetic_regex("O'Brien"), None);
+    }
+}
@@ -0,0 +1,118 @@
+//! Named combinations of the tolerance options suited to a kind of text, e.g. the output of
+//! `pdftotext`, so that the knobs needn't be learned one by one.
+
+use std::sync::Arc;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use crate::code::CodeMode;
+use crate::normalize::{CaseFold, Dehyphenate, Nfkc, NormalizeNewlines, Normalizer, StripControls, StripLines};
+use crate::{QueryOptions, Separators};
+
+/// What may separate consecutive characters of scripts written without spaces in text
+/// extracted from pages: whitespace, and a short noise line such as a page number.
+const PAGE_NOISE_GAP: &str = r"\s*(?:\n.{0,8}\n)?\s*";
+
+/// Page numbers and running `Page 3 of 10` footers on lines of their own.
+const PAGE_NUMBER_LINES: &str = r"\s*(?:[Pp]age\s+)?\d+(?:\s+of\s+\d+)?\s*";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// Text extracted from PDFs: line breaks and form feeds normalized, page numbers removed,
+    /// words hyphenated across lines joined and ligatures expanded; CJK phrases may span a page
+    /// number.
+    PdfToText,
+    /// Text recognized from scans: like `PdfToText` but keeping the lines that are numbers, and
+    /// case-folded, since the case is often misrecognized.
+    OcrScan,
+    /// Clean UTF-8 text, matched as is.
+    CleanUtf8,
+    /// Source code, of which only the comments and string literals are matched.
+    Code,
+}
+
+impl Preset {
+    pub fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "pdftotext" => Ok(Preset::PdfToText),
+            "ocr-scan" => Ok(Preset::OcrScan),
+            "clean-utf8" => Ok(Preset::CleanUtf8),
+            "code" => Ok(Preset::Code),
+            _ => Err(PyValueError::new_err(format!("unknown preset {:?}, expected \"pdftotext\", \"ocr-scan\", \"clean-utf8\" or \"code\"", name))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::PdfToText => "pdftotext",
+            Preset::OcrScan => "ocr-scan",
+            Preset::CleanUtf8 => "clean-utf8",
+            Preset::Code => "code",
+        }
+    }
+
+    /// `options` with the query options of the preset where they aren't set.
+    pub fn query_options(self, options: QueryOptions) -> QueryOptions {
+        let (cjk_gap, case_folding) = match self {
+            Preset::PdfToText => (Some(PAGE_NOISE_GAP), None),
+            Preset::OcrScan => (Some(PAGE_NOISE_GAP), Some(CaseFold { turkic: false })),
+            Preset::CleanUtf8 | Preset::Code => (None, None),
+        };
+        QueryOptions {
+            separators: Separators {
+                cjk: options.separators.cjk.or_else(|| cjk_gap.map(String::from)),
+                ..options.separators
+            },
+            case_folding: options.case_folding.or(case_folding),
+            // like the contents, which the normalizers of these presets normalize
+            nfkc: options.nfkc || matches!(self, Preset::PdfToText | Preset::OcrScan),
+            ..options
+        }
+    }
+
+    /// The normalizers of the preset, applied before any other.
+    pub fn normalizers(self) -> Vec<Arc<dyn Normalizer>> {
+        let cleanup: Vec<Arc<dyn Normalizer>> = vec![Arc::new(NormalizeNewlines), Arc::new(StripControls::new(" "))];
+        match self {
+            Preset::PdfToText => {
+                let page_numbers = StripLines::new(PAGE_NUMBER_LINES).expect("the pattern is valid");
+                cleanup.into_iter().chain([Arc::new(page_numbers) as _, Arc::new(Dehyphenate) as _, Arc::new(Nfkc) as _]).collect()
+            }
+            // folded first, so that words hyphenated before a capital are joined too
+            Preset::OcrScan => cleanup.into_iter().chain([Arc::new(Nfkc) as _, Arc::new(CaseFold { turkic: false }) as _, Arc::new(Dehyphenate) as _]).collect(),
+            Preset::CleanUtf8 => Vec::new(),
+            Preset::Code => vec![Arc::new(NormalizeNewlines)],
+        }
+    }
+
+    /// The parts of source files the preset matches, if it's meant for code.
+    pub fn code(self) -> Option<CodeMode> {
+        (self == Preset::Code).then_some(CodeMode::Prose)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::normalize::normalize;
+    use crate::{is_match_str, QueryGroup, QueryOptions, Separators};
+    use super::Preset;
+
+    #[test]
+    fn test_presets() {
+        let options = Preset::OcrScan.query_options(QueryOptions::default());
+        let query_group = QueryGroup::with_options(vec![vec!["Fine Experiment".to_string()]], options).unwrap();
+        let contents = normalize(&Preset::OcrScan.normalizers(), "the ﬁne experi-\r\nMENT\x0c");
+        assert!(is_match_str(&query_group, &contents));
+        let contents = normalize(&Preset::PdfToText.normalizers(), "an experi-\n  12  \nment");
+        assert_eq!(contents, "an experiment");
+        assert!(Preset::CleanUtf8.normalizers().is_empty());
+        let cjk_gap = Preset::PdfToText.query_options(QueryOptions {
+            separators: Separators {
+                cjk: Some(r"\s*".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        assert_eq!(cjk_gap.separators.cjk.as_deref(), Some(r"\s*"));
+        assert!(Preset::from_name("pdf").is_err());
+    }
+}
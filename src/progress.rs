@@ -0,0 +1,113 @@
+//! Feedback on long searches and a way to stop them: a callback told how many of the files have
+//! been searched, and a token that cancels the search between files, e.g. on Ctrl-C.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use pyo3::prelude::*;
+
+/// How often signals are checked while a search from Python runs.
+pub(crate) const SIGNAL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Cancels the searches it's given to, and their clones, once cancelled from any thread. The
+/// files being searched then are finished, and no other is started.
+#[pyclass]
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[pymethods]
+impl CancelToken {
+    #[new]
+    fn py_new() -> Self {
+        CancelToken::new()
+    }
+
+    #[pyo3(name = "cancel")]
+    fn py_cancel(&self) {
+        self.cancel()
+    }
+
+    #[getter]
+    fn cancelled(&self) -> bool {
+        self.is_cancelled()
+    }
+}
+
+/// Called with the number of files searched so far and the number of files to search, every
+/// `every` files and once all of them are searched.
+#[derive(Clone)]
+pub struct Progress {
+    pub every: usize,
+    pub callback: Arc<dyn Fn(usize, usize) + Send + Sync>,
+}
+
+impl Progress {
+    pub fn new<F: Fn(usize, usize) + Send + Sync + 'static>(every: usize, callback: F) -> Self {
+        Progress {
+            every: every.max(1),
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Tell the callback that `done` of the `total` files are searched, if it's its turn.
+    pub(crate) fn report(&self, done: usize, total: usize) {
+        if done.is_multiple_of(self.every) || done == total {
+            (self.callback)(done, total);
+        }
+    }
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Progress(every {})", self.every)
+    }
+}
+
+/// Run `search` on another thread with the GIL released, checking meanwhile for signals such as
+/// Ctrl-C, which only the main thread receives. A signal cancels the search with `cancel`, and is
+/// returned as the error its handler raised, e.g. `KeyboardInterrupt`.
+pub(crate) fn interruptible<T, F>(py: Python<'_>, cancel: &CancelToken, search: F) -> (T, Option<PyErr>)
+where
+    T: Send,
+    F: FnOnce() -> T + Send,
+{
+    py.allow_threads(|| {
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            let searching = scope.spawn(move || {
+                let outcome = search();
+                let _ = sender.send(());
+                outcome
+            });
+            let mut interrupted = None;
+            while let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(SIGNAL_INTERVAL) {
+                if interrupted.is_none() {
+                    if let Err(e) = Python::with_gil(|py| py.check_signals()) {
+                        cancel.cancel();
+                        interrupted = Some(e);
+                    }
+                }
+            }
+            (searching.join().unwrap(), interrupted)
+        })
+    })
+}
@@ -0,0 +1,656 @@
+//! Boolean expressions over atoms, compiled into query groups.
+
+// the glue pyo3 0.18 generates for the `&` and `|` operators trips this lint
+#![allow(non_local_definitions)]
+
+use std::fmt;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use serde_json::Value;
+use crate::error::QueryError;
+use crate::normalize::CaseFold;
+use crate::{QueryGroup, QueryOptions, Separators, THEN_PREFIX, THEN_SEPARATOR};
+
+/// Above this many AND groups, expanding an expression into a query group is refused.
+const MAX_CLAUSES: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Query {
+    Atom(String),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+}
+
+/// An atom, negated or not.
+type Literal = (bool, String);
+
+/// ORs of atoms, as passed to `QueryGroup::with_exclusions`.
+type OrGroups = Vec<Vec<String>>;
+
+impl Query {
+    pub fn atom<S: Into<String>>(atom: S) -> Self {
+        Query::Atom(atom.into())
+    }
+
+    pub fn and(self, other: Query) -> Self {
+        match self {
+            Query::And(mut operands) => {
+                operands.push(other);
+                Query::And(operands)
+            }
+            query => Query::And(vec![query, other]),
+        }
+    }
+
+    pub fn or(self, other: Query) -> Self {
+        match self {
+            Query::Or(mut operands) => {
+                operands.push(other);
+                Query::Or(operands)
+            }
+            query => Query::Or(vec![query, other]),
+        }
+    }
+
+    /// The atom matching where `self` is followed by `other`, e.g. "introduction" then
+    /// "methodology"; sequences are extended rather than nested. Only atoms can be put in
+    /// sequence.
+    pub fn then(self, other: Query) -> Result<Self, QueryError> {
+        let sequence = |query: Query| match query {
+            Query::Atom(atom) => Ok(atom.strip_prefix(THEN_PREFIX).map(str::to_string).unwrap_or(atom)),
+            query => Err(QueryError::new(format!("only atoms can be put in sequence, not {}", query))),
+        };
+        Ok(Query::Atom(format!("{}{}{}{}", THEN_PREFIX, sequence(self)?, THEN_SEPARATOR, sequence(other)?)))
+    }
+
+    pub fn negate(self) -> Self {
+        match self {
+            Query::Not(query) => *query,
+            query => Query::Not(Box::new(query)),
+        }
+    }
+
+    /// The expression as an AND of ORs of literals.
+    fn clauses(&self, negated: bool) -> Result<Vec<Vec<Literal>>, QueryError> {
+        match (self, negated) {
+            (Query::Atom(atom), _) => Ok(vec![vec![(negated, atom.clone())]]),
+            (Query::Not(query), _) => query.clauses(!negated),
+            (Query::And(operands), false) | (Query::Or(operands), true) => {
+                let mut clauses = Vec::new();
+                for operand in operands.iter() {
+                    clauses.extend(operand.clauses(negated)?);
+                }
+                Ok(clauses)
+            }
+            (Query::Or(operands), false) | (Query::And(operands), true) => {
+                // distribute the OR over the ANDs of the operands
+                let mut clauses: Vec<Vec<Literal>> = vec![Vec::new()];
+                for operand in operands.iter() {
+                    let operand = operand.clauses(negated)?;
+                    if clauses.len() * operand.len() > MAX_CLAUSES {
+                        return Err(QueryError::new("query expands into too many AND groups"));
+                    }
+                    clauses = clauses
+                        .iter()
+                        .flat_map(|clause| operand.iter().map(move |other| [clause.as_slice(), other].concat()))
+                        .collect();
+                }
+                Ok(clauses)
+            }
+        }
+    }
+
+    /// The OR groups to AND and those to exclude. Each OR group must be either all atoms or a
+    /// single negated atom, e.g. `~(a & b)` can't be expressed.
+    pub fn to_or_groups(&self) -> Result<(OrGroups, OrGroups), QueryError> {
+        let mut and_of_or_atoms = Vec::new();
+        let mut excluded = Vec::new();
+        for clause in self.clauses(false)? {
+            let mut unique: Vec<Literal> = Vec::with_capacity(clause.len());
+            for literal in clause {
+                if !unique.contains(&literal) {
+                    unique.push(literal);
+                }
+            }
+            let clause = unique;
+            match clause.as_slice() {
+                [(true, atom)] => excluded.push(vec![atom.clone()]),
+                _ if clause.iter().all(|(negated, _)| !negated) => {
+                    and_of_or_atoms.push(clause.into_iter().map(|(_, atom)| atom).collect());
+                }
+                _ => return Err(QueryError::new(format!("can't OR a negated atom with other atoms in {}", self))),
+            }
+        }
+        if and_of_or_atoms.is_empty() {
+            return Err(QueryError::new(format!("query must require some atom to match: {}", self)));
+        }
+        Ok((and_of_or_atoms, excluded))
+    }
+
+    pub fn compile(&self, options: QueryOptions) -> Result<QueryGroup, QueryError> {
+        let (and_of_or_atoms, excluded) = self.to_or_groups()?;
+        QueryGroup::with_exclusions(and_of_or_atoms, excluded, options)
+    }
+}
+
+/// A token of a query string.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    And,
+    Or,
+    Not,
+    Then,
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().enumerate().peekable();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            ch if ch.is_whitespace() => (),
+            '(' => tokens.push((i, Token::Open)),
+            ')' => tokens.push((i, Token::Close)),
+            '"' => {
+                let mut phrase = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err(QueryError::new(format!("unclosed quote at position {} of {:?}", i, text))),
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => {
+                            if let Some((_, escaped)) = chars.next() {
+                                phrase.push(escaped);
+                            }
+                        }
+                        Some((_, ch)) => phrase.push(ch),
+                    }
+                }
+                tokens.push((i, Token::Phrase(phrase)));
+            }
+            ch => {
+                let mut word = String::from(ch);
+                while let Some(&(_, ch)) = chars.peek() {
+                    if ch.is_whitespace() || matches!(ch, '(' | ')' | '"') {
+                        break;
+                    }
+                    word.push(ch);
+                    chars.next();
+                }
+                let token = match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    "THEN" => Token::Then,
+                    _ => Token::Word(word),
+                };
+                tokens.push((i, token));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens of a query string: OR binds loosest, then AND, then NOT,
+/// then THEN.
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    fn error(&self, expected: &str) -> QueryError {
+        match self.tokens.get(self.next) {
+            Some((i, _)) => QueryError::new(format!("expected {} at position {} of {:?}", expected, i, self.text)),
+            None => QueryError::new(format!("expected {} at the end of {:?}", expected, self.text)),
+        }
+    }
+
+    fn or(&mut self) -> Result<Query, QueryError> {
+        let mut query = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next += 1;
+            query = query.or(self.and()?);
+        }
+        Ok(query)
+    }
+
+    fn and(&mut self) -> Result<Query, QueryError> {
+        let mut query = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next += 1;
+            query = query.and(self.not()?);
+        }
+        Ok(query)
+    }
+
+    fn not(&mut self) -> Result<Query, QueryError> {
+        if self.peek() == Some(&Token::Not) {
+            self.next += 1;
+            return Ok(self.not()?.negate());
+        }
+        self.then()
+    }
+
+    fn then(&mut self) -> Result<Query, QueryError> {
+        let mut query = self.operand()?;
+        while self.peek() == Some(&Token::Then) {
+            self.next += 1;
+            query = query.then(self.operand()?)?;
+        }
+        Ok(query)
+    }
+
+    fn operand(&mut self) -> Result<Query, QueryError> {
+        match self.peek().cloned() {
+            Some(Token::Open) => {
+                let open = self.tokens[self.next].0;
+                self.next += 1;
+                let query = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(match self.peek() {
+                        None => QueryError::new(format!("unclosed parenthesis at position {} of {:?}", open, self.text)),
+                        Some(_) => self.error("AND, OR or ')'"),
+                    });
+                }
+                self.next += 1;
+                Ok(query)
+            }
+            Some(Token::Phrase(phrase)) => {
+                self.next += 1;
+                match phrase.trim() {
+                    "" => Err(QueryError::new(format!("empty phrase at position {} of {:?}", self.tokens[self.next - 1].0, self.text))),
+                    _ => Ok(Query::Atom(phrase)),
+                }
+            }
+            Some(Token::Word(word)) => {
+                // consecutive words are a phrase, matched across any whitespace
+                let mut atom = word;
+                self.next += 1;
+                while let Some(Token::Word(word)) = self.peek() {
+                    atom.push(' ');
+                    atom.push_str(word);
+                    self.next += 1;
+                }
+                Ok(Query::Atom(atom))
+            }
+            _ => Err(self.error("an atom, a quoted phrase, NOT or '('")),
+        }
+    }
+}
+
+impl Query {
+    /// Parse a query string, e.g. `foo AND (bar OR "baz qux") AND NOT quux`. Consecutive words
+    /// are a single atom, like a quoted phrase; `a THEN b` matches `a` followed by `b`. AND, OR,
+    /// NOT and THEN are only operators in capitals.
+    pub fn parse(text: &str) -> Result<Query, QueryError> {
+        let mut parser = Parser {
+            text,
+            tokens: tokenize(text)?,
+            next: 0,
+        };
+        if parser.tokens.is_empty() {
+            return Err(QueryError::new("query string must not be empty"));
+        }
+        let query = parser.or()?;
+        if parser.peek().is_some() {
+            return Err(parser.error("AND or OR"));
+        }
+        Ok(query)
+    }
+}
+
+/// A query given as an Elasticsearch-style bool query, e.g.
+/// `{"bool": {"must": ["foo"], "should": ["bar", "baz"], "must_not": [{"match_phrase": {"body": "qux quux"}}]}}`.
+/// The clauses are atoms, `match`, `match_phrase` or `term` queries, whose fields are ignored, or
+/// nested bool queries. `filter` is taken as `must`.
+pub fn from_bool_json(value: &Value) -> PyResult<Query> {
+    let error = |message: String| PyValueError::new_err(message);
+    match value {
+        Value::String(atom) => Ok(Query::atom(atom.as_str())),
+        Value::Object(object) if object.len() == 1 => {
+            let (key, inner) = object.iter().next().unwrap();
+            match key.as_str() {
+                "query" | "bool" => from_bool_json(inner),
+                "match" | "match_phrase" | "term" => leaf_query(key, inner),
+                _ => from_bool_object(object),
+            }
+        }
+        Value::Object(object) => from_bool_object(object),
+        _ => Err(error(format!("unsupported query clause: {}", value))),
+    }
+}
+
+/// The query of a `match`, `match_phrase` or `term` clause.
+fn leaf_query(kind: &str, value: &Value) -> PyResult<Query> {
+    let error = || PyValueError::new_err(format!("unsupported {} clause: {}", kind, value));
+    // `{"field": "text"}` or `{"field": {"query": "text", "operator": "and"}}`
+    let (text, operator) = match value.as_object().and_then(|o| o.values().next()).ok_or_else(error)? {
+        Value::String(text) => (text.as_str(), "or"),
+        Value::Object(options) => (
+            options.get("query").and_then(Value::as_str).ok_or_else(error)?,
+            options.get("operator").and_then(Value::as_str).unwrap_or("or"),
+        ),
+        _ => return Err(error()),
+    };
+    if kind != "match" {
+        return Ok(Query::atom(text));
+    }
+    let words: Vec<_> = text.split_whitespace().map(Query::atom).collect();
+    match (words.len(), operator.to_ascii_lowercase().as_str()) {
+        (0, _) => Err(error()),
+        (1, _) => Ok(words.into_iter().next().unwrap()),
+        (_, "and") => Ok(Query::And(words)),
+        _ => Ok(Query::Or(words)),
+    }
+}
+
+fn from_bool_object(object: &serde_json::Map<String, Value>) -> PyResult<Query> {
+    let clauses = |key: &str| -> PyResult<Vec<Query>> {
+        match object.get(key) {
+            None => Ok(Vec::new()),
+            Some(Value::Array(clauses)) => clauses.iter().map(from_bool_json).collect(),
+            Some(clause) => Ok(vec![from_bool_json(clause)?]),
+        }
+    };
+    if let Some(key) = object.keys().find(|k| !["must", "filter", "should", "must_not", "minimum_should_match"].contains(&k.as_str())) {
+        return Err(PyValueError::new_err(format!("unsupported bool query key: {:?}", key)));
+    }
+    let mut operands = clauses("must")?;
+    operands.extend(clauses("filter")?);
+    let should = clauses("should")?;
+    // like Elasticsearch, the should clauses are optional when there are must clauses
+    let default = usize::from(operands.is_empty());
+    let minimum = match object.get("minimum_should_match") {
+        None => default,
+        Some(value) => minimum_should_match(value, should.len())?,
+    };
+    if minimum > should.len() {
+        return Err(PyValueError::new_err(format!("minimum_should_match of {} with only {} should clauses", minimum, should.len())));
+    }
+    if minimum > 0 {
+        operands.push(at_least(should, minimum)?);
+    }
+    let must_not = clauses("must_not")?;
+    if !must_not.is_empty() {
+        operands.push(any_of(must_not).negate());
+    }
+    match operands.len() {
+        0 => Err(PyValueError::new_err("bool query without any clause")),
+        1 => Ok(operands.pop().unwrap()),
+        _ => Ok(Query::And(operands)),
+    }
+}
+
+/// `minimum_should_match` as a number of clauses out of `n`: an integer or a percentage,
+/// negative ones counting the clauses that may be missing.
+fn minimum_should_match(value: &Value, n: usize) -> PyResult<usize> {
+    let error = || PyValueError::new_err(format!("unsupported minimum_should_match: {}", value));
+    let count = match value {
+        Value::Number(number) => number.as_i64().ok_or_else(error)?,
+        Value::String(text) => match text.trim().strip_suffix('%') {
+            Some(percent) => {
+                let percent: i64 = percent.trim().parse().map_err(|_| error())?;
+                // rounded down, like Elasticsearch
+                percent.signum() * (n as i64 * percent.abs() / 100)
+            }
+            None => text.trim().parse().map_err(|_| error())?,
+        },
+        _ => return Err(error()),
+    };
+    Ok(if count < 0 { n.saturating_sub(count.unsigned_abs() as usize) } else { count as usize })
+}
+
+/// The OR of the queries, or the query if there's only one.
+fn any_of(mut queries: Vec<Query>) -> Query {
+    match queries.len() {
+        1 => queries.pop().unwrap(),
+        _ => Query::Or(queries),
+    }
+}
+
+/// At least `k` of the queries: any `n - k + 1` of them contain one that holds.
+fn at_least(queries: Vec<Query>, k: usize) -> PyResult<Query> {
+    let n = queries.len();
+    if k == 1 {
+        return Ok(any_of(queries));
+    }
+    if k == n {
+        return Ok(Query::And(queries));
+    }
+    let mut subsets = Vec::new();
+    let mut indices: Vec<usize> = (0..n - k + 1).collect();
+    loop {
+        if subsets.len() >= MAX_CLAUSES {
+            return Err(PyValueError::new_err("minimum_should_match expands into too many AND groups"));
+        }
+        subsets.push(Query::Or(indices.iter().map(|&i| queries[i].clone()).collect()));
+        // the next combination in lexicographic order
+        let size = indices.len();
+        match (0..size).rev().find(|&i| indices[i] < n - size + i) {
+            None => break,
+            Some(i) => {
+                indices[i] += 1;
+                for j in i + 1..size {
+                    indices[j] = indices[j - 1] + 1;
+                }
+            }
+        }
+    }
+    Ok(Query::And(subsets))
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operand = |f: &mut fmt::Formatter<'_>, query: &Query| match query {
+            Query::And(_) | Query::Or(_) => write!(f, "({})", query),
+            _ => write!(f, "{}", query),
+        };
+        match self {
+            Query::Atom(atom) => write!(f, "Q({:?})", atom),
+            Query::Not(query) => {
+                write!(f, "~")?;
+                operand(f, query)
+            }
+            Query::And(operands) | Query::Or(operands) => {
+                let op = if matches!(self, Query::And(_)) { " & " } else { " | " };
+                for (i, query) in operands.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "{}", op)?;
+                    }
+                    operand(f, query)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A query built from atoms with `&`, `|` and `~`, e.g. `Q("foo") & (Q("bar") | ~Q("baz"))`.
+#[pyclass(name = "Query")]
+#[derive(Clone)]
+pub struct PyQuery {
+    pub query: Query,
+}
+
+#[pymethods]
+impl PyQuery {
+    #[new]
+    fn py_new(atom: String) -> Self {
+        PyQuery {
+            query: Query::Atom(atom),
+        }
+    }
+
+    fn __and__(&self, other: PyRef<PyQuery>) -> PyQuery {
+        PyQuery {
+            query: self.query.clone().and(other.query.clone()),
+        }
+    }
+
+    fn __or__(&self, other: PyRef<PyQuery>) -> PyQuery {
+        PyQuery {
+            query: self.query.clone().or(other.query.clone()),
+        }
+    }
+
+    /// `Q("a") >> Q("b")`: `a` followed by `b`.
+    fn __rshift__(&self, other: PyRef<PyQuery>) -> PyResult<PyQuery> {
+        Ok(PyQuery {
+            query: self.query.clone().then(other.query.clone())?,
+        })
+    }
+
+    fn __invert__(&self) -> PyQuery {
+        PyQuery {
+            query: self.query.clone().negate(),
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        self.query.to_string()
+    }
+
+    /// The query of a query string, e.g. `foo AND (bar OR "baz qux") AND NOT quux`.
+    #[staticmethod]
+    #[pyo3(name = "parse")]
+    fn py_parse(text: &str) -> PyResult<PyQuery> {
+        Ok(PyQuery {
+            query: Query::parse(text)?,
+        })
+    }
+
+    /// The query of an Elasticsearch-style bool query, given as a dict or as JSON.
+    #[staticmethod]
+    fn from_bool(py: Python<'_>, query: &PyAny) -> PyResult<PyQuery> {
+        let json: String = match query.extract() {
+            Ok(json) => json,
+            Err(_) => py.import("json")?.call_method1("dumps", (query,))?.extract()?,
+        };
+        let value: Value = serde_json::from_str(&json).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyQuery {
+            query: from_bool_json(&value)?,
+        })
+    }
+
+    /// The query group matching the query, with the same options as `QueryGroup`.
+    #[pyo3(signature = (kana_folding = false, romaji = false, romaji_dictionary = None, phonetic = false, case_folding = None, cjk_gap = None, within = None, apart = None, within_lines = None, whole_words = false, nfkc = false, fuzzy = None, word_gap = None, hyphens = false))]
+    #[allow(clippy::too_many_arguments)]
+    fn compile(
+        &self,
+        kana_folding: bool,
+        romaji: bool,
+        romaji_dictionary: Option<std::collections::HashMap<String, Vec<String>>>,
+        phonetic: bool,
+        case_folding: Option<&str>,
+        cjk_gap: Option<String>,
+        within: Option<usize>,
+        apart: Option<usize>,
+        within_lines: Option<usize>,
+        whole_words: bool,
+        nfkc: bool,
+        fuzzy: Option<usize>,
+        word_gap: Option<String>,
+        hyphens: bool,
+    ) -> PyResult<QueryGroup> {
+        let query_group = self.query.compile(QueryOptions {
+            kana_folding,
+            romaji,
+            romaji_dictionary: romaji_dictionary.unwrap_or_default(),
+            phonetic,
+            case_folding: case_folding.map(CaseFold::from_name).transpose()?,
+            separators: Separators {
+                words: word_gap,
+                cjk: cjk_gap,
+                hyphens,
+            },
+            whole_words,
+            nfkc,
+            fuzzy,
+            ..Default::default()
+        })?;
+        Ok(QueryGroup {
+            within,
+            within_lines,
+            apart,
+            ..query_group
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::QueryGroup;
+    use super::{from_bool_json, Query};
+
+    #[test]
+    fn test_parse() {
+        let parse = |text: &str| Query::parse(text).map(|q| q.to_string()).ok();
+        assert_eq!(parse("foo AND (bar OR baz) AND NOT qux").as_deref(), Some(r#"Q("foo") & (Q("bar") | Q("baz")) & ~Q("qux")"#));
+        assert_eq!(parse(r#"disk full OR "AND \"gate\"" AND x"#).as_deref(), Some(r#"Q("disk full") | (Q("AND \"gate\"") & Q("x"))"#));
+        assert_eq!(parse("NOT NOT a and b").as_deref(), Some(r#"Q("a and b")"#));
+        let query_group = QueryGroup::parse("a AND NOT b").unwrap();
+        assert_eq!((query_group.atoms, query_group.excluded_atoms), (vec![vec!["a".to_string()]], vec![vec!["b".to_string()]]));
+        assert_eq!(parse("x AND intro THEN \"data set\" THEN results").as_deref(), Some(r#"Q("x") & Q("then:intro ... data set ... results")"#));
+        for invalid in ["", "a AND", "(a OR b", "a)", "\"a", "a OR OR b", "\"\" AND a", "a THEN (b OR c)", "a THEN"] {
+            assert_eq!(parse(invalid), None, "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_to_or_groups() {
+        let q = |atom: &str| Query::atom(atom);
+        let groups = |query: Query| query.to_or_groups().ok();
+        let strings = |atoms: &[&[&str]]| -> Vec<Vec<String>> {
+            atoms.iter().map(|or| or.iter().map(|a| a.to_string()).collect()).collect()
+        };
+
+        let query = q("foo").and(q("bar").or(q("baz")));
+        assert_eq!(query.to_string(), r#"Q("foo") & (Q("bar") | Q("baz"))"#);
+        assert_eq!(groups(query), Some((strings(&[&["foo"], &["bar", "baz"]]), strings(&[]))));
+
+        // (a & b) | c == (a | c) & (b | c)
+        let query = q("a").and(q("b")).or(q("c"));
+        assert_eq!(groups(query), Some((strings(&[&["a", "c"], &["b", "c"]]), strings(&[]))));
+
+        // a & ~(b | c) == a & ~b & ~c
+        let query = q("a").and(q("b").or(q("c")).negate());
+        assert_eq!(query.to_string(), r#"Q("a") & ~(Q("b") | Q("c"))"#);
+        assert_eq!(groups(query), Some((strings(&[&["a"]]), strings(&[&["b"], &["c"]]))));
+
+        assert_eq!(groups(q("a").negate().negate()), Some((strings(&[&["a"]]), strings(&[]))));
+        assert_eq!(groups(q("a").and(q("b").and(q("c")).negate())), None);
+        assert_eq!(groups(q("a").or(q("b").negate())), None);
+        assert_eq!(groups(q("a").negate()), None);
+    }
+
+    #[test]
+    fn test_from_bool_json() {
+        let query = |json: &str| from_bool_json(&serde_json::from_str(json).unwrap()).map(|q| q.to_string()).ok();
+        assert_eq!(
+            query(r#"{"bool": {"must": ["foo"], "should": ["bar", "baz"], "must_not": {"match_phrase": {"body": "qux quux"}}}}"#).as_deref(),
+            Some(r#"Q("foo") & ~Q("qux quux")"#),
+        );
+        assert_eq!(query(r#"{"should": ["a", "b"]}"#).as_deref(), Some(r#"Q("a") | Q("b")"#));
+        assert_eq!(
+            query(r#"{"must": "a", "should": ["b", {"match": {"f": {"query": "c d", "operator": "and"}}}], "minimum_should_match": 1}"#).as_deref(),
+            Some(r#"Q("a") & (Q("b") | (Q("c") & Q("d")))"#),
+        );
+        assert_eq!(
+            query(r#"{"should": ["a", "b", "c"], "minimum_should_match": "67%"}"#).as_deref(),
+            Some(r#"(Q("a") | Q("b")) & (Q("a") | Q("c")) & (Q("b") | Q("c"))"#),
+        );
+        assert_eq!(query(r#"{"should": ["a", "b"], "minimum_should_match": -1}"#).as_deref(), Some(r#"Q("a") | Q("b")"#));
+        assert_eq!(query(r#"{"should": ["a"], "minimum_should_match": 2}"#), None);
+        assert_eq!(query(r#"{"must": ["a"], "boost": 2}"#), None);
+        assert_eq!(query(r#"{"range": {"year": {"gte": 2020}}}"#), None);
+    }
+}
@@ -0,0 +1,112 @@
+//! Searches restricted to parts of files, e.g. the passages a previous pass found, so that a
+//! follow-up query only reads and matches those in large files.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use crate::encoding::{self, EncodingHints};
+
+/// What the ranges of a `FileRanges` count.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RangeUnit {
+    #[default]
+    Bytes,
+    Lines,
+}
+
+impl RangeUnit {
+    pub fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "bytes" => Ok(RangeUnit::Bytes),
+            "lines" => Ok(RangeUnit::Lines),
+            _ => Err(PyValueError::new_err(format!("unknown range unit {:?}, expected \"bytes\" or \"lines\"", name))),
+        }
+    }
+}
+
+/// The `start..end` ranges to search of each file, in bytes or in lines counted from 0. Files
+/// without ranges are searched whole. The ranges of a file are searched as if they were lines
+/// of their own, so that no match spans two of them.
+#[derive(Clone, Debug, Default)]
+pub struct FileRanges {
+    pub unit: RangeUnit,
+    ranges: HashMap<String, Vec<(usize, usize)>>,
+}
+
+impl FileRanges {
+    pub fn new(ranges: HashMap<String, Vec<(usize, usize)>>, unit: RangeUnit) -> PyResult<Self> {
+        if let Some((path, (start, end))) = ranges.iter().find_map(|(path, ranges)| ranges.iter().find(|(start, end)| start > end).map(|range| (path, range))) {
+            return Err(PyValueError::new_err(format!("range {}..{} of {} ends before it starts", start, end, path)));
+        }
+        Ok(FileRanges {
+            unit,
+            ranges,
+        })
+    }
+
+    /// The ranges of `path`, if restricted.
+    pub fn get(&self, path: &str) -> Option<&[(usize, usize)]> {
+        self.ranges.get(path).map(Vec::as_slice)
+    }
+
+    /// The ranges of all the files, sorted by path, e.g. to hash them.
+    pub fn sorted(&self) -> Vec<(&str, &[(usize, usize)])> {
+        let mut sorted: Vec<_> = self.ranges.iter().map(|(path, ranges)| (path.as_str(), ranges.as_slice())).collect();
+        sorted.sort_unstable();
+        sorted
+    }
+}
+
+/// Read the `ranges` of `path`, decoded like `encoding::read_to_string` does, one per line.
+/// Byte ranges are read on their own, leaving out the characters they cut short at the end, and
+/// the UTF-8 characters they cut short at the start.
+pub(crate) fn read_ranges(path: &str, hints: &EncodingHints, ranges: &[(usize, usize)], unit: RangeUnit) -> io::Result<String> {
+    let parts = match unit {
+        RangeUnit::Bytes => {
+            let mut file = fs::File::open(path)?;
+            let mut parts = Vec::with_capacity(ranges.len());
+            for &(start, end) in ranges.iter() {
+                let mut bytes = Vec::new();
+                file.seek(SeekFrom::Start(start as u64))?;
+                (&mut file).take((end - start) as u64).read_to_end(&mut bytes)?;
+                if hints.encoding_for(path).is_none() {
+                    // UTF-8 continuation bytes of a character starting before the range
+                    let skipped = bytes.iter().take_while(|&&b| b & 0xc0 == 0x80).count();
+                    bytes.drain(..skipped);
+                }
+                let len = bytes.len() as u64;
+                parts.push(encoding::decode_head(path, bytes, hints, len)?);
+            }
+            parts
+        }
+        RangeUnit::Lines => {
+            let contents = encoding::read_to_string(path, hints)?;
+            let lines: Vec<_> = contents.lines().collect();
+            ranges
+                .iter()
+                .map(|&(start, end)| lines[start.min(lines.len())..end.min(lines.len())].join("\n"))
+                .collect()
+        }
+    };
+    Ok(parts.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::encoding::EncodingHints;
+    use super::{read_ranges, FileRanges, RangeUnit};
+
+    #[test]
+    fn test_read_ranges() {
+        let hints = EncodingHints::default();
+        assert_eq!(read_ranges("sample_texts/hello.txt", &hints, &[(4, 7), (0, 3)], RangeUnit::Bytes).unwrap(), "bar\nfoo");
+        assert_eq!(read_ranges("sample_texts/hello.txt", &hints, &[(1, 2)], RangeUnit::Lines).unwrap(), "baz");
+        assert_eq!(read_ranges("sample_texts/hello.txt", &hints, &[(5, 100)], RangeUnit::Lines).unwrap(), "");
+        let gbk = EncodingHints::new(&[("sample_texts/gbk*".to_string(), "gbk".to_string())]).unwrap();
+        assert_eq!(read_ranges("sample_texts/gbk.txt", &gbk, &[(2, 7)], RangeUnit::Bytes).unwrap(), "是中");
+        assert!(FileRanges::new(HashMap::from([("a".to_string(), vec![(3, 1)])]), RangeUnit::Bytes).is_err());
+    }
+}
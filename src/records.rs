@@ -0,0 +1,317 @@
+//! Searches of files made of records, e.g. the rows of CSV files, the lines of JSONL files or
+//! the messages of mbox files, reporting which records match rather than which files.
+
+use mailparse::{MailHeaderMap, ParsedMail};
+use pyo3::prelude::*;
+use crate::{match_contents, scan_files, QueryGroup, SearchOptions};
+
+/// A record of a file matching a query.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordMatch {
+    #[pyo3(get)]
+    pub path: String,
+
+    /// Index of the record in the file, from 0, e.g. of the row of a CSV file not counting the
+    /// header, of the line of a JSONL file or of the message of an mbox file.
+    #[pyo3(get)]
+    pub record: usize,
+
+    #[pyo3(get)]
+    pub context: Option<String>,
+}
+
+#[pymethods]
+impl RecordMatch {
+    fn __repr__(&self) -> String {
+        format!("RecordMatch(path={:?}, record={})", self.path, self.record)
+    }
+}
+
+/// A column of a CSV file, by index from 0 or by name in the header.
+#[derive(Clone, Debug, PartialEq, Eq, FromPyObject)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+/// How CSV files are read and matched.
+#[derive(Clone, Debug, Default)]
+pub struct CsvOptions {
+    /// Field delimiter, by default `\t` for `.tsv` files and `,` otherwise.
+    pub delimiter: Option<u8>,
+    /// Whether the first row is a header naming the columns rather than a record.
+    pub headers: bool,
+    /// The columns matched, one per line, by default all of them.
+    pub columns: Vec<Column>,
+    /// Give the whole row, its fields separated by the delimiter, as the context of a match.
+    pub whole_row: bool,
+}
+
+/// The indices of `columns` in a file with the `header`, or `None` if one isn't in it.
+fn column_indices(columns: &[Column], header: Option<&csv::StringRecord>) -> Option<Vec<usize>> {
+    columns
+        .iter()
+        .map(|column| match column {
+            Column::Index(i) => Some(*i),
+            Column::Name(name) => header?.iter().position(|field| field == name),
+        })
+        .collect()
+}
+
+fn search_csv_file(query_group: &QueryGroup, path: &str, contents: &str, options: &SearchOptions, csv_options: &CsvOptions) -> Vec<RecordMatch> {
+    let delimiter = csv_options.delimiter.unwrap_or(if path.to_ascii_lowercase().ends_with(".tsv") { b'\t' } else { b',' });
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(csv_options.headers)
+        .flexible(true)
+        .from_reader(contents.as_bytes());
+    let header = csv_options.headers.then(|| reader.headers().ok().cloned()).flatten();
+    let indices = match csv_options.columns.is_empty() {
+        true => None,
+        // a file without the columns has nothing to match
+        false => match column_indices(&csv_options.columns, header.as_ref()) {
+            Some(indices) => Some(indices),
+            None => return Vec::new(),
+        },
+    };
+    let mut matches = Vec::new();
+    for (record, row) in reader.records().enumerate() {
+        // rows after a malformed one can't be told apart reliably
+        let Ok(row) = row else {
+            break;
+        };
+        let fields: Vec<_> = match &indices {
+            None => row.iter().collect(),
+            Some(indices) => indices.iter().filter_map(|&i| row.get(i)).collect(),
+        };
+        if let Some((result, _)) = match_contents(query_group, path, &fields.join("\n"), options) {
+            let context = match csv_options.whole_row {
+                true => Some(row.iter().collect::<Vec<_>>().join(&char::from(delimiter).to_string())),
+                false => result.context().map(String::from),
+            };
+            matches.push(RecordMatch {
+                path: path.to_string(),
+                record,
+                context,
+            });
+        }
+    }
+    matches
+}
+
+fn search_jsonl_file(query_group: &QueryGroup, path: &str, contents: &str, options: &SearchOptions) -> Vec<RecordMatch> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(record, line)| {
+            let (result, _) = match_contents(query_group, path, line, options)?;
+            Some(RecordMatch {
+                path: path.to_string(),
+                record,
+                context: result.context().map(String::from),
+            })
+        })
+        .collect()
+}
+
+/// The headers of a message matched, before its text.
+const EMAIL_HEADERS: [&str; 5] = ["Subject", "From", "To", "Cc", "Date"];
+
+/// The messages of an mbox file, each starting with a `From ` line, or the whole of an email
+/// that isn't one. Lines quoted as `>From ` in the messages are unquoted.
+fn split_messages(contents: &str) -> Vec<String> {
+    if !contents.starts_with("From ") {
+        return vec![contents.to_string()];
+    }
+    let mut messages: Vec<String> = Vec::new();
+    for line in contents.split_inclusive('\n') {
+        if line.starts_with("From ") {
+            messages.push(String::new());
+            continue;
+        }
+        let message = messages.last_mut().unwrap();
+        match line.trim_start_matches('>').starts_with("From ") {
+            true => message.push_str(&line[1..]),
+            false => message.push_str(line),
+        }
+    }
+    messages
+}
+
+/// Push the decoded text parts of `mail` to `text`; HTML parts are only used without a plain
+/// text alternative.
+fn push_text_parts(mail: &ParsedMail, text: &mut Vec<String>) {
+    if mail.subparts.is_empty() {
+        if mail.ctype.mimetype.starts_with("text/") {
+            text.extend(mail.get_body().ok());
+        }
+        return;
+    }
+    let plain = mail.subparts.iter().any(|part| part.ctype.mimetype == "text/plain");
+    for part in mail.subparts.iter() {
+        if mail.ctype.mimetype == "multipart/alternative" && plain && part.ctype.mimetype != "text/plain" {
+            continue;
+        }
+        push_text_parts(part, text);
+    }
+}
+
+/// The text of an email that's matched: its decoded `EMAIL_HEADERS`, one per line, then its text
+/// parts, decoded from quoted-printable or base64 and their charsets.
+fn email_text(message: &str) -> Option<String> {
+    let mail = mailparse::parse_mail(message.as_bytes()).ok()?;
+    let mut text: Vec<_> = EMAIL_HEADERS
+        .iter()
+        .filter_map(|name| Some(format!("{}: {}", name, mail.headers.get_first_value(name)?)))
+        .collect();
+    push_text_parts(&mail, &mut text);
+    Some(text.join("\n"))
+}
+
+fn search_email_file(query_group: &QueryGroup, path: &str, contents: &str, options: &SearchOptions) -> Vec<RecordMatch> {
+    split_messages(contents)
+        .iter()
+        .enumerate()
+        .filter_map(|(record, message)| {
+            let (result, _) = match_contents(query_group, path, &email_text(message)?, options)?;
+            Some(RecordMatch {
+                path: path.to_string(),
+                record,
+                context: result.context().map(String::from),
+            })
+        })
+        .collect()
+}
+
+/// The matches of `search_file` in the contents of each of `paths`, in their order.
+fn search_records<F>(paths: &[String], options: &SearchOptions, search_file: F) -> Vec<RecordMatch>
+where
+    F: Fn(&str, &str) -> Vec<RecordMatch> + Sync,
+{
+    scan_files(paths, options, search_file).0
+}
+
+/// The rows of the CSV files whose columns match `query_group`, each row matched on its own, in
+/// the order of the files and of their rows. Unreadable files, and rows after a malformed one,
+/// aren't searched.
+pub fn search_csv(query_group: &QueryGroup, paths: &[String], options: &SearchOptions, csv_options: &CsvOptions) -> Vec<RecordMatch> {
+    search_records(paths, options, |path, contents| search_csv_file(query_group, path, contents, options, csv_options))
+}
+
+/// The lines of the JSONL files matching `query_group`, each line matched on its own, with the
+/// normalizers applied to it, in the order of the files and of their lines. Putting a
+/// `normalize::JsonFields` first in the normalizers matches only some fields of each record.
+/// Blank lines are never matched, but still counted.
+pub fn search_jsonl(query_group: &QueryGroup, paths: &[String], options: &SearchOptions) -> Vec<RecordMatch> {
+    search_records(paths, options, |path, contents| search_jsonl_file(query_group, path, contents, options))
+}
+
+/// The messages of the mbox files, or the emails (e.g. `.eml` files), matching `query_group`,
+/// each message matched on its own, in the order of the files and of their messages. A message
+/// is matched on its subject, sender, recipients and date, then its text parts, all decoded.
+/// Files that aren't emails aren't matched.
+pub fn search_email(query_group: &QueryGroup, paths: &[String], options: &SearchOptions) -> Vec<RecordMatch> {
+    search_records(paths, options, |path, contents| search_email_file(query_group, path, contents, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::{ContextOptions, QueryGroup, SearchOptions};
+    use std::sync::Arc;
+    use crate::normalize::JsonFields;
+    use super::{email_text, search_csv, search_email, search_jsonl, split_messages, Column, CsvOptions};
+
+    #[test]
+    fn test_search_csv() {
+        let path = std::env::temp_dir().join(format!("textsearcher-records-{}.csv", std::process::id()));
+        fs::write(&path, "title,notes\nfoo server,bar\n\"a, b\",foo\nbaz,\"foo \"\"server\"\"\"\n").unwrap();
+        let paths = vec![path.to_string_lossy().into_owned()];
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string()], vec!["server".to_string()]]).unwrap();
+        let csv_options = CsvOptions {
+            headers: true,
+            ..Default::default()
+        };
+        let records: Vec<_> = search_csv(&query_group, &paths, &SearchOptions::default(), &csv_options).iter().map(|m| m.record).collect();
+        assert_eq!(records, vec![0, 2]);
+
+        let titles = CsvOptions {
+            headers: true,
+            columns: vec![Column::Name("title".to_string())],
+            whole_row: true,
+            ..Default::default()
+        };
+        let matches = search_csv(&query_group, &paths, &SearchOptions::default(), &titles);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].context.as_deref(), Some("foo server,bar"));
+
+        let options = SearchOptions {
+            context: Some(ContextOptions {
+                before: 0,
+                after: 3,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let notes = CsvOptions {
+            headers: true,
+            columns: vec![Column::Index(1)],
+            ..Default::default()
+        };
+        let matches = search_csv(&QueryGroup::new(vec![vec!["foo".to_string()]]).unwrap(), &paths, &options, &notes);
+        let contexts: Vec<_> = matches.iter().map(|m| (m.record, m.context.as_deref())).collect();
+        assert_eq!(contexts, vec![(1, Some("foo")), (2, Some("foo \"s"))]);
+        let missing = CsvOptions {
+            headers: true,
+            columns: vec![Column::Name("body".to_string())],
+            ..Default::default()
+        };
+        assert!(search_csv(&query_group, &paths, &SearchOptions::default(), &missing).is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_search_jsonl() {
+        let path = std::env::temp_dir().join(format!("textsearcher-records-{}.jsonl", std::process::id()));
+        fs::write(&path, "{\"title\": \"foo\", \"body\": \"bar\"}\n\n{\"title\": \"bar\", \"body\": \"foo\"}\n").unwrap();
+        let paths = vec![path.to_string_lossy().into_owned()];
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string()]]).unwrap();
+        let records: Vec<_> = search_jsonl(&query_group, &paths, &SearchOptions::default()).iter().map(|m| m.record).collect();
+        assert_eq!(records, vec![0, 2]);
+        let options = SearchOptions {
+            normalizers: vec![Arc::new(JsonFields::new(vec!["/body".to_string()]).unwrap())],
+            context: Some(ContextOptions::default()),
+            ..Default::default()
+        };
+        let matches = search_jsonl(&query_group, &paths, &options);
+        assert_eq!((matches.len(), matches[0].record, matches[0].context.as_deref()), (1, 2, Some("foo")));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_search_email() {
+        let mbox = concat!(
+            "From ann@example.com Mon Jan  1 00:00:00 2024\n",
+            "Subject: =?UTF-8?Q?Caf=C3=A9?=\nFrom: Ann <ann@example.com>\n\nsee you\n>From here\n\n",
+            "From bob@example.com Tue Jan  2 00:00:00 2024\n",
+            "Subject: disk\nMIME-Version: 1.0\nContent-Type: multipart/alternative; boundary=b\n\n",
+            "--b\nContent-Type: text/plain; charset=utf-8\nContent-Transfer-Encoding: base64\n\nc2VydmVyIGZ1bGw=\n",
+            "--b\nContent-Type: text/html\n\n<p>server full</p>\n--b--\n",
+        );
+        let messages = split_messages(mbox);
+        assert_eq!(messages.len(), 2);
+        let text = email_text(&messages[0]).unwrap();
+        assert!(text.starts_with("Subject: Café\nFrom: Ann <ann@example.com>\nsee you\nFrom here"));
+        assert_eq!(email_text(&messages[1]).unwrap().trim_end(), "Subject: disk\nserver full");
+
+        let path = std::env::temp_dir().join(format!("textsearcher-records-{}.mbox", std::process::id()));
+        fs::write(&path, mbox).unwrap();
+        let paths = vec![path.to_string_lossy().into_owned()];
+        let query_group = QueryGroup::new(vec![vec!["server".to_string()], vec!["disk".to_string()]]).unwrap();
+        let records: Vec<_> = search_email(&query_group, &paths, &SearchOptions::default()).iter().map(|m| m.record).collect();
+        assert_eq!(records, vec![1]);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,310 @@
+//! Rewriting the matches of a query across files, previewed as a diff before being applied.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use encoding_rs::Encoding;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use crate::binary::{self, BinaryPolicy};
+use crate::cache::Fingerprint;
+use crate::encoding;
+use crate::pattern::PatternCaptures;
+use crate::{is_match_str, QueryGroup, SearchOptions};
+
+/// The planned rewrite of one file: the matches of the query group's first pattern, replaced by
+/// the expanded template.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct FileEdit {
+    path: String,
+    encoding: Option<&'static Encoding>,
+    fingerprint: Fingerprint,
+    /// Whether the file decoded without errors, so that rewriting it keeps the rest of its bytes.
+    lossless: bool,
+    /// The text the file decoded to, a byte order mark kept as U+FEFF.
+    original: String,
+    /// (start, end, replacement) of each replaced match of `original`, in order.
+    edits: Vec<(usize, usize, String)>,
+}
+
+/// Expand `$1`, `${1}`, `$name` and `${name}` in `template` to the captured text, and `$$` to
+/// `$`, like `regex::Captures::expand`. Groups that don't exist or didn't participate expand to
+/// nothing.
+pub fn expand(template: &str, caps: &PatternCaptures<'_>, names: &[Option<String>]) -> String {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(i) = rest.find('$') {
+        expanded.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+        let (group, after) = match rest.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(j) => (&braced[..j], &braced[j + 1..]),
+                None => ("", rest),
+            },
+            None => {
+                let j = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                (&rest[..j], &rest[j..])
+            }
+        };
+        if group.is_empty() {
+            expanded.push('$');
+            continue;
+        }
+        let index = match group.parse::<usize>() {
+            Ok(index) => Some(index),
+            Err(_) => names.iter().position(|n| n.as_deref() == Some(group)),
+        };
+        if let Some(m) = index.and_then(|i| caps.get(i)) {
+            expanded.push_str(m.as_str());
+        }
+        rest = after;
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Number of lines of `text`, counting a last line without newline.
+fn count_lines(text: &str) -> usize {
+    text.matches('\n').count() + usize::from(!text.is_empty() && !text.ends_with('\n'))
+}
+
+/// A line range in the `-l,s`/`+l,s` form of unified diff hunk headers, `before` being the number
+/// of lines before the range.
+fn hunk_range(before: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", before),
+        1 => format!("{}", before + 1),
+        _ => format!("{},{}", before + 1, count),
+    }
+}
+
+fn push_lines(diff: &mut String, prefix: char, text: &str) {
+    for line in text.split_inclusive('\n') {
+        diff.push(prefix);
+        diff.push_str(line);
+        if !line.ends_with('\n') {
+            diff.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+impl FileEdit {
+    fn plan(query_group: &QueryGroup, path: &str, template: &str, options: &SearchOptions) -> Option<Self> {
+        let fingerprint = Fingerprint::of(path).ok()?;
+        // bytes that don't decode are replaced by U+FFFD, and would be written back as such
+        let (original, encoding, lossless) = encoding::decode_exact(path, &fs::read(path).ok()?, &options.encoding_hints).ok()?;
+        if binary::is_binary(original.as_bytes()) && options.binary != BinaryPolicy::SearchAsLossyText {
+            return None;
+        }
+        if !is_match_str(query_group, &original) {
+            return None;
+        }
+        let pattern = &query_group.patterns[0];
+        let names = pattern.capture_names();
+        let edits: Vec<_> = pattern
+            .captures_iter(&original)
+            .filter_map(|caps| {
+                let m = caps.get(0)?;
+                let replacement = expand(template, &caps, &names);
+                if replacement == m.as_str() {
+                    None
+                } else {
+                    Some((m.start(), m.end(), replacement))
+                }
+            })
+            .collect();
+        if edits.is_empty() {
+            return None;
+        }
+        Some(FileEdit {
+            path: path.to_string(),
+            encoding,
+            fingerprint,
+            lossless,
+            original,
+            edits,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Number of matches that are replaced.
+    pub fn replacements(&self) -> usize {
+        self.edits.len()
+    }
+
+    /// Replace `original[start..end]`, which must contain whole edits, according to the edits.
+    fn replace_range(&self, start: usize, end: usize) -> String {
+        let mut replaced = String::new();
+        let mut last = start;
+        for (s, e, replacement) in self.edits.iter().filter(|(s, e, _)| start <= *s && *e <= end) {
+            replaced.push_str(&self.original[last..*s]);
+            replaced.push_str(replacement);
+            last = *e;
+        }
+        replaced.push_str(&self.original[last..end]);
+        replaced
+    }
+
+    /// The contents after the rewrite.
+    pub fn replaced(&self) -> String {
+        self.replace_range(0, self.original.len())
+    }
+
+    /// The rewrite as a unified diff without context lines, as `diff -U0` would output.
+    pub fn diff(&self) -> String {
+        let text = &self.original;
+        // the whole lines touched by the edits, merging edits that touch the same lines
+        let mut hunks: Vec<(usize, usize)> = Vec::new();
+        for (start, end, _) in self.edits.iter() {
+            let line_start = text[..*start].rfind('\n').map_or(0, |i| i + 1);
+            let last = if end > start { end - 1 } else { *start };
+            let line_end = text.as_bytes()[last..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(text.len(), |i| last + i + 1);
+            match hunks.last_mut() {
+                Some(hunk) if line_start < hunk.1 => hunk.1 = hunk.1.max(line_end),
+                _ => hunks.push((line_start, line_end)),
+            }
+        }
+        let mut diff = format!("--- {}\n+++ {}\n", self.path, self.path);
+        let mut old_before = 0;
+        let mut last = 0;
+        let mut delta: isize = 0;
+        for (start, end) in hunks {
+            old_before += text[last..start].matches('\n').count();
+            last = start;
+            let old = &text[start..end];
+            let new = self.replace_range(start, end);
+            let (old_count, new_count) = (count_lines(old), count_lines(&new));
+            let new_before = (old_before as isize + delta) as usize;
+            diff.push_str(&format!("@@ -{} +{} @@\n", hunk_range(old_before, old_count), hunk_range(new_before, new_count)));
+            push_lines(&mut diff, '-', old);
+            push_lines(&mut diff, '+', &new);
+            delta += new_count as isize - old_count as isize;
+        }
+        diff
+    }
+}
+
+#[pymethods]
+impl FileEdit {
+    #[getter(path)]
+    fn py_path(&self) -> String {
+        self.path.clone()
+    }
+
+    #[getter(replacements)]
+    fn py_replacements(&self) -> usize {
+        self.replacements()
+    }
+
+    #[pyo3(name = "replaced")]
+    fn py_replaced(&self) -> String {
+        self.replaced()
+    }
+
+    #[pyo3(name = "diff")]
+    fn py_diff(&self) -> String {
+        self.diff()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("FileEdit(path={:?}, replacements={})", self.path, self.edits.len())
+    }
+}
+
+/// Plan the replacement of the matches of the first pattern of `query_group` by `template` in
+/// the files matching the whole group. Nothing is written until the edits are applied. Files are
+/// read whole in the encoding hinted or detected by `options`, whatever their head, ranges, code
+/// or decompression options, and binary files are left out unless searched as text.
+pub fn plan_replace(query_group: &QueryGroup, paths: &[String], template: &str, options: &SearchOptions) -> Vec<FileEdit> {
+    if options.parallel {
+        paths
+            .par_iter()
+            .filter_map(|path| FileEdit::plan(query_group, path, template, options))
+            .collect()
+    } else {
+        paths
+            .iter()
+            .filter_map(|path| FileEdit::plan(query_group, path, template, options))
+            .collect()
+    }
+}
+
+/// Write the planned edits, first copying each file to its path suffixed with `backup_suffix`
+/// if given. Fails without writing anything if any file changed since the edits were planned,
+/// had bytes that didn't decode in its encoding, which rewriting it would lose, has a replacement
+/// its encoding can't represent, or already has a backup, which isn't overwritten.
+///
+/// The rewritten files are all written next to the originals before any original is replaced,
+/// and the written ones removed if one fails. Each is then renamed over its original, which
+/// replaces it atomically, but a rename failing part way, e.g. for an original removed
+/// meanwhile, leaves the files renamed before it rewritten and the others untouched.
+pub fn apply_edits(edits: &[FileEdit], backup_suffix: Option<&str>) -> io::Result<()> {
+    let mut rewrites = Vec::new();
+    for edit in edits.iter() {
+        if Fingerprint::of(&edit.path)? != edit.fingerprint {
+            return Err(io::Error::other(format!("{} changed since the replacement was planned", edit.path)));
+        }
+        let encoding = edit.encoding.map_or("UTF-8", |encoding| encoding.name());
+        if !edit.lossless {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} isn't valid {}, so it isn't rewritten", edit.path, encoding)));
+        }
+        let bytes = encoding::encode(&edit.replaced(), edit.encoding)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("the replacements in {} can't be encoded in {}", edit.path, encoding)))?;
+        let backup = backup_suffix.map(|suffix| format!("{}{}", edit.path, suffix));
+        if let Some(backup) = backup.as_ref().filter(|backup| Path::new(backup).exists()) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("the backup {} already exists", backup)));
+        }
+        rewrites.push((edit, bytes, backup));
+    }
+    let mut written = Vec::new();
+    let staged = rewrites.iter().try_for_each(|(edit, bytes, backup)| {
+        if let Some(backup) = backup {
+            // refusing to overwrite a backup made meanwhile
+            let mut file = fs::OpenOptions::new().write(true).create_new(true).open(backup)?;
+            written.push(backup.clone());
+            io::copy(&mut fs::File::open(&edit.path)?, &mut file)?;
+        }
+        let tmp_path = format!("{}.textsearcher-tmp", edit.path);
+        written.push(tmp_path.clone());
+        fs::write(&tmp_path, bytes)?;
+        fs::set_permissions(&tmp_path, fs::metadata(&edit.path)?.permissions())
+    });
+    if let Err(e) = staged {
+        for path in written.iter() {
+            let _ = fs::remove_file(path);
+        }
+        return Err(e);
+    }
+    for (edit, _, _) in rewrites.iter() {
+        fs::rename(format!("{}.textsearcher-tmp", edit.path), &edit.path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::Pattern;
+    use super::expand;
+
+    #[test]
+    fn test_expand() {
+        let pattern = Pattern::Regex(regex::Regex::new(r"(?P<y>\d{4})-(\d{2})?").unwrap());
+        let names = pattern.capture_names();
+        let caps = pattern.captures_at("on 2021-", 0).unwrap();
+        assert_eq!(expand("$y/${2}/$1$$ $3 ${y}x $yx $", &caps, &names), "2021//2021$  2021x  $");
+    }
+}
@@ -0,0 +1,281 @@
+//! Statistics about a search, to operate it responsibly over very large corpora.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use pyo3::prelude::*;
+use crate::{binary, metrics, paths, trace};
+use crate::pattern::Pattern;
+
+#[pyclass]
+#[derive(Clone, Debug, Default)]
+pub struct SearchReport {
+    /// Files considered, i.e. passing the tag filter, less the paths collapsed.
+    #[pyo3(get)]
+    pub files_total: usize,
+
+    /// Files read and matched against the query.
+    #[pyo3(get)]
+    pub files_scanned: usize,
+
+    /// Files whose outcome was taken from the result cache.
+    #[pyo3(get)]
+    pub files_cached: usize,
+
+    #[pyo3(get)]
+    pub files_matched: usize,
+
+    /// Size of the text of the scanned files.
+    #[pyo3(get)]
+    pub bytes_read: u64,
+
+    /// Number of files that couldn't be searched, by reason, e.g. "not_found", "invalid_data" or
+    /// "binary".
+    #[pyo3(get)]
+    pub files_skipped: HashMap<String, usize>,
+
+    /// Paths left out for leading to the same file as one before them, when deduplicating them.
+    #[pyo3(get)]
+    pub paths_collapsed: usize,
+
+    /// The path, reason and error message of each file skipped, sorted by path.
+    #[pyo3(get)]
+    pub errors: Vec<(String, String, String)>,
+
+    /// Seconds spent in the whole search.
+    #[pyo3(get)]
+    pub wall_time: f64,
+
+    /// Fraction of the wall time each worker thread spent searching files, by thread index.
+    #[pyo3(get)]
+    pub thread_utilization: HashMap<usize, f64>,
+
+    /// Whether the deadline of the search passed before all the files were searched, the
+    /// results being those found until then.
+    #[pyo3(get)]
+    pub timed_out: bool,
+
+    /// Whether the search was cancelled before all the files were searched, the results being
+    /// those found until then.
+    #[pyo3(get)]
+    pub cancelled: bool,
+
+    /// Whether the search stopped once `max_results` files matched, before all the files were
+    /// searched.
+    #[pyo3(get)]
+    pub limited: bool,
+
+    /// When profiling, the slowest files and the seconds spent searching each, slowest first.
+    #[pyo3(get)]
+    pub slowest_files: Vec<(String, f64)>,
+
+    /// When profiling, the seconds spent matching each pattern against all the files read, in
+    /// the order of the OR groups of the query.
+    #[pyo3(get)]
+    pub pattern_times: Vec<f64>,
+
+    /// When profiling, the number of files read each pattern matched, in the order of the OR
+    /// groups of the query, e.g. to tell which clause rules out most files.
+    #[pyo3(get)]
+    pub pattern_matches: Vec<usize>,
+}
+
+#[pymethods]
+impl SearchReport {
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+fn skip_reason(error: &io::Error) -> String {
+    if binary::is_binary_error(error) {
+        return "binary".to_string();
+    }
+    if let Some(reason) = paths::skip_reason(error) {
+        return reason.to_string();
+    }
+    match error.kind() {
+        io::ErrorKind::NotFound => "not_found".to_string(),
+        io::ErrorKind::PermissionDenied => "permission_denied".to_string(),
+        io::ErrorKind::InvalidData => "invalid_data".to_string(),
+        io::ErrorKind::FileTooLarge => "too_large".to_string(),
+        io::ErrorKind::TimedOut => "timed_out".to_string(),
+        kind => format!("{:?}", kind).to_lowercase(),
+    }
+}
+
+/// Counters shared by the workers of a search.
+#[derive(Default)]
+pub(crate) struct ScanStats {
+    files_scanned: AtomicUsize,
+    files_cached: AtomicUsize,
+    bytes_read: AtomicU64,
+    files_skipped: Mutex<HashMap<String, usize>>,
+    paths_collapsed: AtomicUsize,
+    errors: Mutex<Vec<(String, String, String)>>,
+    busy: Mutex<HashMap<usize, Duration>>,
+    timed_out: AtomicBool,
+    cancelled: AtomicBool,
+    limited: AtomicBool,
+    profile: Option<Box<Mutex<Profile>>>,
+}
+
+/// Timings recorded when profiling a search.
+#[derive(Default)]
+struct Profile {
+    /// Number of the slowest files to keep.
+    top: usize,
+    /// The slowest files so far, the fastest of them on top.
+    slowest_files: BinaryHeap<Reverse<(Duration, String)>>,
+    pattern_times: Vec<Duration>,
+    pattern_matches: Vec<usize>,
+}
+
+impl ScanStats {
+    /// Counters that also record the time spent per pattern and the `top` slowest files.
+    pub(crate) fn profiling(top: usize) -> Self {
+        ScanStats {
+            profile: Some(Box::new(Mutex::new(Profile {
+                top,
+                ..Default::default()
+            }))),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn is_profiling(&self) -> bool {
+        self.profile.is_some()
+    }
+
+    pub(crate) fn record_file_time(&self, path: &str, elapsed: Duration) {
+        if let Some(profile) = &self.profile {
+            let mut profile = profile.lock().unwrap();
+            if profile.top == 0 {
+                return;
+            }
+            profile.slowest_files.push(Reverse((elapsed, path.to_string())));
+            if profile.slowest_files.len() > profile.top {
+                profile.slowest_files.pop();
+            }
+        }
+    }
+
+    /// Time matching each of `patterns` against `contents` on its own, and count the matches.
+    pub(crate) fn record_pattern_times(&self, patterns: &[Pattern], contents: &str) {
+        if let Some(profile) = &self.profile {
+            let outcomes: Vec<_> = patterns
+                .iter()
+                .map(|pat| {
+                    let started = Instant::now();
+                    let matched = pat.is_match(contents);
+                    (started.elapsed(), matched)
+                })
+                .collect();
+            let mut profile = profile.lock().unwrap();
+            profile.pattern_times.resize(outcomes.len(), Duration::ZERO);
+            profile.pattern_matches.resize(outcomes.len(), 0);
+            for (i, (time, matched)) in outcomes.into_iter().enumerate() {
+                profile.pattern_times[i] += time;
+                profile.pattern_matches[i] += usize::from(matched);
+            }
+        }
+    }
+
+    pub(crate) fn record_read(&self, bytes: usize) {
+        self.files_scanned.fetch_add(1, Ordering::Relaxed);
+        self.bytes_read.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_cached(&self) {
+        self.files_cached.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_skip(&self, path: &str, error: &io::Error) {
+        let reason = skip_reason(error);
+        trace::file_skipped(path, &reason, error);
+        *self.files_skipped.lock().unwrap().entry(reason.clone()).or_default() += 1;
+        self.errors.lock().unwrap().push((path.to_string(), reason, error.to_string()));
+    }
+
+    pub(crate) fn record_collapsed(&self, paths: usize) {
+        self.paths_collapsed.fetch_add(paths, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_timeout(&self) {
+        self.timed_out.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_limit(&self) {
+        self.limited.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn limited(&self) -> bool {
+        self.limited.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_busy(&self, elapsed: Duration) {
+        let thread = rayon::current_thread_index().unwrap_or(0);
+        *self.busy.lock().unwrap().entry(thread).or_default() += elapsed;
+    }
+
+    /// The report of the search, `files_matched` of its `files_total` files matching in
+    /// `wall_time`. Every search ends here, which counts it in the metrics and logs it.
+    pub(crate) fn finish(self, files_total: usize, files_matched: usize, wall_time: Duration) -> SearchReport {
+        let report = self.into_report(files_total, files_matched, wall_time);
+        metrics::record(&report);
+        trace::search_done(&report);
+        report
+    }
+
+    fn into_report(self, files_total: usize, files_matched: usize, wall_time: Duration) -> SearchReport {
+        let wall = wall_time.as_secs_f64();
+        let thread_utilization = self.busy
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|(thread, busy)| (thread, if wall > 0.0 { busy.as_secs_f64() / wall } else { 0.0 }))
+            .collect();
+        let profile = self.profile.map(|profile| profile.into_inner().unwrap()).unwrap_or_default();
+        let slowest_files = profile
+            .slowest_files
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((elapsed, path))| (path, elapsed.as_secs_f64()))
+            .collect();
+        let mut errors = self.errors.into_inner().unwrap();
+        errors.sort_unstable();
+        SearchReport {
+            files_total,
+            files_scanned: self.files_scanned.into_inner(),
+            files_cached: self.files_cached.into_inner(),
+            files_matched,
+            bytes_read: self.bytes_read.into_inner(),
+            files_skipped: self.files_skipped.into_inner().unwrap(),
+            paths_collapsed: self.paths_collapsed.into_inner(),
+            errors,
+            wall_time: wall,
+            thread_utilization,
+            timed_out: self.timed_out.into_inner(),
+            cancelled: self.cancelled.into_inner(),
+            limited: self.limited.into_inner(),
+            slowest_files,
+            pattern_times: profile.pattern_times.iter().map(Duration::as_secs_f64).collect(),
+            pattern_matches: profile.pattern_matches,
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Conversion of (Hepburn or Kunrei-shiki) romaji to kana, so that romaji atoms can be
+//! expanded into alternatives matching Japanese text.
+
+/// Syllables ordered so that longer spellings are tried first.
+const SYLLABLES: &[(&str, &str)] = &[
+    ("kya", "きゃ"), ("kyu", "きゅ"), ("kyo", "きょ"),
+    ("gya", "ぎゃ"), ("gyu", "ぎゅ"), ("gyo", "ぎょ"),
+    ("sha", "しゃ"), ("shu", "しゅ"), ("sho", "しょ"), ("shi", "し"),
+    ("sya", "しゃ"), ("syu", "しゅ"), ("syo", "しょ"),
+    ("cha", "ちゃ"), ("chu", "ちゅ"), ("cho", "ちょ"), ("chi", "ち"),
+    ("tya", "ちゃ"), ("tyu", "ちゅ"), ("tyo", "ちょ"),
+    ("nya", "にゃ"), ("nyu", "にゅ"), ("nyo", "にょ"),
+    ("hya", "ひゃ"), ("hyu", "ひゅ"), ("hyo", "ひょ"),
+    ("bya", "びゃ"), ("byu", "びゅ"), ("byo", "びょ"),
+    ("pya", "ぴゃ"), ("pyu", "ぴゅ"), ("pyo", "ぴょ"),
+    ("mya", "みゃ"), ("myu", "みゅ"), ("myo", "みょ"),
+    ("rya", "りゃ"), ("ryu", "りゅ"), ("ryo", "りょ"),
+    ("tsu", "つ"),
+    ("ja", "じゃ"), ("ju", "じゅ"), ("jo", "じょ"), ("ji", "じ"),
+    ("zya", "じゃ"), ("zyu", "じゅ"), ("zyo", "じょ"),
+    ("ka", "か"), ("ki", "き"), ("ku", "く"), ("ke", "け"), ("ko", "こ"),
+    ("ga", "が"), ("gi", "ぎ"), ("gu", "ぐ"), ("ge", "げ"), ("go", "ご"),
+    ("sa", "さ"), ("si", "し"), ("su", "す"), ("se", "せ"), ("so", "そ"),
+    ("za", "ざ"), ("zi", "じ"), ("zu", "ず"), ("ze", "ぜ"), ("zo", "ぞ"),
+    ("ta", "た"), ("ti", "ち"), ("tu", "つ"), ("te", "て"), ("to", "と"),
+    ("da", "だ"), ("di", "ぢ"), ("du", "づ"), ("de", "で"), ("do", "ど"),
+    ("na", "な"), ("ni", "に"), ("nu", "ぬ"), ("ne", "ね"), ("no", "の"),
+    ("ha", "は"), ("hi", "ひ"), ("hu", "ふ"), ("fu", "ふ"), ("he", "へ"), ("ho", "ほ"),
+    ("ba", "ば"), ("bi", "び"), ("bu", "ぶ"), ("be", "べ"), ("bo", "ぼ"),
+    ("pa", "ぱ"), ("pi", "ぴ"), ("pu", "ぷ"), ("pe", "ぺ"), ("po", "ぽ"),
+    ("ma", "ま"), ("mi", "み"), ("mu", "む"), ("me", "め"), ("mo", "も"),
+    ("ya", "や"), ("yu", "ゆ"), ("yo", "よ"),
+    ("ra", "ら"), ("ri", "り"), ("ru", "る"), ("re", "れ"), ("ro", "ろ"),
+    ("wa", "わ"), ("wo", "を"),
+    ("a", "あ"), ("i", "い"), ("u", "う"), ("e", "え"), ("o", "お"),
+];
+
+fn is_vowel(ch: u8) -> bool {
+    matches!(ch, b'a' | b'i' | b'u' | b'e' | b'o')
+}
+
+/// Convert a single romaji word to hiragana, or `None` if it isn't valid romaji.
+pub(crate) fn to_hiragana(word: &str) -> Option<String> {
+    let word = word.to_ascii_lowercase();
+    let bytes = word.as_bytes();
+    let mut kana = String::new();
+    let mut i = 0;
+    'outer: while i < bytes.len() {
+        let ch = bytes[i];
+        if !ch.is_ascii_alphabetic() && ch != b'\'' && ch != b'-' {
+            return None;
+        }
+        // "n" not followed by a vowel or "y" is the moraic nasal; "n'" disambiguates e.g. "kin'en"
+        if ch == b'n' {
+            match bytes.get(i + 1) {
+                None => {
+                    kana.push('ん');
+                    i += 1;
+                    continue;
+                }
+                Some(b'\'') => {
+                    kana.push('ん');
+                    i += 2;
+                    continue;
+                }
+                Some(&next) if !is_vowel(next) && next != b'y' => {
+                    kana.push('ん');
+                    i += 1;
+                    continue;
+                }
+                _ => (),
+            }
+        }
+        // doubled consonant is the small tsu
+        if let Some(&next) = bytes.get(i + 1) {
+            if next == ch && !is_vowel(ch) {
+                kana.push('っ');
+                i += 1;
+                continue;
+            }
+            if ch == b't' && next == b'c' {
+                kana.push('っ');
+                i += 1;
+                continue;
+            }
+        }
+        if ch == b'-' {
+            kana.push('ー');
+            i += 1;
+            continue;
+        }
+        for (romaji, hira) in SYLLABLES {
+            if word[i..].starts_with(romaji) {
+                kana.push_str(hira);
+                i += romaji.len();
+                continue 'outer;
+            }
+        }
+        return None;
+    }
+    Some(kana)
+}
+
+pub(crate) fn to_katakana(hiragana: &str) -> String {
+    hiragana
+        .chars()
+        .map(|ch| {
+            if ('\u{3041}'..='\u{3096}').contains(&ch) {
+                char::from_u32(ch as u32 + 0x60).unwrap_or(ch)
+            } else {
+                ch
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_hiragana, to_katakana};
+
+    #[test]
+    fn test_to_hiragana() {
+        assert_eq!(to_hiragana("kikai").as_deref(), Some("きかい"));
+        assert_eq!(to_hiragana("gakushuu").as_deref(), Some("がくしゅう"));
+        assert_eq!(to_hiragana("Tokyo").as_deref(), Some("ときょ"));
+        assert_eq!(to_hiragana("kitte").as_deref(), Some("きって"));
+        assert_eq!(to_hiragana("shinbun").as_deref(), Some("しんぶん"));
+        assert_eq!(to_hiragana("konnichiwa").as_deref(), Some("こんにちわ"));
+        assert_eq!(to_hiragana("kin'en").as_deref(), Some("きんえん"));
+        assert_eq!(to_hiragana("matcha").as_deref(), Some("まっちゃ"));
+        assert_eq!(to_hiragana("sa-ba-").as_deref(), Some("さーばー"));
+        assert_eq!(to_hiragana("xyz"), None);
+        assert_eq!(to_katakana("さーばー"), "サーバー");
+    }
+}
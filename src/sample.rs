@@ -0,0 +1,135 @@
+//! Random sampling of the matching files during the scan, without collecting all the results.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::report::SearchReport;
+use crate::tags::{TagFilter, Tags};
+use crate::{file_entries, scan_entries, score, FileMatchResult, FilePaths, QueryGroup, SearchOptions};
+
+/// Number of files searched, in parallel, between updates of the sample.
+const CHUNK_SIZE: usize = 4096;
+
+/// SplitMix64, which is plenty for sampling and spares a dependency.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`, up to a negligible bias.
+    fn below(&mut self, n: usize) -> usize {
+        ((self.next() as u128 * n as u128) >> 64) as usize
+    }
+}
+
+/// A uniform sample of at most `k` of the items offered, in the order they were offered.
+struct Reservoir<T> {
+    k: usize,
+    offered: usize,
+    items: Vec<(usize, T)>,
+    rng: SplitMix64,
+}
+
+impl<T> Reservoir<T> {
+    fn new(k: usize, seed: u64) -> Self {
+        Reservoir {
+            k,
+            offered: 0,
+            items: Vec::with_capacity(k),
+            rng: SplitMix64(seed),
+        }
+    }
+
+    fn offer(&mut self, item: T) {
+        let i = self.offered;
+        self.offered += 1;
+        if self.items.len() < self.k {
+            self.items.push((i, item));
+        } else {
+            let j = self.rng.below(i + 1);
+            if j < self.k {
+                self.items[j] = (i, item);
+            }
+        }
+    }
+
+    fn into_items(mut self) -> Vec<T> {
+        self.items.sort_unstable_by_key(|(i, _)| *i);
+        self.items.into_iter().map(|(_, item)| item).collect()
+    }
+}
+
+/// Like `search_text_report`, but returns only `k` of the matching files chosen uniformly at
+/// random, in the order of the paths, or ranked if scored. The same `seed` gives the same sample
+/// of the same results; without a seed, one is drawn from the clock.
+/// `on_result` is called with every match, sampled or not.
+pub fn search_text_sample<F>(query_group: &QueryGroup, textfile_paths: &[String], options: &SearchOptions, k: usize, seed: Option<u64>, on_result: F) -> (Vec<FileMatchResult>, SearchReport)
+where
+    F: Fn(&FileMatchResult) + Sync,
+{
+    let no_tags = Tags::new();
+    let entries: Vec<_> = textfile_paths.iter().map(|path| (path.as_str(), &no_tags)).collect();
+    sample_entries(query_group, &entries, options, k, seed, on_result)
+}
+
+/// Like `search_files_report`, but with the results sampled as in `search_text_sample`.
+pub fn search_files_sample<F>(query_group: &QueryGroup, files: &FilePaths, filter: &TagFilter, options: &SearchOptions, k: usize, seed: Option<u64>, on_result: F) -> (Vec<FileMatchResult>, SearchReport)
+where
+    F: Fn(&FileMatchResult) + Sync,
+{
+    sample_entries(query_group, &file_entries(files, filter), options, k, seed, on_result)
+}
+
+fn sample_entries<F>(query_group: &QueryGroup, entries: &[(&str, &Tags)], options: &SearchOptions, k: usize, seed: Option<u64>, on_result: F) -> (Vec<FileMatchResult>, SearchReport)
+where
+    F: Fn(&FileMatchResult) + Sync,
+{
+    let seed = seed.unwrap_or_else(|| {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        now ^ ((std::process::id() as u64) << 32)
+    });
+    let mut reservoir = Reservoir::new(k, seed);
+    // the chunks are offered in order, so that the sample doesn't depend on thread scheduling
+    let report = scan_entries(query_group, entries, options, CHUNK_SIZE, on_result, |chunk| {
+        for result in chunk {
+            reservoir.offer(result);
+        }
+    });
+    let mut results = reservoir.into_items();
+    if let Some(scoring) = &options.scoring {
+        score::rank(&mut results, scoring.top_k);
+    }
+    (results, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Reservoir;
+
+    #[test]
+    fn test_reservoir() {
+        let sample = |k: usize, n: usize, seed: u64| {
+            let mut reservoir = Reservoir::new(k, seed);
+            (0..n).for_each(|i| reservoir.offer(i));
+            reservoir.into_items()
+        };
+        assert_eq!(sample(5, 3, 0), vec![0, 1, 2]);
+        assert_eq!(sample(3, 100, 1), sample(3, 100, 1));
+        let drawn = sample(3, 100, 2);
+        assert_eq!(drawn.len(), 3);
+        assert!(drawn.windows(2).all(|w| w[0] < w[1]));
+
+        // each item is drawn with probability k / n
+        let mut hits = [0; 10];
+        for seed in 0..10000 {
+            for i in sample(2, 10, seed) {
+                hits[i] += 1;
+            }
+        }
+        assert!(hits.iter().all(|&h| (1700..2300).contains(&h)), "{:?}", hits);
+    }
+}
@@ -0,0 +1,288 @@
+//! Ranking of matching files by a user-supplied score.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::tags::Tags;
+use crate::{FileMatchResult, QueryGroup};
+
+/// What is known of the matches of a query group in a file, to score it by.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MatchStats {
+    /// Number of matches of each pattern of the query group.
+    pub counts: Vec<usize>,
+    /// Byte offset of the first match of each pattern.
+    pub first_positions: Vec<usize>,
+    /// Length of the contents in bytes, after normalization.
+    pub length: usize,
+    /// Total number of matches, each counting as the weight of its alternative times the boost
+    /// of its OR group, if the query is weighted.
+    #[serde(default)]
+    pub weighted_total: Option<f64>,
+}
+
+impl MatchStats {
+    pub(crate) fn of(query_group: &QueryGroup, contents: &str) -> Self {
+        let mut stats = MatchStats {
+            length: contents.len(),
+            ..Default::default()
+        };
+        for pat in query_group.patterns.iter() {
+            let mut matches = pat.find_iter(contents);
+            let first = matches.next();
+            stats.first_positions.push(first.map_or(contents.len(), |m| m.start()));
+            stats.counts.push(usize::from(first.is_some()) + matches.count());
+        }
+        if query_group.is_weighted() {
+            let total = (0..query_group.patterns.len())
+                .map(|i| {
+                    let count = match query_group.atom_weights[i].iter().all(|&weight| weight == 1.0) {
+                        true => stats.counts[i] as f64,
+                        false => query_group.alternatives[i]
+                            .iter()
+                            .zip(query_group.atom_weights[i].iter())
+                            .map(|(pat, weight)| weight * pat.find_iter(contents).count() as f64)
+                            .sum(),
+                    };
+                    query_group.group_boosts[i] * count
+                })
+                .sum();
+            stats.weighted_total = Some(total);
+        }
+        stats
+    }
+
+    /// The weighted total number of matches, or the total number if the query isn't weighted.
+    pub fn total(&self) -> f64 {
+        self.weighted_total.unwrap_or_else(|| self.counts.iter().sum::<usize>() as f64)
+    }
+}
+
+/// A matching file to be scored.
+pub struct Candidate<'a> {
+    pub path: &'a str,
+    pub tags: &'a Tags,
+    pub stats: &'a MatchStats,
+}
+
+/// Combines what is known of a matching file into a score; higher scores rank first.
+pub trait Scorer: Send + Sync {
+    fn score(&self, candidate: &Candidate<'_>) -> f64;
+}
+
+impl<F> Scorer for F
+where
+    F: Fn(&Candidate<'_>) -> f64 + Send + Sync,
+{
+    fn score(&self, candidate: &Candidate<'_>) -> f64 {
+        self(candidate)
+    }
+}
+
+/// The total number of matches of all the patterns, weighted if the query is, the score used
+/// when none is given.
+pub struct TotalCount;
+
+impl Scorer for TotalCount {
+    fn score(&self, candidate: &Candidate<'_>) -> f64 {
+        candidate.stats.total()
+    }
+}
+
+/// The total number of matches like `TotalCount`, weighted down in longer files so that a file
+/// mentioning the query a few times in passing doesn't outrank a shorter one about it: the
+/// square root of the total over the square root of the length in kilobytes, at least one.
+pub struct LengthNormalized;
+
+impl Scorer for LengthNormalized {
+    fn score(&self, candidate: &Candidate<'_>) -> f64 {
+        let kilobytes = (candidate.stats.length as f64 / 1024.0).max(1.0);
+        (candidate.stats.total() / kilobytes).sqrt()
+    }
+}
+
+/// The built-in scorer named `name`: `count` for `TotalCount`, or `length_normalized` for
+/// `LengthNormalized`.
+pub fn named(name: &str) -> PyResult<Arc<dyn Scorer>> {
+    match name {
+        "count" => Ok(Arc::new(TotalCount)),
+        "length_normalized" => Ok(Arc::new(LengthNormalized)),
+        _ => Err(PyValueError::new_err(format!("unknown score {:?}, expected count or length_normalized", name))),
+    }
+}
+
+/// How to rank the matching files.
+#[derive(Clone)]
+pub struct ScoringOptions {
+    pub scorer: Arc<dyn Scorer>,
+    /// Keep only this many of the best-scoring files.
+    pub top_k: Option<usize>,
+}
+
+impl fmt::Debug for ScoringOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScoringOptions").field("top_k", &self.top_k).finish_non_exhaustive()
+    }
+}
+
+pub(crate) fn apply_score(scoring: &ScoringOptions, result: &mut FileMatchResult, stats: &MatchStats) {
+    let candidate = Candidate {
+        path: &result.path,
+        tags: &result.tags,
+        stats,
+    };
+    result.score = Some(scoring.scorer.score(&candidate));
+}
+
+/// Sort by descending score, keeping the input order among equal scores and putting NaN last,
+/// and keep the `top_k` first.
+pub(crate) fn rank(results: &mut Vec<FileMatchResult>, top_k: Option<usize>) {
+    let key = |r: &FileMatchResult| r.score.filter(|s| !s.is_nan()).unwrap_or(f64::NEG_INFINITY);
+    results.sort_by(|a, b| key(b).partial_cmp(&key(a)).unwrap_or(Ordering::Equal));
+    if let Some(top_k) = top_k {
+        results.truncate(top_k);
+    }
+}
+
+/// A matching file as passed to a Python scoring function.
+#[pyclass(name = "Candidate")]
+pub struct PyCandidate {
+    #[pyo3(get)]
+    path: String,
+    #[pyo3(get)]
+    tags: Tags,
+    #[pyo3(get)]
+    counts: Vec<usize>,
+    #[pyo3(get)]
+    first_positions: Vec<usize>,
+    #[pyo3(get)]
+    length: usize,
+    /// The total number of matches, weighted if the query is.
+    #[pyo3(get)]
+    total: f64,
+}
+
+/// A Python callable taking a `Candidate` and returning a float. The first error it raises is
+/// kept, to be re-raised once the search is over; meanwhile the candidate scores NaN.
+pub struct PyScorer {
+    callable: PyObject,
+    error: Mutex<Option<PyErr>>,
+}
+
+impl PyScorer {
+    pub fn new(callable: PyObject) -> Self {
+        PyScorer {
+            callable,
+            error: Mutex::new(None),
+        }
+    }
+
+    pub fn take_error(&self) -> Option<PyErr> {
+        self.error.lock().unwrap().take()
+    }
+}
+
+impl Scorer for PyScorer {
+    fn score(&self, candidate: &Candidate<'_>) -> f64 {
+        let score = Python::with_gil(|py| {
+            let candidate = PyCandidate {
+                path: candidate.path.to_string(),
+                tags: candidate.tags.clone(),
+                counts: candidate.stats.counts.clone(),
+                first_positions: candidate.stats.first_positions.clone(),
+                length: candidate.stats.length,
+                total: candidate.stats.total(),
+            };
+            self.callable
+                .call1(py, (Py::new(py, candidate)?,))
+                .and_then(|score| score.extract::<f64>(py))
+        });
+        score.unwrap_or_else(|e| {
+            self.error.lock().unwrap().get_or_insert(e);
+            f64::NAN
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::tags::{TagValue, Tags};
+    use crate::{FileMatchResult, QueryGroup};
+    use super::{apply_score, rank, Candidate, LengthNormalized, MatchStats, ScoringOptions, Scorer, TotalCount};
+
+    #[test]
+    fn test_rank() {
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string()], vec!["bar".to_string()]]).unwrap();
+        let stats = MatchStats::of(&query_group, "foo bar foo");
+        assert_eq!(stats, MatchStats {
+            counts: vec![2, 1],
+            first_positions: vec![0, 4],
+            length: 11,
+            weighted_total: None,
+        });
+
+        let result = |path: &str, year: i64| FileMatchResult {
+            path: path.to_string(),
+            context: None,
+            tags: Tags::from([("year".to_string(), TagValue::Int(year))]),
+            score: None,
+            truncated_before: false,
+            truncated_after: false,
+            position: None,
+            contexts: Vec::new(),
+            clause_contexts: Vec::new(),
+            highlights: Vec::new(),
+        };
+        let newest = ScoringOptions {
+            scorer: Arc::new(|c: &Candidate<'_>| match c.tags.get("year") {
+                Some(TagValue::Int(year)) => *year as f64,
+                _ => 0.0,
+            }),
+            top_k: Some(2),
+        };
+        let mut results = vec![result("a", 2019), result("b", 2021), result("c", 2020)];
+        for result in results.iter_mut() {
+            apply_score(&newest, result, &stats);
+        }
+        rank(&mut results, newest.top_k);
+        let paths: Vec<_> = results.iter().map(|r| r.path()).collect();
+        assert_eq!(paths, vec!["b", "c"]);
+
+        let total = ScoringOptions {
+            scorer: Arc::new(TotalCount),
+            top_k: None,
+        };
+        apply_score(&total, &mut results[0], &stats);
+        assert_eq!(results[0].score(), Some(3.0));
+
+        let tags = Tags::new();
+        let candidate = |stats: &MatchStats| LengthNormalized.score(&Candidate {
+            path: "a",
+            tags: &tags,
+            stats,
+        });
+        let long = MatchStats {
+            counts: vec![8, 8],
+            length: 16 << 10,
+            ..Default::default()
+        };
+        assert_eq!(candidate(&stats), 3f64.sqrt());
+        assert_eq!(candidate(&long), 1.0);
+        assert!(super::named("bm25").is_err());
+    }
+
+    #[test]
+    fn test_weighted() {
+        let atoms = vec![vec!["error".to_string(), "glitch".to_string()], vec!["disk".to_string()]];
+        let query_group = QueryGroup::new(atoms).unwrap().weighted(vec![vec![1.0, 0.25], vec![1.0]], vec![1.0, 2.0]).unwrap();
+        let primary = MatchStats::of(&query_group, "disk error");
+        let synonym = MatchStats::of(&query_group, "disk glitch, glitch");
+        assert_eq!((primary.total(), synonym.total()), (3.0, 2.5));
+        assert_eq!(MatchStats::of(&QueryGroup::new(vec![vec!["disk".to_string()]]).unwrap(), "disk disk").total(), 2.0);
+        assert!(QueryGroup::new(vec![vec!["a".to_string()]]).unwrap().weighted(vec![vec![1.0, 1.0]], vec![1.0]).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+//! Rendering of contexts into snippets ready to be shown to users, and slicing of text at
+//! character boundaries.
+
+use std::borrow::Cow;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::pattern::Pattern;
+
+const ELLIPSIS: &str = "…";
+
+/// Escape the characters that are special in HTML text and attribute values.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Sorted, non-overlapping byte spans of the non-empty matches of `patterns` in `text`.
+fn match_spans(patterns: &[Pattern], text: &str) -> Vec<(usize, usize)> {
+    let mut spans: Vec<_> = patterns
+        .iter()
+        .flat_map(|pat| pat.find_iter(text))
+        .filter(|m| m.start() < m.end())
+        .map(|m| (m.start(), m.end()))
+        .collect();
+    spans.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// The greatest character boundary of `text` at or before `index`, which may be past its end.
+pub fn floor_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// The least character boundary of `text` at or after `index`, or its length.
+pub fn ceil_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+/// The byte range `start..end` of `text`, clamped to it and shrunk to the nearest character
+/// boundaries within, so that slicing with it can't panic. Empty, at `start`, if nothing is left.
+pub fn approx_substring_range(text: &str, start: usize, end: usize) -> (usize, usize) {
+    let start = ceil_char_boundary(text, start);
+    (start, floor_char_boundary(text, end).max(start))
+}
+
+/// `text[start..end]`, shrunk to the nearest character boundaries within.
+pub fn approx_substring(text: &str, start: usize, end: usize) -> &str {
+    let (start, end) = approx_substring_range(text, start, end);
+    &text[start..end]
+}
+
+/// What `slice_around` counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SliceUnit {
+    /// Bytes, the slice shrunk to character boundaries.
+    Bytes,
+    Chars,
+    /// Extended grapheme clusters, e.g. so that an emoji sequence or a letter with combining
+    /// marks isn't cut.
+    Graphemes,
+}
+
+impl SliceUnit {
+    /// `bytes`, `chars` or `graphemes`.
+    pub fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "bytes" => Ok(SliceUnit::Bytes),
+            "chars" => Ok(SliceUnit::Chars),
+            "graphemes" => Ok(SliceUnit::Graphemes),
+            _ => Err(PyValueError::new_err(format!("unknown unit {:?}; expected bytes, chars or graphemes", name))),
+        }
+    }
+}
+
+/// Byte range of `before` units before and `after` units after the byte `offset` of `text`,
+/// which is first moved back to the boundary of its character, or grapheme with `Graphemes`.
+pub fn slice_around(text: &str, offset: usize, before: usize, after: usize, unit: SliceUnit) -> (usize, usize) {
+    let offset = floor_char_boundary(text, offset);
+    match unit {
+        SliceUnit::Bytes => approx_substring_range(text, offset.saturating_sub(before), offset.saturating_add(after)),
+        SliceUnit::Chars => {
+            let start = text[..offset].char_indices().rev().nth(before.saturating_sub(1)).map_or(0, |(i, _)| i);
+            let end = text[offset..].char_indices().nth(after).map_or(text.len(), |(i, _)| offset + i);
+            (if before == 0 { offset } else { start }, end)
+        }
+        SliceUnit::Graphemes => {
+            let boundaries: Vec<usize> = text.grapheme_indices(true).map(|(i, _)| i).chain(std::iter::once(text.len())).collect();
+            let at = boundaries.partition_point(|&i| i <= offset) - 1;
+            let start = boundaries[at.saturating_sub(before)];
+            let end = boundaries[at.saturating_add(after).min(boundaries.len() - 1)];
+            (start, end)
+        }
+    }
+}
+
+/// Byte range of at most `max_chars` characters of `text`, centered on `focus` (a byte span).
+pub(crate) fn window(text: &str, focus: (usize, usize), max_chars: usize) -> (usize, usize) {
+    let offsets: Vec<usize> = text.char_indices().map(|(i, _)| i).chain(std::iter::once(text.len())).collect();
+    let total = offsets.len() - 1;
+    if total <= max_chars {
+        return (0, text.len());
+    }
+    let focus_start = offsets.partition_point(|&i| i < focus.0);
+    let focus_end = offsets.partition_point(|&i| i < focus.1);
+    let slack = max_chars.saturating_sub(focus_end - focus_start) / 2;
+    let end = (focus_start.saturating_sub(slack) + max_chars).min(total);
+    let start = end.saturating_sub(max_chars);
+    (offsets[start], offsets[end])
+}
+
+/// The byte range `start..end` of `text` without the words it cuts at its ends, nor the
+/// whitespace left there, but still holding the byte span `focus`. Words of unspaced scripts,
+/// which aren't delimited, are cut anywhere.
+pub(crate) fn snap_to_words(text: &str, (start, end): (usize, usize), focus: (usize, usize)) -> (usize, usize) {
+    let in_word = |ch: char| (ch.is_alphanumeric() || ch == '_') && !crate::is_unspaced(ch);
+    let (mut start, mut end) = (start, end);
+    if text[..start].chars().next_back().is_some_and(in_word) {
+        // a word running into the match is kept whole
+        if let Some(i) = text[start..focus.0].find(|ch| !in_word(ch)) {
+            start += i;
+        }
+    }
+    if text[end..].chars().next().is_some_and(in_word) {
+        if let Some((i, ch)) = text[focus.1..end].char_indices().rev().find(|&(_, ch)| !in_word(ch)) {
+            end = focus.1 + i + ch.len_utf8();
+        }
+    }
+    let head = &text[start..focus.0];
+    let tail = &text[focus.1..end];
+    (start + head.len() - head.trim_start().len(), end - tail.len() + tail.trim_end().len())
+}
+
+/// `text` with each run of whitespace, e.g. a line break and the indentation after it,
+/// collapsed into a space.
+pub(crate) fn collapse_whitespace(text: &str) -> Cow<'_, str> {
+    if !text.contains(|ch: char| ch.is_whitespace() && ch != ' ') && !text.contains("  ") {
+        return Cow::Borrowed(text);
+    }
+    let mut collapsed = String::with_capacity(text.len());
+    let mut in_whitespace = false;
+    for ch in text.chars() {
+        match ch.is_whitespace() {
+            true if in_whitespace => {}
+            true => collapsed.push(' '),
+            false => collapsed.push(ch),
+        }
+        in_whitespace = ch.is_whitespace();
+    }
+    Cow::Owned(collapsed)
+}
+
+/// Render `text` as an HTML-safe snippet: the text is HTML-escaped and the matches of `patterns`
+/// are wrapped in `<mark>`. If `max_chars` is given, the text is truncated to that many
+/// characters around the first match, with ellipses marking the truncated ends.
+pub fn html_snippet(patterns: &[Pattern], text: &str, max_chars: Option<usize>) -> String {
+    let spans = match_spans(patterns, text);
+    let (start, end) = match max_chars {
+        Some(max_chars) => window(text, spans.first().copied().unwrap_or((0, 0)), max_chars),
+        None => (0, text.len()),
+    };
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push_str(ELLIPSIS);
+    }
+    let mut last = start;
+    for (span_start, span_end) in spans {
+        let (span_start, span_end) = (span_start.max(start), span_end.min(end));
+        if span_start >= span_end {
+            continue;
+        }
+        snippet.push_str(&escape_html(&text[last..span_start]));
+        snippet.push_str("<mark>");
+        snippet.push_str(&escape_html(&text[span_start..span_end]));
+        snippet.push_str("</mark>");
+        last = span_end;
+    }
+    snippet.push_str(&escape_html(&text[last..end]));
+    if end < text.len() {
+        snippet.push_str(ELLIPSIS);
+    }
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+    use crate::pattern::Pattern;
+    use super::{approx_substring, collapse_whitespace, html_snippet, slice_around, snap_to_words, SliceUnit};
+
+    #[test]
+    fn test_html_snippet() {
+        let patterns = vec![Pattern::Regex(Regex::new("b<r").unwrap()), Pattern::Regex(Regex::new("qux").unwrap())];
+        assert_eq!(html_snippet(&patterns, "foo b<r & qux", None), "foo <mark>b&lt;r</mark> &amp; <mark>qux</mark>");
+        assert_eq!(html_snippet(&patterns, "foo b<r & qux", Some(5)), "… <mark>b&lt;r</mark> …");
+        assert_eq!(html_snippet(&patterns, "中文中文 b<r", Some(4)), "… <mark>b&lt;r</mark>");
+        assert_eq!(html_snippet(&patterns, "nothing <here>", Some(7)), "nothing…");
+    }
+
+    #[test]
+    fn test_snap_to_words() {
+        let text = "the quick brown fox\n  jumps over";
+        let snap = |start: usize, end: usize| {
+            let (start, end) = snap_to_words(text, (start, end), (10, 15));
+            &text[start..end]
+        };
+        assert_eq!(snap(6, 24), "brown fox");
+        assert_eq!(snap(3, 28), "quick brown fox\n  jumps");
+        // a word running into the match is kept whole
+        assert_eq!(snap_to_words("concatenate", (1, 8), (3, 6)), (1, 8));
+        assert_eq!(snap_to_words("中文字", (3, 9), (3, 6)), (3, 9));
+        assert_eq!(collapse_whitespace("fox\n  jumps \t over"), "fox jumps over");
+        assert_eq!(collapse_whitespace("fox jumps"), "fox jumps");
+    }
+
+    #[test]
+    fn test_slicing() {
+        let text = "ab中文cd";
+        assert_eq!(approx_substring(text, 1, 4), "b");
+        assert_eq!(approx_substring(text, 3, 100), "文cd");
+        assert_eq!(approx_substring(text, 4, 3), "");
+        // the offset is in the middle of 文
+        assert_eq!(slice_around(text, 6, 1, 1, SliceUnit::Chars), (2, 8));
+        assert_eq!(slice_around(text, 6, 3, 1, SliceUnit::Bytes), (2, 5));
+        let text = "ne\u{301}e\u{301}!";
+        // é is e and a combining accent, one grapheme but two chars
+        assert_eq!(slice_around(text, 1, 0, 1, SliceUnit::Graphemes), (1, 4));
+        assert_eq!(slice_around(text, 1, 0, 1, SliceUnit::Chars), (1, 2));
+        assert_eq!(slice_around(text, 4, 10, 10, SliceUnit::Graphemes), (0, text.len()));
+    }
+}
@@ -0,0 +1,174 @@
+//! Results streamed into a temporary file instead of being held in memory, for queries matching
+//! too many files for their results to fit in RAM.
+
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use pyo3::exceptions::PyIOError;
+use pyo3::prelude::*;
+use crate::report::SearchReport;
+use crate::tags::{TagFilter, Tags};
+use crate::{file_entries, scan_entries, FileMatchResult, FilePaths, QueryGroup, SearchOptions};
+
+/// Number of files searched, in parallel, between writes of their matches to disk.
+const CHUNK_SIZE: usize = 4096;
+
+/// Names tried at most for a spill file before giving up.
+const MAX_ATTEMPTS: usize = 16;
+
+/// Results stored as JSON lines in a temporary file, which is removed when this is dropped.
+#[pyclass]
+#[derive(Debug)]
+pub struct SpilledResults {
+    path: PathBuf,
+    len: usize,
+}
+
+impl SpilledResults {
+    /// Create a spill file in the temporary directory. Its name is unpredictable, and the file
+    /// mustn't exist yet, so that a link planted in the shared directory isn't written through.
+    fn create() -> io::Result<(Self, BufWriter<fs::File>)> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let mut attempts = 0;
+        loop {
+            // the keys of `RandomState` are drawn from the system's randomness
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_usize(COUNTER.fetch_add(1, Ordering::SeqCst));
+            let name = format!("textsearcher-spill-{}-{:016x}.jsonl", std::process::id(), hasher.finish());
+            let path = std::env::temp_dir().join(name);
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            match options.open(&path) {
+                Ok(file) => {
+                    return Ok((SpilledResults {
+                        path,
+                        len: 0,
+                    }, BufWriter::new(file)));
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempts < MAX_ATTEMPTS => attempts += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Read the results back, in the order they were found.
+    pub fn iter(&self) -> io::Result<SpilledIter> {
+        Ok(SpilledIter {
+            lines: BufReader::new(fs::File::open(&self.path)?).lines(),
+        })
+    }
+}
+
+impl Drop for SpilledResults {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub struct SpilledIter {
+    lines: Lines<BufReader<fs::File>>,
+}
+
+impl Iterator for SpilledIter {
+    type Item = io::Result<FileMatchResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.lines.next()?;
+        Some(line.and_then(|line| serde_json::from_str(&line).map_err(io::Error::from)))
+    }
+}
+
+#[pymethods]
+impl SpilledResults {
+    fn __len__(&self) -> usize {
+        self.len
+    }
+
+    fn __iter__(&self) -> PyResult<PySpilledIter> {
+        let iter = self.iter().map_err(|e| PyIOError::new_err(e.to_string()))?;
+        Ok(PySpilledIter {
+            iter,
+        })
+    }
+}
+
+#[pyclass(name = "SpilledResultsIter")]
+pub struct PySpilledIter {
+    iter: SpilledIter,
+}
+
+#[pymethods]
+impl PySpilledIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<FileMatchResult>> {
+        self.iter
+            .next()
+            .transpose()
+            .map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+}
+
+/// Like `search_text_report`, but with the results written to a temporary file as they're
+/// found, in the order of the paths. The results aren't ranked even if scored.
+pub fn search_text_spilled<F>(query_group: &QueryGroup, textfile_paths: &[String], options: &SearchOptions, on_result: F) -> io::Result<(SpilledResults, SearchReport)>
+where
+    F: Fn(&FileMatchResult) + Sync,
+{
+    let no_tags = Tags::new();
+    let entries: Vec<_> = textfile_paths.iter().map(|path| (path.as_str(), &no_tags)).collect();
+    spill_entries(query_group, &entries, options, on_result)
+}
+
+/// Like `search_files_report`, but with the results spilled as in `search_text_spilled`.
+pub fn search_files_spilled<F>(query_group: &QueryGroup, files: &FilePaths, filter: &TagFilter, options: &SearchOptions, on_result: F) -> io::Result<(SpilledResults, SearchReport)>
+where
+    F: Fn(&FileMatchResult) + Sync,
+{
+    spill_entries(query_group, &file_entries(files, filter), options, on_result)
+}
+
+fn spill_entries<F>(query_group: &QueryGroup, entries: &[(&str, &Tags)], options: &SearchOptions, on_result: F) -> io::Result<(SpilledResults, SearchReport)>
+where
+    F: Fn(&FileMatchResult) + Sync,
+{
+    let (mut spilled, mut writer) = SpilledResults::create()?;
+    let mut error = None;
+    let report = scan_entries(query_group, entries, options, CHUNK_SIZE, on_result, |chunk| {
+        if error.is_some() {
+            return;
+        }
+        for result in chunk.iter() {
+            let written = serde_json::to_writer(&mut writer, result)
+                .map_err(io::Error::from)
+                .and_then(|_| writer.write_all(b"\n"));
+            if let Err(e) = written {
+                error = Some(e);
+                return;
+            }
+        }
+        spilled.len += chunk.len();
+    });
+    match error {
+        Some(e) => Err(e),
+        None => {
+            writer.flush()?;
+            Ok((spilled, report))
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Searches of contents that aren't files, e.g. read from Python file-like objects, sockets or
+//! streams produced by other libraries, or texts already loaded such as scraped web pages and
+//! database rows.
+
+use std::io::{self, Read};
+use std::time::Instant;
+use encoding_rs::Encoding;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyString};
+use rayon::prelude::*;
+use crate::{binary, encoding, finish_match, match_contents, score, FileMatchResult, QueryGroup, SearchOptions};
+use crate::report::ScanStats;
+use crate::tags::Tags;
+
+/// Size of the chunks read from a stream.
+const CHUNK_SIZE: usize = 1 << 16;
+
+/// Read at most `max_bytes` bytes from the file-like `stream` by calling its `read` method, which
+/// may return `bytes` or, for text streams, `str` which is encoded to UTF-8; `bytes` and `str`
+/// given as the stream are taken as they are. Returns whether the stream was cut short.
+pub fn read_stream(stream: &PyAny, max_bytes: usize) -> PyResult<(Vec<u8>, bool)> {
+    let given = match stream.downcast::<PyString>() {
+        Ok(text) => Some(text.to_str()?.as_bytes()),
+        Err(_) => stream.downcast::<PyBytes>().ok().map(PyBytes::as_bytes),
+    };
+    if let Some(given) = given {
+        return Ok((given[..given.len().min(max_bytes)].to_vec(), given.len() > max_bytes));
+    }
+    let mut bytes = Vec::new();
+    loop {
+        let wanted = CHUNK_SIZE.min(max_bytes + 1 - bytes.len());
+        let chunk = stream.call_method1("read", (wanted,))?;
+        let chunk = match chunk.downcast::<PyString>() {
+            Ok(text) => text.to_str()?.as_bytes(),
+            Err(_) => chunk.downcast::<PyBytes>()?.as_bytes(),
+        };
+        if chunk.is_empty() {
+            return Ok((bytes, false));
+        }
+        bytes.extend_from_slice(chunk);
+        if bytes.len() > max_bytes {
+            bytes.truncate(max_bytes);
+            return Ok((bytes, true));
+        }
+    }
+}
+
+/// Decode the `bytes` of the stream `name` with `encoding`, or else as UTF-8, leaving out a
+/// character cut short at the end if the stream was.
+pub fn decode(name: &str, mut bytes: Vec<u8>, encoding: Option<&'static Encoding>, cut: bool) -> PyResult<String> {
+    match encoding {
+        Some(encoding) => {
+            let mut decoder = encoding.new_decoder();
+            let mut contents = String::with_capacity(decoder.max_utf8_buffer_length(bytes.len()).unwrap_or(bytes.len()));
+            let _ = decoder.decode_to_string(&bytes, &mut contents, !cut);
+            Ok(contents)
+        }
+        None => {
+            if let Err(e) = std::str::from_utf8(&bytes) {
+                if cut && e.error_len().is_none() {
+                    bytes.truncate(e.valid_up_to());
+                }
+            }
+            String::from_utf8(bytes).map_err(|e| PyValueError::new_err(format!("{} isn't valid UTF-8: {}", name, e)))
+        }
+    }
+}
+
+/// Texts already in memory, each with an id the results are named after, searched without
+/// touching the filesystem.
+#[pyclass]
+#[derive(Clone, Debug, Default)]
+pub struct DocumentSet {
+    /// Pairs of the id and text of each document.
+    pub documents: Vec<(String, String)>,
+}
+
+impl DocumentSet {
+    pub fn new(documents: Vec<(String, String)>) -> Self {
+        DocumentSet {
+            documents,
+        }
+    }
+}
+
+#[pymethods]
+impl DocumentSet {
+    #[new]
+    #[pyo3(signature = (documents = None))]
+    fn py_new(documents: Option<Vec<(String, String)>>) -> Self {
+        DocumentSet::new(documents.unwrap_or_default())
+    }
+
+    fn append(&mut self, id: String, text: String) {
+        self.documents.push((id, text));
+    }
+
+    /// Append the documents of another `DocumentSet`, or of a list of `(id, text)` pairs.
+    fn extend(&mut self, documents: &PyAny) -> PyResult<()> {
+        match documents.extract::<PyRef<DocumentSet>>() {
+            Ok(other) => self.documents.extend(other.documents.iter().cloned()),
+            Err(_) => self.documents.extend(documents.extract::<Vec<(String, String)>>()?),
+        }
+        Ok(())
+    }
+
+    /// The ids of the documents, in order.
+    #[getter]
+    fn ids(&self) -> Vec<String> {
+        self.documents.iter().map(|(id, _)| id.clone()).collect()
+    }
+
+    fn __len__(&self) -> usize {
+        self.documents.len()
+    }
+
+    fn __contains__(&self, id: &str) -> bool {
+        self.documents.iter().any(|(other, _)| other == id)
+    }
+}
+
+/// The match of `query_group` in the contents read from `reader` to its end, e.g. standard input
+/// or a network stream, like `search_text_with` would match a file with those contents at the
+/// path `label`: the result is named after `label`, whose encoding hint, if any, decodes them.
+pub fn search_reader<R: Read>(query_group: &QueryGroup, mut reader: R, label: &str, options: &SearchOptions) -> io::Result<Option<FileMatchResult>> {
+    let started = Instant::now();
+    let stats = ScanStats::default();
+    let mut bytes = Vec::new();
+    let read = reader.read_to_end(&mut bytes).and_then(|_| {
+        let contents = encoding::decode(label, bytes, &options.encoding_hints)?;
+        binary::check_text(&contents, options.binary)?;
+        Ok(contents)
+    });
+    let result = match read {
+        Ok(contents) => {
+            stats.record_read(contents.len());
+            Ok(match_contents(query_group, label, &contents, options).map(|(result, match_stats)| finish_match(result, match_stats, &Tags::new(), options)))
+        }
+        Err(e) => {
+            stats.record_skip(label, &e);
+            Err(e)
+        }
+    };
+    stats.finish(1, usize::from(matches!(result, Ok(Some(_)))), started.elapsed());
+    result
+}
+
+/// The documents, pairs of a name and contents, matching `query_group`, like `search_text_with`
+/// would match files with those contents; the results are named after the documents.
+pub fn search_contents(query_group: &QueryGroup, documents: &[(String, String)], options: &SearchOptions) -> Vec<FileMatchResult> {
+    let started = Instant::now();
+    let stats = ScanStats::default();
+    let match_document = |(name, contents): &(String, String)| {
+        stats.record_read(contents.len());
+        let (result, match_stats) = match_contents(query_group, name, contents, options)?;
+        Some(finish_match(result, match_stats, &Tags::new(), options))
+    };
+    let mut results: Vec<_> = match options.parallel {
+        true => documents.par_iter().filter_map(match_document).collect(),
+        false => documents.iter().filter_map(match_document).collect(),
+    };
+    stats.finish(documents.len(), results.len(), started.elapsed());
+    if let Some(scoring) = &options.scoring {
+        score::rank(&mut results, scoring.top_k);
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{QueryGroup, SearchOptions};
+    use super::{decode, search_contents, search_reader, DocumentSet};
+
+    #[test]
+    fn test_search_contents() {
+        let documents = vec![("a".to_string(), "foo bar".to_string()), ("b".to_string(), "baz".to_string())];
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string()]]).unwrap();
+        let results = search_contents(&query_group, &documents, &SearchOptions::default());
+        let names: Vec<_> = results.iter().map(|r| r.path()).collect();
+        assert_eq!(names, vec!["a"]);
+        let cut = "ab\u{e9}".as_bytes()[..3].to_vec();
+        assert_eq!(decode("s", cut.clone(), None, true).unwrap(), "ab");
+        assert!(decode("s", cut, None, false).is_err());
+        assert_eq!(decode("s", vec![0xd6, 0xd0], encoding_rs::Encoding::for_label(b"gbk"), false).unwrap(), "中");
+    }
+
+    #[test]
+    fn test_search_reader() {
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string()]]).unwrap();
+        let options = SearchOptions::default();
+        let result = search_reader(&query_group, "bar\nfoo".as_bytes(), "-", &options).unwrap().unwrap();
+        assert_eq!((result.path(), result.position().map(|position| position.line)), ("-", Some(2)));
+        assert!(search_reader(&query_group, "bar".as_bytes(), "-", &options).unwrap().is_none());
+        assert!(search_reader(&query_group, &b"foo\xff"[..], "-", &options).is_err());
+        assert!(search_reader(&query_group, &b"foo\0"[..], "-", &options).is_err());
+    }
+
+    #[test]
+    fn test_document_set() {
+        let documents = DocumentSet::new((0..100).map(|i| (format!("row {}", i), if i % 10 == 0 { "foo bar" } else { "bar" }.to_string())).collect());
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string()], vec!["bar".to_string()]]).unwrap();
+        let options = SearchOptions {
+            parallel: true,
+            ..Default::default()
+        };
+        let results = search_contents(&query_group, &documents.documents, &options);
+        let names: Vec<_> = results.iter().map(|r| r.path()).collect();
+        assert_eq!(names, (0..10).map(|i| format!("row {}", i * 10)).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,440 @@
+//! Named queries loaded from a configuration file, for reproducible batch searches.
+//!
+//! A suite is a TOML (or JSON) file with a table per query:
+//!
+//! ```toml
+//! [queries.ml_surveys]
+//! atoms = [["machine learning", "ML"], ["survey", "review"]]
+//! exclude = [["draft"]]
+//! a = 100
+//! b = 100
+//! tag_filter = { year = { ">=" = 2020 } }
+//!
+//! [queries.servers]
+//! bool = { must = ["サーバー"], must_not = ["クライアント"] }
+//! kana_folding = true
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::normalize::CaseFold;
+use crate::presets::Preset;
+use crate::{context_window, query, ContextOptions, ContextUnit, QueryGroup, QueryOptions, RegexFlags, Separators};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SuiteFile {
+    queries: BTreeMap<String, QuerySpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuerySpec {
+    /// AND of ORs of atoms, as passed to `QueryGroup`.
+    atoms: Option<Vec<Vec<String>>>,
+    /// ORs of atoms none of which may match.
+    #[serde(default)]
+    exclude: Vec<Vec<String>>,
+    /// Elasticsearch-style bool query, instead of `atoms`.
+    bool: Option<Value>,
+    #[serde(default)]
+    kana_folding: bool,
+    #[serde(default)]
+    romaji: bool,
+    #[serde(default)]
+    romaji_dictionary: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    phonetic: bool,
+    /// `full` or `turkic`, as passed to `QueryGroup`.
+    case_folding: Option<String>,
+    cjk_gap: Option<String>,
+    word_gap: Option<String>,
+    #[serde(default)]
+    hyphens: bool,
+    #[serde(default)]
+    whole_words: bool,
+    #[serde(default)]
+    nfkc: bool,
+    fuzzy: Option<usize>,
+    /// The regex flags, as passed to `QueryGroup`.
+    #[serde(default)]
+    case_sensitive: bool,
+    multi_line: Option<bool>,
+    #[serde(default)]
+    dot_matches_new_line: bool,
+    unicode: Option<bool>,
+    /// Number of characters within which all the atoms must match.
+    within: Option<usize>,
+    /// Number of lines within which all the atoms must match.
+    within_lines: Option<usize>,
+    /// Number of characters by which the atoms must match apart from the first group.
+    apart: Option<usize>,
+    /// Number of the atoms of each OR group that must match.
+    minimum_should_match: Option<Vec<usize>>,
+    /// Weight of each atom of each OR group in the relevance score.
+    weights: Option<Vec<Vec<f64>>>,
+    /// Weight of each OR group in the relevance score.
+    boosts: Option<Vec<f64>>,
+    /// The name of a preset, as passed to `QueryGroup`.
+    preset: Option<String>,
+    a: Option<usize>,
+    b: Option<usize>,
+    #[serde(default)]
+    context_chars: bool,
+    before_lines: Option<usize>,
+    after_lines: Option<usize>,
+    max_context_chars: Option<usize>,
+    /// Only require the other atoms to match within the context.
+    #[serde(default)]
+    scoped: bool,
+    #[serde(default)]
+    highlight_begin: String,
+    #[serde(default)]
+    highlight_end: String,
+    /// Highlight the matches of all the atoms, as passed to `search_text`.
+    #[serde(default)]
+    highlight_all: bool,
+    /// Mark contexts that stop short of the file with `…`.
+    #[serde(default)]
+    ellipsis: bool,
+    /// Tidy the contexts into snippets, as passed to `search_text`.
+    #[serde(default)]
+    snippet: bool,
+    /// Extract the contexts of all the matches, up to this many per file.
+    max_contexts: Option<usize>,
+    /// Extract the context of the first match of each AND group.
+    #[serde(default)]
+    clause_contexts: bool,
+    /// As passed to `search_text`.
+    tag_filter: Option<Value>,
+    #[serde(default)]
+    invert: bool,
+}
+
+/// A query of a suite, with the options to search with.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct SuiteQuery {
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub query_group: QueryGroup,
+    pub context: Option<ContextOptions>,
+    /// The tag filter, in the form taken by `search_text`.
+    pub tag_filter: Option<Value>,
+    pub invert: bool,
+}
+
+impl QuerySpec {
+    fn compile(self, name: String) -> PyResult<SuiteQuery> {
+        let error = |message: &str| PyValueError::new_err(format!("query {:?}: {}", name, message));
+        let options = QueryOptions {
+            kana_folding: self.kana_folding,
+            romaji: self.romaji,
+            romaji_dictionary: self.romaji_dictionary,
+            phonetic: self.phonetic,
+            case_folding: self.case_folding.as_deref().map(CaseFold::from_name).transpose().map_err(|e| error(&e.to_string()))?,
+            separators: Separators {
+                words: self.word_gap,
+                cjk: self.cjk_gap,
+                hyphens: self.hyphens,
+            },
+            whole_words: self.whole_words,
+            nfkc: self.nfkc,
+            fuzzy: self.fuzzy,
+            flags: RegexFlags {
+                case_sensitive: self.case_sensitive,
+                multi_line: self.multi_line.unwrap_or(true),
+                dot_matches_new_line: self.dot_matches_new_line,
+                unicode: self.unicode.unwrap_or(true),
+            },
+        };
+        let preset = self.preset.as_deref().map(Preset::from_name).transpose().map_err(|e| error(&e.to_string()))?;
+        let options = match preset {
+            Some(preset) => preset.query_options(options),
+            None => options,
+        };
+        let query_group = match (self.atoms, self.bool) {
+            (Some(atoms), None) => QueryGroup::with_exclusions(atoms, self.exclude, options),
+            (None, Some(bool_query)) if self.exclude.is_empty() => query::from_bool_json(&bool_query)?.compile(options),
+            (None, Some(_)) => return Err(error("exclude can't be combined with bool; use must_not")),
+            (Some(_), Some(_)) => return Err(error("atoms and bool can't be combined")),
+            (None, None) => return Err(error("either atoms or bool is required")),
+        }
+        .map_err(|e| error(&e.to_string()))?;
+        let query_group = match self.minimum_should_match {
+            Some(counts) => query_group.minimum_should_match(counts).map_err(|e| error(&e.to_string()))?,
+            None => query_group,
+        };
+        let query_group = match (self.weights, self.boosts) {
+            (None, None) => query_group,
+            (weights, boosts) => {
+                let weights = weights.unwrap_or_else(|| query_group.atom_weights.clone());
+                let boosts = boosts.unwrap_or_else(|| query_group.group_boosts.clone());
+                query_group.weighted(weights, boosts).map_err(|e| error(&e.to_string()))?
+            }
+        };
+        let query_group = QueryGroup {
+            within: self.within,
+            within_lines: self.within_lines,
+            apart: self.apart,
+            preset,
+            ..query_group
+        };
+        let (a, b, unit) = context_window(self.a, self.b, self.context_chars, self.before_lines, self.after_lines)
+            .map_err(|e| error(&e.to_string()))?;
+        let context = match (a, b) {
+            (Some(a), Some(b)) => Some(ContextOptions {
+                before: a,
+                after: b,
+                unit,
+                max_chars: self.max_context_chars,
+                highlight_begin: self.highlight_begin,
+                highlight_end: self.highlight_end,
+                highlight_all: self.highlight_all,
+                scoped: self.scoped,
+                ellipsis: self.ellipsis,
+                snippet: self.snippet,
+                max_contexts: self.max_contexts,
+                clause_contexts: self.clause_contexts,
+            }),
+            (None, None) if self.scoped => return Err(error("scoped requires a context")),
+            (None, None) => None,
+            _ => return Err(error("a and b must be given together")),
+        };
+        Ok(SuiteQuery {
+            name,
+            query_group,
+            context,
+            tag_filter: self.tag_filter,
+            invert: self.invert,
+        })
+    }
+}
+
+/// `query_group` searched with `context` and `invert`, written like a query of a suite. The
+/// worker compiles the atoms again, with the options of `query_group`.
+pub(crate) fn query_spec(query_group: &QueryGroup, context: Option<&ContextOptions>, invert: bool) -> Value {
+    let options = &query_group.query_options;
+    let mut spec = json!({
+        "atoms": query_group.atoms,
+        "exclude": query_group.excluded_atoms,
+        "kana_folding": options.kana_folding,
+        "romaji": options.romaji,
+        "romaji_dictionary": options.romaji_dictionary,
+        "phonetic": options.phonetic,
+        "case_folding": options.case_folding.map(|fold| if fold.turkic { "turkic" } else { "full" }),
+        "cjk_gap": options.separators.cjk,
+        "word_gap": options.separators.words,
+        "hyphens": options.separators.hyphens,
+        "whole_words": options.whole_words,
+        "nfkc": options.nfkc,
+        "fuzzy": options.fuzzy,
+        "case_sensitive": options.flags.case_sensitive,
+        "multi_line": options.flags.multi_line,
+        "dot_matches_new_line": options.flags.dot_matches_new_line,
+        "unicode": options.flags.unicode,
+        "within": query_group.within,
+        "within_lines": query_group.within_lines,
+        "apart": query_group.apart,
+        "minimum_should_match": query_group.minimum_should_match,
+        "weights": query_group.atom_weights,
+        "boosts": query_group.group_boosts,
+        "invert": invert,
+        "preset": query_group.preset.map(Preset::name),
+    });
+    if let Some(context) = context {
+        let (before, after) = match context.unit {
+            ContextUnit::Lines => ("before_lines", "after_lines"),
+            _ => ("a", "b"),
+        };
+        spec[before] = context.before.into();
+        spec[after] = context.after.into();
+        spec["context_chars"] = (context.unit == ContextUnit::Chars).into();
+        spec["max_context_chars"] = context.max_chars.into();
+        spec["scoped"] = context.scoped.into();
+        spec["highlight_begin"] = context.highlight_begin.clone().into();
+        spec["highlight_end"] = context.highlight_end.clone().into();
+        spec["highlight_all"] = context.highlight_all.into();
+        spec["ellipsis"] = context.ellipsis.into();
+        spec["snippet"] = context.snippet.into();
+        spec["max_contexts"] = context.max_contexts.into();
+        spec["clause_contexts"] = context.clause_contexts.into();
+    }
+    spec
+}
+
+/// Parse a suite, as TOML or, if `json` is set, as JSON.
+pub fn parse_suite(text: &str, json: bool) -> PyResult<Vec<SuiteQuery>> {
+    let suite: SuiteFile = if json {
+        serde_json::from_str(text).map_err(|e| PyValueError::new_err(e.to_string()))?
+    } else {
+        toml::from_str(text).map_err(|e| PyValueError::new_err(e.to_string()))?
+    };
+    suite
+        .queries
+        .into_iter()
+        .map(|(name, spec)| spec.compile(name))
+        .collect()
+}
+
+/// Compile a query given like in a suite, as JSON.
+pub(crate) fn compile_query(name: &str, spec: Value) -> PyResult<SuiteQuery> {
+    let spec: QuerySpec = serde_json::from_value(spec).map_err(|e| PyValueError::new_err(format!("query {:?}: {}", name, e)))?;
+    spec.compile(name.to_string())
+}
+
+/// A query as its atoms and options, without the compiled patterns, so that it's cheap to
+/// pickle, e.g. to send to `multiprocessing` workers that each compile it once.
+#[pyclass(name = "QuerySpec", module = "textsearcher")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyQuerySpec {
+    spec: Value,
+}
+
+#[pymethods]
+impl PyQuerySpec {
+    #[new]
+    fn py_new(query_group: &QueryGroup) -> Self {
+        PyQuerySpec {
+            spec: query_spec(query_group, None, false),
+        }
+    }
+
+    /// The spec written by `to_json`, i.e. a query written like in a JSON suite.
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        let spec: Value = serde_json::from_str(json).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        serde_json::from_value::<QuerySpec>(spec.clone()).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyQuerySpec {
+            spec,
+        })
+    }
+
+    fn to_json(&self) -> String {
+        self.spec.to_string()
+    }
+
+    /// Compile the query.
+    fn compile(&self) -> PyResult<QueryGroup> {
+        Ok(compile_query("spec", self.spec.clone())?.query_group)
+    }
+
+    fn __reduce__(&self, py: Python<'_>) -> PyResult<(PyObject, (String,))> {
+        let from_json = py.get_type::<PyQuerySpec>().getattr("from_json")?;
+        Ok((from_json.into(), (self.to_json(),)))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("QuerySpec({})", self.spec)
+    }
+}
+
+/// Load the suite at `path`, JSON if it ends with `.json` and TOML otherwise. The queries are
+/// sorted by name.
+pub fn load_suite<P: AsRef<Path>>(path: P) -> PyResult<Vec<SuiteQuery>> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path).map_err(|e| PyIOError::new_err(format!("{}: {}", path.display(), e)))?;
+    let json = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    parse_suite(&text, json)
+}
+
+#[pymethods]
+impl SuiteQuery {
+    /// The keyword arguments of `search_text` to search with, e.g.
+    /// `search_text(q.query_group, files, **q.search_kwargs)`.
+    #[getter]
+    fn search_kwargs<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let kwargs = PyDict::new(py);
+        if let Some(context) = &self.context {
+            let (before, after) = match context.unit {
+                ContextUnit::Lines => ("before_lines", "after_lines"),
+                _ => ("a", "b"),
+            };
+            kwargs.set_item(before, context.before)?;
+            kwargs.set_item(after, context.after)?;
+            kwargs.set_item("context_chars", context.unit == ContextUnit::Chars)?;
+            kwargs.set_item("max_context_chars", context.max_chars)?;
+            kwargs.set_item("scoped", context.scoped)?;
+            kwargs.set_item("highlight_begin", &context.highlight_begin)?;
+            kwargs.set_item("highlight_end", &context.highlight_end)?;
+            kwargs.set_item("highlight_all", context.highlight_all)?;
+            kwargs.set_item("ellipsis", context.ellipsis)?;
+            kwargs.set_item("snippet", context.snippet)?;
+            kwargs.set_item("max_contexts", context.max_contexts)?;
+            kwargs.set_item("clause_contexts", context.clause_contexts)?;
+        }
+        if let Some(tag_filter) = &self.tag_filter {
+            let tag_filter = py.import("json")?.call_method1("loads", (tag_filter.to_string(),))?;
+            kwargs.set_item("tag_filter", tag_filter)?;
+        }
+        kwargs.set_item("invert", self.invert)?;
+        Ok(kwargs)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("SuiteQuery(name={:?})", self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::presets::Preset;
+    use crate::QueryGroup;
+    use super::{parse_suite, PyQuerySpec};
+
+    #[test]
+    fn test_parse_suite() {
+        let suite = parse_suite(r#"
+            [queries.ml]
+            atoms = [["machine learning", "ML"], ["survey"]]
+            exclude = [["draft"]]
+            within = 200
+            a = 10
+            b = 20
+            tag_filter = { year = { ">=" = 2020 } }
+
+            [queries.absent]
+            bool = { must = ["foo"], must_not = ["bar"] }
+            invert = true
+        "#, false).unwrap();
+        let names: Vec<_> = suite.iter().map(|q| q.name.as_str()).collect();
+        assert_eq!(names, vec!["absent", "ml"]);
+        assert!(suite[0].invert && suite[0].context.is_none());
+        assert_eq!(suite[0].query_group.excluded.len(), 1);
+        let context = suite[1].context.as_ref().unwrap();
+        assert_eq!((context.before, context.after), (10, 20));
+        assert_eq!(suite[1].query_group.patterns.len(), 2);
+        assert_eq!(suite[1].query_group.within, Some(200));
+        assert_eq!(suite[1].tag_filter.as_ref().unwrap().to_string(), r#"{"year":{">=":2020}}"#);
+
+        let json = parse_suite(r#"{"queries": {"q": {"atoms": [["foo"]]}}}"#, true).unwrap();
+        assert_eq!(json[0].query_group.patterns.len(), 1);
+
+        assert!(parse_suite("[queries.q]\natoms = [['foo']]\nbool = { must = ['foo'] }", false).is_err());
+        assert!(parse_suite("[queries.q]\natoms = [['foo']]\na = 1", false).is_err());
+        assert!(parse_suite("[queries.q]\natoms = [['foo']]\ncase = true", false).is_err());
+    }
+
+    #[test]
+    fn test_query_spec() {
+        let query_group = QueryGroup::with_exclusions(vec![vec!["foo".to_string(), "bar".to_string()]], vec![vec!["baz".to_string()]], Default::default())
+            .unwrap()
+            .within(20);
+        let query_group = QueryGroup {
+            preset: Some(Preset::PdfToText),
+            ..query_group
+        };
+        let spec = PyQuerySpec::from_json(&PyQuerySpec::py_new(&query_group).to_json()).unwrap();
+        let compiled = spec.compile().unwrap();
+        assert_eq!((compiled.atoms, compiled.excluded_atoms), (query_group.atoms, query_group.excluded_atoms));
+        assert_eq!((compiled.within, compiled.preset), (Some(20), Some(Preset::PdfToText)));
+        assert!(PyQuerySpec::from_json(r#"{"atoms": [["foo"]], "case": true}"#).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! User metadata attached to files, and predicates over it.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyList};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TagValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+pub type Tags = HashMap<String, TagValue>;
+
+impl TagValue {
+    /// Order of comparable values: numbers among numbers, strings among strings, bools among bools.
+    fn partial_cmp(&self, other: &TagValue) -> Option<Ordering> {
+        match (self, other) {
+            (TagValue::Bool(a), TagValue::Bool(b)) => a.partial_cmp(b),
+            (TagValue::Int(a), TagValue::Int(b)) => a.partial_cmp(b),
+            (TagValue::Int(a), TagValue::Float(b)) => (*a as f64).partial_cmp(b),
+            (TagValue::Float(a), TagValue::Int(b)) => a.partial_cmp(&(*b as f64)),
+            (TagValue::Float(a), TagValue::Float(b)) => a.partial_cmp(b),
+            (TagValue::Str(a), TagValue::Str(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
+impl<'source> FromPyObject<'source> for TagValue {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        // bool must be checked before int, of which it's a subclass in Python
+        if let Ok(b) = ob.downcast::<PyBool>() {
+            Ok(TagValue::Bool(b.is_true()))
+        } else if let Ok(i) = ob.extract::<i64>() {
+            Ok(TagValue::Int(i))
+        } else if let Ok(f) = ob.extract::<f64>() {
+            Ok(TagValue::Float(f))
+        } else if let Ok(s) = ob.extract::<String>() {
+            Ok(TagValue::Str(s))
+        } else {
+            Err(PyValueError::new_err(format!("unsupported tag value: {}", ob)))
+        }
+    }
+}
+
+impl ToPyObject for TagValue {
+    fn to_object(&self, py: Python<'_>) -> PyObject {
+        match self {
+            TagValue::Bool(b) => b.to_object(py),
+            TagValue::Int(i) => i.to_object(py),
+            TagValue::Float(f) => f.to_object(py),
+            TagValue::Str(s) => s.to_object(py),
+        }
+    }
+}
+
+impl IntoPy<PyObject> for TagValue {
+    fn into_py(self, py: Python<'_>) -> PyObject {
+        self.to_object(py)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum TagCondition {
+    Eq(TagValue),
+    Ne(TagValue),
+    Lt(TagValue),
+    Le(TagValue),
+    Gt(TagValue),
+    Ge(TagValue),
+    In(Vec<TagValue>),
+}
+
+impl TagCondition {
+    fn holds(&self, value: Option<&TagValue>) -> bool {
+        let value = match value {
+            None => return matches!(self, TagCondition::Ne(_)),
+            Some(value) => value,
+        };
+        match self {
+            TagCondition::Eq(v) => value.partial_cmp(v) == Some(Ordering::Equal),
+            TagCondition::Ne(v) => value.partial_cmp(v) != Some(Ordering::Equal),
+            TagCondition::Lt(v) => value.partial_cmp(v) == Some(Ordering::Less),
+            TagCondition::Le(v) => matches!(value.partial_cmp(v), Some(Ordering::Less | Ordering::Equal)),
+            TagCondition::Gt(v) => value.partial_cmp(v) == Some(Ordering::Greater),
+            TagCondition::Ge(v) => matches!(value.partial_cmp(v), Some(Ordering::Greater | Ordering::Equal)),
+            TagCondition::In(vs) => vs.iter().any(|v| value.partial_cmp(v) == Some(Ordering::Equal)),
+        }
+    }
+}
+
+/// Conjunction of conditions on the tags of a file. A file without the tag satisfies only `Ne`.
+#[derive(Clone, Debug, Default)]
+pub struct TagFilter {
+    pub conditions: Vec<(String, TagCondition)>,
+}
+
+impl TagFilter {
+    pub fn accepts(&self, tags: Option<&Tags>) -> bool {
+        self.conditions
+            .iter()
+            .all(|(key, condition)| condition.holds(tags.and_then(|t| t.get(key))))
+    }
+
+    /// Build from a Python dict such as `{"venue": "neurips", "year": {">=": 2020},
+    /// "lang": ["en", "de"]}`: a scalar means equality, a list means membership, and a dict maps
+    /// comparison operators to operands.
+    pub fn from_py(filter: &PyDict) -> PyResult<Self> {
+        let mut conditions = Vec::new();
+        for (key, spec) in filter.iter() {
+            let key: String = key.extract()?;
+            if let Ok(ops) = spec.downcast::<PyDict>() {
+                for (op, operand) in ops.iter() {
+                    let op: String = op.extract()?;
+                    let condition = match op.as_str() {
+                        "==" => TagCondition::Eq(operand.extract()?),
+                        "!=" => TagCondition::Ne(operand.extract()?),
+                        "<" => TagCondition::Lt(operand.extract()?),
+                        "<=" => TagCondition::Le(operand.extract()?),
+                        ">" => TagCondition::Gt(operand.extract()?),
+                        ">=" => TagCondition::Ge(operand.extract()?),
+                        "in" => TagCondition::In(operand.extract()?),
+                        _ => return Err(PyValueError::new_err(format!("unknown tag operator {:?}", op))),
+                    };
+                    conditions.push((key.clone(), condition));
+                }
+            } else if let Ok(values) = spec.downcast::<PyList>() {
+                conditions.push((key, TagCondition::In(values.extract()?)));
+            } else {
+                conditions.push((key, TagCondition::Eq(spec.extract()?)));
+            }
+        }
+        Ok(TagFilter {
+            conditions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TagCondition, TagFilter, TagValue, Tags};
+
+    #[test]
+    fn test_tag_filter() {
+        let tags = Tags::from([
+            ("year".to_string(), TagValue::Int(2021)),
+            ("venue".to_string(), TagValue::Str("neurips".to_string())),
+        ]);
+        let filter = |conditions: Vec<(&str, TagCondition)>| TagFilter {
+            conditions: conditions.into_iter().map(|(k, c)| (k.to_string(), c)).collect(),
+        };
+        assert!(filter(vec![("year", TagCondition::Ge(TagValue::Float(2020.5)))]).accepts(Some(&tags)));
+        assert!(filter(vec![
+            ("venue", TagCondition::In(vec![TagValue::Str("icml".to_string()), TagValue::Str("neurips".to_string())])),
+            ("year", TagCondition::Lt(TagValue::Int(2022))),
+        ]).accepts(Some(&tags)));
+        assert!(!filter(vec![("year", TagCondition::Eq(TagValue::Str("2021".to_string())))]).accepts(Some(&tags)));
+        assert!(!filter(vec![("missing", TagCondition::Eq(TagValue::Bool(true)))]).accepts(Some(&tags)));
+        assert!(filter(vec![("missing", TagCondition::Ne(TagValue::Bool(true)))]).accepts(None));
+        assert!(TagFilter::default().accepts(None));
+    }
+}
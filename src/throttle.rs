@@ -0,0 +1,153 @@
+//! Limits on how many files are read at once and on how much of them is held in memory at once,
+//! independent of how many threads search them.
+
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// A counting semaphore shared by the clones of the options of a search, so that e.g. an NFS
+/// server isn't overwhelmed by every worker thread reading at once, while the files read are
+/// still matched on all the threads.
+#[derive(Clone)]
+pub struct ReadLimit {
+    inner: Arc<(Mutex<usize>, Condvar)>,
+    max: usize,
+}
+
+/// Held while reading a file; lets another read start when dropped.
+pub struct ReadPermit<'a> {
+    limit: &'a ReadLimit,
+}
+
+impl ReadLimit {
+    /// At most `max` reads at once, and at least one.
+    pub fn new(max: usize) -> Self {
+        ReadLimit {
+            inner: Arc::new((Mutex::new(0), Condvar::new())),
+            max: max.max(1),
+        }
+    }
+
+    /// Wait until fewer than the maximum reads are in progress.
+    pub fn acquire(&self) -> ReadPermit<'_> {
+        let (reading, freed) = &*self.inner;
+        let mut reading = freed.wait_while(reading.lock().unwrap(), |reading| *reading >= self.max).unwrap();
+        *reading += 1;
+        ReadPermit {
+            limit: self,
+        }
+    }
+}
+
+impl Drop for ReadPermit<'_> {
+    fn drop(&mut self) {
+        let (reading, freed) = &*self.limit.inner;
+        *reading.lock().unwrap() -= 1;
+        freed.notify_one();
+    }
+}
+
+impl fmt::Debug for ReadLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ReadLimit({})", self.max)
+    }
+}
+
+/// An approximate budget of the bytes of the files held in memory at once by the threads of a
+/// search, shared by the clones of its options, so that a few large files searched together
+/// don't exhaust the memory.
+#[derive(Clone)]
+pub struct MemoryBudget {
+    inner: Arc<(Mutex<u64>, Condvar)>,
+    max: u64,
+}
+
+/// Held while a file is in memory; returns its bytes to the budget when dropped.
+pub struct MemoryReservation<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl MemoryBudget {
+    /// At most `max_bytes` bytes at once, except for a single file larger than them, which is
+    /// searched alone.
+    pub fn new(max_bytes: u64) -> Self {
+        MemoryBudget {
+            inner: Arc::new((Mutex::new(0), Condvar::new())),
+            max: max_bytes.max(1),
+        }
+    }
+
+    /// Wait until `bytes` more fit in the budget, or until nothing else is reserved if they're
+    /// more than all of it.
+    pub fn reserve(&self, bytes: u64) -> MemoryReservation<'_> {
+        let bytes = bytes.min(self.max);
+        let (used, freed) = &*self.inner;
+        let mut used = freed.wait_while(used.lock().unwrap(), |used| *used + bytes > self.max).unwrap();
+        *used += bytes;
+        MemoryReservation {
+            budget: self,
+            bytes,
+        }
+    }
+}
+
+impl Drop for MemoryReservation<'_> {
+    fn drop(&mut self) {
+        let (used, freed) = &*self.budget.inner;
+        *used.lock().unwrap() -= self.bytes;
+        // the bytes freed may fit several of the reservations waiting
+        freed.notify_all();
+    }
+}
+
+impl fmt::Debug for MemoryBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MemoryBudget({} bytes)", self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+    use super::{MemoryBudget, ReadLimit};
+
+    #[test]
+    fn test_read_limit() {
+        let limit = ReadLimit::new(2);
+        let (reading, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    let _permit = limit.acquire();
+                    let now = reading.fetch_add(1, Ordering::SeqCst) + 1;
+                    most.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    reading.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let budget = MemoryBudget::new(100);
+        let (used, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        thread::scope(|scope| {
+            for bytes in [40, 40, 40, 30, 1000, 20] {
+                let (budget, used, most) = (&budget, &used, &most);
+                scope.spawn(move || {
+                    let reservation = budget.reserve(bytes);
+                    let now = used.fetch_add(reservation.bytes as usize, Ordering::SeqCst) + reservation.bytes as usize;
+                    most.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    used.fetch_sub(reservation.bytes as usize, Ordering::SeqCst);
+                });
+            }
+        });
+        // the file larger than the budget is reserved all of it, and searched alone
+        assert_eq!(most.load(Ordering::SeqCst), 100);
+    }
+}
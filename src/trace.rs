@@ -0,0 +1,133 @@
+//! Events of the searches done, e.g. each file searched and how long it took, files skipped
+//! and searches stopped early, to diagnose slow searches in production. They're logged with the
+//! `log` crate when built with the `logging` feature; `tracing` isn't depended on, since what's
+//! logged are a few events per file rather than nested spans. A process that hasn't installed a
+//! logger of its own has them written to the standard error once `set_level` is called.
+
+use std::time::Duration;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use crate::report::SearchReport;
+
+#[cfg(feature = "logging")]
+mod enabled {
+    use std::io::Write;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::Duration;
+    use log::{Log, Metadata, Record};
+
+    /// Files taking at least this many microseconds to match are logged as slow, a second
+    /// unless set otherwise.
+    pub(super) static SLOW_FILE_MICROS: AtomicU64 = AtomicU64::new(1_000_000);
+
+    pub(super) fn slow_file() -> Duration {
+        Duration::from_micros(SLOW_FILE_MICROS.load(Ordering::Relaxed))
+    }
+
+    /// Writes the events to the standard error, one line each.
+    pub(super) struct StderrLogger;
+
+    impl Log for StderrLogger {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.level() <= log::max_level()
+        }
+
+        fn log(&self, record: &Record<'_>) {
+            if self.enabled(record.metadata()) {
+                let _ = writeln!(std::io::stderr().lock(), "[{} {}] {}", record.level(), record.target(), record.args());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    pub(super) static LOGGER: StderrLogger = StderrLogger;
+}
+
+/// The level named `name`: "off", "error", "warn", "info", "debug" or "trace".
+#[cfg(feature = "logging")]
+fn parse_level(name: &str) -> PyResult<log::LevelFilter> {
+    name.parse().map_err(|_| PyValueError::new_err(format!("unknown log level {:?}, expected \"off\", \"error\", \"warn\", \"info\", \"debug\" or \"trace\"", name)))
+}
+
+/// Log the events up to `level`, e.g. "debug" for each file searched, and files taking at least
+/// `slow_file` to match as slow.
+#[cfg(feature = "logging")]
+pub fn set_level(level: &str, slow_file: Duration) -> PyResult<()> {
+    use std::sync::atomic::Ordering;
+    let level = parse_level(level)?;
+    // a logger installed already, e.g. by the embedding process, is kept
+    let _ = log::set_logger(&enabled::LOGGER);
+    log::set_max_level(level);
+    enabled::SLOW_FILE_MICROS.store(slow_file.as_micros() as u64, Ordering::Relaxed);
+    Ok(())
+}
+
+#[cfg(not(feature = "logging"))]
+pub fn set_level(level: &str, _slow_file: Duration) -> PyResult<()> {
+    match level {
+        "off" => Ok(()),
+        _ => Err(PyValueError::new_err("logging requires textsearcher to be built with the logging feature")),
+    }
+}
+
+/// Log the file at `path` searched in `elapsed`, and as slow if it took that long.
+pub(crate) fn file_searched(path: &str, elapsed: Duration, matched: bool) {
+    #[cfg(feature = "logging")]
+    {
+        log::debug!("searched {} in {:?}: {}", path, elapsed, if matched { "matched" } else { "no match" });
+        if elapsed >= enabled::slow_file() {
+            log::warn!("slow file {}: searched in {:?}", path, elapsed);
+        }
+    }
+    #[cfg(not(feature = "logging"))]
+    let _ = (path, elapsed, matched);
+}
+
+/// Log the file at `path` skipped for `reason`.
+pub(crate) fn file_skipped(path: &str, reason: &str, error: &std::io::Error) {
+    #[cfg(feature = "logging")]
+    log::warn!("skipped {} ({}): {}", path, reason, error);
+    #[cfg(not(feature = "logging"))]
+    let _ = (path, reason, error);
+}
+
+/// Log a finished search, and why it stopped early if it did.
+pub(crate) fn search_done(report: &SearchReport) {
+    #[cfg(feature = "logging")]
+    {
+        let skipped: usize = report.files_skipped.values().sum();
+        log::info!(
+            "searched {} of {} files in {:.3}s: {} matched, {} cached, {} skipped",
+            report.files_scanned,
+            report.files_total,
+            report.wall_time,
+            report.files_matched,
+            report.files_cached,
+            skipped
+        );
+        for (stopped, reason) in [(report.timed_out, "its deadline passed"), (report.cancelled, "it was cancelled"), (report.limited, "max_results were found")] {
+            if stopped {
+                log::warn!("search stopped early: {}", reason);
+            }
+        }
+    }
+    #[cfg(not(feature = "logging"))]
+    let _ = report;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::set_level;
+
+    const SLOW_FILE: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_set_level() {
+        assert!(set_level("off", SLOW_FILE).is_ok());
+        assert_eq!(set_level("debug", SLOW_FILE).is_ok(), cfg!(feature = "logging"));
+        assert!(set_level("verbose", SLOW_FILE).is_err());
+        set_level("off", SLOW_FILE).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+//! Search as you type: each keystroke only searches the files that matched the text it extends.
+
+use std::sync::Arc;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use crate::corpus::{self, Corpus};
+use crate::error::QueryError;
+use crate::options::{ContextArgs, ResultArgs, SearchKwargs};
+use crate::{score, FileMatchResult, QueryGroup, QueryOptions, SearchOptions, FANCY_PREFIX, PCRE_PREFIX, REGEX_PREFIX};
+
+/// A search of a corpus as its query is typed. The text is taken as the AND of its
+/// whitespace-separated words, each an atom.
+pub struct TypeAhead {
+    corpus: Arc<Corpus>,
+    query_options: QueryOptions,
+    options: SearchOptions,
+    /// Texts searched, each extending the previous one, with the indices of the files it matched.
+    history: Vec<(String, Vec<usize>)>,
+}
+
+impl TypeAhead {
+    pub fn new(corpus: Arc<Corpus>, query_options: QueryOptions, options: SearchOptions) -> Self {
+        TypeAhead {
+            corpus,
+            query_options,
+            options,
+            history: Vec::new(),
+        }
+    }
+
+    /// Whether the files matching `text` are among those matching `previous`. Appending to the
+    /// text appends to its last word or adds words, which can only narrow the matches, unless
+    /// atoms are regexes or are expanded into other spellings.
+    fn refines(&self, previous: &str, text: &str) -> bool {
+        text.starts_with(previous)
+            && !self.query_options.romaji
+            && !self.query_options.phonetic
+            && !text.split_whitespace().any(|w| [REGEX_PREFIX, FANCY_PREFIX, PCRE_PREFIX].iter().any(|prefix| w.starts_with(prefix)))
+    }
+
+    /// The matches of `text`, now typed in place of the previous text.
+    pub fn update(&mut self, text: &str) -> Result<Vec<FileMatchResult>, QueryError> {
+        while let Some((previous, _)) = self.history.last() {
+            if self.refines(previous, text) {
+                break;
+            }
+            self.history.pop();
+        }
+        let words: Vec<_> = text.split_whitespace().map(|w| vec![w.to_string()]).collect();
+        if words.is_empty() {
+            return Ok(Vec::new());
+        }
+        let query_group = QueryGroup::with_options(words, self.query_options.clone())?;
+        let all: Vec<usize>;
+        let candidates = match self.history.last() {
+            Some((_, candidates)) => candidates,
+            None => {
+                all = (0..self.corpus.paths().len()).collect();
+                &all
+            }
+        };
+        let matches = self.corpus.search_among(&query_group, &self.options, candidates);
+        let indices = matches.iter().map(|(i, _)| *i).collect();
+        if self.history.last().is_some_and(|(previous, _)| previous == text) {
+            self.history.pop();
+        }
+        self.history.push((text.to_string(), indices));
+        let mut results: Vec<_> = matches.into_iter().map(|(_, result)| result).collect();
+        if let Some(scoring) = &self.options.scoring {
+            score::rank(&mut results, scoring.top_k);
+        }
+        Ok(results)
+    }
+}
+
+/// A search-as-you-type session over a registered corpus, best registered with its contents
+/// cached in memory.
+#[pyclass(name = "TypeAheadSession")]
+pub struct PyTypeAhead {
+    inner: TypeAhead,
+}
+
+#[pymethods]
+impl PyTypeAhead {
+    #[new]
+    #[pyo3(signature = (corpus, a = None, b = None, context_chars = false, max_context_chars = None, highlight_begin = String::new(), highlight_end = String::new(), kana_folding = false, top_k = None))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        corpus: &str,
+        a: Option<usize>,
+        b: Option<usize>,
+        context_chars: bool,
+        max_context_chars: Option<usize>,
+        highlight_begin: String,
+        highlight_end: String,
+        kana_folding: bool,
+        top_k: Option<usize>,
+    ) -> PyResult<Self> {
+        let corpus = corpus::get_corpus(corpus)
+            .ok_or_else(|| PyValueError::new_err(format!("no corpus named {:?}", corpus)))?;
+        let context = ContextArgs {
+            a,
+            b,
+            context_chars,
+            max_context_chars,
+            highlight_begin,
+            highlight_end,
+            ..Default::default()
+        };
+        let results = ResultArgs {
+            top_k,
+            ..Default::default()
+        };
+        let options = SearchKwargs::new(None, None, Default::default(), context, results, Default::default())?.options;
+        let query_options = QueryOptions {
+            kana_folding,
+            ..Default::default()
+        };
+        Ok(PyTypeAhead {
+            inner: TypeAhead::new(corpus, query_options, options),
+        })
+    }
+
+    /// The matches of `text`, typically the contents of the search box after a keystroke.
+    fn update(&mut self, py: Python<'_>, text: &str) -> PyResult<Vec<FileMatchResult>> {
+        let inner = &mut self.inner;
+        Ok(py.allow_threads(|| inner.update(text))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::corpus::Corpus;
+    use crate::{QueryOptions, SearchOptions};
+    use super::TypeAhead;
+
+    #[test]
+    fn test_update() {
+        let paths = vec![String::from("sample_texts/hello.txt"), String::from("sample_texts/world.txt")];
+        let mut session = TypeAhead::new(Arc::new(Corpus::new(paths, true)), QueryOptions::default(), SearchOptions::default());
+        let update = |session: &mut TypeAhead, text: &str| -> Vec<String> {
+            session.update(text).unwrap().iter().map(|r| r.path().to_string()).collect()
+        };
+        assert_eq!(update(&mut session, "o"), vec!["sample_texts/hello.txt", "sample_texts/world.txt"]);
+        assert_eq!(update(&mut session, "wo"), vec!["sample_texts/world.txt"]);
+        assert_eq!(update(&mut session, "wor"), vec!["sample_texts/world.txt"]);
+        assert_eq!(session.history.len(), 2);
+        assert_eq!(update(&mut session, "f"), vec!["sample_texts/hello.txt"]);
+        assert_eq!(session.history.len(), 1);
+        assert_eq!(update(&mut session, "foo ba"), vec!["sample_texts/hello.txt"]);
+        assert_eq!(update(&mut session, "foo qux"), Vec::<String>::new());
+        // back to a prefix, which is searched among its own candidates
+        assert_eq!(update(&mut session, "foo "), vec!["sample_texts/hello.txt"]);
+        assert_eq!(update(&mut session, " "), Vec::<String>::new());
+        assert!(session.history.is_empty());
+    }
+}
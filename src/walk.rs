@@ -0,0 +1,90 @@
+//! Searches of the files below a directory, walked and filtered by glob patterns here rather
+//! than collected by the caller, optionally leaving out what `.gitignore` files ignore.
+
+use std::io;
+use ignore::WalkBuilder;
+use crate::exclude::Excludes;
+use crate::{search_text_with, FileMatchResult, QueryGroup, SearchOptions};
+
+/// Which files below a directory are searched.
+#[derive(Clone, Debug, Default)]
+pub struct WalkOptions {
+    /// Patterns of the files searched, matched like exclude patterns; all files if none.
+    pub include: Excludes,
+    /// Patterns of the files and directories left out.
+    pub exclude: Excludes,
+    /// Whether to leave out what the `.gitignore` files of the directories walked, and of those
+    /// above them, ignore, inside a git repository or not.
+    pub gitignore: bool,
+}
+
+/// The files below `root`, sorted, that `walk` selects. Hidden files are walked like the others.
+pub fn walk_files(root: &str, walk: &WalkOptions) -> io::Result<Vec<String>> {
+    let excludes = walk.exclude.clone();
+    let walker = WalkBuilder::new(root)
+        .standard_filters(false)
+        .git_ignore(walk.gitignore)
+        .parents(walk.gitignore)
+        .require_git(false)
+        .filter_entry(move |entry| match (entry.depth(), entry.path().to_str()) {
+            (0, _) | (_, None) => true,
+            (_, Some(path)) => match entry.file_type().is_some_and(|t| t.is_dir()) {
+                true => !excludes.is_excluded_dir(path),
+                false => !excludes.is_excluded(path),
+            },
+        })
+        .build();
+    let mut paths = Vec::new();
+    for entry in walker {
+        let entry = entry.map_err(|e| io::Error::other(e.to_string()))?;
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let Some(path) = entry.path().to_str() else {
+            continue;
+        };
+        if walk.include.is_empty() || walk.include.is_match(path) {
+            paths.push(path.to_string());
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Search the files below `root` that `walk` selects.
+pub fn search_dir(query_group: &QueryGroup, root: &str, walk: &WalkOptions, options: &SearchOptions) -> io::Result<Vec<FileMatchResult>> {
+    Ok(search_text_with(query_group, &walk_files(root, walk)?, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::exclude::Excludes;
+    use crate::{QueryGroup, SearchOptions};
+    use super::{search_dir, walk_files, WalkOptions};
+
+    #[test]
+    fn test_walk_files() {
+        let root = std::env::temp_dir().join(format!("textsearcher-walk-{}", std::process::id()));
+        fs::create_dir_all(root.join("src/gen")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        for (path, contents) in [("src/a.txt", "foo"), ("src/b.md", "foo"), ("src/gen/c.txt", "foo"), ("target/d.txt", "foo"), (".gitignore", "target/\n")] {
+            fs::write(root.join(path), contents).unwrap();
+        }
+        let root_str = root.to_str().unwrap();
+        let names = |walk: &WalkOptions| -> Vec<String> {
+            walk_files(root_str, walk).unwrap().iter().map(|path| path[root_str.len() + 1..].to_string()).collect()
+        };
+        let walk = WalkOptions {
+            include: Excludes::new(&["*.txt".to_string()]).unwrap(),
+            exclude: Excludes::new(&["**/gen/**".to_string()]).unwrap(),
+            gitignore: true,
+        };
+        assert_eq!(names(&walk), vec!["src/a.txt"]);
+        let all = WalkOptions::default();
+        assert_eq!(names(&all).len(), 5);
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string()]]).unwrap();
+        assert_eq!(search_dir(&query_group, root_str, &walk, &SearchOptions::default()).unwrap().len(), 1);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+//! Watching a directory for the files created or modified in it, each searched once changed, e.g.
+//! to monitor incoming documents. The directory is polled rather than watched through the
+//! notifications of the operating system, so that no watching library is linked in and network
+//! filesystems, which don't notify, are watched alike. Changes are told by the fingerprints of
+//! the files, like the cache tells them.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use pyo3::exceptions::PyIOError;
+use pyo3::prelude::*;
+use crate::cache::Fingerprint;
+use crate::progress::SIGNAL_INTERVAL;
+use crate::walk::{walk_files, WalkOptions};
+use crate::{search_text_with, FileMatchResult, QueryGroup, SearchOptions};
+
+/// The files below a directory, polled for those created or modified since last polled.
+#[derive(Debug)]
+pub struct Watcher {
+    root: String,
+    walk: WalkOptions,
+    fingerprints: HashMap<String, Fingerprint>,
+}
+
+impl Watcher {
+    /// Watch the files below `root` that `walk` selects, those there already not being changed.
+    pub fn new(root: &str, walk: WalkOptions) -> io::Result<Self> {
+        let mut watcher = Watcher {
+            root: root.to_string(),
+            walk,
+            fingerprints: HashMap::new(),
+        };
+        watcher.changed()?;
+        Ok(watcher)
+    }
+
+    /// The files created or modified since last polled, sorted. Files that can't be looked at,
+    /// e.g. removed meanwhile, are looked at again the next time.
+    pub fn changed(&mut self) -> io::Result<Vec<String>> {
+        let paths = walk_files(&self.root, &self.walk)?;
+        let mut fingerprints = HashMap::with_capacity(paths.len());
+        let mut changed = Vec::new();
+        for path in paths {
+            let Ok(fingerprint) = Fingerprint::of(&path) else {
+                continue;
+            };
+            if self.fingerprints.get(&path) != Some(&fingerprint) {
+                changed.push(path.clone());
+            }
+            fingerprints.insert(path, fingerprint);
+        }
+        self.fingerprints = fingerprints;
+        Ok(changed)
+    }
+
+    /// The matches among the files created or modified since last polled, like `search_text_with`.
+    pub fn poll(&mut self, query_group: &QueryGroup, options: &SearchOptions) -> io::Result<Vec<FileMatchResult>> {
+        Ok(search_text_with(query_group, &self.changed()?, options))
+    }
+}
+
+/// Poll `watcher` every `interval`, calling `on_result` with the matches of the files changed,
+/// until the cancel token of `options`, if any, is cancelled.
+pub fn watch<F>(query_group: &QueryGroup, watcher: &mut Watcher, options: &SearchOptions, interval: Duration, mut on_result: F) -> io::Result<()>
+where
+    F: FnMut(&FileMatchResult),
+{
+    let cancelled = || options.cancel.as_ref().is_some_and(|cancel| cancel.is_cancelled());
+    loop {
+        let next_poll = Instant::now() + interval;
+        for result in watcher.poll(query_group, options)?.iter() {
+            on_result(result);
+        }
+        // cancelling is noticed while waiting for the next poll too
+        while !cancelled() && Instant::now() < next_poll {
+            thread::sleep(next_poll.saturating_duration_since(Instant::now()).min(SIGNAL_INTERVAL));
+        }
+        if cancelled() {
+            return Ok(());
+        }
+    }
+}
+
+struct WatchState {
+    watcher: Watcher,
+    pending: VecDeque<FileMatchResult>,
+    next_poll: Instant,
+}
+
+/// The matches of the files changed below a directory, as they change; iterating over it waits
+/// for the next of them until cancelled.
+#[pyclass(name = "Watch")]
+pub struct PyWatch {
+    query_group: QueryGroup,
+    options: SearchOptions,
+    interval: Duration,
+    state: Mutex<WatchState>,
+}
+
+impl PyWatch {
+    pub fn new(query_group: &QueryGroup, watcher: Watcher, options: SearchOptions, interval: Duration) -> Self {
+        PyWatch {
+            query_group: query_group.clone(),
+            options,
+            interval,
+            state: Mutex::new(WatchState {
+                watcher,
+                pending: VecDeque::new(),
+                next_poll: Instant::now() + interval,
+            }),
+        }
+    }
+
+    fn cancelled(&self) -> bool {
+        self.options.cancel.as_ref().is_some_and(|cancel| cancel.is_cancelled())
+    }
+
+    /// The next match, if one is found within `timeout`, polling when it's time to.
+    fn next_timeout(&self, timeout: Duration) -> io::Result<Option<FileMatchResult>> {
+        let mut state = self.state.lock().unwrap();
+        if state.pending.is_empty() {
+            let wait = state.next_poll.saturating_duration_since(Instant::now());
+            if wait > timeout {
+                thread::sleep(timeout);
+                return Ok(None);
+            }
+            thread::sleep(wait);
+            state.next_poll = Instant::now() + self.interval;
+            let results = state.watcher.poll(&self.query_group, &self.options)?;
+            state.pending.extend(results);
+        }
+        Ok(state.pending.pop_front())
+    }
+}
+
+#[pymethods]
+impl PyWatch {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// The next match, once a file changes to match; none once cancelled. A signal such as
+    /// Ctrl-C received meanwhile stops watching and is raised.
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<FileMatchResult>> {
+        loop {
+            if self.cancelled() {
+                return Ok(None);
+            }
+            // the state is locked only without the GIL, which its other users may be holding
+            match py.allow_threads(|| self.next_timeout(SIGNAL_INTERVAL)) {
+                Ok(Some(result)) => return Ok(Some(result)),
+                Ok(None) => {}
+                Err(e) => return Err(PyIOError::new_err(e.to_string())),
+            }
+            if let Err(e) = py.check_signals() {
+                if let Some(cancel) = &self.options.cancel {
+                    cancel.cancel();
+                }
+                return Err(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::thread;
+    use std::time::Duration;
+    use crate::progress::CancelToken;
+    use crate::walk::WalkOptions;
+    use crate::{QueryGroup, SearchOptions};
+    use super::{watch, Watcher};
+
+    #[test]
+    fn test_watch() {
+        let root = std::env::temp_dir().join(format!("textsearcher-watch-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "foo").unwrap();
+        fs::write(root.join("b.txt"), "bar").unwrap();
+        let query_group = QueryGroup::new(vec![vec!["foo".to_string()]]).unwrap();
+        let options = SearchOptions::default();
+        let mut watcher = Watcher::new(root.to_str().unwrap(), WalkOptions::default()).unwrap();
+        assert!(watcher.poll(&query_group, &options).unwrap().is_empty());
+        fs::write(root.join("b.txt"), "bar foo").unwrap();
+        fs::write(root.join("c.txt"), "foo").unwrap();
+        fs::write(root.join("d.txt"), "baz").unwrap();
+        let names = |results: &[crate::FileMatchResult]| -> Vec<String> {
+            results.iter().map(|result| result.path()[root.to_str().unwrap().len() + 1..].to_string()).collect()
+        };
+        assert_eq!(names(&watcher.poll(&query_group, &options).unwrap()), vec!["b.txt", "c.txt"]);
+        assert!(watcher.poll(&query_group, &options).unwrap().is_empty());
+
+        let cancel = CancelToken::new();
+        let options = SearchOptions {
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        };
+        let writing = {
+            let path = root.join("e.txt");
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                fs::write(path, "foo").unwrap();
+            })
+        };
+        let mut results = Vec::new();
+        watch(&query_group, &mut watcher, &options, Duration::from_millis(10), |result| {
+            results.push(result.clone());
+            cancel.cancel();
+        })
+        .unwrap();
+        writing.join().unwrap();
+        assert_eq!(names(&results), vec!["e.txt"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}